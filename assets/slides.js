@@ -0,0 +1,60 @@
+(function () {
+    var slides = document.querySelectorAll(".slide");
+    var counter = document.querySelector(".slide-number");
+    var current = 0;
+
+    function show(index) {
+        if (slides.length === 0) {
+            return;
+        }
+        current = Math.max(0, Math.min(slides.length - 1, index));
+        for (var i = 0; i < slides.length; i++) {
+            slides[i].classList.toggle("current", i === current);
+        }
+        counter.textContent = current + 1 + " / " + slides.length;
+        history.replaceState(null, "", "#" + (current + 1));
+    }
+
+    function fromHash() {
+        show((parseInt(location.hash.slice(1), 10) || 1) - 1);
+    }
+
+    document.addEventListener("keydown", function (event) {
+        if (event.altKey || event.ctrlKey || event.metaKey) {
+            return;
+        }
+        switch (event.key) {
+            case "ArrowRight":
+            case "ArrowDown":
+            case "PageDown":
+            case " ":
+            case "l":
+            case "j":
+                show(current + 1);
+                break;
+            case "ArrowLeft":
+            case "ArrowUp":
+            case "PageUp":
+            case "Backspace":
+            case "h":
+            case "k":
+                show(current - 1);
+                break;
+            case "Home":
+                show(0);
+                break;
+            case "End":
+                show(slides.length - 1);
+                break;
+            case "n":
+                document.body.classList.toggle("show-notes");
+                break;
+            default:
+                return;
+        }
+        event.preventDefault();
+    });
+
+    window.addEventListener("hashchange", fromHash);
+    fromHash();
+})();
@@ -0,0 +1,75 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use markdown_converter::html::HtmlRenderer;
+use markdown_converter::parser::MarkdownParser;
+use markdown_converter::render::Renderer;
+use std::hint::black_box;
+
+const EXAMPLE_MD: &str = include_str!("../example.md");
+
+const MB: usize = 1024 * 1024;
+
+/// Repeats `unit` until the result is at least `size` bytes long.
+fn repeat_to(unit: &str, size: usize) -> String {
+    unit.repeat(size.div_ceil(unit.len()))
+}
+
+fn bench_input(c: &mut Criterion, group_name: &str, inputs: &[(&str, String)]) {
+    let parser = MarkdownParser::new();
+    let renderer = HtmlRenderer::default();
+    let mut group = c.benchmark_group(group_name);
+    group.sample_size(10);
+
+    for (name, input) in inputs {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), input, |b, input| {
+            b.iter(|| renderer.render(&parser.parse_document(black_box(input))))
+        });
+    }
+    group.finish();
+}
+
+fn documents(c: &mut Criterion) {
+    bench_input(
+        c,
+        "document",
+        &[
+            ("1MB", repeat_to(EXAMPLE_MD, MB)),
+            ("10MB", repeat_to(EXAMPLE_MD, 10 * MB)),
+        ],
+    );
+}
+
+fn pathological(c: &mut Criterion) {
+    let nested_list: String = (0..4096)
+        .map(|depth| format!("{}- item\n", "  ".repeat(depth % 64)))
+        .collect();
+
+    bench_input(
+        c,
+        "pathological",
+        &[
+            ("nested_list", repeat_to(&nested_list, MB)),
+            (
+                "nested_blockquote",
+                repeat_to(&format!("{}x\n", ">".repeat(4096)), MB),
+            ),
+            (
+                "nested_emphasis",
+                format!("{}x{}", "*_".repeat(MB / 4), "_*".repeat(MB / 4)),
+            ),
+            ("unclosed_emphasis", repeat_to("*a ", MB)),
+            ("star_run", "*".repeat(MB)),
+            ("unclosed_brackets", repeat_to("[a", MB)),
+            ("unclosed_links", repeat_to("[a](", MB)),
+            (
+                "backtick_runs",
+                (1..1448).map(|n| "`".repeat(n) + " ").collect(),
+            ),
+            ("dollars", repeat_to("$a", MB)),
+            ("table_pipes", format!("|{}|\n", "a|".repeat(MB / 2))),
+        ],
+    );
+}
+
+criterion_group!(benches, documents, pathological);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use markdown_converter::parser::MarkdownParser;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        let html = MarkdownParser::new()
+            .parse(input)
+            .expect("parsing never fails");
+        assert!(html.len() <= 32 * input.len() + 128);
+    }
+});
@@ -0,0 +1,172 @@
+/// A parsed Markdown document, as produced by
+/// [`MarkdownParser::parse_document`](crate::parser::MarkdownParser::parse_document).
+#[derive(Debug, Clone, Default, PartialEq, Hash)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+}
+
+/// Where a block is in the source, as 1-based line numbers, both inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum Block {
+    Heading { level: u8, content: Vec<Inline> },
+    Paragraph(Vec<Inline>),
+    List(List),
+    CodeBlock(CodeBlock),
+    Table(Table),
+    Blockquote(Vec<Block>),
+    MathBlock(MathBlock),
+    HorizontalRule,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct List {
+    pub ordered: bool,
+    /// Number of the first item; always 1 for unordered lists.
+    pub start: u64,
+    pub items: Vec<ListItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct ListItem {
+    /// `Some` for task list items, holding whether the box is ticked.
+    pub checked: Option<bool>,
+    pub content: Vec<Inline>,
+    /// Nested blocks (usually sub-lists) indented under the item.
+    pub children: Vec<Block>,
+}
+
+/// The options a code block's info string may start with, which are not
+/// taken for a language even when written bare, as in `linenos`.
+const CODE_BLOCK_OPTIONS: &[&str] = &["linenos", "hl_lines", "title", "start"];
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct CodeBlock {
+    /// Everything after the opening fence, e.g. `rust` or `python title="x"`.
+    pub info: String,
+    pub code: String,
+}
+
+impl CodeBlock {
+    /// The first word of the info string, if any and it isn't an option.
+    pub fn language(&self) -> Option<&str> {
+        self.info
+            .split_whitespace()
+            .next()
+            .filter(|word| !word.contains('=') && !CODE_BLOCK_OPTIONS.contains(word))
+    }
+
+    /// The value of the option `name` following the language, if any, in
+    /// the info string, written `name=value` or `name="a value"`, or `""`
+    /// for a bare `name`.
+    pub fn option(&self, name: &str) -> Option<&str> {
+        let language_len = self.language().map_or(0, str::len);
+        let mut rest = self.info.trim_start()[language_len..].trim_start();
+        while !rest.is_empty() {
+            let key_end = rest
+                .find(|c: char| c.is_whitespace() || c == '=')
+                .unwrap_or(rest.len());
+            let key = &rest[..key_end];
+            rest = &rest[key_end..];
+            let value = match rest.strip_prefix('=') {
+                Some(quoted) if quoted.starts_with(['"', '\'']) => {
+                    let quote = quoted.chars().next().unwrap_or('"');
+                    let end = quoted[1..].find(quote).map_or(quoted.len(), |end| end + 1);
+                    rest = quoted.get(end + 1..).unwrap_or_default();
+                    &quoted[1..end]
+                }
+                Some(bare) => {
+                    let end = bare.find(char::is_whitespace).unwrap_or(bare.len());
+                    rest = &bare[end..];
+                    &bare[..end]
+                }
+                None => "",
+            };
+            if key == name {
+                return Some(value);
+            }
+            rest = rest.trim_start();
+        }
+        None
+    }
+}
+
+/// Display math between `$$` lines.
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct MathBlock {
+    pub math: String,
+    /// The `{#id}` after the closing `$$`, e.g. `eq:energy`.
+    pub id: Option<String>,
+}
+
+impl MathBlock {
+    /// The label equations are referred to by: the id, or else a
+    /// `\label{...}` in the math.
+    pub fn label(&self) -> Option<&str> {
+        self.id.as_deref().or_else(|| tex_label(&self.math))
+    }
+}
+
+/// The argument of the first `\label{...}` in `tex`.
+fn tex_label(tex: &str) -> Option<&str> {
+    let start = tex.find("\\label{")? + "\\label{".len();
+    let len = tex[start..].find('}')?;
+    Some(tex[start..start + len].trim()).filter(|label| !label.is_empty())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Alignment {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct Table {
+    pub alignments: Vec<Alignment>,
+    pub header: Vec<Vec<Inline>>,
+    pub rows: Vec<Vec<Vec<Inline>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum Inline {
+    Text(String),
+    Code(String),
+    Math(String),
+    Strong(Vec<Inline>),
+    Emphasis(Vec<Inline>),
+    Strikethrough(Vec<Inline>),
+    Link { url: String, content: Vec<Inline> },
+    Image { url: String, alt: String },
+    SoftBreak,
+    LineBreak,
+}
+
+/// Concatenates the text of `inlines` with all markup removed.
+pub fn plain_text(inlines: &[Inline]) -> String {
+    let mut text = String::new();
+    push_plain_text(inlines, &mut text);
+    text
+}
+
+fn push_plain_text(inlines: &[Inline], text: &mut String) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(t) | Inline::Code(t) | Inline::Math(t) => text.push_str(t),
+            Inline::Strong(children)
+            | Inline::Emphasis(children)
+            | Inline::Strikethrough(children)
+            | Inline::Link {
+                content: children, ..
+            } => push_plain_text(children, text),
+            Inline::Image { alt, .. } => text.push_str(alt),
+            Inline::SoftBreak | Inline::LineBreak => text.push(' '),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::ast::{Alignment, Block, CodeBlock, Inline, List, ListItem, MathBlock, Table};
+use crate::html;
+use crate::render::Renderer;
+
+/// Renders a document in Confluence's storage format, the XHTML with `ac:`
+/// macros that the Confluence REST API and "Insert markup" accept.
+///
+/// Code blocks become the `code` macro, task lists Confluence task lists,
+/// and GitHub-style alerts (a quote starting `[!NOTE]`, `[!TIP]`,
+/// `[!IMPORTANT]`, `[!WARNING]` or `[!CAUTION]`) the `info`, `tip`, `note`
+/// and `warning` panels. Images with a URL are linked; others are taken to
+/// be attachments of the page, by file name. Confluence has no math, so math
+/// is kept as TeX in code.
+#[derive(Default)]
+pub struct ConfluenceRenderer;
+
+impl ConfluenceRenderer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// The panel macro for a GitHub alert label such as `[!NOTE]`.
+fn panel(label: &str) -> Option<&'static str> {
+    match label.to_ascii_uppercase().as_str() {
+        "[!NOTE]" | "[!IMPORTANT]" => Some("info"),
+        "[!TIP]" => Some("tip"),
+        "[!WARNING]" => Some("note"),
+        "[!CAUTION]" => Some("warning"),
+        _ => None,
+    }
+}
+
+fn push_text(out: &mut String, text: &str) {
+    html_escape::encode_text_to_string(text, out);
+}
+
+fn push_attribute(out: &mut String, value: &str) {
+    html_escape::encode_double_quoted_attribute_to_string(value, out);
+}
+
+/// Appends `text` as CDATA, splitting it where it contains the `]]>` that
+/// would end the section.
+fn push_cdata(out: &mut String, text: &str) {
+    out.push_str("<![CDATA[");
+    out.push_str(&text.replace("]]>", "]]]]><![CDATA[>"));
+    out.push_str("]]>");
+}
+
+/// A `code` macro, with its language if given.
+fn push_code_macro(out: &mut String, language: Option<&str>, code: &str) {
+    out.push_str(r#"<ac:structured-macro ac:name="code">"#);
+    if let Some(language) = language {
+        out.push_str(r#"<ac:parameter ac:name="language">"#);
+        push_text(out, language);
+        out.push_str("</ac:parameter>");
+    }
+    out.push_str("<ac:plain-text-body>");
+    push_cdata(out, code.trim_end_matches('\n'));
+    out.push_str("</ac:plain-text-body></ac:structured-macro>\n");
+}
+
+impl Renderer for ConfluenceRenderer {
+    fn list(&self, list: &List, out: &mut String) {
+        if list.items.is_empty() || list.items.iter().any(|item| item.checked.is_none()) {
+            return html::list(self, list, out);
+        }
+
+        out.push_str("<ac:task-list>\n");
+        for item in &list.items {
+            out.push_str("<ac:task>\n<ac:task-status>");
+            out.push_str(if item.checked == Some(true) {
+                "complete"
+            } else {
+                "incomplete"
+            });
+            out.push_str("</ac:task-status>\n<ac:task-body>");
+            self.inlines(&item.content, out);
+            out.push_str("</ac:task-body>\n</ac:task>\n");
+            self.blocks(&item.children, out);
+        }
+        out.push_str("</ac:task-list>\n");
+    }
+
+    fn list_item(&self, item: &ListItem, out: &mut String) {
+        out.push_str("<li>");
+        match item.checked {
+            Some(true) => out.push_str("☑ "),
+            Some(false) => out.push_str("☐ "),
+            None => {}
+        }
+        self.inlines(&item.content, out);
+        if !item.children.is_empty() {
+            out.push('\n');
+            self.blocks(&item.children, out);
+        }
+        out.push_str("</li>\n");
+    }
+
+    fn code_block(&self, code_block: &CodeBlock, out: &mut String) {
+        push_code_macro(out, code_block.language(), &code_block.code);
+    }
+
+    fn table(&self, table: &Table, out: &mut String) {
+        out.push_str("<table>\n<tbody>\n");
+        for (tag, row) in
+            std::iter::once(("th", &table.header)).chain(table.rows.iter().map(|row| ("td", row)))
+        {
+            out.push_str("<tr>\n");
+            for (i, cell) in row.iter().enumerate() {
+                out.push('<');
+                out.push_str(tag);
+                match table.alignments.get(i) {
+                    Some(Alignment::Left) => out.push_str(r#" style="text-align: left;""#),
+                    Some(Alignment::Center) => out.push_str(r#" style="text-align: center;""#),
+                    Some(Alignment::Right) => out.push_str(r#" style="text-align: right;""#),
+                    Some(Alignment::None) | None => {}
+                }
+                out.push('>');
+                self.inlines(cell, out);
+                out.push_str("</");
+                out.push_str(tag);
+                out.push_str(">\n");
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</tbody>\n</table>\n");
+    }
+
+    fn blockquote(&self, blocks: &[Block], out: &mut String) {
+        let alert = match blocks.first() {
+            Some(Block::Paragraph(content)) => match content.first() {
+                Some(Inline::Text(label)) => panel(label.trim()).map(|name| (name, content)),
+                _ => None,
+            },
+            _ => None,
+        };
+        let Some((name, first)) = alert else {
+            return html::blockquote(self, blocks, out);
+        };
+
+        out.push_str(&format!(
+            "<ac:structured-macro ac:name=\"{}\"><ac:rich-text-body>\n",
+            name
+        ));
+        // The label's line is dropped; the rest of its paragraph stays.
+        let rest: Vec<Inline> = first
+            .iter()
+            .skip(1)
+            .skip_while(|inline| matches!(inline, Inline::SoftBreak | Inline::LineBreak))
+            .cloned()
+            .collect();
+        if !rest.is_empty() {
+            self.paragraph(&rest, out);
+        }
+        self.blocks(&blocks[1..], out);
+        out.push_str("</ac:rich-text-body></ac:structured-macro>\n");
+    }
+
+    fn math_block(&self, math_block: &MathBlock, out: &mut String) {
+        push_code_macro(out, None, &math_block.math);
+    }
+
+    fn horizontal_rule(&self, out: &mut String) {
+        out.push_str("<hr />\n");
+    }
+
+    fn math(&self, math: &str, out: &mut String) {
+        html::code(self, math, out);
+    }
+
+    fn strikethrough(&self, children: &[Inline], out: &mut String) {
+        out.push_str(r#"<span style="text-decoration: line-through;">"#);
+        self.inlines(children, out);
+        out.push_str("</span>");
+    }
+
+    fn link(&self, url: &str, content: &[Inline], out: &mut String) {
+        let Some(anchor) = url.strip_prefix('#') else {
+            return html::link(self, url, content, out);
+        };
+        out.push_str(r#"<ac:link ac:anchor=""#);
+        push_attribute(out, anchor);
+        out.push_str(r#""><ac:link-body>"#);
+        self.inlines(content, out);
+        out.push_str("</ac:link-body></ac:link>");
+    }
+
+    fn image(&self, url: &str, alt: &str, out: &mut String) {
+        out.push_str(r#"<ac:image ac:alt=""#);
+        push_attribute(out, alt);
+        out.push_str(r#"">"#);
+        if url.contains("://") {
+            out.push_str(r#"<ri:url ri:value=""#);
+            push_attribute(out, url);
+        } else {
+            let file_name = url.rsplit('/').next().unwrap_or(url);
+            out.push_str(r#"<ri:attachment ri:filename=""#);
+            push_attribute(out, file_name);
+        }
+        out.push_str(r#"" /></ac:image>"#);
+    }
+
+    fn line_break(&self, out: &mut String) {
+        out.push_str("<br />\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::MarkdownParser;
+    use pretty_assertions::assert_eq;
+
+    fn render(markdown: &str) -> String {
+        ConfluenceRenderer::new().render(&MarkdownParser::new().parse_document(markdown))
+    }
+
+    #[test]
+    fn test_macros() {
+        assert_eq!(
+            render("```rust\nlet end = \"]]>\";\n```\n\n> [!WARNING]\n> Mind the *gap*.\n\n- [x] done\n- [ ] todo"),
+            "<ac:structured-macro ac:name=\"code\">\
+             <ac:parameter ac:name=\"language\">rust</ac:parameter>\
+             <ac:plain-text-body><![CDATA[let end = \"]]]]><![CDATA[>\";]]></ac:plain-text-body>\
+             </ac:structured-macro>\n\
+             <ac:structured-macro ac:name=\"note\"><ac:rich-text-body>\n\
+             <p>Mind the <em>gap</em>.</p>\n\
+             </ac:rich-text-body></ac:structured-macro>\n\
+             <ac:task-list>\n\
+             <ac:task>\n<ac:task-status>complete</ac:task-status>\n<ac:task-body>done</ac:task-body>\n</ac:task>\n\
+             <ac:task>\n<ac:task-status>incomplete</ac:task-status>\n<ac:task-body>todo</ac:task-body>\n</ac:task>\n\
+             </ac:task-list>\n"
+        );
+    }
+
+    #[test]
+    fn test_well_formed_markup() {
+        assert_eq!(
+            render("| a | b |\n|:-:|---|\n| ~~x~~ | [top](#intro) |\n\nline  \nbreak ![logo](img/logo.png)\n\n---"),
+            "<table>\n<tbody>\n\
+             <tr>\n<th style=\"text-align: center;\">a</th>\n<th>b</th>\n</tr>\n\
+             <tr>\n<td style=\"text-align: center;\"><span style=\"text-decoration: line-through;\">x</span></td>\n\
+             <td><ac:link ac:anchor=\"intro\"><ac:link-body>top</ac:link-body></ac:link></td>\n</tr>\n\
+             </tbody>\n</table>\n\
+             <p>line<br />\nbreak <ac:image ac:alt=\"logo\"><ri:attachment ri:filename=\"logo.png\" /></ac:image></p>\n\
+             <hr />\n"
+        );
+    }
+}
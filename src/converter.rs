@@ -1,26 +1,184 @@
+use crate::ast::{plain_text, Block, Document};
+use crate::confluence::ConfluenceRenderer;
+use crate::crossref::{Equations, LabelCheck};
+use crate::docx::{DocxOptions, DocxWriter};
+use crate::email::{EmailOptions, EmailWriter};
+use crate::epub::{Chapter, EpubOptions, EpubWriter};
 use crate::error::ConverterError;
+use crate::front_matter::FrontMatter;
+use crate::gemtext::GemtextRenderer;
+use crate::html::{self, HighlightOptions, Highlighter, HtmlOptions, HtmlRenderer};
+use crate::json;
+use crate::latex::{LatexOptions, LatexRenderer};
+use crate::man::ManRenderer;
+use crate::mathml;
+use crate::offline::OfflineRenderer;
 use crate::parser::MarkdownParser;
+use crate::pdf::{PdfOptions, PdfRenderer};
+use crate::render::Renderer;
+use crate::slides::{SlideOptions, SlideSplit, SlidesWriter};
+use crate::template::{Context, Template, Value};
+use crate::terminal::{TerminalOptions, TerminalRenderer};
+use crate::text::{TextOptions, TextRenderer};
+use crate::utils;
 use anyhow::Result;
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
-use lazy_static::lazy_static;
-use log::{error, info};
+use log::{error, info, warn};
 use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
-use regex::Regex;
-use std::fs;
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::mpsc::channel;
-use syntect::highlighting::ThemeSet;
-use syntect::html::highlighted_html_for_string;
-use syntect::parsing::SyntaxSet;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Html,
+    Text,
+    Latex,
+    Pdf,
+    Epub,
+    Docx,
+    Man,
+    Json,
+    Terminal,
+    Slides,
+    Gemtext,
+    Confluence,
+    Email,
+}
+
+impl OutputFormat {
+    /// Every format, in the order the interactive menu offers them.
+    pub const ALL: [OutputFormat; 13] = [
+        OutputFormat::Html,
+        OutputFormat::Text,
+        OutputFormat::Latex,
+        OutputFormat::Pdf,
+        OutputFormat::Epub,
+        OutputFormat::Docx,
+        OutputFormat::Man,
+        OutputFormat::Json,
+        OutputFormat::Terminal,
+        OutputFormat::Slides,
+        OutputFormat::Gemtext,
+        OutputFormat::Confluence,
+        OutputFormat::Email,
+    ];
+
+    /// The extension of output files when no path is given.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Html => "html",
+            OutputFormat::Text => "txt",
+            OutputFormat::Latex => "tex",
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Epub => "epub",
+            OutputFormat::Docx => "docx",
+            // Section 1, user commands, unless the page says otherwise.
+            OutputFormat::Man => "1",
+            OutputFormat::Json => "json",
+            OutputFormat::Terminal => "ans",
+            OutputFormat::Slides => "html",
+            OutputFormat::Gemtext => "gmi",
+            // Storage format, as the Confluence REST API takes it.
+            OutputFormat::Confluence => "xml",
+            OutputFormat::Email => "html",
+        }
+    }
+
+    /// The format of output files with `extension`, if it is one of the
+    /// formats' own. `.html` is taken to be a page rather than slides.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let extension = extension.to_ascii_lowercase();
+        if matches!(
+            extension.as_str(),
+            "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9"
+        ) {
+            return Some(OutputFormat::Man);
+        }
+        // HTML comes before the other formats written to `.html` files.
+        OutputFormat::ALL
+            .into_iter()
+            .find(|format| format.extension() == extension)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Html => "HTML",
+            OutputFormat::Text => "plain text",
+            OutputFormat::Latex => "LaTeX",
+            OutputFormat::Pdf => "PDF",
+            OutputFormat::Epub => "EPUB",
+            OutputFormat::Docx => "Word",
+            OutputFormat::Man => "man page",
+            OutputFormat::Json => "JSON",
+            OutputFormat::Terminal => "terminal",
+            OutputFormat::Slides => "slides",
+            OutputFormat::Gemtext => "Gemtext",
+            OutputFormat::Confluence => "Confluence",
+            OutputFormat::Email => "email",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = ConverterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "html" => Ok(OutputFormat::Html),
+            "text" | "txt" => Ok(OutputFormat::Text),
+            "latex" | "tex" => Ok(OutputFormat::Latex),
+            "pdf" => Ok(OutputFormat::Pdf),
+            "epub" => Ok(OutputFormat::Epub),
+            "docx" | "word" => Ok(OutputFormat::Docx),
+            "man" | "roff" => Ok(OutputFormat::Man),
+            "json" => Ok(OutputFormat::Json),
+            "terminal" | "ansi" => Ok(OutputFormat::Terminal),
+            "slides" => Ok(OutputFormat::Slides),
+            "gemtext" | "gmi" | "gemini" => Ok(OutputFormat::Gemtext),
+            "confluence" => Ok(OutputFormat::Confluence),
+            "email" => Ok(OutputFormat::Email),
+            _ => Err(ConverterError::UnknownFormat(s.to_string())),
+        }
+    }
+}
 
 pub struct MarkdownConverter {
     input_path: PathBuf,
     output_path: PathBuf,
+    /// Whether `output_path` was derived from the input rather than given.
+    default_output: bool,
     css_path: Option<PathBuf>,
     syntax_highlight: bool,
+    highlight_options: HighlightOptions,
+    /// Built from `highlight_options` on first use and kept, so converting
+    /// again in watch mode doesn't load the syntaxes and themes again.
+    highlighter: OnceLock<Highlighter>,
     generate_toc: bool,
     minify: bool,
+    /// Whether HTML output is just the rendered body, for embedding.
+    fragment: bool,
+    template_path: Option<PathBuf>,
+    /// Whether HTML math is left for KaTeX rather than written as MathML.
+    katex: bool,
+    /// Whether HTML output works offline, without KaTeX or linked images.
+    self_contained: bool,
+    format: OutputFormat,
+    text_options: TextOptions,
+    latex_options: LatexOptions,
+    preamble_path: Option<PathBuf>,
+    pdf_options: PdfOptions,
+    /// Further chapters following the input file in an EPUB book.
+    chapters: Vec<PathBuf>,
+    docx_options: DocxOptions,
+    reference_doc: Option<PathBuf>,
+    slide_split: SlideSplit,
 }
 
 impl MarkdownConverter {
@@ -32,22 +190,133 @@ impl MarkdownConverter {
         generate_toc: bool,
         minify: bool,
     ) -> Result<Self> {
+        let default_output = output_path.is_none();
         let output_path = output_path.unwrap_or_else(|| {
             let mut output = input_path.clone();
-            output.set_extension("html");
+            output.set_extension(OutputFormat::Html.extension());
             output
         });
 
         Ok(Self {
             input_path,
             output_path,
+            default_output,
             css_path,
             syntax_highlight,
+            highlight_options: HighlightOptions::default(),
+            highlighter: OnceLock::new(),
             generate_toc,
             minify,
+            fragment: false,
+            template_path: None,
+            katex: false,
+            self_contained: false,
+            format: OutputFormat::Html,
+            text_options: TextOptions::default(),
+            latex_options: LatexOptions::default(),
+            preamble_path: None,
+            pdf_options: PdfOptions::default(),
+            chapters: Vec::new(),
+            docx_options: DocxOptions::default(),
+            reference_doc: None,
+            slide_split: SlideSplit::default(),
         })
     }
 
+    /// Converts to `format` instead of HTML. A default output path takes the
+    /// format's extension.
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        if self.default_output {
+            self.output_path.set_extension(format.extension());
+        }
+        self.format = format;
+        self
+    }
+
+    /// The theme, extra themes and syntaxes, and markup of highlighted code
+    /// in HTML pages.
+    pub fn with_highlight_options(mut self, highlight_options: HighlightOptions) -> Self {
+        self.highlight_options = highlight_options;
+        self.highlighter = OnceLock::new();
+        self
+    }
+
+    /// Writes only the rendered body of HTML output, leaving out the page
+    /// around it, for embedding in an existing page or template. The host
+    /// page includes [`Self::styles`] and [`Self::scripts`] once instead.
+    pub fn with_fragment(mut self, fragment: bool) -> Self {
+        self.fragment = fragment;
+        self
+    }
+
+    /// Lays HTML output out with the template at `template_path`, a file or
+    /// a directory holding a `page.html`, instead of the default page.
+    pub fn with_template(mut self, template_path: Option<PathBuf>) -> Self {
+        self.template_path = template_path;
+        self
+    }
+
+    /// Leaves the math of HTML pages as TeX for KaTeX to typeset in the
+    /// browser, instead of writing MathML.
+    pub fn with_katex(mut self, katex: bool) -> Self {
+        self.katex = katex;
+        self
+    }
+
+    /// Makes HTML output a single file that works offline: math is always
+    /// MathML, never left for KaTeX from a CDN, and local images are inlined
+    /// as `data:` URIs.
+    pub fn with_self_contained(mut self, self_contained: bool) -> Self {
+        self.self_contained = self_contained;
+        self
+    }
+
+    pub fn with_text_options(mut self, text_options: TextOptions) -> Self {
+        self.text_options = text_options;
+        self
+    }
+
+    pub fn with_latex_options(mut self, latex_options: LatexOptions) -> Self {
+        self.latex_options = latex_options;
+        self
+    }
+
+    /// Starts LaTeX output with the contents of `preamble_path` instead of the
+    /// default preamble.
+    pub fn with_preamble(mut self, preamble_path: Option<PathBuf>) -> Self {
+        self.preamble_path = preamble_path;
+        self
+    }
+
+    pub fn with_pdf_options(mut self, pdf_options: PdfOptions) -> Self {
+        self.pdf_options = pdf_options;
+        self
+    }
+
+    /// Adds the files of `chapters` to EPUB output, in order, after the input
+    /// file.
+    pub fn with_chapters(mut self, chapters: Vec<PathBuf>) -> Self {
+        self.chapters = chapters;
+        self
+    }
+
+    pub fn with_docx_options(mut self, docx_options: DocxOptions) -> Self {
+        self.docx_options = docx_options;
+        self
+    }
+
+    /// Takes the styles of Word output from the `.docx` file `reference_doc`.
+    pub fn with_reference_doc(mut self, reference_doc: Option<PathBuf>) -> Self {
+        self.reference_doc = reference_doc;
+        self
+    }
+
+    /// Where slides output starts a new slide.
+    pub fn with_slide_split(mut self, slide_split: SlideSplit) -> Self {
+        self.slide_split = slide_split;
+        self
+    }
+
     pub fn convert(&self) -> Result<()> {
         let pb = ProgressBar::new_spinner();
         pb.set_style(
@@ -55,38 +324,33 @@ impl MarkdownConverter {
                 .template("{spinner:.green} {msg}")
                 .unwrap(),
         );
-        pb.set_message("Converting markdown to HTML...");
-
-        let content =
-            fs::read_to_string(&self.input_path).map_err(ConverterError::InputFileError)?;
-
-        let parser = MarkdownParser::new();
-        let mut html = parser.parse(&content)?;
+        pb.set_message(format!("Converting markdown to {}...", self.format.name()));
 
-        if self.syntax_highlight {
-            html = self.apply_syntax_highlighting(&html)?;
-        }
-
-        if self.generate_toc {
-            html = self.generate_table_of_contents(&html)?;
-        }
-
-        html = self.add_css(&html)?;
+        let source = Source::read(&self.input_path)?;
 
-        if self.minify {
-            html = self.minify_html(&html);
+        if self.output_path == Path::new("-") {
+            self.write_output(&source, io::stdout().lock())?;
+        } else {
+            let file = File::create(&self.output_path).map_err(|e| {
+                ConverterError::OutputFileError(format!("Failed to write output: {}", e))
+            })?;
+            self.write_output(&source, BufWriter::new(file))?;
         }
 
-        fs::write(&self.output_path, html).map_err(|e| {
-            ConverterError::OutputFileError(format!("Failed to write output: {}", e))
-        })?;
-
         pb.finish_with_message("Conversion completed successfully!");
         info!("Output saved to: {:?}", self.output_path);
 
         Ok(())
     }
 
+    /// Converts the input file and streams the output (for HTML, the complete
+    /// page) to `writer`. Where the format allows, the document is parsed and
+    /// rendered a top-level block at a time rather than held in memory whole.
+    pub fn convert_to<W: Write>(&self, writer: W) -> Result<()> {
+        let source = Source::read(&self.input_path)?;
+        self.write_output(&source, writer)
+    }
+
     pub fn watch(&self) -> Result<()> {
         info!("Watching for changes in {:?}", self.input_path);
 
@@ -110,125 +374,630 @@ impl MarkdownConverter {
         }
     }
 
-    fn apply_syntax_highlighting(&self, html: &str) -> Result<String> {
-        let ss = SyntaxSet::load_defaults_newlines();
-        let ts = ThemeSet::load_defaults();
-        let theme = &ts.themes["base16-ocean.dark"];
+    /// Formats without a place for metadata leave the front matter out.
+    fn write_output<W: Write>(&self, source: &Source, writer: W) -> Result<()> {
+        match self.format {
+            OutputFormat::Html => self.write_html(source, writer),
+            OutputFormat::Text => self.write_text(source, writer),
+            OutputFormat::Latex => self.write_latex(source, writer),
+            OutputFormat::Pdf => self.write_pdf(source, writer),
+            OutputFormat::Epub => self.write_epub(source, writer),
+            OutputFormat::Docx => self.write_docx(source, writer),
+            OutputFormat::Man => self.write_man(source, writer),
+            OutputFormat::Json => self.write_json(source, writer),
+            OutputFormat::Terminal => self.write_terminal(source, writer),
+            OutputFormat::Slides => self.write_slides(source, writer),
+            OutputFormat::Gemtext => self.write_blocks(&GemtextRenderer::new(), source, writer),
+            OutputFormat::Confluence => {
+                self.write_blocks(&ConfluenceRenderer::new(), source, writer)
+            }
+            OutputFormat::Email => self.write_email(source, writer),
+        }
+    }
 
-        let code_block_regex =
-            Regex::new(r#"<pre><code class="language-(\w+)">([\s\S]*?)</code></pre>"#).unwrap();
+    /// Streams the blocks of `source` through `renderer`, separated by
+    /// blank lines for the line-based formats.
+    fn write_blocks<R: Renderer, W: Write>(
+        &self,
+        renderer: &R,
+        source: &Source,
+        mut writer: W,
+    ) -> Result<()> {
+        let separate = self.format == OutputFormat::Gemtext;
+        for (i, block) in source.blocks().enumerate() {
+            if separate && i > 0 {
+                writer.write_all(b"\n")?;
+            }
+            renderer.render_block_to(&block, &mut writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
 
-        let html = code_block_regex.replace_all(html, |caps: &regex::Captures| {
-            let lang = &caps[1];
-            let code = &caps[2];
+    /// The custom CSS, or else the default stylesheet, is inlined.
+    fn write_email<W: Write>(&self, source: &Source, writer: W) -> Result<()> {
+        let (front_matter, document) = source.parse();
+        EmailWriter::new(EmailOptions {
+            syntax_highlight: self.syntax_highlight,
+        })
+        .with_css(self.css()?)
+        .write_document(&front_matter, &document, writer)
+    }
 
-            if let Some(syntax) = ss.find_syntax_by_token(lang) {
-                match highlighted_html_for_string(code, &ss, syntax, theme) {
-                    Ok(highlighted) => highlighted,
-                    Err(_) => caps[0].to_string(),
-                }
-            } else {
-                caps[0].to_string()
-            }
+    /// The deck has its own stylesheet; custom CSS is added after it.
+    fn write_slides<W: Write>(&self, source: &Source, writer: W) -> Result<()> {
+        if let Source::Markdown(markdown) = source {
+            report_math_errors(&self.input_path, markdown);
+        }
+        let mut slides_writer = SlidesWriter::new(SlideOptions {
+            split: self.slide_split,
+            syntax_highlight: self.syntax_highlight,
         });
+        if self.css_path.is_some() {
+            slides_writer = slides_writer.with_css(&self.css()?);
+        }
+        slides_writer.write_blocks(&source.front_matter(), || source.blocks(), writer)
+    }
+
+    /// Wraps at the plain-text width, which `preview` sets to the terminal's.
+    fn write_terminal<W: Write>(&self, source: &Source, mut writer: W) -> Result<()> {
+        let renderer = TerminalRenderer::new(TerminalOptions {
+            width: self.text_options.width,
+            syntax_highlight: self.syntax_highlight,
+        });
+        for (i, block) in source.blocks().enumerate() {
+            if i > 0 {
+                writer.write_all(b"\n")?;
+            }
+            renderer.render_block_to(&block, &mut writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Positions are written for Markdown input only.
+    fn write_json<W: Write>(&self, source: &Source, mut writer: W) -> Result<()> {
+        let value = match source {
+            Source::Markdown(markdown) => json::to_json(markdown),
+            Source::Document(front_matter, document) => {
+                json::document_to_json(front_matter, document)
+            }
+        };
+        serde_json::to_writer_pretty(&mut writer, &value)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The page's title and section come from its front matter, the title
+    /// defaulting to the input file's name.
+    fn write_man<W: Write>(&self, source: &Source, mut writer: W) -> Result<()> {
+        let front_matter = source.front_matter();
+        let name = self
+            .input_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        writer.write_all(ManRenderer::title_header(&front_matter, &name).as_bytes())?;
+
+        let renderer = ManRenderer::new();
+        for block in source.blocks() {
+            renderer.render_block_to(&block, &mut writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
 
-        Ok(html.to_string())
+    fn write_docx<W: Write>(&self, source: &Source, writer: W) -> Result<()> {
+        let base_dir = self.input_path.parent().unwrap_or(Path::new(""));
+        let mut docx_writer = DocxWriter::new(self.docx_options).with_base_dir(base_dir);
+        if let Some(reference_doc) = &self.reference_doc {
+            let reference = fs::read(reference_doc).map_err(|e| {
+                ConverterError::ReferenceDocError(format!(
+                    "Failed to read {:?}: {}",
+                    reference_doc, e
+                ))
+            })?;
+            docx_writer = docx_writer.with_reference(&reference)?;
+        }
+        let (front_matter, document) = source.parse();
+        docx_writer.write_document(&front_matter, &document, writer)
     }
 
-    fn generate_table_of_contents(&self, html: &str) -> Result<String> {
-        lazy_static! {
-            static ref HEADING_RE: Regex = Regex::new(r"<h([1-6])>(.*?)</h[1-6]>").unwrap();
+    fn write_epub<W: Write>(&self, source: &Source, writer: W) -> Result<()> {
+        let chapter = |path: &Path, source: &Source| {
+            if let Source::Markdown(markdown) = source {
+                report_math_errors(path, markdown);
+            }
+            let (front_matter, document) = source.parse();
+            (front_matter, document.into_owned())
+        };
+        let mut parts = vec![chapter(&self.input_path, source)];
+        for path in &self.chapters {
+            parts.push(chapter(path, &Source::read(path)?));
         }
+        let chapters: Vec<Chapter> = std::iter::once(&self.input_path)
+            .chain(&self.chapters)
+            .zip(parts)
+            .map(|(path, (front_matter, document))| Chapter {
+                path,
+                front_matter,
+                document,
+            })
+            .collect();
+
+        EpubWriter::new(EpubOptions {
+            syntax_highlight: self.syntax_highlight,
+        })
+        .with_css(self.css()?)
+        .write(&chapters, writer)
+    }
 
-        let mut toc =
-            String::from("<div class=\"table-of-contents\">\n<h2>Table of Contents</h2>\n<ul>\n");
-        let mut current_level = 1;
+    /// Unlike the other formats, the PDF is laid out from the whole document
+    /// at once, since the table of contents needs every heading's page.
+    fn write_pdf<W: Write>(&self, source: &Source, mut writer: W) -> Result<()> {
+        let (_, document) = source.parse();
+        let base_dir = self.input_path.parent().unwrap_or(Path::new(""));
+        let pdf = PdfRenderer::new(self.pdf_options)
+            .with_base_dir(base_dir)
+            .render(&document)?;
+        writer.write_all(&pdf)?;
+        writer.flush()?;
+        Ok(())
+    }
 
-        for cap in HEADING_RE.captures_iter(html) {
-            let level = cap[1].parse::<i32>().unwrap();
-            let text = &cap[2];
-            let id = text.to_lowercase().replace(' ', "-");
+    fn write_latex<W: Write>(&self, source: &Source, mut writer: W) -> Result<()> {
+        let mut renderer = LatexRenderer::new(self.latex_options);
+        if let Some(preamble_path) = &self.preamble_path {
+            let preamble = fs::read_to_string(preamble_path).map_err(|e| {
+                ConverterError::PreambleError(format!("Failed to read preamble file: {}", e))
+            })?;
+            renderer = renderer.with_preamble(preamble);
+        }
 
-            while level > current_level {
-                toc.push_str("<ul>\n");
-                current_level += 1;
+        writer.write_all(renderer.document_start().as_bytes())?;
+        for (i, block) in source.blocks().enumerate() {
+            if i > 0 {
+                writer.write_all(b"\n")?;
             }
-            while level < current_level {
-                toc.push_str("</ul>\n");
-                current_level -= 1;
+            renderer.render_block_to(&block, &mut writer)?;
+        }
+        writer.write_all(renderer.document_end().as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn write_text<W: Write>(&self, source: &Source, mut writer: W) -> Result<()> {
+        let renderer = TextRenderer::new(self.text_options);
+        for (i, block) in source.blocks().enumerate() {
+            if i > 0 {
+                writer.write_all(b"\n")?;
             }
+            renderer.render_block_to(&block, &mut writer)?;
+        }
+        writer.write_all(renderer.footnotes().as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
 
-            toc.push_str(&format!("<li><a href=\"#{id}\">{text}</a></li>\n"));
+    fn write_html<W: Write>(&self, source: &Source, writer: W) -> Result<()> {
+        if self.minify {
+            let mut writer = MinifyWriter::new(writer);
+            self.write_page(source, &mut writer)?;
+            writer.finish()?;
+        } else {
+            let mut writer = writer;
+            self.write_page(source, &mut writer)?;
+            writer.flush()?;
         }
+        Ok(())
+    }
 
-        while current_level > 1 {
-            toc.push_str("</ul>\n");
-            current_level -= 1;
+    /// Fills the page template in around the document, which is rendered
+    /// into its `{{ content }}`.
+    fn write_page<W: Write>(&self, source: &Source, writer: &mut W) -> Result<()> {
+        let (headings, equations) = self.outline(source);
+        let mut html_renderer = HtmlRenderer::new(HtmlOptions {
+            // Code is highlighted with the chosen theme, below.
+            syntax_highlight: false,
+            // A custom template can link to them through `headings`.
+            heading_ids: self.generate_toc || self.template_path.is_some(),
+            katex: self.uses_katex(),
+        })
+        .with_equations(equations.clone());
+        if self.syntax_highlight {
+            html_renderer = html_renderer.with_highlighter(self.highlighter()?.clone());
         }
 
-        toc.push_str("</ul>\n</div>\n");
+        let toc = if self.generate_toc {
+            html_renderer.table_of_contents(&headings)
+        } else {
+            String::new()
+        };
 
-        let html = HEADING_RE.replace_all(html, |caps: &regex::Captures| {
-            let level = &caps[1];
-            let text = &caps[2];
-            let id = text.to_lowercase().replace(' ', "-");
-            format!("<h{level} id=\"{id}\">{text}</h{level}>")
-        });
+        let renderer: Box<dyn Renderer> = if self.self_contained {
+            let base_dir = self.input_path.parent().unwrap_or(Path::new(""));
+            Box::new(OfflineRenderer::new(html_renderer, base_dir))
+        } else {
+            Box::new(html_renderer)
+        };
+        let mut write_body = |writer: &mut W| -> Result<()> {
+            for mut block in source.blocks() {
+                equations.link_references(&mut block);
+                renderer.render_block_to(&block, writer)?;
+            }
+            Ok(())
+        };
+
+        if self.fragment {
+            writer.write_all(toc.as_bytes())?;
+            return write_body(writer);
+        }
+
+        let template = match &self.template_path {
+            Some(template_path) => Template::load(template_path)?,
+            None => Template::default(),
+        };
+        let context = self.page_context(&source.front_matter(), &headings, toc)?;
+        template.render(&context, writer, &mut write_body)
+    }
+
+    /// The first of the two passes `write_page` makes over `source`: warns
+    /// of mistakes in the math and equation labels of Markdown, numbers the
+    /// equations, and keeps the blocks holding headings, which the title,
+    /// table of contents and anchors are made from. No other block is kept,
+    /// so the second pass can parse and render the page a block at a time.
+    fn outline(&self, source: &Source) -> (Document, Equations) {
+        let mut headings = Document::default();
+        let mut equations = Equations::default();
+        let mut add = |block: Block| {
+            equations.add(&block);
+            // Nested headings are kept for the anchors they take up.
+            if html::has_heading(&block) {
+                headings.blocks.push(block);
+            }
+        };
+
+        match source {
+            Source::Markdown(content) => {
+                let body = FrontMatter::split(content).1;
+                let front_matter_lines =
+                    content[..content.len() - body.len()].matches('\n').count();
+                let lines: Vec<&str> = content.lines().collect();
+                let check_math = !self.uses_katex();
+                let mut labels = LabelCheck::default();
+                for (block, mut position) in MarkdownParser::new().blocks(body).with_positions() {
+                    position.start_line += front_matter_lines;
+                    position.end_line += front_matter_lines;
+                    if check_math {
+                        for error in mathml::block_errors(&block, position, &lines) {
+                            warn!(
+                                "{}:{}: {}",
+                                self.input_path.display(),
+                                error.line,
+                                error.message
+                            );
+                        }
+                    }
+                    labels.add(&block, position, &lines);
+                    add(block);
+                }
+                for error in labels.errors() {
+                    warn!(
+                        "{}:{}: {}",
+                        self.input_path.display(),
+                        error.line,
+                        error.message
+                    );
+                }
+            }
+            // There are no lines to point a warning at.
+            Source::Document(_, document) => document.blocks.iter().cloned().for_each(add),
+        }
 
-        Ok(format!("{}{}", toc, html))
+        for heading in &mut headings.blocks {
+            equations.link_references(heading);
+        }
+        (headings, equations)
     }
 
-    fn add_css(&self, html: &str) -> Result<String> {
-        let css = if let Some(css_path) = &self.css_path {
+    /// The values a page template is filled in with: the front matter's
+    /// fields, then `title`, `toc`, `headings`, `css`, `styles` and
+    /// `scripts`.
+    fn page_context(
+        &self,
+        front_matter: &FrontMatter,
+        document: &Document,
+        toc: String,
+    ) -> Result<Context> {
+        let mut context = Context::new();
+        for (key, values) in front_matter.entries() {
+            let value = match values {
+                [value] => Value::Text(value.clone()),
+                values => Value::List(values.iter().cloned().map(Value::Text).collect()),
+            };
+            context.insert(key, value);
+        }
+
+        let title = match front_matter.get("title") {
+            Some(title) => title.to_string(),
+            None => document
+                .blocks
+                .iter()
+                .find_map(|block| match block {
+                    Block::Heading { content, .. } => Some(plain_text(content)),
+                    _ => None,
+                })
+                .unwrap_or_default(),
+        };
+        let headings: Vec<Value> = html::heading_anchors(document)
+            .into_iter()
+            .map(|(level, content, id)| {
+                Value::Map(vec![
+                    ("level".to_string(), Value::Text(level.to_string())),
+                    ("title".to_string(), Value::Text(plain_text(content))),
+                    ("href".to_string(), Value::Text(format!("#{}", id))),
+                    ("id".to_string(), Value::Text(id)),
+                ])
+            })
+            .collect();
+
+        context.insert("title", Value::Text(title));
+        context.insert("toc", Value::Html(toc));
+        context.insert("headings", Value::List(headings));
+        context.insert("css", Value::Html(self.css()?));
+        context.insert("styles", Value::Html(self.styles()?));
+        context.insert("scripts", Value::Html(self.scripts()));
+        Ok(context)
+    }
+
+    /// Whether HTML pages typeset their math with KaTeX, which
+    /// self-contained pages cannot load.
+    fn uses_katex(&self) -> bool {
+        self.katex && !self.self_contained
+    }
+
+    fn highlighter(&self) -> Result<&Highlighter> {
+        if let Some(highlighter) = self.highlighter.get() {
+            return Ok(highlighter);
+        }
+        let highlighter = Highlighter::new(&self.highlight_options)?;
+        Ok(self.highlighter.get_or_init(|| highlighter))
+    }
+
+    /// The custom CSS, or the default stylesheet.
+    fn css(&self) -> Result<String> {
+        Ok(if let Some(css_path) = &self.css_path {
             fs::read_to_string(css_path)
                 .map_err(|e| ConverterError::CssError(format!("Failed to read CSS file: {}", e)))?
         } else {
             include_str!("../assets/default.css").to_string()
-        };
+        })
+    }
 
+    /// The `<head>` tags for the styles of HTML output: KaTeX's stylesheet
+    /// when math is left for KaTeX, the colours of code highlighted with
+    /// classes, and the custom CSS, or the default stylesheet.
+    pub fn styles(&self) -> Result<String> {
+        let katex = if self.uses_katex() {
+            "    <link rel=\"stylesheet\" href=\"https://cdn.jsdelivr.net/npm/katex@0.16.9/dist/katex.min.css\">\n"
+        } else {
+            ""
+        };
+        let highlight = if self.syntax_highlight && self.highlight_options.classes {
+            format!(
+                "    <style>\n{}    </style>\n",
+                self.highlighter()?.stylesheet()?
+            )
+        } else {
+            String::new()
+        };
         Ok(format!(
-            r#"<!DOCTYPE html>
-<html>
-<head>
-    <meta charset="UTF-8">
-    <link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/katex@0.16.9/dist/katex.min.css">
-    <script src="https://cdn.jsdelivr.net/npm/katex@0.16.9/dist/katex.min.js"></script>
-    <script src="https://cdn.jsdelivr.net/npm/katex@0.16.9/dist/contrib/auto-render.min.js"></script>
-    <style>
+            r#"{}{}    <style>
         {}
     </style>
+"#,
+            katex,
+            highlight,
+            self.css()?
+        ))
+    }
+
+    /// The `<head>` tags for the scripts HTML output needs: KaTeX, which
+    /// typesets math once the page has loaded. Highlighted code and MathML
+    /// need none.
+    pub fn scripts(&self) -> String {
+        if !self.uses_katex() {
+            return String::new();
+        }
+        r#"    <script src="https://cdn.jsdelivr.net/npm/katex@0.16.9/dist/katex.min.js"></script>
+    <script src="https://cdn.jsdelivr.net/npm/katex@0.16.9/dist/contrib/auto-render.min.js"></script>
     <script>
-        document.addEventListener("DOMContentLoaded", function() {{
-            renderMathInElement(document.body, {{
+        document.addEventListener("DOMContentLoaded", function() {
+            renderMathInElement(document.body, {
                 delimiters: [
-                    {{left: "$$", right: "$$", display: true}},
-                    {{left: "$", right: "$", display: false}}
+                    {left: "$$", right: "$$", display: true},
+                    {left: "$", right: "$", display: false}
                 ],
                 throwOnError: false,
                 fleqn: false,
                 leqno: false,
                 strict: false,
                 trust: true,
-                macros: {{
+                macros: {
                     "\\mathbf": "\\boldsymbol"
-                }}
-            }});
-        }});
+                }
+            });
+        });
     </script>
-</head>
-<body>
-{}
-</body>
-</html>"#,
-            css, html
-        ))
+"#
+        .to_string()
+    }
+}
+
+/// Warns of TeX errors in the math of `markdown`, read from `path`, by the
+/// line they are on.
+fn report_math_errors(path: &Path, markdown: &str) {
+    for error in mathml::errors(markdown) {
+        warn!("{}:{}: {}", path.display(), error.line, error.message);
+    }
+}
+
+/// The parser for Markdown sources, which their blocks borrow.
+static PARSER: MarkdownParser = MarkdownParser {};
+
+/// What is converted: Markdown, parsed a block at a time as it is rendered,
+/// or a document imported from JSON, which is rendered as it is. An imported
+/// document may hold what Markdown cannot express, such as a `$$` line in
+/// math, so it is never turned back into Markdown on the way.
+enum Source {
+    Markdown(String),
+    Document(FrontMatter, Document),
+}
+
+impl Source {
+    /// Reads the file at `path`. A `.json` file holds a document in the
+    /// format `--format json` writes; anything else is Markdown.
+    fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(ConverterError::InputFileError)?;
+        if !utils::is_json_file(path) {
+            return Ok(Source::Markdown(content));
+        }
+        let (metadata, document) = json::from_json(&content)?;
+        Ok(Source::Document(metadata, document))
+    }
+
+    fn front_matter(&self) -> FrontMatter {
+        match self {
+            Source::Markdown(markdown) => FrontMatter::split(markdown).0,
+            Source::Document(front_matter, _) => front_matter.clone(),
+        }
+    }
+
+    /// The top-level blocks after the front matter, in order.
+    fn blocks(&self) -> Box<dyn Iterator<Item = Block> + '_> {
+        match self {
+            Source::Markdown(markdown) => Box::new(PARSER.blocks(FrontMatter::split(markdown).1)),
+            Source::Document(_, document) => Box::new(document.blocks.iter().cloned()),
+        }
+    }
+
+    /// The front matter and the whole document, for the formats laid out
+    /// from all of it at once.
+    fn parse(&self) -> (FrontMatter, Cow<'_, Document>) {
+        match self {
+            Source::Markdown(markdown) => {
+                let (front_matter, body) = FrontMatter::split(markdown);
+                (front_matter, Cow::Owned(PARSER.parse_document(body)))
+            }
+            Source::Document(front_matter, document) => {
+                (front_matter.clone(), Cow::Borrowed(document))
+            }
+        }
+    }
+}
+
+/// Trims every line written through it and drops the line breaks and empty
+/// lines, holding back no more than the current line. A line break next to
+/// text becomes a space, and the lines of `<pre>` elements are kept as they
+/// are.
+struct MinifyWriter<W: Write> {
+    inner: W,
+    line: Vec<u8>,
+    /// Whether the output so far ends in a tag.
+    after_tag: bool,
+    /// The closing tag of the `<pre>`, `<style>` or `<script>` element the
+    /// current line starts in, if any.
+    inside: Option<&'static str>,
+}
+
+impl<W: Write> MinifyWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            line: Vec::new(),
+            after_tag: true,
+            inside: None,
+        }
+    }
+
+    fn flush_line(&mut self, line_break: bool) -> io::Result<()> {
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+        let line = if self.inside == Some("</pre>") {
+            line.as_str()
+        } else {
+            let line = line.trim();
+            if line.is_empty() {
+                return Ok(());
+            }
+            // Styles and scripts need no space, and tags next to each other
+            // can do without one.
+            if self.inside.is_none() && !(self.after_tag && line.starts_with('<')) {
+                self.inner.write_all(b" ")?;
+            }
+            line
+        };
+        self.inner.write_all(line.as_bytes())?;
+        self.after_tag = line.ends_with('>');
+        self.inside = element_after(line, self.inside);
+        if line_break && self.inside == Some("</pre>") {
+            self.inner.write_all(b"\n")?;
+        }
+        Ok(())
     }
 
-    fn minify_html(&self, html: &str) -> String {
-        html.lines()
-            .map(|line| line.trim())
-            .filter(|line| !line.is_empty())
-            .collect::<String>()
+    fn finish(mut self) -> io::Result<W> {
+        self.flush_line(false)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for MinifyWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while let Some(newline) = rest.iter().position(|&b| b == b'\n') {
+            self.line.extend_from_slice(&rest[..newline]);
+            self.flush_line(true)?;
+            rest = &rest[newline + 1..];
+        }
+        self.line.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The closing tag of the `<pre>`, `<style>` or `<script>` element open at
+/// the end of `line`, given the one open at its start.
+fn element_after(line: &str, mut inside: Option<&'static str>) -> Option<&'static str> {
+    const ELEMENTS: [(&str, &str); 3] = [
+        ("<pre", "</pre>"),
+        ("<style", "</style>"),
+        ("<script", "</script>"),
+    ];
+    let mut rest = line;
+    loop {
+        match inside {
+            Some(close) => {
+                let Some(end) = rest.find(close) else {
+                    return inside;
+                };
+                rest = &rest[end + close.len()..];
+                inside = None;
+            }
+            None => {
+                let (start, open, close) = ELEMENTS
+                    .iter()
+                    .filter_map(|&(open, close)| Some((rest.find(open)?, open, close)))
+                    .min()?;
+                rest = &rest[start + open.len()..];
+                inside = Some(close);
+            }
+        }
     }
 }
 
@@ -236,8 +1005,6 @@ impl MarkdownConverter {
 mod tests {
     use super::*;
     use std::env::temp_dir;
-    use std::fs::File;
-    use std::io::Write;
 
     fn create_temp_file(content: &str) -> PathBuf {
         let temp_path = temp_dir().join("test.md");
@@ -258,9 +1025,9 @@ mod tests {
             MarkdownConverter::new(temp_path.clone(), None, None, true, false, false).unwrap();
 
         assert_eq!(converter.input_path, temp_path);
-        assert_eq!(converter.syntax_highlight, true);
-        assert_eq!(converter.generate_toc, false);
-        assert_eq!(converter.minify, false);
+        assert!(converter.syntax_highlight);
+        assert!(!converter.generate_toc);
+        assert!(!converter.minify);
 
         cleanup_temp_file(&temp_path);
     }
@@ -280,17 +1047,34 @@ mod tests {
 
     #[test]
     fn test_minification() {
-        let temp_path = create_temp_file("# Test\n\nSome content");
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("page.md");
+        fs::write(
+            &input,
+            "# Test\n\nSome content\nover two lines\n\n- one\n- two\n\n\
+             ```rust\nfn main() {\n    body();\n}\n```",
+        )
+        .unwrap();
+        let converter = MarkdownConverter::new(input, None, None, false, false, true).unwrap();
 
-        let converter =
-            MarkdownConverter::new(temp_path.clone(), None, None, false, false, true).unwrap();
+        let mut output = Vec::new();
+        converter.convert_to(&mut output).unwrap();
+        let html = String::from_utf8(output).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html><html"));
+        assert!(html.contains(
+            "<body><h1>Test</h1><p>Some content over two lines</p>\
+             <ul><li>one</li><li>two</li></ul>\
+             <pre><code class=\"language-rust\">fn main() {\n    body();\n}\n</code></pre>"
+        ));
+        assert!(!html.contains(">\n"));
 
-        let html = "<div>\n    <p>Test</p>\n</div>";
-        let minified = converter.minify_html(html);
-        assert!(!minified.contains('\n'));
-        assert!(!minified.contains("    "));
-
-        cleanup_temp_file(&temp_path);
+        let mut writer = MinifyWriter::new(Vec::new());
+        // Line breaks may fall anywhere relative to the writes.
+        for chunk in ["<div>\n  ", "  <p>Test</p>", "\n</div>"] {
+            writer.write_all(chunk.as_bytes()).unwrap();
+        }
+        let minified = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(minified, "<div><p>Test</p></div>");
     }
 
     #[test]
@@ -300,7 +1084,11 @@ mod tests {
             MarkdownConverter::new(temp_path.clone(), None, None, false, false, false).unwrap();
 
         let html = "<p>Test</p>";
-        let result = converter.add_css(html).unwrap();
+        let mut page = Vec::new();
+        converter
+            .write_page(&Source::Markdown("Test".to_string()), &mut page)
+            .unwrap();
+        let result = String::from_utf8(page).unwrap();
 
         assert!(result.contains("<!DOCTYPE html>"));
         assert!(result.contains("<style>"));
@@ -309,4 +1097,449 @@ mod tests {
 
         cleanup_temp_file(&temp_path);
     }
+
+    #[test]
+    fn test_convert_to_writer() {
+        let mut input = tempfile::NamedTempFile::new().unwrap();
+        writeln!(input, "# Title\n\n## Section\n\nBody text").unwrap();
+        let converter =
+            MarkdownConverter::new(input.path().to_path_buf(), None, None, false, true, false)
+                .unwrap();
+
+        let mut output = Vec::new();
+        converter.convert_to(&mut output).unwrap();
+        let html = String::from_utf8(output).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains(r##"<li><a href="#section">Section</a></li>"##));
+        assert!(html.contains(r#"<h2 id="section">Section</h2>"#));
+        assert!(html.contains("<p>Body text</p>"));
+        assert!(html.ends_with("</html>"));
+    }
+
+    #[test]
+    fn test_all_formats() {
+        for format in OutputFormat::ALL {
+            assert_eq!(
+                format!("{:?}", format).parse::<OutputFormat>().unwrap(),
+                format
+            );
+        }
+        assert_eq!(
+            OutputFormat::from_extension("HTML"),
+            Some(OutputFormat::Html)
+        );
+        assert_eq!(
+            OutputFormat::from_extension("gmi"),
+            Some(OutputFormat::Gemtext)
+        );
+        assert_eq!(OutputFormat::from_extension("3"), Some(OutputFormat::Man));
+    }
+
+    #[test]
+    fn test_text_format() {
+        let mut input = tempfile::Builder::new().suffix(".md").tempfile().unwrap();
+        writeln!(input, "# Title\n\nSee [the docs](https://example.com).").unwrap();
+        let converter =
+            MarkdownConverter::new(input.path().to_path_buf(), None, None, false, false, false)
+                .unwrap()
+                .with_format("text".parse().unwrap());
+
+        assert_eq!(converter.output_path.extension().unwrap(), "txt");
+        let mut output = Vec::new();
+        converter.convert_to(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Title\n=====\n\nSee the docs[1].\n\n[1] https://example.com\n"
+        );
+    }
+
+    #[test]
+    fn test_latex_format() {
+        let mut input = tempfile::Builder::new().suffix(".md").tempfile().unwrap();
+        writeln!(input, "# Title\n\nBody").unwrap();
+        let mut preamble = tempfile::NamedTempFile::new().unwrap();
+        writeln!(preamble, "\\documentclass{{memoir}}").unwrap();
+        let converter =
+            MarkdownConverter::new(input.path().to_path_buf(), None, None, false, false, false)
+                .unwrap()
+                .with_format(OutputFormat::Latex)
+                .with_preamble(Some(preamble.path().to_path_buf()));
+
+        assert_eq!(converter.output_path.extension().unwrap(), "tex");
+        let mut output = Vec::new();
+        converter.convert_to(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\\documentclass{memoir}\n\n\\begin{document}\n\n\\section{Title}\n\nBody\n\n\\end{document}\n"
+        );
+    }
+
+    #[test]
+    fn test_pdf_format() {
+        let mut input = tempfile::Builder::new().suffix(".md").tempfile().unwrap();
+        writeln!(input, "# Title\n\nBody").unwrap();
+        let converter =
+            MarkdownConverter::new(input.path().to_path_buf(), None, None, false, false, false)
+                .unwrap()
+                .with_format("pdf".parse().unwrap());
+
+        assert_eq!(converter.output_path.extension().unwrap(), "pdf");
+        let mut output = Vec::new();
+        converter.convert_to(&mut output).unwrap();
+        assert!(output.starts_with(b"%PDF-"));
+        assert!(output.ends_with(b"%%EOF"));
+    }
+
+    #[test]
+    fn test_epub_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("one.md");
+        let second = dir.path().join("two.md");
+        fs::write(&first, "# One\n\nFirst").unwrap();
+        fs::write(&second, "# Two\n\nSecond").unwrap();
+        let converter = MarkdownConverter::new(first, None, None, false, false, false)
+            .unwrap()
+            .with_format("epub".parse().unwrap())
+            .with_chapters(vec![second]);
+
+        assert_eq!(converter.output_path.extension().unwrap(), "epub");
+        let mut output = Vec::new();
+        converter.convert_to(&mut output).unwrap();
+        let archive = zip::ZipArchive::new(io::Cursor::new(output)).unwrap();
+        assert!(archive.index_for_name("OEBPS/chapter-2.xhtml").is_some());
+    }
+
+    #[test]
+    fn test_docx_format() {
+        let mut input = tempfile::Builder::new().suffix(".md").tempfile().unwrap();
+        writeln!(input, "# Title\n\nBody").unwrap();
+        let converter =
+            MarkdownConverter::new(input.path().to_path_buf(), None, None, false, false, false)
+                .unwrap()
+                .with_format("docx".parse().unwrap())
+                .with_reference_doc(Some(PathBuf::from("missing.docx")));
+
+        assert_eq!(converter.output_path.extension().unwrap(), "docx");
+        let error = converter.convert_to(Vec::new()).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid reference document"));
+
+        let mut output = Vec::new();
+        converter
+            .with_reference_doc(None)
+            .convert_to(&mut output)
+            .unwrap();
+        let archive = zip::ZipArchive::new(io::Cursor::new(output)).unwrap();
+        assert!(archive.index_for_name("word/document.xml").is_some());
+    }
+
+    #[test]
+    fn test_man_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("mdconv.md");
+        fs::write(&input, "---\nsection: 5\n---\n# Name\n\nmdconv - convert").unwrap();
+        let converter = MarkdownConverter::new(input, None, None, false, false, false)
+            .unwrap()
+            .with_format("man".parse().unwrap());
+
+        assert_eq!(converter.output_path.extension().unwrap(), "1");
+        let mut output = Vec::new();
+        converter.convert_to(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "'\\\" t\n.TH \"MDCONV\" \"5\"\n.SH\nNAME\n.PP\nmdconv \\- convert\n"
+        );
+    }
+
+    #[test]
+    fn test_terminal_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("doc.md");
+        fs::write(&input, "---\ntitle: Doc\n---\n# Title\n\nSome *text*.").unwrap();
+        let converter = MarkdownConverter::new(input, None, None, false, false, false)
+            .unwrap()
+            .with_format("ansi".parse().unwrap());
+
+        let mut output = Vec::new();
+        converter.convert_to(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\x1b[35m\x1b[1m\x1b[4mTitle\x1b[0m\n\nSome \x1b[3mtext\x1b[0m.\n"
+        );
+    }
+
+    #[test]
+    fn test_slides_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("talk.md");
+        fs::write(&input, "# Talk\n\n## Part one\n\ntext\n\n## Part two").unwrap();
+        let converter = MarkdownConverter::new(input, None, None, false, false, false)
+            .unwrap()
+            .with_format("slides".parse().unwrap())
+            .with_slide_split(SlideSplit::Headings);
+
+        assert_eq!(converter.output_path.extension().unwrap(), "html");
+        let mut output = Vec::new();
+        converter.convert_to(&mut output).unwrap();
+        let html = String::from_utf8(output).unwrap();
+        assert!(html.contains("<title>Talk</title>"));
+        assert!(html.contains("id=\"slide-3\">\n<h2>Part two</h2>"));
+        assert!(!html.contains("slide-4"));
+    }
+
+    #[test]
+    fn test_template() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("guide.md");
+        fs::write(
+            &input,
+            "---\nauthor: [Ann, Bo]\n---\n# Guide\n\n## Setup\n\nText\n\n## Setup",
+        )
+        .unwrap();
+        let template = dir.path().join("brand");
+        fs::create_dir(&template).unwrap();
+        fs::write(
+            template.join("page.html"),
+            "<title>{{ title }}</title>\n{% include \"nav.html\" %}\n\
+             <main>{{ content }}</main>\n<footer>By {{ author }}</footer>\n",
+        )
+        .unwrap();
+        fs::write(
+            template.join("nav.html"),
+            "<nav>\n{% for heading in headings %}\n{% if not loop.first %}\n\
+             <a href=\"{{ heading.href }}\">{{ heading.title }}</a>\n{% endif %}\n{% endfor %}\n</nav>\n",
+        )
+        .unwrap();
+
+        let mut output = Vec::new();
+        // The anchors `headings` links to are there without `--toc`.
+        MarkdownConverter::new(input, None, None, false, false, false)
+            .unwrap()
+            .with_template(Some(template))
+            .convert_to(&mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<title>Guide</title>\n<nav>\n<a href=\"#setup\">Setup</a>\n\
+             <a href=\"#setup-1\">Setup</a>\n</nav>\n\
+             <main><h1 id=\"guide\">Guide</h1>\n<h2 id=\"setup\">Setup</h2>\n<p>Text</p>\n\
+             <h2 id=\"setup-1\">Setup</h2>\n</main>\n<footer>By Ann, Bo</footer>\n"
+        );
+    }
+
+    #[test]
+    fn test_fragment() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("post.md");
+        fs::write(&input, "---\ntitle: Post\n---\n# Post\n\nArea $\\pi r^2$.").unwrap();
+        let converter = MarkdownConverter::new(input, None, None, false, false, false)
+            .unwrap()
+            .with_fragment(true)
+            .with_katex(true);
+        let mut output = Vec::new();
+        converter.convert_to(&mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<h1>Post</h1>\n<p>Area <span class=\"math-inline\">$\\pi r^2$</span>.</p>\n"
+        );
+        assert!(converter.styles().unwrap().contains("katex.min.css"));
+        assert!(converter.styles().unwrap().contains("<style>"));
+        assert!(converter.scripts().contains("renderMathInElement"));
+    }
+
+    #[test]
+    fn test_equation_references() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("energy.md");
+        fs::write(
+            &input,
+            "$$ E = mc^2 $$ {#eq:energy}\n\nBy @eq:energy, not @eq:mass.",
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        MarkdownConverter::new(input, None, None, false, false, false)
+            .unwrap()
+            .with_fragment(true)
+            .with_katex(true)
+            .convert_to(&mut output)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<div class=\"math-block\" id=\"eq:energy\">$$E = mc^2$$<span class=\"math-number\">(1)</span></div>\n\
+             <p>By <a href=\"#eq:energy\">(1)</a>, not @eq:mass.</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_highlight_classes() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("code.md");
+        fs::write(&input, "```rust\nfn main() {}\n```").unwrap();
+        let converter = |highlight_options| {
+            MarkdownConverter::new(input.clone(), None, None, true, false, false)
+                .unwrap()
+                .with_fragment(true)
+                .with_highlight_options(highlight_options)
+        };
+
+        let classed = converter(HighlightOptions {
+            theme: Some("InspiredGitHub".to_string()),
+            dark_theme: Some("base16-ocean.dark".to_string()),
+            classes: true,
+            ..HighlightOptions::default()
+        });
+        let mut output = Vec::new();
+        classed.convert_to(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("<pre class=\"hl-code\">\n"));
+        assert!(output.contains("<span class=\"hl-storage hl-type hl-function hl-rust\">fn</span>"));
+        assert!(!output.contains("style="));
+        // The highlighter is built once and kept for later conversions.
+        let highlighter = classed.highlighter().unwrap() as *const Highlighter;
+        classed.convert_to(Vec::new()).unwrap();
+        assert_eq!(classed.highlighter().unwrap() as *const _, highlighter);
+        let styles = classed.styles().unwrap();
+        assert!(styles.contains("theme \"GitHub\""));
+        assert!(styles.contains("@media (prefers-color-scheme: dark) {"));
+
+        let unknown = converter(HighlightOptions {
+            theme: Some("Nope".to_string()),
+            ..HighlightOptions::default()
+        });
+        let error = unknown.convert_to(Vec::new()).unwrap_err();
+        assert!(error.to_string().contains("no theme named \"Nope\""));
+    }
+
+    #[test]
+    fn test_self_contained() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("notes.md");
+        fs::write(&input, "# Notes\n\n![plot](plot.png)\n\n$$x^2$$").unwrap();
+        fs::write(dir.path().join("plot.png"), b"\x89PNG").unwrap();
+        let mut output = Vec::new();
+        MarkdownConverter::new(input, None, None, false, false, false)
+            .unwrap()
+            .with_self_contained(true)
+            .convert_to(&mut output)
+            .unwrap();
+        let result = String::from_utf8(output).unwrap();
+
+        assert!(!result.contains("cdn.jsdelivr.net"));
+        assert!(!result.contains("<script"));
+        assert!(result.contains("<img src=\"data:image/png;base64,iVBORw==\" alt=\"plot\">"));
+        assert!(result.contains(
+            "<div class=\"math-block\"><math display=\"block\" alttext=\"x^2\">\
+             <msup><mi>x</mi><mn>2</mn></msup></math></div>"
+        ));
+    }
+
+    #[test]
+    fn test_email_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("report.md");
+        fs::write(&input, "# Report\n\nGrowth was $r^2$.\n\n> Quoted").unwrap();
+        let mut output = Vec::new();
+        MarkdownConverter::new(input, None, None, true, false, false)
+            .unwrap()
+            .with_format(OutputFormat::Email)
+            .convert_to(&mut output)
+            .unwrap();
+        let result = String::from_utf8(output).unwrap();
+
+        assert!(result.contains("<h1 style=\"color: #2c3e50;"));
+        assert!(result.contains("<i>r</i><sup>2</sup>"));
+        assert!(result.contains("<blockquote style=\""));
+        assert!(!result.contains("<script") && !result.contains("<style"));
+    }
+
+    #[test]
+    fn test_format_from_extension() {
+        assert_eq!(
+            OutputFormat::from_extension("GMI"),
+            Some(OutputFormat::Gemtext)
+        );
+        assert_eq!(
+            OutputFormat::from_extension("xml"),
+            Some(OutputFormat::Confluence)
+        );
+        assert_eq!(
+            OutputFormat::from_extension("html"),
+            Some(OutputFormat::Html)
+        );
+        assert_eq!(OutputFormat::from_extension("8"), Some(OutputFormat::Man));
+        assert_eq!(OutputFormat::from_extension("md"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("post.md");
+        fs::write(&input, "# Post\n\nSee [more](gemini://example.org).").unwrap();
+        let mut output = Vec::new();
+        MarkdownConverter::new(input, None, None, false, false, false)
+            .unwrap()
+            .with_format(OutputFormat::Gemtext)
+            .convert_to(&mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "# Post\n\nSee more.\n=> gemini://example.org more\n"
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("doc.md");
+        fs::write(&input, "---\ntitle: Doc\n---\n# Title\n\n- *item*").unwrap();
+        let exported = dir.path().join("exported.json");
+        MarkdownConverter::new(input, Some(exported.clone()), None, false, false, false)
+            .unwrap()
+            .with_format(OutputFormat::Json)
+            .convert_to(File::create(&exported).unwrap())
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&exported).unwrap()).unwrap();
+        assert_eq!(json["blocks"][1]["position"]["start_line"], 6);
+
+        let converter = MarkdownConverter::new(exported, None, None, false, false, false).unwrap();
+        let mut output = Vec::new();
+        converter.convert_to(&mut output).unwrap();
+        let html = String::from_utf8(output).unwrap();
+        assert!(html.contains("<body>\n<h1>Title</h1>\n<ul>\n<li><em>item</em></li>\n</ul>"));
+    }
+    #[test]
+    fn test_json_import_beyond_markdown() {
+        // A `$$` line in math, a list item's paragraph and code without a
+        // final line break have no Markdown spelling to round-trip through.
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("doc.json");
+        let text = |text: &str| serde_json::json!([{ "type": "text", "text": text }]);
+        let document = serde_json::json!({
+            "version": 1,
+            "blocks": [
+                { "type": "math_block", "math": "a\n$$\nb", "id": null },
+                { "type": "list", "ordered": false, "start": 1, "items": [{
+                    "checked": null,
+                    "content": text("item"),
+                    "children": [{ "type": "paragraph", "content": text("more") }],
+                }]},
+                { "type": "code_block", "info": "", "code": "x = 1" },
+                { "type": "paragraph", "content": text("After") },
+            ],
+        });
+        fs::write(&input, document.to_string()).unwrap();
+
+        let converter = MarkdownConverter::new(input, None, None, false, false, false)
+            .unwrap()
+            .with_fragment(true);
+        let mut output = Vec::new();
+        converter.convert_to(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<div class=\"math-block\"><math display=\"block\" alttext=\"a\n$$\nb\">\
+             <mi>a</mi><mo>$</mo><mo>$</mo><mi>b</mi></math></div>\n\
+             <ul>\n<li>item\n<p>more</p>\n</li>\n</ul>\n\
+             <pre><code>x = 1</code></pre>\n<p>After</p>\n"
+        );
+    }
 }
@@ -0,0 +1,344 @@
+use crate::ast::{Block, Inline, Position};
+use crate::front_matter::FrontMatter;
+use crate::parser::MarkdownParser;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+lazy_static! {
+    /// `@eq:label` or `\eqref{label}`.
+    static ref REFERENCE_RE: Regex =
+        Regex::new(r"@(eq:[\w:.-]*\w)|\\eqref\{\s*([^{}\s]+)\s*\}").unwrap();
+}
+
+/// A mistake in the equation labels of a document, on a line counted
+/// from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for LabelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// The numbers of a document's labelled display equations, counted from 1
+/// in the order they appear. Equations without a label are not numbered.
+#[derive(Debug, Clone, Default)]
+pub struct Equations {
+    numbers: HashMap<String, usize>,
+    count: usize,
+}
+
+impl Equations {
+    pub fn new(blocks: impl IntoIterator<Item = Block>) -> Self {
+        let mut equations = Self::default();
+        for block in blocks {
+            equations.add(&block);
+        }
+        equations
+    }
+
+    /// Numbers the labelled equations in `block`, following those of the
+    /// blocks added before it.
+    pub fn add(&mut self, block: &Block) {
+        let mut labels = Vec::new();
+        block_labels(block, &mut labels);
+        for label in labels {
+            self.count += 1;
+            self.numbers.entry(label.to_string()).or_insert(self.count);
+        }
+    }
+
+    /// The number of the equation labelled `label`.
+    pub fn number(&self, label: &str) -> Option<usize> {
+        self.numbers.get(label).copied()
+    }
+
+    /// Turns the references to known equations in the text of `block` into
+    /// links to them, showing their numbers. Other references are left as
+    /// written.
+    pub fn link_references(&self, block: &mut Block) {
+        match block {
+            Block::Heading { content, .. } | Block::Paragraph(content) => {
+                self.link_inlines(content)
+            }
+            Block::List(list) => {
+                for item in &mut list.items {
+                    self.link_inlines(&mut item.content);
+                    for child in &mut item.children {
+                        self.link_references(child);
+                    }
+                }
+            }
+            Block::Table(table) => {
+                for cell in table
+                    .header
+                    .iter_mut()
+                    .chain(table.rows.iter_mut().flatten())
+                {
+                    self.link_inlines(cell);
+                }
+            }
+            Block::Blockquote(blocks) => {
+                for block in blocks {
+                    self.link_references(block);
+                }
+            }
+            Block::CodeBlock(_) | Block::MathBlock(_) | Block::HorizontalRule => {}
+        }
+    }
+
+    fn link_inlines(&self, inlines: &mut Vec<Inline>) {
+        for inline in std::mem::take(inlines) {
+            match inline {
+                Inline::Text(text) => self.link_text(&text, inlines),
+                Inline::Strong(mut children) => {
+                    self.link_inlines(&mut children);
+                    inlines.push(Inline::Strong(children));
+                }
+                Inline::Emphasis(mut children) => {
+                    self.link_inlines(&mut children);
+                    inlines.push(Inline::Emphasis(children));
+                }
+                Inline::Strikethrough(mut children) => {
+                    self.link_inlines(&mut children);
+                    inlines.push(Inline::Strikethrough(children));
+                }
+                // Links can't hold links, and code is literal.
+                inline => inlines.push(inline),
+            }
+        }
+    }
+
+    fn link_text(&self, text: &str, out: &mut Vec<Inline>) {
+        let mut last = 0;
+        for (range, label) in references(text) {
+            let Some(number) = self.number(label) else {
+                continue;
+            };
+            if range.start > last {
+                out.push(Inline::Text(text[last..range.start].to_string()));
+            }
+            out.push(Inline::Link {
+                url: format!("#{}", label),
+                content: vec![Inline::Text(format!("({})", number))],
+            });
+            last = range.end;
+        }
+        if last < text.len() {
+            out.push(Inline::Text(text[last..].to_string()));
+        }
+    }
+}
+
+/// Where the references in `text` are, with the labels they refer to. An
+/// `@` inside a word, as in an email address, does not start one.
+pub(crate) fn references(text: &str) -> impl Iterator<Item = (Range<usize>, &str)> {
+    REFERENCE_RE
+        .captures_iter(text)
+        .filter_map(move |captures| {
+            let whole = captures.get(0).unwrap();
+            let in_word = text[..whole.start()]
+                .chars()
+                .next_back()
+                .is_some_and(char::is_alphanumeric);
+            if whole.as_str().starts_with('@') && in_word {
+                return None;
+            }
+            let label = captures
+                .get(1)
+                .or_else(|| captures.get(2))
+                .unwrap()
+                .as_str();
+            Some((whole.range(), label))
+        })
+}
+
+/// The references to undefined labels and the repeated labels in the
+/// Markdown file `markdown`, numbered by the lines of the file they are on.
+pub fn errors(markdown: &str) -> Vec<LabelError> {
+    let body = FrontMatter::split(markdown).1;
+    let front_matter_lines = markdown[..markdown.len() - body.len()]
+        .matches('\n')
+        .count();
+    let lines: Vec<&str> = body.lines().collect();
+
+    let mut check = LabelCheck::default();
+    for (block, position) in MarkdownParser::new().blocks(body).with_positions() {
+        check.add(&block, position, &lines);
+    }
+    let mut errors = check.errors();
+    for error in &mut errors {
+        error.line += front_matter_lines;
+    }
+    errors
+}
+
+/// Finds the mistakes in a document's equation labels block by block, for
+/// a caller walking the document anyway.
+#[derive(Debug, Default)]
+pub struct LabelCheck {
+    errors: Vec<LabelError>,
+    defined: HashMap<String, usize>,
+    referenced: Vec<(String, usize)>,
+}
+
+impl LabelCheck {
+    /// Checks the labels and references in `block`, parsed from `position`
+    /// in `lines`.
+    pub fn add(&mut self, block: &Block, position: Position, lines: &[&str]) {
+        let (start, end) = (position.start_line, position.end_line);
+        // The line in the block holding `needle`, or else its first.
+        let find = |needle: &str| {
+            (start..=end)
+                .find(|&n| lines[n - 1].contains(needle))
+                .unwrap_or(start)
+        };
+
+        let mut labels = Vec::new();
+        block_labels(block, &mut labels);
+        for label in labels {
+            let line = find(label);
+            if self.defined.insert(label.to_string(), line).is_some() {
+                self.errors.push(LabelError {
+                    line,
+                    message: format!("Equation label `{}` is used more than once", label),
+                });
+            }
+        }
+
+        let mut texts = Vec::new();
+        block_text(block, &mut texts);
+        for text in texts {
+            for (range, label) in references(text) {
+                let line = find(&text[range]);
+                self.referenced.push((label.to_string(), line));
+            }
+        }
+    }
+
+    /// The mistakes in the blocks added, by line: references to labels no
+    /// block defined, and labels defined twice.
+    pub fn errors(mut self) -> Vec<LabelError> {
+        for (label, line) in self.referenced {
+            if !self.defined.contains_key(&label) {
+                self.errors.push(LabelError {
+                    line,
+                    message: format!("Reference to undefined equation label `{}`", label),
+                });
+            }
+        }
+        self.errors.sort_by_key(|error| error.line);
+        self.errors
+    }
+}
+
+/// Collects the labels of the display equations in `block`, in order.
+fn block_labels<'a>(block: &'a Block, labels: &mut Vec<&'a str>) {
+    match block {
+        Block::MathBlock(math_block) => labels.extend(math_block.label()),
+        Block::List(list) => {
+            for child in list.items.iter().flat_map(|item| &item.children) {
+                block_labels(child, labels);
+            }
+        }
+        Block::Blockquote(blocks) => {
+            for block in blocks {
+                block_labels(block, labels);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Collects the text of `block` that references can be written in.
+fn block_text<'a>(block: &'a Block, texts: &mut Vec<&'a str>) {
+    match block {
+        Block::Heading { content, .. } | Block::Paragraph(content) => inline_text(content, texts),
+        Block::List(list) => {
+            for item in &list.items {
+                inline_text(&item.content, texts);
+                for child in &item.children {
+                    block_text(child, texts);
+                }
+            }
+        }
+        Block::Table(table) => {
+            for cell in table.header.iter().chain(table.rows.iter().flatten()) {
+                inline_text(cell, texts);
+            }
+        }
+        Block::Blockquote(blocks) => {
+            for block in blocks {
+                block_text(block, texts);
+            }
+        }
+        Block::CodeBlock(_) | Block::MathBlock(_) | Block::HorizontalRule => {}
+    }
+}
+
+fn inline_text<'a>(inlines: &'a [Inline], texts: &mut Vec<&'a str>) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(text) => texts.push(text),
+            Inline::Strong(children)
+            | Inline::Emphasis(children)
+            | Inline::Strikethrough(children) => inline_text(children, texts),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_link_references() {
+        let document = MarkdownParser::new().parse_document(
+            "$$ a $$ {#eq:a}\n\n$$\nb \\label{b}\n$$\n\nSee @eq:a, \\eqref{b}, @eq:c and me@eq:a.",
+        );
+        let equations = Equations::new(document.blocks.clone());
+        assert_eq!(equations.number("eq:a"), Some(1));
+        assert_eq!(equations.number("b"), Some(2));
+
+        let mut paragraph = document.blocks[2].clone();
+        equations.link_references(&mut paragraph);
+        let link = |label: &str, number: usize| Inline::Link {
+            url: format!("#{}", label),
+            content: vec![Inline::Text(format!("({})", number))],
+        };
+        assert_eq!(
+            paragraph,
+            Block::Paragraph(vec![
+                Inline::Text("See ".to_string()),
+                link("eq:a", 1),
+                Inline::Text(", ".to_string()),
+                link("b", 2),
+                Inline::Text(", @eq:c and me@eq:a.".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let markdown = "---\ntitle: T\n---\n$$ a $$ {#eq:a}\n\nSee @eq:a and\n\\eqref{eq:gone}.\n\n$$\na \\label{eq:a}\n$$";
+        assert_eq!(
+            errors(markdown)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "line 7: Reference to undefined equation label `eq:gone`",
+                "line 10: Equation label `eq:a` is used more than once",
+            ]
+        );
+    }
+}
@@ -0,0 +1,871 @@
+use crate::ast::{
+    plain_text, Alignment, Block, CodeBlock, Document, Inline, List, ListItem, MathBlock, Table,
+};
+use crate::error::ConverterError;
+use crate::front_matter::FrontMatter;
+use crate::html::HeadingIds;
+use crate::parser::MarkdownParser;
+use crate::pdf::PaperSize;
+use crate::render::Renderer;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use log::warn;
+use regex::Regex;
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+lazy_static! {
+    static ref STYLE_RE: Regex =
+        Regex::new(r#"(?s)<w:style [^>]*w:styleId="([^"]+)".*?</w:style>"#).unwrap();
+}
+
+const DEFAULT_STYLES: &str = include_str!("../assets/docx-styles.xml");
+
+/// Margin on every side of the page, in twentieths of a point.
+const MARGIN: u32 = 1440;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DocxOptions {
+    pub paper: PaperSize,
+}
+
+/// Writes Word (Office Open XML) documents without any external tooling.
+///
+/// Headings use the `Heading1`–`Heading6` styles, lists get numbering
+/// definitions, code blocks the `SourceCode` paragraph style and code spans
+/// `VerbatimChar`. Styles come from a reference document when one is given;
+/// any of these it lacks are added from the defaults. Math is set as TeX
+/// source in the code style.
+pub struct DocxWriter {
+    options: DocxOptions,
+    /// Directory relative image paths are resolved against.
+    base_dir: PathBuf,
+    styles: String,
+    theme: Option<Vec<u8>>,
+}
+
+struct Relationship {
+    id: String,
+    kind: &'static str,
+    target: String,
+    external: bool,
+}
+
+struct NumberingInstance {
+    ordered: bool,
+    start: u64,
+    level: usize,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Format {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    code: bool,
+    link: bool,
+}
+
+impl DocxWriter {
+    pub fn new(options: DocxOptions) -> Self {
+        Self {
+            options,
+            base_dir: PathBuf::new(),
+            styles: DEFAULT_STYLES.to_string(),
+            theme: None,
+        }
+    }
+
+    pub fn with_base_dir(mut self, base_dir: impl Into<PathBuf>) -> Self {
+        self.base_dir = base_dir.into();
+        self
+    }
+
+    /// Takes the styles (and theme) of the `.docx` file `reference`.
+    pub fn with_reference(mut self, reference: &[u8]) -> Result<Self> {
+        let invalid = |e: &dyn std::fmt::Display| ConverterError::ReferenceDocError(e.to_string());
+        let mut archive = ZipArchive::new(Cursor::new(reference)).map_err(|e| invalid(&e))?;
+
+        let mut styles = String::new();
+        archive
+            .by_name("word/styles.xml")
+            .map_err(|e| invalid(&e))?
+            .read_to_string(&mut styles)
+            .map_err(|e| invalid(&e))?;
+        self.styles = with_missing_styles(styles);
+
+        if let Ok(mut file) = archive.by_name("word/theme/theme1.xml") {
+            let mut theme = Vec::new();
+            file.read_to_end(&mut theme)?;
+            self.theme = Some(theme);
+        }
+        Ok(self)
+    }
+
+    pub fn write<W: Write>(&self, markdown: &str, writer: W) -> Result<()> {
+        let (metadata, markdown) = FrontMatter::split(markdown);
+        let document = MarkdownParser::new().parse_document(markdown);
+        self.write_document(&metadata, &document, writer)
+    }
+
+    /// Writes the file for a document already parsed, or imported.
+    pub fn write_document<W: Write>(
+        &self,
+        metadata: &FrontMatter,
+        document: &Document,
+        mut writer: W,
+    ) -> Result<()> {
+        let (width, height) = self.options.paper.dimensions();
+        let (width, height) = (
+            (width * 20.0).round() as u32,
+            (height * 20.0).round() as u32,
+        );
+        let renderer = DocumentRenderer {
+            base_dir: &self.base_dir,
+            text_width: width - 2 * MARGIN,
+            relationships: RefCell::new(vec![
+                Relationship::internal("rIdStyles", STYLES, "styles.xml"),
+                Relationship::internal("rIdNumbering", NUMBERING, "numbering.xml"),
+            ]),
+            media: RefCell::new(Vec::new()),
+            numbering: RefCell::new(Vec::new()),
+            list: Cell::new(None),
+            depth: Cell::new(0),
+            quote: Cell::new(0),
+            format: Cell::new(Format::default()),
+            ids: Cell::new(0),
+            heading_ids: RefCell::default(),
+        };
+        if self.theme.is_some() {
+            renderer
+                .relationships
+                .borrow_mut()
+                .push(Relationship::internal(
+                    "rIdTheme",
+                    THEME,
+                    "theme/theme1.xml",
+                ));
+        }
+        let body = renderer.render(document);
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        let media = renderer.media.into_inner();
+        zip.start_file("[Content_Types].xml", options)?;
+        zip.write_all(content_types(&media, self.theme.is_some()).as_bytes())?;
+        zip.start_file("_rels/.rels", options)?;
+        zip.write_all(PACKAGE_RELATIONSHIPS.as_bytes())?;
+        zip.start_file("docProps/core.xml", options)?;
+        zip.write_all(core_properties(metadata, document).as_bytes())?;
+
+        zip.start_file("word/document.xml", options)?;
+        write!(
+            zip,
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="{W}" xmlns:r="{R}" xmlns:wp="{WP}" xmlns:a="{A}" xmlns:pic="{PIC}">
+<w:body>
+{body}<w:sectPr><w:pgSz w:w="{width}" w:h="{height}"/><w:pgMar w:top="{MARGIN}" w:right="{MARGIN}" w:bottom="{MARGIN}" w:left="{MARGIN}" w:header="720" w:footer="720" w:gutter="0"/></w:sectPr>
+</w:body>
+</w:document>
+"#
+        )?;
+        zip.start_file("word/_rels/document.xml.rels", options)?;
+        zip.write_all(relationships(&renderer.relationships.borrow()).as_bytes())?;
+        zip.start_file("word/styles.xml", options)?;
+        zip.write_all(self.styles.as_bytes())?;
+        zip.start_file("word/numbering.xml", options)?;
+        zip.write_all(numbering(&renderer.numbering.borrow()).as_bytes())?;
+        if let Some(theme) = &self.theme {
+            zip.start_file("word/theme/theme1.xml", options)?;
+            zip.write_all(theme)?;
+        }
+        for (path, name) in &media {
+            let data = fs::read(path)?;
+            zip.start_file(format!("word/media/{}", name), options)?;
+            zip.write_all(&data)?;
+        }
+
+        let zip = zip.finish()?;
+        writer.write_all(zip.get_ref())?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// `styles` plus the default styles whose ids it lacks, so every style the
+/// writer refers to is defined.
+fn with_missing_styles(mut styles: String) -> String {
+    let Some(end) = styles.rfind("</w:styles>") else {
+        return styles;
+    };
+    let missing: String = STYLE_RE
+        .captures_iter(DEFAULT_STYLES)
+        .filter(|captures| !styles.contains(&format!(r#"w:styleId="{}""#, &captures[1])))
+        .map(|captures| format!("{}\n", &captures[0]))
+        .collect();
+    styles.insert_str(end, &missing);
+    styles
+}
+
+const W: &str = "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+const R: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+const WP: &str = "http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing";
+const A: &str = "http://schemas.openxmlformats.org/drawingml/2006/main";
+const PIC: &str = "http://schemas.openxmlformats.org/drawingml/2006/picture";
+
+const STYLES: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles";
+const NUMBERING: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering";
+const THEME: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/theme";
+const HYPERLINK: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink";
+const IMAGE: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/image";
+
+const PACKAGE_RELATIONSHIPS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/>
+  <Relationship Id="rId2" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="docProps/core.xml"/>
+</Relationships>
+"#;
+
+impl Relationship {
+    fn internal(id: &str, kind: &'static str, target: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            kind,
+            target: target.to_string(),
+            external: false,
+        }
+    }
+}
+
+fn content_types(media: &[(PathBuf, String)], theme: bool) -> String {
+    let mut types = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+  <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+  <Default Extension="xml" ContentType="application/xml"/>
+"#,
+    );
+    let mut extensions: Vec<&str> = media
+        .iter()
+        .filter_map(|(_, name)| name.rsplit_once('.').map(|(_, extension)| extension))
+        .collect();
+    extensions.sort_unstable();
+    extensions.dedup();
+    for extension in extensions {
+        let content_type = if extension == "png" {
+            "image/png"
+        } else {
+            "image/jpeg"
+        };
+        types.push_str(&format!(
+            "  <Default Extension=\"{}\" ContentType=\"{}\"/>\n",
+            extension, content_type
+        ));
+    }
+
+    let main = "application/vnd.openxmlformats-officedocument.wordprocessingml";
+    types.push_str(&format!(
+        r#"  <Override PartName="/word/document.xml" ContentType="{main}.document.main+xml"/>
+  <Override PartName="/word/styles.xml" ContentType="{main}.styles+xml"/>
+  <Override PartName="/word/numbering.xml" ContentType="{main}.numbering+xml"/>
+  <Override PartName="/docProps/core.xml" ContentType="application/vnd.openxmlformats-package.core-properties+xml"/>
+"#
+    ));
+    if theme {
+        types.push_str(r#"  <Override PartName="/word/theme/theme1.xml" ContentType="application/vnd.openxmlformats-officedocument.theme+xml"/>
+"#);
+    }
+    types.push_str("</Types>\n");
+    types
+}
+
+fn relationships(relationships: &[Relationship]) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+"#,
+    );
+    for relationship in relationships {
+        xml.push_str(&format!(
+            "  <Relationship Id=\"{}\" Type=\"{}\" Target=\"{}\"{}/>\n",
+            relationship.id,
+            relationship.kind,
+            attribute(&relationship.target),
+            if relationship.external {
+                r#" TargetMode="External""#
+            } else {
+                ""
+            }
+        ));
+    }
+    xml.push_str("</Relationships>\n");
+    xml
+}
+
+/// Bulleted and decimal definitions for nine levels of nesting, and an
+/// instance of one of them for every list. Ordered lists restart at their
+/// own first number.
+fn numbering(instances: &[NumberingInstance]) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:numbering xmlns:w=\"{}\">\n",
+        W
+    );
+    for (id, ordered) in [(0, false), (1, true)] {
+        xml.push_str(&format!("<w:abstractNum w:abstractNumId=\"{}\">\n", id));
+        for level in 0..9 {
+            let (format, text) = if ordered {
+                ("decimal", format!("%{}.", level + 1))
+            } else {
+                ("bullet", ["•", "◦", "▪"][level % 3].to_string())
+            };
+            xml.push_str(&format!(
+                "<w:lvl w:ilvl=\"{}\"><w:start w:val=\"1\"/><w:numFmt w:val=\"{}\"/><w:lvlText w:val=\"{}\"/><w:lvlJc w:val=\"left\"/><w:pPr><w:ind w:left=\"{}\" w:hanging=\"360\"/></w:pPr></w:lvl>\n",
+                level,
+                format,
+                text,
+                720 * (level + 1)
+            ));
+        }
+        xml.push_str("</w:abstractNum>\n");
+    }
+    for (i, instance) in instances.iter().enumerate() {
+        xml.push_str(&format!(
+            "<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"{}\"/>",
+            i + 1,
+            u8::from(instance.ordered)
+        ));
+        if instance.ordered {
+            xml.push_str(&format!(
+                "<w:lvlOverride w:ilvl=\"{}\"><w:startOverride w:val=\"{}\"/></w:lvlOverride>",
+                instance.level, instance.start
+            ));
+        }
+        xml.push_str("</w:num>\n");
+    }
+    xml.push_str("</w:numbering>\n");
+    xml
+}
+
+fn core_properties(metadata: &FrontMatter, document: &Document) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+"#,
+    );
+    let title = metadata.get("title").map(str::to_string).or_else(|| {
+        document.blocks.iter().find_map(|block| match block {
+            Block::Heading { level: 1, content } => Some(plain_text(content)),
+            _ => None,
+        })
+    });
+    if let Some(title) = title {
+        xml.push_str(&format!("  <dc:title>{}</dc:title>\n", text(&title)));
+    }
+    let authors = metadata.get_all("author");
+    if !authors.is_empty() {
+        xml.push_str(&format!(
+            "  <dc:creator>{}</dc:creator>\n",
+            text(&authors.join("; "))
+        ));
+    }
+    if let Some(description) = metadata.get("description") {
+        xml.push_str(&format!(
+            "  <dc:description>{}</dc:description>\n",
+            text(description)
+        ));
+    }
+    if let Some(date) = metadata.get("date") {
+        xml.push_str(&format!(
+            "  <dcterms:created xsi:type=\"dcterms:W3CDTF\">{}</dcterms:created>\n",
+            text(date)
+        ));
+    }
+    xml.push_str("</cp:coreProperties>\n");
+    xml
+}
+
+fn text(value: &str) -> String {
+    html_escape::encode_text(value).into_owned()
+}
+
+fn attribute(value: &str) -> String {
+    html_escape::encode_double_quoted_attribute(value).into_owned()
+}
+
+/// Renders the body of `word/document.xml`, collecting the relationships,
+/// images and list numbering it refers to.
+struct DocumentRenderer<'a> {
+    base_dir: &'a Path,
+    /// Width between the margins, in twentieths of a point.
+    text_width: u32,
+    relationships: RefCell<Vec<Relationship>>,
+    /// Embedded images and their names under `word/media`.
+    media: RefCell<Vec<(PathBuf, String)>>,
+    numbering: RefCell<Vec<NumberingInstance>>,
+    /// Numbering instance of the list being rendered.
+    list: Cell<Option<usize>>,
+    /// How many lists the current block is nested in.
+    depth: Cell<usize>,
+    /// How many blockquotes the current block is nested in.
+    quote: Cell<usize>,
+    format: Cell<Format>,
+    /// Last id given to a bookmark or drawing.
+    ids: Cell<u32>,
+    heading_ids: RefCell<HeadingIds>,
+}
+
+impl DocumentRenderer<'_> {
+    fn next_id(&self) -> u32 {
+        self.ids.set(self.ids.get() + 1);
+        self.ids.get()
+    }
+
+    /// Opens a paragraph in `style`, or in the style of the enclosing
+    /// blockquote, indented under the enclosing list item.
+    fn open_paragraph(&self, style: Option<&str>, justification: Option<&str>, out: &mut String) {
+        let style = style.or((self.quote.get() > 0).then_some("BlockText"));
+        let indent = 720 * self.depth.get();
+        out.push_str("<w:p>");
+        if style.is_none() && justification.is_none() && indent == 0 {
+            return;
+        }
+        out.push_str("<w:pPr>");
+        if let Some(style) = style {
+            out.push_str(&format!(r#"<w:pStyle w:val="{}"/>"#, style));
+        }
+        if indent > 0 {
+            out.push_str(&format!(r#"<w:ind w:left="{}"/>"#, indent));
+        }
+        if let Some(justification) = justification {
+            out.push_str(&format!(r#"<w:jc w:val="{}"/>"#, justification));
+        }
+        out.push_str("</w:pPr>");
+    }
+
+    /// A run of `content` in the current format.
+    fn run(&self, content: &str, out: &mut String) {
+        if content.is_empty() {
+            return;
+        }
+        let format = self.format.get();
+        out.push_str("<w:r>");
+        if format.code || format.link || format.bold || format.italic || format.strike {
+            out.push_str("<w:rPr>");
+            if format.code {
+                out.push_str(r#"<w:rStyle w:val="VerbatimChar"/>"#);
+            } else if format.link {
+                out.push_str(r#"<w:rStyle w:val="Hyperlink"/>"#);
+            }
+            if format.bold {
+                out.push_str("<w:b/>");
+            }
+            if format.italic {
+                out.push_str("<w:i/>");
+            }
+            if format.strike {
+                out.push_str("<w:strike/>");
+            }
+            out.push_str("</w:rPr>");
+        }
+        out.push_str(r#"<w:t xml:space="preserve">"#);
+        out.push_str(&text(content));
+        out.push_str("</w:t></w:r>");
+    }
+
+    fn with_format(&self, change: impl FnOnce(&mut Format), render: impl FnOnce()) {
+        let outer = self.format.get();
+        let mut format = outer;
+        change(&mut format);
+        self.format.set(format);
+        render();
+        self.format.set(outer);
+    }
+
+    /// The id of the relationship of `kind` to `target`, added if new.
+    fn relationship(&self, kind: &'static str, target: &str, external: bool) -> String {
+        let mut relationships = self.relationships.borrow_mut();
+        if let Some(relationship) = relationships
+            .iter()
+            .find(|r| r.kind == kind && r.target == target)
+        {
+            return relationship.id.clone();
+        }
+        let id = format!("rId{}", relationships.len() + 1);
+        relationships.push(Relationship {
+            id: id.clone(),
+            kind,
+            target: target.to_string(),
+            external,
+        });
+        id
+    }
+
+    /// An inline drawing of the local image at `url`, no wider than the text.
+    fn drawing(&self, url: &str, alt: &str) -> Option<String> {
+        if url.contains("://") || url.starts_with("data:") {
+            return None;
+        }
+        let path = self.base_dir.join(url);
+        let (width, height) = match image::image_dimensions(&path) {
+            Ok(dimensions) => dimensions,
+            Err(e) => {
+                warn!("Leaving out image {}: {}", path.display(), e);
+                return None;
+            }
+        };
+
+        let name = {
+            let mut media = self.media.borrow_mut();
+            match media.iter().find(|(p, _)| *p == path) {
+                Some((_, name)) => name.clone(),
+                None => {
+                    let extension = path
+                        .extension()
+                        .map_or("png".to_string(), |e| e.to_string_lossy().to_lowercase());
+                    let name = format!("image{}.{}", media.len() + 1, extension);
+                    media.push((path, name.clone()));
+                    name
+                }
+            }
+        };
+        let id = self.relationship(IMAGE, &format!("media/{}", name), false);
+
+        // 9525 EMU to a pixel at 96 dpi, 635 to a twentieth of a point.
+        let max_width = u64::from(self.text_width) * 635;
+        let (mut cx, mut cy) = (u64::from(width) * 9525, u64::from(height) * 9525);
+        if cx > max_width {
+            cy = cy * max_width / cx;
+            cx = max_width;
+        }
+        let drawing_id = self.next_id();
+        let alt = attribute(alt);
+        Some(format!(
+            r#"<w:r><w:drawing><wp:inline distT="0" distB="0" distL="0" distR="0"><wp:extent cx="{cx}" cy="{cy}"/><wp:docPr id="{drawing_id}" name="Picture {drawing_id}" descr="{alt}"/><wp:cNvGraphicFramePr><a:graphicFrameLocks noChangeAspect="1"/></wp:cNvGraphicFramePr><a:graphic><a:graphicData uri="{PIC}"><pic:pic><pic:nvPicPr><pic:cNvPr id="0" name="{name}" descr="{alt}"/><pic:cNvPicPr/></pic:nvPicPr><pic:blipFill><a:blip r:embed="{id}"/><a:stretch><a:fillRect/></a:stretch></pic:blipFill><pic:spPr><a:xfrm><a:off x="0" y="0"/><a:ext cx="{cx}" cy="{cy}"/></a:xfrm><a:prstGeom prst="rect"><a:avLst/></a:prstGeom></pic:spPr></pic:pic></a:graphicData></a:graphic></wp:inline></w:drawing></w:r>"#
+        ))
+    }
+}
+
+impl Renderer for DocumentRenderer<'_> {
+    fn heading(&self, level: u8, content: &[Inline], out: &mut String) {
+        let id = self.next_id();
+        out.push_str(&format!(
+            r#"<w:p><w:pPr><w:pStyle w:val="Heading{}"/></w:pPr><w:bookmarkStart w:id="{}" w:name="{}"/>"#,
+            level,
+            id,
+            attribute(&self.heading_ids.borrow_mut().next(content))
+        ));
+        self.inlines(content, out);
+        out.push_str(&format!(r#"<w:bookmarkEnd w:id="{}"/></w:p>"#, id));
+        out.push('\n');
+    }
+
+    fn paragraph(&self, content: &[Inline], out: &mut String) {
+        // A paragraph holding only an image becomes a centered figure with
+        // the alt text as its caption.
+        if let [Inline::Image { url, alt }] = content {
+            if let Some(drawing) = self.drawing(url, alt) {
+                self.open_paragraph(None, Some("center"), out);
+                out.push_str(&drawing);
+                out.push_str("</w:p>\n");
+                if !alt.is_empty() {
+                    self.open_paragraph(Some("Caption"), Some("center"), out);
+                    self.run(alt, out);
+                    out.push_str("</w:p>\n");
+                }
+                return;
+            }
+        }
+
+        self.open_paragraph(None, None, out);
+        self.inlines(content, out);
+        out.push_str("</w:p>\n");
+    }
+
+    fn list(&self, list: &List, out: &mut String) {
+        let instance = {
+            let mut numbering = self.numbering.borrow_mut();
+            numbering.push(NumberingInstance {
+                ordered: list.ordered,
+                start: list.start,
+                level: self.depth.get().min(8),
+            });
+            numbering.len()
+        };
+        let outer = self.list.replace(Some(instance));
+        self.depth.set(self.depth.get() + 1);
+        for item in &list.items {
+            self.list_item(item, out);
+        }
+        self.depth.set(self.depth.get() - 1);
+        self.list.set(outer);
+    }
+
+    fn list_item(&self, item: &ListItem, out: &mut String) {
+        out.push_str(&format!(
+            r#"<w:p><w:pPr><w:pStyle w:val="ListParagraph"/><w:numPr><w:ilvl w:val="{}"/><w:numId w:val="{}"/></w:numPr></w:pPr>"#,
+            (self.depth.get() - 1).min(8),
+            self.list.get().unwrap_or(0)
+        ));
+        match item.checked {
+            Some(true) => self.run("☒ ", out),
+            Some(false) => self.run("☐ ", out),
+            None => {}
+        }
+        self.inlines(&item.content, out);
+        out.push_str("</w:p>\n");
+        self.blocks(&item.children, out);
+    }
+
+    fn code_block(&self, code_block: &CodeBlock, out: &mut String) {
+        self.open_paragraph(Some("SourceCode"), None, out);
+        out.push_str("<w:r>");
+        for (i, line) in code_block
+            .code
+            .trim_end_matches('\n')
+            .split('\n')
+            .enumerate()
+        {
+            if i > 0 {
+                out.push_str("<w:br/>");
+            }
+            out.push_str(r#"<w:t xml:space="preserve">"#);
+            out.push_str(&text(&line.replace('\t', "    ")));
+            out.push_str("</w:t>");
+        }
+        out.push_str("</w:r></w:p>\n");
+    }
+
+    fn table(&self, table: &Table, out: &mut String) {
+        let columns = table.alignments.len().max(1) as u32;
+        let width = (self.text_width - 720 * self.depth.get() as u32) / columns;
+        out.push_str(
+            r#"<w:tbl><w:tblPr><w:tblStyle w:val="Table"/><w:tblW w:w="0" w:type="auto"/>"#,
+        );
+        if self.depth.get() > 0 {
+            out.push_str(&format!(
+                r#"<w:tblInd w:w="{}" w:type="dxa"/>"#,
+                720 * self.depth.get()
+            ));
+        }
+        out.push_str("</w:tblPr><w:tblGrid>");
+        for _ in 0..columns {
+            out.push_str(&format!(r#"<w:gridCol w:w="{}"/>"#, width));
+        }
+        out.push_str("</w:tblGrid>\n");
+
+        let rows = std::iter::once(&table.header).chain(&table.rows);
+        for (i, row) in rows.enumerate() {
+            out.push_str("<w:tr>");
+            if i == 0 {
+                out.push_str("<w:trPr><w:tblHeader/></w:trPr>");
+            }
+            for (cell, alignment) in row.iter().zip(&table.alignments) {
+                out.push_str(&format!(
+                    r#"<w:tc><w:tcPr><w:tcW w:w="{}" w:type="dxa"/></w:tcPr><w:p>"#,
+                    width
+                ));
+                let justification = match alignment {
+                    Alignment::None => None,
+                    Alignment::Left => Some("left"),
+                    Alignment::Center => Some("center"),
+                    Alignment::Right => Some("right"),
+                };
+                if let Some(justification) = justification {
+                    out.push_str(&format!(
+                        r#"<w:pPr><w:jc w:val="{}"/></w:pPr>"#,
+                        justification
+                    ));
+                }
+                self.with_format(|f| f.bold |= i == 0, || self.inlines(cell, out));
+                out.push_str("</w:p></w:tc>");
+            }
+            out.push_str("</w:tr>\n");
+        }
+        out.push_str("</w:tbl>\n");
+        // Keeps a following table from merging into this one.
+        out.push_str("<w:p/>\n");
+    }
+
+    fn blockquote(&self, blocks: &[Block], out: &mut String) {
+        self.quote.set(self.quote.get() + 1);
+        self.blocks(blocks, out);
+        self.quote.set(self.quote.get() - 1);
+    }
+
+    fn math_block(&self, math_block: &MathBlock, out: &mut String) {
+        self.open_paragraph(None, Some("center"), out);
+        self.with_format(|f| f.code = true, || self.run(math_block.math.trim(), out));
+        out.push_str("</w:p>\n");
+    }
+
+    fn horizontal_rule(&self, out: &mut String) {
+        out.push_str(r#"<w:p><w:pPr><w:pBdr><w:bottom w:val="single" w:sz="6" w:space="1" w:color="auto"/></w:pBdr></w:pPr></w:p>"#);
+        out.push('\n');
+    }
+
+    fn text(&self, text: &str, out: &mut String) {
+        self.run(text, out);
+    }
+
+    fn code(&self, code: &str, out: &mut String) {
+        self.with_format(|f| f.code = true, || self.run(code, out));
+    }
+
+    fn math(&self, math: &str, out: &mut String) {
+        self.with_format(|f| f.code = true, || self.run(math, out));
+    }
+
+    fn strong(&self, children: &[Inline], out: &mut String) {
+        self.with_format(|f| f.bold = true, || self.inlines(children, out));
+    }
+
+    fn emphasis(&self, children: &[Inline], out: &mut String) {
+        self.with_format(|f| f.italic = true, || self.inlines(children, out));
+    }
+
+    fn strikethrough(&self, children: &[Inline], out: &mut String) {
+        self.with_format(|f| f.strike = true, || self.inlines(children, out));
+    }
+
+    fn link(&self, url: &str, content: &[Inline], out: &mut String) {
+        match url.strip_prefix('#') {
+            Some(anchor) => out.push_str(&format!(
+                r#"<w:hyperlink w:anchor="{}" w:history="1">"#,
+                attribute(anchor)
+            )),
+            None => out.push_str(&format!(
+                r#"<w:hyperlink r:id="{}" w:history="1">"#,
+                self.relationship(HYPERLINK, url, true)
+            )),
+        }
+        self.with_format(|f| f.link = true, || self.inlines(content, out));
+        out.push_str("</w:hyperlink>");
+    }
+
+    fn image(&self, url: &str, alt: &str, out: &mut String) {
+        if let Some(drawing) = self.drawing(url, alt) {
+            out.push_str(&drawing);
+        } else if url.contains("://") && !self.format.get().link {
+            self.link(url, &[Inline::Text(alt.to_string())], out);
+        } else {
+            self.run(alt, out);
+        }
+    }
+
+    fn soft_break(&self, out: &mut String) {
+        self.run(" ", out);
+    }
+
+    fn line_break(&self, out: &mut String) {
+        out.push_str("<w:r><w:br/></w:r>");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(docx: &[u8], name: &str) -> String {
+        let mut archive = ZipArchive::new(Cursor::new(docx)).unwrap();
+        let mut content = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn test_document() {
+        let markdown = "---\ntitle: Report\nauthor: A. Writer\n---\n# Overview\n\nSee [the site](https://example.com) and [below](#usage), **bold** `code`.\n\n1. one\n   - nested\n2. two\n\n3. three\n\n```\nfn main() {}\nlet x = 1;\n```";
+        let mut docx = Vec::new();
+        DocxWriter::new(DocxOptions::default())
+            .write(markdown, &mut docx)
+            .unwrap();
+
+        let document = entry(&docx, "word/document.xml");
+        assert!(document.contains(r#"<w:pStyle w:val="Heading1"/></w:pPr><w:bookmarkStart w:id="1" w:name="overview"/><w:r><w:t xml:space="preserve">Overview</w:t></w:r><w:bookmarkEnd w:id="1"/>"#));
+        assert!(document.contains(r#"<w:hyperlink r:id="rId3" w:history="1"><w:r><w:rPr><w:rStyle w:val="Hyperlink"/></w:rPr><w:t xml:space="preserve">the site</w:t></w:r></w:hyperlink>"#));
+        assert!(document.contains(r#"<w:hyperlink w:anchor="usage" w:history="1">"#));
+        assert!(document
+            .contains(r#"<w:r><w:rPr><w:b/></w:rPr><w:t xml:space="preserve">bold</w:t></w:r>"#));
+        assert!(document.contains(
+            r#"<w:rStyle w:val="VerbatimChar"/></w:rPr><w:t xml:space="preserve">code</w:t>"#
+        ));
+        assert!(document.contains(r#"<w:numPr><w:ilvl w:val="1"/><w:numId w:val="2"/></w:numPr></w:pPr><w:r><w:t xml:space="preserve">nested</w:t>"#));
+        assert!(document.contains(r#"<w:numId w:val="3"/>"#));
+        assert!(document.contains(r#"<w:pStyle w:val="SourceCode"/></w:pPr><w:r><w:t xml:space="preserve">fn main() {}</w:t><w:br/><w:t xml:space="preserve">let x = 1;</w:t></w:r>"#));
+
+        let relationships = entry(&docx, "word/_rels/document.xml.rels");
+        assert!(relationships.contains(r#"Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink" Target="https://example.com" TargetMode="External""#));
+        let numbering = entry(&docx, "word/numbering.xml");
+        assert!(numbering.contains(r#"<w:num w:numId="2"><w:abstractNumId w:val="0"/></w:num>"#));
+        assert!(numbering.contains(r#"<w:num w:numId="3"><w:abstractNumId w:val="1"/><w:lvlOverride w:ilvl="0"><w:startOverride w:val="3"/></w:lvlOverride></w:num>"#));
+        let core = entry(&docx, "docProps/core.xml");
+        assert!(core.contains("<dc:title>Report</dc:title>\n  <dc:creator>A. Writer</dc:creator>"));
+    }
+
+    #[test]
+    fn test_images() {
+        let dir = tempfile::tempdir().unwrap();
+        image::RgbImage::new(1600, 400)
+            .save(dir.path().join("wide.png"))
+            .unwrap();
+        let markdown = "![Wide](wide.png)\n\nInline ![again](wide.png) and ![remote](https://example.com/a.png) and ![gone](missing.png)";
+
+        let mut docx = Vec::new();
+        DocxWriter::new(DocxOptions::default())
+            .with_base_dir(dir.path())
+            .write(markdown, &mut docx)
+            .unwrap();
+
+        let document = entry(&docx, "word/document.xml");
+        // Shrunk to the 451.28pt between the A4 margins.
+        assert!(document.contains(r#"<wp:extent cx="5731510" cy="1432877"/>"#));
+        assert!(document.contains(r#"<w:pStyle w:val="Caption"/><w:jc w:val="center"/></w:pPr><w:r><w:t xml:space="preserve">Wide</w:t>"#));
+        assert_eq!(document.matches(r#"<a:blip r:embed="rId3"/>"#).count(), 2);
+        assert!(document.contains(r#"<w:t xml:space="preserve">remote</w:t></w:r></w:hyperlink>"#));
+        assert!(document.contains(r#"<w:t xml:space="preserve">gone</w:t>"#));
+
+        let content_types = entry(&docx, "[Content_Types].xml");
+        assert!(content_types.contains(r#"<Default Extension="png" ContentType="image/png"/>"#));
+        let mut archive = ZipArchive::new(Cursor::new(&docx)).unwrap();
+        assert!(archive.by_name("word/media/image1.png").is_ok());
+        assert!(archive.by_name("word/media/image2.png").is_err());
+    }
+
+    #[test]
+    fn test_reference_styles() {
+        let styles = r#"<w:styles xmlns:w="x"><w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/><w:rPr><w:color w:val="FF0000"/></w:rPr></w:style></w:styles>"#;
+        let mut reference = ZipWriter::new(Cursor::new(Vec::new()));
+        reference
+            .start_file("word/styles.xml", SimpleFileOptions::default())
+            .unwrap();
+        reference.write_all(styles.as_bytes()).unwrap();
+        let reference = reference.finish().unwrap().into_inner();
+
+        let mut docx = Vec::new();
+        DocxWriter::new(DocxOptions::default())
+            .with_reference(&reference)
+            .unwrap()
+            .write("# Title", &mut docx)
+            .unwrap();
+
+        let styles = entry(&docx, "word/styles.xml");
+        assert!(styles.contains(r#"<w:color w:val="FF0000"/>"#));
+        assert_eq!(styles.matches(r#"w:styleId="Heading1""#).count(), 1);
+        assert!(styles.contains(r#"w:styleId="SourceCode""#));
+
+        assert!(DocxWriter::new(DocxOptions::default())
+            .with_reference(b"not a zip")
+            .is_err());
+    }
+}
@@ -0,0 +1,430 @@
+use crate::ast::{plain_text, Block, CodeBlock, Document, ListItem, MathBlock};
+use crate::front_matter::FrontMatter;
+use crate::html::{HtmlOptions, HtmlRenderer};
+use crate::parser::MarkdownParser;
+use crate::render::Renderer;
+use crate::tex::tex_to_html;
+use anyhow::Result;
+use scraper::node::Node;
+use scraper::{ElementRef, Html, Selector};
+use std::io::Write;
+
+/// Elements mail clients strip or refuse to run.
+const DROPPED: &[&str] = &["script", "style", "link", "noscript"];
+
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "meta", "source", "track", "wbr",
+];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmailOptions {
+    pub syntax_highlight: bool,
+}
+
+/// Writes HTML that survives being sent as email. Mail clients drop
+/// `<style>` and `<script>` and ignore `max-width` and `margin: auto` on
+/// blocks, so the stylesheet's rules are copied onto each element's `style`
+/// attribute, the page is centred with layout tables, math is typeset ahead
+/// of time as HTML text, and task-list checkboxes become ☑ and ☐.
+///
+/// Rules for `body` style the content area, since most clients replace the
+/// body of a message. Rules with pseudo-elements, `:hover` and the like, and
+/// at-rules such as `@media` cannot be inlined and are left out.
+pub struct EmailWriter {
+    options: EmailOptions,
+    css: String,
+}
+
+impl EmailWriter {
+    pub fn new(options: EmailOptions) -> Self {
+        Self {
+            options,
+            css: include_str!("../assets/default.css").to_string(),
+        }
+    }
+
+    /// Inlines `css` instead of the default stylesheet.
+    pub fn with_css(mut self, css: String) -> Self {
+        self.css = css;
+        self
+    }
+
+    /// Writes the message for `markdown`, titled by its front matter or else
+    /// its first heading.
+    pub fn write<W: Write>(&self, markdown: &str, writer: W) -> Result<()> {
+        let (front_matter, content) = FrontMatter::split(markdown);
+        let document = MarkdownParser::new().parse_document(content);
+        self.write_document(&front_matter, &document, writer)
+    }
+
+    /// Writes the message for a document already parsed, or imported.
+    pub fn write_document<W: Write>(
+        &self,
+        front_matter: &FrontMatter,
+        document: &Document,
+        mut writer: W,
+    ) -> Result<()> {
+        let title = match front_matter.get("title") {
+            Some(title) => title.to_string(),
+            None => document
+                .blocks
+                .iter()
+                .find_map(|block| match block {
+                    Block::Heading { content, .. } => Some(plain_text(content)),
+                    _ => None,
+                })
+                .unwrap_or_default(),
+        };
+
+        let mut page = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"UTF-8\">\n\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>",
+        );
+        html_escape::encode_text_to_string(&title, &mut page);
+        page.push_str(
+            "</title>\n</head>\n<body style=\"margin: 0; padding: 0;\">\n\
+             <table role=\"presentation\" width=\"100%\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\">\n\
+             <tr>\n<td align=\"center\">\n\
+             <table role=\"presentation\" width=\"100%\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\" style=\"max-width: 800px;\">\n\
+             <tr>\n<td align=\"left\">\n<div class=\"email-body\">\n",
+        );
+        EmailRenderer::new(self.options).blocks(&document.blocks, &mut page);
+        page.push_str("</div>\n</td>\n</tr>\n</table>\n</td>\n</tr>\n</table>\n</body>\n</html>\n");
+
+        writer.write_all(inline_css(&page, &self.css).as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// The HTML renderer with math typeset as text and checkboxes as characters.
+struct EmailRenderer {
+    html: HtmlRenderer,
+}
+
+impl EmailRenderer {
+    fn new(options: EmailOptions) -> Self {
+        Self {
+            html: HtmlRenderer::new(HtmlOptions {
+                syntax_highlight: options.syntax_highlight,
+                heading_ids: false,
+                katex: false,
+            }),
+        }
+    }
+}
+
+impl Renderer for EmailRenderer {
+    fn highlight(&self, code_block: &CodeBlock) -> Option<String> {
+        self.html.highlight(code_block)
+    }
+
+    fn list_item(&self, item: &ListItem, out: &mut String) {
+        out.push_str("<li>");
+        match item.checked {
+            Some(true) => out.push_str("☑ "),
+            Some(false) => out.push_str("☐ "),
+            None => {}
+        }
+        self.inlines(&item.content, out);
+        if !item.children.is_empty() {
+            out.push('\n');
+            self.blocks(&item.children, out);
+        }
+        out.push_str("</li>\n");
+    }
+
+    fn math_block(&self, math_block: &MathBlock, out: &mut String) {
+        out.push_str(r#"<div class="math-block">"#);
+        out.push_str(&tex_to_html(&math_block.math));
+        out.push_str("</div>\n");
+    }
+
+    fn math(&self, math: &str, out: &mut String) {
+        out.push_str(r#"<span class="math-inline">"#);
+        out.push_str(&tex_to_html(math));
+        out.push_str("</span>");
+    }
+}
+
+/// A style rule that can be inlined: one selector of a rule's list, with
+/// the rule's declarations.
+struct Rule {
+    selector: Selector,
+    specificity: (usize, usize, usize),
+    declarations: Vec<Declaration>,
+}
+
+#[derive(Clone)]
+struct Declaration {
+    property: String,
+    value: String,
+    important: bool,
+}
+
+/// Splits `css` into rules, leaving out at-rules and selectors that match
+/// states or pseudo-elements rather than elements.
+fn parse_css(css: &str) -> Vec<Rule> {
+    let mut css = css.to_string();
+    while let Some(start) = css.find("/*") {
+        let end = css[start..]
+            .find("*/")
+            .map_or(css.len(), |end| start + end + 2);
+        css.replace_range(start..end, "");
+    }
+
+    let mut rules = Vec::new();
+    let mut rest = css.as_str();
+    while let Some(open) = rest.find('{') {
+        let prelude = rest[..open].trim();
+        let close = matching_brace(&rest[open..]).map_or(rest.len(), |close| open + close);
+        let body = &rest[open + 1..close.max(open + 1)];
+        rest = rest.get(close + 1..).unwrap_or("");
+
+        if prelude.starts_with('@') {
+            continue;
+        }
+        let declarations = parse_declarations(body);
+        for selector in prelude.split(',').map(str::trim) {
+            if selector.is_empty() || selector.contains(':') {
+                continue;
+            }
+            let selector = match selector.strip_prefix("body") {
+                Some(rest) if rest.is_empty() || rest.starts_with([' ', '>', '.', '[', '#']) => {
+                    format!(".email-body{}", rest)
+                }
+                _ => selector.to_string(),
+            };
+            let Ok(parsed) = Selector::parse(&selector) else {
+                continue;
+            };
+            rules.push(Rule {
+                selector: parsed,
+                specificity: specificity(&selector),
+                declarations: declarations.clone(),
+            });
+        }
+    }
+    rules
+}
+
+/// The offset of the `}` closing the block `text` opens.
+fn matching_brace(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_declarations(body: &str) -> Vec<Declaration> {
+    body.split(';')
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let property = property.trim().to_ascii_lowercase();
+            let value = value.trim();
+            let (value, important) = match value.strip_suffix("!important") {
+                Some(value) => (value.trim_end(), true),
+                None => (value, false),
+            };
+            (!property.is_empty() && !value.is_empty()).then(|| Declaration {
+                property,
+                value: value.to_string(),
+                important,
+            })
+        })
+        .collect()
+}
+
+/// Counts the ids, the classes and attributes, and the element names in
+/// `selector`.
+fn specificity(selector: &str) -> (usize, usize, usize) {
+    let mut specificity = (0, 0, 0);
+    let mut chars = selector.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => specificity.0 += 1,
+            '.' => specificity.1 += 1,
+            '[' => {
+                specificity.1 += 1;
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                }
+                continue;
+            }
+            c if c.is_alphabetic() => specificity.2 += 1,
+            _ => continue,
+        }
+        while chars
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+        {
+            chars.next();
+        }
+    }
+    specificity
+}
+
+/// Re-serialises `page` with the declarations of the rules in `css` that
+/// match each element in its `style` attribute, ahead of any it already
+/// has. Scripts, styles and links are dropped, and the layout tables
+/// (`role="presentation"`) and their cells are left as they are.
+pub fn inline_css(page: &str, css: &str) -> String {
+    let rules = parse_css(css);
+    let document = Html::parse_document(page);
+    let mut out = String::from("<!DOCTYPE html>\n");
+    write_element(document.root_element(), &rules, false, &mut out);
+    out.push('\n');
+    out
+}
+
+fn write_element(element: ElementRef, rules: &[Rule], in_layout: bool, out: &mut String) {
+    let name = element.value().name();
+    let layout = element.value().attr("role") == Some("presentation")
+        || (in_layout && matches!(name, "tbody" | "tr" | "td"));
+
+    out.push('<');
+    out.push_str(name);
+    for (attribute, value) in element.value().attrs() {
+        if attribute != "style" {
+            push_attribute(out, attribute, value);
+        }
+    }
+    let style = if layout {
+        element
+            .value()
+            .attr("style")
+            .unwrap_or_default()
+            .to_string()
+    } else {
+        style(element, rules)
+    };
+    if !style.is_empty() {
+        push_attribute(out, "style", &style);
+    }
+    out.push('>');
+    if VOID.contains(&name) {
+        return;
+    }
+
+    for (i, child) in element.children().enumerate() {
+        match child.value() {
+            Node::Text(text) => {
+                // A newline opening a `<pre>` is dropped when parsed, so a
+                // second one keeps the first.
+                if i == 0 && name == "pre" && text.starts_with('\n') {
+                    out.push('\n');
+                }
+                html_escape::encode_text_to_string(&**text, out);
+            }
+            Node::Element(child_element) if !DROPPED.contains(&child_element.name()) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    write_element(child, rules, layout, out);
+                }
+            }
+            _ => {}
+        }
+    }
+    out.push_str("</");
+    out.push_str(name);
+    out.push('>');
+}
+
+fn push_attribute(out: &mut String, name: &str, value: &str) {
+    out.push(' ');
+    out.push_str(name);
+    out.push_str("=\"");
+    html_escape::encode_double_quoted_attribute_to_string(value, out);
+    out.push('"');
+}
+
+/// The cascaded `style` of `element`: the matching rules in order of
+/// specificity, then its own `style`, then the `!important` declarations.
+fn style(element: ElementRef, rules: &[Rule]) -> String {
+    let mut matching: Vec<(bool, (usize, usize, usize), &Declaration)> = Vec::new();
+    for rule in rules {
+        if rule.selector.matches(&element) {
+            for declaration in &rule.declarations {
+                matching.push((declaration.important, rule.specificity, declaration));
+            }
+        }
+    }
+    let own = parse_declarations(element.value().attr("style").unwrap_or_default());
+    for declaration in &own {
+        matching.push((declaration.important, (usize::MAX, 0, 0), declaration));
+    }
+    // A stable sort keeps the order of the stylesheet among equals.
+    matching.sort_by_key(|(important, specificity, _)| (*important, *specificity));
+
+    let mut properties: Vec<(&str, &str)> = Vec::new();
+    for (_, _, declaration) in matching {
+        match properties
+            .iter_mut()
+            .find(|(property, _)| *property == declaration.property)
+        {
+            Some(property) => property.1 = &declaration.value,
+            None => properties.push((&declaration.property, &declaration.value)),
+        }
+    }
+    properties
+        .iter()
+        .map(|(property, value)| format!("{}: {};", property, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_inline_css() {
+        let css = "/* base */\np { color: red; margin: 0 }\n.note p { color: blue }\n\
+                   p { color: green !important; }\nbody { font-family: serif; }\n\
+                   a:hover { color: pink }\n@media (max-width: 600px) { p { color: black } }";
+        let page = "<html><head><style>p { x: y }</style><script>alert(1)</script></head>\
+                    <body><div class=\"email-body\"><div class=\"note\">\
+                    <p style=\"margin: 1em\">Hi <a href=\"?a=1&amp;b=2\">there</a></p>\
+                    </div></div></body></html>";
+        assert_eq!(
+            inline_css(page, css),
+            "<!DOCTYPE html>\n<html><head></head><body>\
+             <div class=\"email-body\" style=\"font-family: serif;\"><div class=\"note\">\
+             <p style=\"color: green; margin: 1em;\">Hi <a href=\"?a=1&amp;b=2\">there</a></p>\
+             </div></div></body></html>\n"
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let mut output = Vec::new();
+        EmailWriter::new(EmailOptions::default())
+            .write(
+                "# Weekly report\n\n> Quoted $x^2$\n\n- [x] shipped\n\n$$\\alpha$$",
+                &mut output,
+            )
+            .unwrap();
+        let html = String::from_utf8(output).unwrap();
+
+        assert!(html.contains("<title>Weekly report</title>"));
+        assert!(html.contains("cellspacing=\"0\" role=\"presentation\" width=\"100%\">"));
+        assert!(html.contains("<div class=\"email-body\" style=\"font-family: "));
+        assert!(html.contains("<blockquote style=\"border-left: 4px solid #42b983;"));
+        assert!(html.contains("<i>x</i><sup>2</sup></span>"));
+        assert!(html.contains("<li>☑ shipped</li>"));
+        assert!(html.contains("text-align: center;\">α</div>"));
+        assert!(!html.contains("<script") && !html.contains("<style") && !html.contains("$"));
+    }
+}
@@ -0,0 +1,534 @@
+use crate::ast::{plain_text, Block, CodeBlock, Document, Inline, ListItem};
+use crate::error::ConverterError;
+use crate::front_matter::FrontMatter;
+use crate::html::{self, toc_list, HtmlOptions, HtmlRenderer, TocEntry};
+use crate::mathml;
+use crate::parser::MarkdownParser;
+use crate::render::Renderer;
+use crate::utils;
+use anyhow::Result;
+use log::warn;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EpubOptions {
+    pub syntax_highlight: bool,
+}
+
+/// A file making up one chapter of a book.
+pub struct Chapter<'a> {
+    /// Where the file is; images are resolved relative to its directory.
+    pub path: &'a Path,
+    pub front_matter: FrontMatter,
+    pub document: Document,
+}
+
+impl<'a> Chapter<'a> {
+    /// The chapter for `markdown`, read from `path`.
+    pub fn parse(path: &'a Path, markdown: &str) -> Self {
+        let (front_matter, content) = FrontMatter::split(markdown);
+        Self {
+            path,
+            front_matter,
+            document: MarkdownParser::new().parse_document(content),
+        }
+    }
+}
+
+/// Writes EPUB 3 books: one XHTML file per chapter, a navigation document
+/// listing the headings of every chapter, the stylesheet, and the local
+/// images the chapters use. The book's metadata comes from the chapters'
+/// front matter (`title`, `author`, `lang`, `date`, `publisher`,
+/// `description`, `rights`, `identifier`), earlier chapters taking precedence.
+pub struct EpubWriter {
+    options: EpubOptions,
+    css: String,
+}
+
+struct Image {
+    path: PathBuf,
+    href: String,
+}
+
+impl EpubWriter {
+    pub fn new(options: EpubOptions) -> Self {
+        Self {
+            options,
+            css: include_str!("../assets/default.css").to_string(),
+        }
+    }
+
+    pub fn with_css(mut self, css: String) -> Self {
+        self.css = css;
+        self
+    }
+
+    pub fn write<W: Write>(&self, chapters: &[Chapter], mut writer: W) -> Result<()> {
+        let mut metadata = FrontMatter::default();
+        for chapter in chapters {
+            metadata.merge(chapter.front_matter.clone());
+        }
+        let title = metadata
+            .get("title")
+            .map(str::to_string)
+            .or_else(|| {
+                chapters
+                    .iter()
+                    .find_map(|chapter| first_heading(&chapter.document))
+            })
+            .or_else(|| {
+                let stem = chapters.first()?.path.file_stem()?;
+                Some(stem.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "Untitled".to_string());
+        let language = metadata
+            .get("lang")
+            .or_else(|| metadata.get("language"))
+            .unwrap_or("en")
+            .to_string();
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        // The mimetype must come first and uncompressed, so readers can
+        // identify the file from its first bytes.
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(b"application/epub+zip")?;
+        zip.start_file("META-INF/container.xml", deflated)?;
+        zip.write_all(CONTAINER_XML.as_bytes())?;
+        zip.start_file("OEBPS/style.css", deflated)?;
+        zip.write_all(self.css.as_bytes())?;
+
+        let html = HtmlRenderer::new(HtmlOptions {
+            syntax_highlight: self.options.syntax_highlight,
+            heading_ids: true,
+            katex: false,
+        });
+        let mut images: Vec<Image> = Vec::new();
+        let mut toc = Vec::new();
+        let mut manifest = String::new();
+        let mut spine = String::new();
+
+        for (i, chapter) in chapters.iter().enumerate() {
+            let document = &chapter.document;
+            let file = format!("chapter-{}.xhtml", i + 1);
+            // Anchors are unique within a chapter's file.
+            html.reset_heading_ids();
+            let renderer = ChapterRenderer {
+                html: &html,
+                base_dir: chapter.path.parent().unwrap_or(Path::new("")),
+                images: RefCell::new(std::mem::take(&mut images)),
+                remote: Cell::new(false),
+                mathml: Cell::new(false),
+            };
+            let body = renderer.render(document);
+            images = renderer.images.into_inner();
+
+            let chapter_title =
+                first_heading(document).unwrap_or_else(|| format!("Chapter {}", i + 1));
+            let headings = headings(document, &file);
+            if headings.is_empty() {
+                toc.push(TocEntry {
+                    level: 1,
+                    href: file.clone(),
+                    title: chapter_title.clone(),
+                });
+            }
+            toc.extend(headings);
+
+            zip.start_file(format!("OEBPS/{}", file), deflated)?;
+            zip.write_all(xhtml_page(&language, &chapter_title, &body).as_bytes())?;
+
+            let properties: Vec<&str> = [
+                (renderer.mathml.get(), "mathml"),
+                (renderer.remote.get(), "remote-resources"),
+            ]
+            .into_iter()
+            .filter_map(|(has, property)| has.then_some(property))
+            .collect();
+            let properties = match properties.is_empty() {
+                true => String::new(),
+                false => format!(" properties=\"{}\"", properties.join(" ")),
+            };
+            manifest.push_str(&format!(
+                "    <item id=\"chapter-{0}\" href=\"{1}\" media-type=\"application/xhtml+xml\"{2}/>\n",
+                i + 1,
+                file,
+                properties
+            ));
+            spine.push_str(&format!("    <itemref idref=\"chapter-{}\"/>\n", i + 1));
+        }
+
+        for (i, image) in images.iter().enumerate() {
+            let data = fs::read(&image.path).map_err(|e| {
+                ConverterError::InputFileError(std::io::Error::new(
+                    e.kind(),
+                    format!("{}: {}", image.path.display(), e),
+                ))
+            })?;
+            zip.start_file(format!("OEBPS/{}", image.href), deflated)?;
+            zip.write_all(&data)?;
+            manifest.push_str(&format!(
+                "    <item id=\"image-{}\" href=\"{}\" media-type=\"{}\"/>\n",
+                i + 1,
+                image.href,
+                utils::image_media_type(&image.href)
+            ));
+        }
+
+        let nav = format!(
+            "<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n{}</nav>\n",
+            toc_list("ol", &toc)
+        );
+        zip.start_file("OEBPS/nav.xhtml", deflated)?;
+        zip.write_all(xhtml_page(&language, "Contents", &nav).as_bytes())?;
+
+        zip.start_file("OEBPS/content.opf", deflated)?;
+        let opf = package_document(&metadata, &title, &language, chapters, &manifest, &spine);
+        zip.write_all(opf.as_bytes())?;
+
+        let zip = zip.finish()?;
+        writer.write_all(zip.get_ref())?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn package_document(
+    metadata: &FrontMatter,
+    title: &str,
+    language: &str,
+    chapters: &[Chapter],
+    manifest: &str,
+    spine: &str,
+) -> String {
+    let mut dc = String::new();
+    let mut element = |name: &str, value: &str| {
+        dc.push_str(&format!("    <dc:{0}>{1}</dc:{0}>\n", name, escape(value)));
+    };
+    element("title", title);
+    element("language", language);
+    for author in metadata
+        .get_all("author")
+        .iter()
+        .chain(metadata.get_all("authors"))
+    {
+        element("creator", author);
+    }
+    for key in ["date", "publisher", "description", "rights"] {
+        if let Some(value) = metadata.get(key) {
+            element(key, value);
+        }
+    }
+
+    let identifier = match metadata.get("identifier").or_else(|| metadata.get("isbn")) {
+        Some(identifier) => escape(identifier),
+        None => {
+            // Derived from the content, so rebuilding the same book keeps
+            // its identity.
+            let mut hasher = DefaultHasher::new();
+            title.hash(&mut hasher);
+            for chapter in chapters {
+                chapter.document.hash(&mut hasher);
+            }
+            let high = hasher.finish();
+            high.hash(&mut hasher);
+            let low = hasher.finish();
+            format!(
+                "urn:uuid:{:08x}-{:04x}-4{:03x}-8{:03x}-{:012x}",
+                high >> 32,
+                (high >> 16) & 0xffff,
+                high & 0xfff,
+                low >> 52,
+                low & 0xffff_ffff_ffff
+            )
+        }
+    };
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" xml:lang="{language}">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">{identifier}</dc:identifier>
+{dc}    <meta property="dcterms:modified">{modified}</meta>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="css" href="style.css" media-type="text/css"/>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+        language = escape(language),
+        modified = timestamp(SystemTime::now()),
+    )
+}
+
+fn xhtml_page(language: &str, title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{0}" lang="{0}">
+<head>
+<meta charset="UTF-8"/>
+<title>{1}</title>
+<link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+{2}</body>
+</html>
+"#,
+        escape(language),
+        escape(title),
+        body
+    )
+}
+
+fn first_heading(document: &Document) -> Option<String> {
+    document.blocks.iter().find_map(|block| match block {
+        Block::Heading { content, .. } => Some(plain_text(content)),
+        _ => None,
+    })
+}
+
+fn headings(document: &Document, file: &str) -> Vec<TocEntry> {
+    html::heading_anchors(document)
+        .into_iter()
+        .map(|(level, content, id)| TocEntry {
+            level,
+            href: format!("{}#{}", file, id),
+            title: plain_text(content),
+        })
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    html_escape::encode_text(text).into_owned()
+}
+
+/// `time` as the `CCYY-MM-DDThh:mm:ssZ` that `dcterms:modified` requires.
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
+
+/// The standard HTML, serialized as XHTML, with local images moved into the
+/// book.
+struct ChapterRenderer<'a> {
+    html: &'a HtmlRenderer,
+    base_dir: &'a Path,
+    /// Images of the whole book so far, shared between chapters.
+    images: RefCell<Vec<Image>>,
+    /// Whether the chapter refers to remote images.
+    remote: Cell<bool>,
+    /// Whether the chapter has math, as MathML.
+    mathml: Cell<bool>,
+}
+
+impl ChapterRenderer<'_> {
+    /// The `src` of the image at `url` inside the book.
+    fn image_src(&self, url: &str) -> String {
+        if url.contains("://") {
+            self.remote.set(true);
+            return url.to_string();
+        }
+        if url.starts_with("data:") {
+            return url.to_string();
+        }
+
+        let path = self.base_dir.join(url);
+        if !path.is_file() {
+            warn!(
+                "Image {} not found; leaving the link as it is",
+                path.display()
+            );
+            return url.to_string();
+        }
+        let mut images = self.images.borrow_mut();
+        if let Some(image) = images.iter().find(|image| image.path == path) {
+            return image.href.clone();
+        }
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_else(|| "png".to_string());
+        let href = format!("images/image-{}.{}", images.len() + 1, extension);
+        images.push(Image {
+            path,
+            href: href.clone(),
+        });
+        href
+    }
+}
+
+impl Renderer for ChapterRenderer<'_> {
+    fn heading_id(&self, content: &[Inline]) -> Option<String> {
+        self.html.heading_id(content)
+    }
+
+    fn highlight(&self, code_block: &CodeBlock) -> Option<String> {
+        self.html.highlight(code_block)
+    }
+
+    fn typeset_math(&self, math: &str, display: bool) -> Option<String> {
+        self.mathml.set(true);
+        let typeset = self.html.typeset_math(math, display)?;
+        Some(typeset.replacen(
+            "<math",
+            &format!("<math xmlns=\"{}\"", mathml::NAMESPACE),
+            1,
+        ))
+    }
+
+    fn list_item(&self, item: &ListItem, out: &mut String) {
+        let Some(checked) = item.checked else {
+            return html::list_item(self, item, out);
+        };
+        out.push_str(r#"<li><input type="checkbox" disabled="disabled""#);
+        if checked {
+            out.push_str(r#" checked="checked""#);
+        }
+        out.push_str("/> ");
+        self.inlines(&item.content, out);
+        if !item.children.is_empty() {
+            out.push('\n');
+            self.blocks(&item.children, out);
+        }
+        out.push_str("</li>\n");
+    }
+
+    fn horizontal_rule(&self, out: &mut String) {
+        out.push_str("<hr/>\n");
+    }
+
+    fn image(&self, url: &str, alt: &str, out: &mut String) {
+        out.push_str(r#"<img src=""#);
+        out.push_str(&html_escape::encode_double_quoted_attribute(
+            &self.image_src(url),
+        ));
+        out.push_str(r#"" alt=""#);
+        out.push_str(&html_escape::encode_double_quoted_attribute(alt));
+        out.push_str(r#""/>"#);
+    }
+
+    fn line_break(&self, out: &mut String) {
+        out.push_str("<br/>\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    fn entry(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut content = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn test_book() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("map.png"), b"\x89PNG").unwrap();
+        let first = "---\ntitle: Field Handbook\nauthor: [A. Writer, B. Editor]\n---\n# Setup\n\n## Tools\n\n![Map](map.png)  \nLine $x$";
+        let second =
+            "Text only, with the same ![map](map.png) and ![logo](https://x.org/l.png).\n\n---";
+        let (one, two) = (dir.path().join("one.md"), dir.path().join("two.md"));
+        let chapters = [Chapter::parse(&one, first), Chapter::parse(&two, second)];
+
+        let mut output = Vec::new();
+        EpubWriter::new(EpubOptions::default())
+            .write(&chapters, &mut output)
+            .unwrap();
+        assert_eq!(&output[30..58], b"mimetypeapplication/epub+zip");
+
+        let mut archive = ZipArchive::new(Cursor::new(output)).unwrap();
+        assert_eq!(archive.len(), 8);
+
+        let opf = entry(&mut archive, "OEBPS/content.opf");
+        assert!(opf.contains("<dc:title>Field Handbook</dc:title>"));
+        assert!(opf.contains(
+            "<dc:creator>A. Writer</dc:creator>\n    <dc:creator>B. Editor</dc:creator>"
+        ));
+        assert!(opf
+            .contains(r#"<item id="image-1" href="images/image-1.png" media-type="image/png"/>"#));
+        assert!(opf.contains(
+            r#"href="chapter-1.xhtml" media-type="application/xhtml+xml" properties="mathml"/>"#
+        ));
+        assert!(opf.contains(r#"href="chapter-2.xhtml" media-type="application/xhtml+xml" properties="remote-resources"/>"#));
+        assert!(opf.contains("<itemref idref=\"chapter-1\"/>\n    <itemref idref=\"chapter-2\"/>"));
+
+        let nav = entry(&mut archive, "OEBPS/nav.xhtml");
+        assert!(nav.contains(
+            "<ol>\n<li><a href=\"chapter-1.xhtml#setup\">Setup</a>\n<ol>\n<li><a href=\"chapter-1.xhtml#tools\">Tools</a></li>\n</ol>\n</li>\n<li><a href=\"chapter-2.xhtml\">Chapter 2</a></li>\n</ol>"
+        ));
+
+        let chapter = entry(&mut archive, "OEBPS/chapter-1.xhtml");
+        assert!(chapter.contains("<title>Setup</title>"));
+        assert!(chapter.contains(r#"<h2 id="tools">Tools</h2>"#));
+        assert!(chapter.contains(r#"<img src="images/image-1.png" alt="Map"/><br/>"#));
+        assert!(chapter.contains(
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML" alttext="x"><mi>x</mi></math>"#
+        ));
+        let chapter = entry(&mut archive, "OEBPS/chapter-2.xhtml");
+        assert!(chapter.contains(r#"<img src="images/image-1.png" alt="map"/>"#));
+        assert!(chapter.contains("<hr/>"));
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1_709_251_199);
+        assert_eq!(timestamp(time), "2024-02-29T23:59:59Z");
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+    }
+}
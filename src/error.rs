@@ -10,4 +10,31 @@ pub enum ConverterError {
 
     #[error("Invalid CSS file: {0}")]
     CssError(String),
+
+    #[error("Invalid page template: {0}")]
+    TemplateError(String),
+
+    #[error("Invalid LaTeX preamble: {0}")]
+    PreambleError(String),
+
+    #[error("Failed to build PDF: {0}")]
+    PdfError(String),
+
+    #[error("Invalid highlighting theme or syntax: {0}")]
+    HighlightError(String),
+
+    #[error("Invalid reference document: {0}")]
+    ReferenceDocError(String),
+
+    #[error("Invalid document JSON: {0}")]
+    JsonError(String),
+
+    #[error("Unknown paper size: {0}")]
+    UnknownPaperSize(String),
+
+    #[error("Unknown slide split: {0}")]
+    UnknownSlideSplit(String),
+
+    #[error("Unknown output format: {0}")]
+    UnknownFormat(String),
 }
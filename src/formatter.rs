@@ -0,0 +1,894 @@
+use crate::ast::{
+    Alignment, Block, CodeBlock, Document, Inline, List, ListItem, MathBlock, Position, Table,
+};
+use crate::error::ConverterError;
+use crate::front_matter::FrontMatter;
+use crate::parser::MarkdownParser;
+use crate::render::Renderer;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use unicode_width::UnicodeWidthStr;
+
+lazy_static! {
+    /// A line of `=` or `-` making the paragraph above it a setext heading.
+    static ref SETEXT_UNDERLINE_RE: Regex = Regex::new(r"^ {0,3}(=+|-+)[ \t]*$").unwrap();
+    /// A link or image destination followed by a title.
+    static ref LINK_TITLE_RE: Regex =
+        Regex::new(r#"\]\(\s*(<[^<>]*>|[^\s()]*)\s+["'(]"#).unwrap();
+    static ref REFERENCE_DEFINITION_RE: Regex = Regex::new(r"^ {0,3}\[([^\]]+)\]:").unwrap();
+    static ref HTML_RE: Regex =
+        Regex::new(r"</?[A-Za-z][A-Za-z0-9-]*(\s[^<>]*)?/?>|<!--").unwrap();
+    static ref PAREN_LIST_RE: Regex = Regex::new(r"^\s*\d{1,9}\)(\s|$)").unwrap();
+}
+
+/// Renders a document back to Markdown in one canonical style: ATX headings,
+/// `-` bullets, ordered items numbered up from the list's start, fenced code
+/// and tables with aligned pipes.
+///
+/// Formatting is idempotent, and the output parses back to the same document
+/// up to whitespace: only the places where paragraphs break their lines move.
+/// The exception is emphasis that could only be written as an ambiguous run
+/// of delimiters, such as `**a***b*c`, which may not be read back the same.
+///
+/// Blocks holding syntax the document tree doesn't model, such as setext
+/// headings, indented code, link titles, reference links and raw HTML, are
+/// kept as written rather than lose it.
+#[derive(Default)]
+pub struct MarkdownFormatter {
+    /// Column to wrap paragraphs at; `None` puts each paragraph on one line.
+    width: Option<usize>,
+    /// Escape `|` in text, as needed inside table cells.
+    escape_pipes: bool,
+    /// Offsets of the spaces in the inline output so far that may become
+    /// line breaks.
+    breaks: RefCell<Vec<usize>>,
+    /// Closing delimiters of the spans being written, innermost last.
+    open: RefCell<Vec<String>>,
+}
+
+impl MarkdownFormatter {
+    pub fn new(width: Option<usize>) -> Self {
+        Self {
+            width,
+            ..Self::default()
+        }
+    }
+
+    pub fn format(&self, content: &str) -> String {
+        // Front matter is kept exactly as written, set off by a blank line.
+        let body = FrontMatter::split(content).1;
+        let mut out = content[..content.len() - body.len()].to_string();
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+
+        let lines: Vec<&str> = body.lines().collect();
+        let references = reference_labels(&lines);
+        // Each block with its lines, and whether to keep them as written.
+        let mut chunks: Vec<(Block, Position, bool)> = Vec::new();
+        for (block, position) in MarkdownParser::new().blocks(body).with_positions() {
+            let source = &lines[position.start_line - 1..position.end_line];
+            let underlines = matches!(
+                chunks.last(),
+                Some((Block::Paragraph(_), previous, _))
+                    if previous.end_line + 1 == position.start_line
+            ) && block == Block::HorizontalRule
+                && SETEXT_UNDERLINE_RE.is_match(source[0]);
+            if underlines {
+                // The rule is a setext underline, making a heading of both.
+                let (paragraph, previous, _) = chunks.pop().unwrap();
+                let position = Position {
+                    start_line: previous.start_line,
+                    end_line: position.end_line,
+                };
+                chunks.push((paragraph, position, true));
+            } else {
+                let verbatim = unmodelled(&block, source, &references);
+                chunks.push((block, position, verbatim));
+            }
+        }
+
+        for (i, (block, position, verbatim)) in chunks.iter().enumerate() {
+            if i > 0 || !out.is_empty() {
+                out.push('\n');
+            }
+            if *verbatim {
+                for line in &lines[position.start_line - 1..position.end_line] {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            } else {
+                self.block(block, &mut out);
+            }
+        }
+        out
+    }
+
+    /// Formats the file at `path` in place, returning whether it changed.
+    /// With `check` set the file is only compared, never written.
+    pub fn format_file(&self, path: &Path, check: bool) -> Result<bool> {
+        let content = fs::read_to_string(path).map_err(ConverterError::InputFileError)?;
+        let formatted = self.format(&content);
+        if formatted == content {
+            return Ok(false);
+        }
+
+        if !check {
+            fs::write(path, formatted).map_err(|e| {
+                ConverterError::OutputFileError(format!("Failed to write output: {}", e))
+            })?;
+        }
+        Ok(true)
+    }
+
+    /// A formatter for content indented by `indent` columns.
+    fn nested(&self, indent: usize) -> Self {
+        Self::new(self.width.map(|width| width.saturating_sub(indent)))
+    }
+
+    /// Renders `content` as lines, broken to fit the width. Lines that would
+    /// be read as the start of another block get escaped, except the first
+    /// when `first_line_free` (it follows a list marker).
+    fn fill(&self, content: &[Inline], first_line_free: bool) -> Vec<String> {
+        self.breaks.borrow_mut().clear();
+        let mut text = String::new();
+        self.inlines(content, &mut text);
+        // A line starting with three backticks would open a code block.
+        let mut breaks = self
+            .breaks
+            .take()
+            .into_iter()
+            .filter(|&b| !text[b + 1..].starts_with("```"))
+            .peekable();
+
+        let mut lines = Vec::new();
+        let mut line_start = 0;
+        for hard_break in text.match_indices('\n').map(|(i, _)| i).chain([text.len()]) {
+            let mut candidate = None;
+            let ends = std::iter::from_fn(|| breaks.next_if(|&b| b < hard_break));
+            for end in ends.chain([hard_break]) {
+                if let (Some(width), Some(candidate)) = (self.width, candidate) {
+                    if candidate > line_start && text[line_start..end].width() > width {
+                        lines.push(&text[line_start..candidate]);
+                        line_start = candidate + 1;
+                    }
+                }
+                candidate = Some(end);
+            }
+            lines.push(&text[line_start..hard_break]);
+            line_start = hard_break + 1;
+        }
+
+        let parser = MarkdownParser::new();
+        lines
+            .into_iter()
+            .enumerate()
+            .map(|(i, line)| {
+                if (i > 0 || !first_line_free) && parser.starts_block(line) {
+                    // `1.` is escaped as `1\.`, anything else at its first character.
+                    let at = line.bytes().take_while(u8::is_ascii_digit).count();
+                    format!("{}\\{}", &line[..at], &line[at..])
+                } else {
+                    line.to_string()
+                }
+            })
+            .collect()
+    }
+
+    /// Writes a strong (`width` 2) or emphasis span with `*` where the
+    /// parser will read it back, and with `_` where `*` would run into the
+    /// surrounding characters or close an enclosing span instead of opening.
+    fn emphasized(&self, width: usize, inline: &Inline, next: Option<char>, out: &mut String) {
+        let (Inline::Strong(children) | Inline::Emphasis(children)) = inline else {
+            return self.inline(inline, out);
+        };
+        let previous = out.chars().next_back();
+        let fits = |c: char| {
+            let delimiter = c.to_string().repeat(width);
+            let can_close = previous.is_some_and(|p| !p.is_whitespace());
+            !(can_close && self.open.borrow().contains(&delimiter))
+                && previous != Some(c)
+                && next != Some(c)
+                && (c == '*'
+                    || !(previous.is_some_and(char::is_alphanumeric)
+                        || next.is_some_and(char::is_alphanumeric)))
+        };
+        let c = ['*', '_'].into_iter().find(|&c| fits(c)).unwrap_or('*');
+        self.delimited(&c.to_string().repeat(width), children, out);
+    }
+
+    fn delimited(&self, delimiter: &str, children: &[Inline], out: &mut String) {
+        out.push_str(delimiter);
+        self.open.borrow_mut().push(delimiter.to_string());
+        self.inlines(children, out);
+        self.open.borrow_mut().pop();
+        out.push_str(delimiter);
+    }
+
+    fn list_item_with_marker(&self, marker: &str, item: &ListItem, out: &mut String) {
+        let indent = " ".repeat(marker.len() + 1);
+        let inner = self.nested(indent.len());
+
+        out.push_str(marker);
+        match item.checked {
+            Some(true) => out.push_str(" [x]"),
+            Some(false) => out.push_str(" [ ]"),
+            None => {}
+        }
+        for (i, line) in inner.fill(&item.content, true).iter().enumerate() {
+            if i > 0 {
+                out.push_str(&indent);
+            } else if !line.is_empty() {
+                out.push(' ');
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+
+        // A blank line would end the list, so children are packed tightly.
+        for child in &item.children {
+            let mut block = String::new();
+            inner.block(child, &mut block);
+            push_indented(&indent, &block, out);
+        }
+    }
+}
+
+impl Renderer for MarkdownFormatter {
+    fn render_to(&self, document: &Document, writer: &mut dyn Write) -> io::Result<()> {
+        for (i, block) in document.blocks.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b"\n")?;
+            }
+            self.render_block_to(block, writer)?;
+        }
+        Ok(())
+    }
+
+    fn blocks(&self, blocks: &[Block], out: &mut String) {
+        for (i, block) in blocks.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            self.block(block, out);
+        }
+    }
+
+    fn heading(&self, level: u8, content: &[Inline], out: &mut String) {
+        let mut text = String::new();
+        self.inlines(content, &mut text);
+
+        for _ in 0..level {
+            out.push('#');
+        }
+        out.push(' ');
+        // Trailing `#`s after a space would be read as a closing sequence.
+        let stripped = text.trim_end_matches('#');
+        if text.is_empty() {
+            out.push('#');
+        } else if stripped.len() < text.len() && (stripped.is_empty() || stripped.ends_with(' ')) {
+            out.push_str(stripped);
+            out.push('\\');
+            out.push_str(&text[stripped.len()..]);
+        } else {
+            out.push_str(&text);
+        }
+        out.push('\n');
+    }
+
+    fn paragraph(&self, content: &[Inline], out: &mut String) {
+        for line in self.fill(content, false) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+
+    fn list(&self, list: &List, out: &mut String) {
+        for (number, item) in (list.start..).zip(&list.items) {
+            if list.ordered {
+                self.list_item_with_marker(&format!("{}.", number), item, out);
+            } else {
+                self.list_item(item, out);
+            }
+        }
+    }
+
+    fn list_item(&self, item: &ListItem, out: &mut String) {
+        self.list_item_with_marker("-", item, out);
+    }
+
+    fn code_block(&self, code_block: &CodeBlock, out: &mut String) {
+        // The fence has to be longer than any line of backticks in the code.
+        let longest = code_block
+            .code
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && line.bytes().all(|b| b == b'`'))
+            .map(str::len)
+            .max()
+            .unwrap_or(0);
+        let fence = "`".repeat(longest.max(2) + 1);
+
+        out.push_str(&fence);
+        // An info string starting with a backtick would lengthen the fence.
+        if code_block.info.starts_with('`') {
+            out.push(' ');
+        }
+        out.push_str(&code_block.info);
+        out.push('\n');
+        out.push_str(&code_block.code);
+        if !code_block.code.is_empty() && !code_block.code.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(&fence);
+        out.push('\n');
+    }
+
+    fn table(&self, table: &Table, out: &mut String) {
+        let cells = Self {
+            escape_pipes: true,
+            ..Self::default()
+        };
+        let render_row = |row: &[Vec<Inline>]| -> Vec<String> {
+            row.iter()
+                .map(|cell| {
+                    let mut text = String::new();
+                    cells.inlines(cell, &mut text);
+                    text
+                })
+                .collect()
+        };
+        let header = render_row(&table.header);
+        let rows: Vec<Vec<String>> = table.rows.iter().map(|row| render_row(row)).collect();
+
+        let mut widths = Vec::new();
+        for row in std::iter::once(&header).chain(&rows) {
+            for (i, cell) in row.iter().enumerate() {
+                if widths.len() <= i {
+                    widths.push(3);
+                }
+                widths[i] = widths[i].max(cell.width());
+            }
+        }
+        let alignment = |i: usize| table.alignments.get(i).copied().unwrap_or(Alignment::None);
+
+        push_table_row(&header, &widths, alignment, out);
+        out.push('|');
+        for (i, width) in widths.iter().enumerate().take(header.len()) {
+            let (left, right) = match alignment(i) {
+                Alignment::None => ("-", "-"),
+                Alignment::Left => (":", "-"),
+                Alignment::Center => (":", ":"),
+                Alignment::Right => ("-", ":"),
+            };
+            out.push(' ');
+            out.push_str(left);
+            out.push_str(&"-".repeat(width - 2));
+            out.push_str(right);
+            out.push_str(" |");
+        }
+        out.push('\n');
+        for row in &rows {
+            push_table_row(row, &widths, alignment, out);
+        }
+    }
+
+    fn blockquote(&self, blocks: &[Block], out: &mut String) {
+        let mut inner = String::new();
+        self.nested(2).blocks(blocks, &mut inner);
+        if inner.is_empty() {
+            out.push_str(">\n");
+        }
+        for line in inner.lines() {
+            out.push('>');
+            if !line.is_empty() {
+                out.push(' ');
+                out.push_str(line);
+            }
+            out.push('\n');
+        }
+    }
+
+    fn math_block(&self, math_block: &MathBlock, out: &mut String) {
+        out.push_str("$$\n");
+        if !math_block.math.is_empty() {
+            out.push_str(&math_block.math);
+            out.push('\n');
+        }
+        out.push_str("$$");
+        if let Some(id) = &math_block.id {
+            out.push_str(" {#");
+            out.push_str(id);
+            out.push('}');
+        }
+        out.push('\n');
+    }
+
+    fn horizontal_rule(&self, out: &mut String) {
+        out.push_str("---\n");
+    }
+
+    fn text(&self, text: &str, out: &mut String) {
+        let chars: Vec<char> = text.chars().collect();
+        for (i, &c) in chars.iter().enumerate() {
+            let intraword = i > 0
+                && i + 1 < chars.len()
+                && chars[i - 1].is_alphanumeric()
+                && chars[i + 1].is_alphanumeric();
+            match c {
+                ' ' | '\t' => {
+                    if !out.ends_with(' ') {
+                        self.soft_break(out);
+                    }
+                    continue;
+                }
+                '\\' | '`' | '*' | '$' | '[' | ']' => out.push('\\'),
+                '_' | '~' if !intraword => out.push('\\'),
+                '|' if self.escape_pipes => out.push('\\'),
+                _ => {}
+            }
+            out.push(c);
+        }
+    }
+
+    fn code(&self, code: &str, out: &mut String) {
+        // A span closes at the first run of backticks as long as its opening
+        // one, so use the shortest fence no run inside the code matches.
+        let runs: Vec<usize> = code
+            .split(|c| c != '`')
+            .map(str::len)
+            .filter(|&len| len > 0)
+            .collect();
+        let fence_len = (1..).find(|len| !runs.contains(len)).unwrap_or(1);
+        let fence = "`".repeat(fence_len);
+        // The parser strips one space from each end of a padded span.
+        let pad = code.starts_with('`')
+            || code.ends_with('`')
+            || (code.starts_with(' ') && code.ends_with(' ') && !code.trim().is_empty());
+
+        out.push_str(&fence);
+        if pad {
+            out.push(' ');
+        }
+        out.push_str(code);
+        if pad {
+            out.push(' ');
+        }
+        out.push_str(&fence);
+    }
+
+    fn math(&self, math: &str, out: &mut String) {
+        out.push('$');
+        out.push_str(&math.replace('\n', " "));
+        out.push('$');
+    }
+
+    fn inlines(&self, inlines: &[Inline], out: &mut String) {
+        for (i, inline) in inlines.iter().enumerate() {
+            let width = match inline {
+                Inline::Strong(_) => 2,
+                Inline::Emphasis(_) => 1,
+                inline => {
+                    self.inline(inline, out);
+                    continue;
+                }
+            };
+            let next = match inlines.get(i + 1) {
+                Some(next) => first_char(next),
+                None => self.open.borrow().last().and_then(|d| d.chars().next()),
+            };
+            self.emphasized(width, inline, next, out);
+        }
+    }
+
+    fn strong(&self, children: &[Inline], out: &mut String) {
+        self.delimited("**", children, out);
+    }
+
+    fn emphasis(&self, children: &[Inline], out: &mut String) {
+        self.delimited("*", children, out);
+    }
+
+    fn strikethrough(&self, children: &[Inline], out: &mut String) {
+        self.delimited("~~", children, out);
+    }
+
+    fn link(&self, url: &str, content: &[Inline], out: &mut String) {
+        // `!` straight before the link would make it an image.
+        if out.ends_with('!') {
+            out.insert(out.len() - 1, '\\');
+        }
+        out.push('[');
+        self.open.borrow_mut().push("]".to_string());
+        self.inlines(content, out);
+        self.open.borrow_mut().pop();
+        out.push_str("](");
+        out.push_str(url);
+        out.push(')');
+    }
+
+    fn image(&self, url: &str, alt: &str, out: &mut String) {
+        out.push_str("![");
+        self.text(alt, out);
+        out.push_str("](");
+        out.push_str(url);
+        out.push(')');
+    }
+
+    fn soft_break(&self, out: &mut String) {
+        self.breaks.borrow_mut().push(out.len());
+        out.push(' ');
+    }
+
+    fn line_break(&self, out: &mut String) {
+        out.push_str("\\\n");
+    }
+}
+
+/// The first character `inline` renders to, where that is known up front.
+fn first_char(inline: &Inline) -> Option<char> {
+    match inline {
+        Inline::Text(text) => text.chars().next(),
+        Inline::Code(_) => Some('`'),
+        Inline::Math(_) => Some('$'),
+        Inline::Strikethrough(_) => Some('~'),
+        Inline::Link { .. } => Some('['),
+        Inline::Image { .. } => Some('!'),
+        Inline::SoftBreak => Some(' '),
+        Inline::LineBreak => Some('\\'),
+        Inline::Strong(_) | Inline::Emphasis(_) => None,
+    }
+}
+
+/// The labels of the link reference definitions in `lines`, lowercased.
+fn reference_labels(lines: &[&str]) -> Vec<String> {
+    lines
+        .iter()
+        .filter_map(|line| REFERENCE_DEFINITION_RE.captures(line))
+        .map(|captures| captures[1].to_lowercase())
+        .collect()
+}
+
+/// Whether `block`, parsed from the lines `source`, holds syntax the parser
+/// reads differently from CommonMark: setext headings, indented code, link
+/// titles, reference links and definitions, raw HTML or `1)` lists.
+fn unmodelled(block: &Block, source: &[&str], references: &[String]) -> bool {
+    let indented = source[0].starts_with("    ") || source[0].starts_with('\t');
+    let setext = matches!(block, Block::Paragraph(_))
+        && source[1..]
+            .iter()
+            .any(|line| SETEXT_UNDERLINE_RE.is_match(line));
+    indented
+        || setext
+        || source.iter().any(|line| {
+            let lowercase = line.to_lowercase();
+            LINK_TITLE_RE.is_match(line)
+                || REFERENCE_DEFINITION_RE.is_match(line)
+                || HTML_RE.is_match(line)
+                || PAREN_LIST_RE.is_match(line)
+                || references
+                    .iter()
+                    .any(|label| lowercase.contains(&format!("[{}]", label)))
+        })
+}
+
+fn push_table_row(
+    cells: &[String],
+    widths: &[usize],
+    alignment: impl Fn(usize) -> Alignment,
+    out: &mut String,
+) {
+    out.push('|');
+    for (i, cell) in cells.iter().enumerate() {
+        let padding = widths[i] - cell.width();
+        let before = match alignment(i) {
+            Alignment::Right => padding,
+            Alignment::Center => padding / 2,
+            Alignment::None | Alignment::Left => 0,
+        };
+        out.push(' ');
+        out.push_str(&" ".repeat(before));
+        out.push_str(cell);
+        out.push_str(&" ".repeat(padding - before));
+        out.push_str(" |");
+    }
+    out.push('\n');
+}
+
+fn push_indented(indent: &str, text: &str, out: &mut String) {
+    for line in text.lines() {
+        if !line.is_empty() {
+            out.push_str(indent);
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use proptest::prelude::*;
+
+    /// `document` with soft breaks turned into spaces and runs of whitespace
+    /// collapsed, which is all that formatting may change.
+    fn normalized(document: &Document) -> Document {
+        fn inlines(nodes: &[Inline]) -> Vec<Inline> {
+            let mut result: Vec<Inline> = Vec::new();
+            for node in nodes {
+                let node = match node {
+                    Inline::SoftBreak => Inline::Text(" ".to_string()),
+                    Inline::Text(text) => Inline::Text(text.replace('\t', " ")),
+                    Inline::Code(code) => Inline::Code(code.clone()),
+                    Inline::Math(math) => Inline::Math(math.replace('\n', " ")),
+                    Inline::Strong(children) => Inline::Strong(inlines(children)),
+                    Inline::Emphasis(children) => Inline::Emphasis(inlines(children)),
+                    Inline::Strikethrough(children) => Inline::Strikethrough(inlines(children)),
+                    Inline::Link { url, content } => Inline::Link {
+                        url: url.clone(),
+                        content: inlines(content),
+                    },
+                    Inline::Image { url, alt } => Inline::Image {
+                        url: url.clone(),
+                        alt: alt.split_whitespace().collect::<Vec<_>>().join(" "),
+                    },
+                    Inline::LineBreak => Inline::LineBreak,
+                };
+                match (result.last_mut(), node) {
+                    (Some(Inline::Text(previous)), Inline::Text(text)) => previous.push_str(&text),
+                    (_, node) => result.push(node),
+                }
+            }
+            for node in &mut result {
+                if let Inline::Text(text) = node {
+                    while text.contains("  ") {
+                        *text = text.replace("  ", " ");
+                    }
+                }
+            }
+            result
+        }
+
+        fn blocks(nodes: &[Block]) -> Vec<Block> {
+            nodes
+                .iter()
+                .map(|block| match block {
+                    Block::Heading { level, content } => Block::Heading {
+                        level: *level,
+                        content: inlines(content),
+                    },
+                    Block::Paragraph(content) => Block::Paragraph(inlines(content)),
+                    Block::List(list) => Block::List(List {
+                        items: list
+                            .items
+                            .iter()
+                            .map(|item| ListItem {
+                                checked: item.checked,
+                                content: inlines(&item.content),
+                                children: blocks(&item.children),
+                            })
+                            .collect(),
+                        ..list.clone()
+                    }),
+                    Block::Table(table) => Block::Table(Table {
+                        alignments: table.alignments.clone(),
+                        header: table.header.iter().map(|cell| inlines(cell)).collect(),
+                        rows: table
+                            .rows
+                            .iter()
+                            .map(|row| row.iter().map(|cell| inlines(cell)).collect())
+                            .collect(),
+                    }),
+                    Block::Blockquote(children) => Block::Blockquote(blocks(children)),
+                    block => block.clone(),
+                })
+                .collect()
+        }
+
+        Document {
+            blocks: blocks(&document.blocks),
+        }
+    }
+
+    fn assert_stable(input: &str, width: Option<usize>) {
+        let parser = MarkdownParser::new();
+        let formatter = MarkdownFormatter::new(width);
+        let formatted = formatter.format(input);
+
+        assert_eq!(formatter.format(&formatted), formatted);
+        assert_eq!(
+            normalized(&parser.parse_document(&formatted)),
+            normalized(&parser.parse_document(input))
+        );
+    }
+
+    #[test]
+    fn test_canonical_style() {
+        let input = "Title ##\n\
+                     * one\n\
+                     * two\n   continued\n\n\
+                     3. first\n\
+                     7. second\n\n\
+                     |a|Longer|\n\
+                     |:-:|--:|\n\
+                     |cell|x|\n\n\
+                     ````rust\nfn main() {}\n````\n\n\
+                     ***";
+        let input = format!("# {}", input);
+
+        assert_eq!(
+            MarkdownFormatter::default().format(&input),
+            "# Title\n\n\
+             - one\n\
+             - two continued\n\n\
+             3. first\n\
+             4. second\n\n\
+             |  a   | Longer |\n\
+             | :--: | -----: |\n\
+             | cell |      x |\n\n\
+             ```rust\nfn main() {}\n```\n\n\
+             ---\n"
+        );
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let input = "A paragraph that is long enough to need wrapping somewhere.\n\n\
+                     - An item that wraps onto an indented continuation line.";
+        let formatted = MarkdownFormatter::new(Some(24)).format(input);
+
+        assert_eq!(
+            formatted,
+            "A paragraph that is long\nenough to need wrapping\nsomewhere.\n\n\
+             - An item that wraps\n  onto an indented\n  continuation line.\n"
+        );
+        assert_eq!(
+            MarkdownFormatter::default().format(&formatted),
+            MarkdownFormatter::default().format(input)
+        );
+    }
+
+    #[test]
+    fn test_escapes_block_syntax() {
+        let input = "Totals for 2024. # - > `a`\\|b* [x]";
+        let formatted = MarkdownFormatter::new(Some(1)).format(input);
+
+        assert_eq!(
+            formatted,
+            "Totals\nfor\n2024\\.\n#\n\\-\n\\>\n`a`|b\\*\n\\[x\\]\n"
+        );
+        assert_stable(input, Some(1));
+    }
+
+    #[test]
+    fn test_example_round_trips() {
+        let example = include_str!("../example.md");
+        for width in [None, Some(40), Some(80)] {
+            assert_stable(example, width);
+        }
+    }
+
+    #[test]
+    fn test_keeps_front_matter() {
+        let input = "---\ntitle: Doc\nauthor:\n  - Me\n---\n* item\n";
+        assert_eq!(
+            MarkdownFormatter::default().format(input),
+            "---\ntitle: Doc\nauthor:\n  - Me\n---\n\n- item\n"
+        );
+        assert_stable(input, None);
+    }
+
+    #[test]
+    fn test_format_file_check() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "* item").unwrap();
+        let formatter = MarkdownFormatter::default();
+
+        assert!(formatter.format_file(file.path(), true).unwrap());
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "* item");
+        assert!(formatter.format_file(file.path(), false).unwrap());
+        assert!(!formatter.format_file(file.path(), true).unwrap());
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "- item\n");
+    }
+
+    #[test]
+    fn test_keeps_unmodelled_syntax() {
+        for input in [
+            "Setext heading\n==============\n",
+            "Setext heading\n--------------\n",
+            "A [link](https://example.org \"Title\") and ![image](a.png 'Alt').\n",
+            "    indented code\n    more\n",
+            "1) one\n2) two\n",
+            "A [ref][r], [R] and [r][].\n\n[r]: https://example.org/r \"Ref\"\n",
+            "<div class=\"note\">\n  <p>*raw*</p>\n</div>\n",
+            "Text with <span title=\"a_b\">*inline*</span> html.\n",
+            "<!-- a comment -->\n",
+        ] {
+            assert_eq!(MarkdownFormatter::default().format(input), input);
+        }
+    }
+
+    #[test]
+    fn test_formats_around_unmodelled_syntax() {
+        let input = "* item\n\nTitle\n---\n* after\n\n    code\n\n[x](y \"t\")\n***";
+        assert_eq!(
+            MarkdownFormatter::default().format(input),
+            "- item\n\nTitle\n---\n\n- after\n\n    code\n\n[x](y \"t\")\n\n---\n"
+        );
+        assert_stable(input, Some(20));
+    }
+
+    fn inline_markdown() -> impl Strategy<Value = String> {
+        let word = "[a-z0-9#>|.!$\\-]{1,6}";
+        let span = prop_oneof![
+            word.prop_map(|w| w.to_string()),
+            "[*_~`\\\\\\[\\]]".prop_map(|w| w.to_string()),
+            word.prop_map(|w| format!("*{}*", w)),
+            (word, word).prop_map(|(a, b)| format!("**{} {}**", a, b)),
+            word.prop_map(|w| format!("~~{}~~", w)),
+            "[a-z `]{1,6}".prop_map(|c| format!("`{}`", c)),
+            "[a-z+ ]{1,6}".prop_map(|m| format!("${}$", m.trim())),
+            (word, "[a-z/:.]{0,8}").prop_map(|(t, u)| format!("[{}]({})", t, u)),
+            "[a-z ]{0,6}".prop_map(|alt| format!("![{}](img.png)", alt)),
+        ];
+        prop::collection::vec(
+            (span, prop_oneof![Just(" "), Just("\n"), Just("  \n")]),
+            1..12,
+        )
+        .prop_map(|spans| {
+            let text: String = spans.into_iter().map(|(s, sep)| s + sep).collect();
+            text.trim().to_string()
+        })
+    }
+
+    fn markdown_document() -> impl Strategy<Value = String> {
+        let block = prop_oneof![
+            inline_markdown(),
+            (1..=6usize, inline_markdown()).prop_map(|(level, text)| format!(
+                "{} {}",
+                "#".repeat(level),
+                text.replace('\n', " ")
+            )),
+            prop::collection::vec((any::<bool>(), inline_markdown()), 1..4).prop_map(|items| {
+                items
+                    .into_iter()
+                    .map(|(nested, text)| {
+                        let text = text.replace('\n', "\n  ");
+                        if nested {
+                            format!("- {}\n  1. nested", text)
+                        } else {
+                            format!("- {}", text)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }),
+            (inline_markdown(), inline_markdown()).prop_map(|(a, b)| format!(
+                "| {} | x |\n|:--|--:|\n| {} |",
+                a.replace('\n', " "),
+                b.replace('\n', " ")
+            )),
+            inline_markdown().prop_map(|text| format!("> {}", text.replace('\n', "\n> "))),
+            "[a-z ;{}\n]{0,30}".prop_map(|code| format!("```rust\n{}\n```", code)),
+            "[a-z+=^ ]{1,10}".prop_map(|math| format!("$$\n{}\n$$", math)),
+            Just("---".to_string()),
+        ];
+        prop::collection::vec(block, 1..6).prop_map(|blocks| blocks.join("\n\n"))
+    }
+
+    proptest! {
+        #[test]
+        fn formatting_is_stable(
+            input in markdown_document(),
+            width in prop_oneof![Just(None), (8..60usize).prop_map(Some)],
+        ) {
+            assert_stable(&input, width);
+        }
+    }
+}
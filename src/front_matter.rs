@@ -0,0 +1,224 @@
+use std::fmt;
+
+/// Metadata from a YAML-style block at the very start of a Markdown file:
+///
+/// ```text
+/// ---
+/// title: Field Handbook
+/// author:
+///   - A. Writer
+///   - B. Editor
+/// ---
+/// ```
+///
+/// Only the simple forms are understood: `key: value`, `key: [a, b]`, and a
+/// `key:` followed by `- item` lines. Keys are compared case-insensitively.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrontMatter {
+    entries: Vec<(String, Vec<String>)>,
+}
+
+impl FrontMatter {
+    /// Splits the front matter off `markdown`, returning it and the rest of
+    /// the document. Without a closed `---` block, the front matter is empty
+    /// and the document is returned whole.
+    pub fn split(markdown: &str) -> (FrontMatter, &str) {
+        let mut lines = markdown.split_inclusive('\n');
+        if lines.next().map(str::trim_end) != Some("---") {
+            return (FrontMatter::default(), markdown);
+        }
+
+        let mut front_matter = FrontMatter::default();
+        let mut offset = markdown.find('\n').map_or(markdown.len(), |i| i + 1);
+        for line in lines {
+            offset += line.len();
+            let line = line.trim_end();
+            if line == "---" || line == "..." {
+                return (front_matter, &markdown[offset..]);
+            }
+            front_matter.parse_line(line);
+        }
+        (FrontMatter::default(), markdown)
+    }
+
+    fn parse_line(&mut self, line: &str) {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return;
+        }
+
+        if let Some(item) = trimmed.strip_prefix("- ") {
+            if let Some((_, values)) = self.entries.last_mut() {
+                values.push(unquote(item).to_string());
+            }
+            return;
+        }
+
+        let Some((key, value)) = trimmed.split_once(':') else {
+            return;
+        };
+        let value = value.trim();
+        let values = match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            Some(list) => split_list(list)
+                .map(|item| unquote(item.trim()).to_string())
+                .filter(|item| !item.is_empty())
+                .collect(),
+            None if value.is_empty() => Vec::new(),
+            None => vec![unquote(value).to_string()],
+        };
+        self.entries.push((key.trim().to_lowercase(), values));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The first value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).first().map(String::as_str)
+    }
+
+    /// Every value of `key`, e.g. each author in a list.
+    pub fn get_all(&self, key: &str) -> &[String] {
+        self.entries
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map_or(&[], |(_, values)| values)
+    }
+
+    /// Sets `key` to `values`, replacing any values it had.
+    pub fn insert(&mut self, key: &str, values: Vec<String>) {
+        let key = key.to_lowercase();
+        match self.entries.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => *existing = values,
+            None => self.entries.push((key, values)),
+        }
+    }
+
+    /// Every key with its values, in the order they were written.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.entries
+            .iter()
+            .map(|(key, values)| (key.as_str(), values.as_slice()))
+    }
+
+    /// Adds the keys of `other` that are not set here.
+    pub fn merge(&mut self, other: FrontMatter) {
+        for (key, values) in other.entries {
+            if self.get_all(&key).is_empty() {
+                self.entries.push((key, values));
+            }
+        }
+    }
+}
+
+/// Writes the `---` block back out, lists in `[a, b]` form. Empty front
+/// matter writes nothing.
+impl fmt::Display for FrontMatter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, "---")?;
+        for (key, values) in &self.entries {
+            match values.as_slice() {
+                [value] => writeln!(f, "{}: {}", key, quote(value, false))?,
+                values => {
+                    let values: Vec<String> = values.iter().map(|v| quote(v, true)).collect();
+                    writeln!(f, "{}: [{}]", key, values.join(", "))?;
+                }
+            }
+        }
+        writeln!(f, "---")
+    }
+}
+
+/// `value`, quoted if it would otherwise be read back differently.
+fn quote(value: &str, in_list: bool) -> String {
+    let needs_quotes = value.is_empty()
+        || value != value.trim()
+        || value.starts_with(['"', '\'', '[', '#', '-'])
+        || (in_list && value.contains([',', ']']));
+    if !needs_quotes {
+        value.to_string()
+    } else if value.contains('"') {
+        format!("'{}'", value)
+    } else {
+        format!("\"{}\"", value)
+    }
+}
+
+/// Splits the inside of a `[a, b]` list at the commas outside quotes.
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    let mut quote = None;
+    let mut start = 0;
+    let mut items = Vec::new();
+    for (i, c) in list.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, ',') => {
+                items.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&list[start..]);
+    items.into_iter()
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let markdown = "---\ntitle: \"Field: Handbook\"\nauthor:\n  - A. Writer\n  - B. Editor\ntags: [a, 'b']\n---\n# Body\n";
+        let (front_matter, body) = FrontMatter::split(markdown);
+
+        assert_eq!(body, "# Body\n");
+        assert_eq!(front_matter.get("Title"), Some("Field: Handbook"));
+        assert_eq!(front_matter.get_all("author"), ["A. Writer", "B. Editor"]);
+        assert_eq!(front_matter.get_all("tags"), ["a", "b"]);
+        assert_eq!(front_matter.get("missing"), None);
+    }
+
+    #[test]
+    fn test_without_front_matter() {
+        for markdown in ["# Title\n\n---\n", "---\nnot closed\n", "--- \n"] {
+            let (front_matter, body) = FrontMatter::split(markdown);
+            assert!(front_matter.is_empty());
+            assert_eq!(body, markdown);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let mut front_matter = FrontMatter::default();
+        front_matter.insert("Title", vec!["#1: \"Intro\"".to_string()]);
+        front_matter.insert("tags", vec!["a, b".to_string(), "c".to_string()]);
+        let written = front_matter.to_string();
+
+        assert_eq!(
+            written,
+            "---\ntitle: '#1: \"Intro\"'\ntags: [\"a, b\", c]\n---\n"
+        );
+        let read = FrontMatter::split(&written).0;
+        assert_eq!(read.get("title"), Some("#1: \"Intro\""));
+        assert_eq!(read.get_all("tags"), ["a, b", "c"]);
+        assert_eq!(FrontMatter::default().to_string(), "");
+    }
+}
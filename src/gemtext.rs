@@ -0,0 +1,286 @@
+use crate::ast::{
+    plain_text, Alignment, Block, CodeBlock, Document, Inline, List, ListItem, MathBlock, Table,
+};
+use crate::render::Renderer;
+use crate::text::{display_width, push_border, push_row};
+use std::cell::RefCell;
+use std::io::{self, Write};
+
+/// Renders a document as Gemtext, the line-based markup of the Gemini
+/// protocol.
+///
+/// Gemtext has no inline markup and no nesting, so styles are dropped,
+/// nested lists are flattened into one level of `*` items, and the links
+/// of each line are listed after it as `=>` lines of their own. Tables are
+/// drawn with box characters in a preformatted block.
+#[derive(Default)]
+pub struct GemtextRenderer {
+    /// Links in the line being rendered, as (URL, label).
+    links: RefCell<Vec<(String, String)>>,
+}
+
+impl GemtextRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Renders `content` as one line, followed by its links.
+    fn line(&self, prefix: &str, content: &[Inline], out: &mut String) {
+        let mut text = String::new();
+        self.inlines(content, &mut text);
+        for (i, line) in text.split('\n').enumerate() {
+            // A line break inside a list item or quote starts another of
+            // the same kind.
+            if i == 0 || !prefix.starts_with('#') {
+                out.push_str(prefix);
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+        self.push_links(out);
+    }
+
+    fn push_links(&self, out: &mut String) {
+        for (url, label) in self.links.take() {
+            out.push_str("=> ");
+            out.push_str(&url);
+            if !label.is_empty() && label != url {
+                out.push(' ');
+                out.push_str(&label);
+            }
+            out.push('\n');
+        }
+    }
+
+    fn preformatted(&self, alt: &str, text: &str, out: &mut String) {
+        out.push_str("```");
+        out.push_str(alt);
+        out.push('\n');
+        out.push_str(text);
+        if !text.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str("```\n");
+    }
+}
+
+impl Renderer for GemtextRenderer {
+    fn render(&self, document: &Document) -> String {
+        let mut out = String::new();
+        self.blocks(&document.blocks, &mut out);
+        out
+    }
+
+    fn render_to(&self, document: &Document, writer: &mut dyn Write) -> io::Result<()> {
+        for (i, block) in document.blocks.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b"\n")?;
+            }
+            self.render_block_to(block, writer)?;
+        }
+        Ok(())
+    }
+
+    fn blocks(&self, blocks: &[Block], out: &mut String) {
+        for (i, block) in blocks.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            self.block(block, out);
+        }
+    }
+
+    fn heading(&self, level: u8, content: &[Inline], out: &mut String) {
+        // Gemtext has three levels of heading.
+        let prefix = format!("{} ", "#".repeat(usize::from(level.min(3))));
+        self.line(&prefix, content, out);
+    }
+
+    fn paragraph(&self, content: &[Inline], out: &mut String) {
+        self.line("", content, out);
+    }
+
+    fn list(&self, list: &List, out: &mut String) {
+        for (number, item) in (list.start..).zip(&list.items) {
+            let prefix = match item.checked {
+                Some(true) => "* [x] ".to_string(),
+                Some(false) => "* [ ] ".to_string(),
+                None if list.ordered => format!("* {}. ", number),
+                None => "* ".to_string(),
+            };
+            self.line(&prefix, &item.content, out);
+            for child in &item.children {
+                match child {
+                    Block::List(list) => self.list(list, out),
+                    child => self.block(child, out),
+                }
+            }
+        }
+    }
+
+    fn list_item(&self, item: &ListItem, out: &mut String) {
+        self.line("* ", &item.content, out);
+        for child in &item.children {
+            self.block(child, out);
+        }
+    }
+
+    fn code_block(&self, code_block: &CodeBlock, out: &mut String) {
+        self.preformatted(
+            code_block.language().unwrap_or_default(),
+            &code_block.code,
+            out,
+        );
+    }
+
+    fn table(&self, table: &Table, out: &mut String) {
+        let render_row = |row: &[Vec<Inline>]| -> Vec<String> {
+            row.iter()
+                .map(|cell| {
+                    let mut text = String::new();
+                    self.inlines(cell, &mut text);
+                    text.replace('\n', " ")
+                })
+                .collect()
+        };
+        let header = render_row(&table.header);
+        let rows: Vec<Vec<String>> = table.rows.iter().map(|row| render_row(row)).collect();
+
+        let mut widths = Vec::new();
+        for row in std::iter::once(&header).chain(&rows) {
+            for (i, cell) in row.iter().enumerate() {
+                if widths.len() <= i {
+                    widths.push(0);
+                }
+                widths[i] = widths[i].max(display_width(cell));
+            }
+        }
+        let alignments: Vec<Alignment> = (0..widths.len())
+            .map(|i| table.alignments.get(i).copied().unwrap_or(Alignment::None))
+            .collect();
+
+        let mut drawn = String::new();
+        push_border(&widths, ['┌', '┬', '┐'], &mut drawn);
+        push_row(
+            &header,
+            &widths,
+            &alignments,
+            ["│ ", " │ ", " │"],
+            &mut drawn,
+        );
+        push_border(&widths, ['├', '┼', '┤'], &mut drawn);
+        for row in &rows {
+            push_row(row, &widths, &alignments, ["│ ", " │ ", " │"], &mut drawn);
+        }
+        push_border(&widths, ['└', '┴', '┘'], &mut drawn);
+        self.preformatted("table", &drawn, out);
+        self.push_links(out);
+    }
+
+    fn blockquote(&self, blocks: &[Block], out: &mut String) {
+        for block in blocks {
+            match block {
+                Block::Paragraph(content) => self.line("> ", content, out),
+                block => self.block(block, out),
+            }
+        }
+    }
+
+    fn math_block(&self, math_block: &MathBlock, out: &mut String) {
+        self.preformatted("math", &math_block.math, out);
+    }
+
+    fn horizontal_rule(&self, out: &mut String) {
+        out.push_str("---\n");
+    }
+
+    fn text(&self, text: &str, out: &mut String) {
+        out.push_str(text);
+    }
+
+    fn code(&self, code: &str, out: &mut String) {
+        out.push_str(code);
+    }
+
+    fn math(&self, math: &str, out: &mut String) {
+        out.push_str(math);
+    }
+
+    fn strong(&self, children: &[Inline], out: &mut String) {
+        self.inlines(children, out);
+    }
+
+    fn emphasis(&self, children: &[Inline], out: &mut String) {
+        self.inlines(children, out);
+    }
+
+    fn strikethrough(&self, children: &[Inline], out: &mut String) {
+        self.inlines(children, out);
+    }
+
+    fn link(&self, url: &str, content: &[Inline], out: &mut String) {
+        self.inlines(content, out);
+        // Links within the page have nowhere to go.
+        if !url.starts_with('#') {
+            self.links
+                .borrow_mut()
+                .push((url.to_string(), plain_text(content)));
+        }
+    }
+
+    fn image(&self, url: &str, alt: &str, out: &mut String) {
+        out.push_str(alt);
+        self.links
+            .borrow_mut()
+            .push((url.to_string(), alt.to_string()));
+    }
+
+    fn soft_break(&self, out: &mut String) {
+        out.push(' ');
+    }
+
+    fn line_break(&self, out: &mut String) {
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::MarkdownParser;
+    use pretty_assertions::assert_eq;
+
+    fn render(markdown: &str) -> String {
+        GemtextRenderer::new().render(&MarkdownParser::new().parse_document(markdown))
+    }
+
+    #[test]
+    fn test_links_and_lists() {
+        assert_eq!(
+            render(
+                "#### Deep **title**\n\nRead [the docs](gemini://example.org/docs) and\n<https://example.org>.\n\n\
+                 - one\n  1. nested [x](/x)\n- [x] done\n\n> quoted\n> ![a cat](cat.png)"
+            ),
+            "### Deep title\n\n\
+             Read the docs and <https://example.org>.\n\
+             => gemini://example.org/docs the docs\n\n\
+             * one\n* 1. nested x\n=> /x x\n* [x] done\n\n\
+             > quoted a cat\n=> cat.png a cat\n"
+        );
+    }
+
+    #[test]
+    fn test_preformatted() {
+        assert_eq!(
+            render("```rust\nfn main() {}\n```\n\n| a | b |\n|---|--:|\n| x | 10 |"),
+            "```rust\nfn main() {}\n```\n\n\
+             ```table\n\
+             ┌───┬────┐\n\
+             │ a │  b │\n\
+             ├───┼────┤\n\
+             │ x │ 10 │\n\
+             └───┴────┘\n\
+             ```\n"
+        );
+    }
+}
@@ -0,0 +1,979 @@
+use crate::ast::{
+    plain_text, Alignment, Block, CodeBlock, Document, Inline, List, ListItem, MathBlock, Table,
+};
+use crate::crossref::Equations;
+use crate::error::ConverterError;
+use crate::mathml;
+use crate::render::Renderer;
+use lazy_static::lazy_static;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{
+    css_for_theme_with_class_style, highlighted_html_for_string, ClassStyle, ClassedHTMLGenerator,
+};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+lazy_static! {
+    // Loaded once, since loading takes a while and watch mode converts
+    // again and again.
+    static ref SYNTAX_SET: Arc<SyntaxSet> = Arc::new(SyntaxSet::load_defaults_newlines());
+    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
+    static ref DEFAULT_HIGHLIGHTER: Highlighter = Highlighter {
+        syntax_set: SYNTAX_SET.clone(),
+        theme: Arc::new(THEME_SET.themes[DEFAULT_THEME].clone()),
+        dark_theme: None,
+        classes: false,
+    };
+}
+
+/// The theme code is highlighted with unless another is chosen.
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// Class names of highlighted tokens start with this, e.g. `hl-keyword`.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Languages often named in info strings by something other than the
+/// names and file extensions syntect knows them by.
+const LANGUAGE_ALIASES: &[(&str, &str)] = &[
+    ("objc", "Objective-C"),
+    ("objective-cpp", "Objective-C++"),
+    ("csharp", "C#"),
+    ("golang", "Go"),
+    ("python3", "Python"),
+    ("shell", "Bourne Again Shell (bash)"),
+    ("zsh", "Bourne Again Shell (bash)"),
+];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HtmlOptions {
+    /// Highlight fenced code with syntect instead of emitting plain `<pre><code>`.
+    pub syntax_highlight: bool,
+    /// Give every heading an `id` so a table of contents can link to it.
+    pub heading_ids: bool,
+    /// Leave math as TeX for KaTeX to typeset in the browser, instead of
+    /// writing MathML.
+    pub katex: bool,
+}
+
+/// How code is highlighted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HighlightOptions {
+    /// The name of a bundled syntect theme, or of a `.tmTheme` file in
+    /// `dir` [default: [`DEFAULT_THEME`]].
+    pub theme: Option<String>,
+    /// A theme for readers who prefer dark colours, switched to by
+    /// [`Highlighter::stylesheet`] with a media query. Only used with
+    /// `classes`.
+    pub dark_theme: Option<String>,
+    /// A directory of `.tmTheme` themes and `.sublime-syntax` definitions
+    /// to load as well as the bundled ones.
+    pub dir: Option<PathBuf>,
+    /// Mark up tokens with CSS classes, coloured by
+    /// [`Highlighter::stylesheet`], instead of inline styles.
+    pub classes: bool,
+}
+
+/// The syntaxes and colour themes syntect highlights code with. Building
+/// one with a [`HighlightOptions::dir`] reads the directory, so keep it
+/// rather than build it again; clones share the loaded sets.
+#[derive(Clone)]
+pub struct Highlighter {
+    pub(crate) syntax_set: Arc<SyntaxSet>,
+    pub(crate) theme: Arc<Theme>,
+    dark_theme: Option<Arc<Theme>>,
+    classes: bool,
+}
+
+impl Highlighter {
+    pub fn new(options: &HighlightOptions) -> Result<Self, ConverterError> {
+        let dir_error = |dir: &PathBuf, e: &dyn std::fmt::Display| {
+            ConverterError::HighlightError(format!("{}: {}", dir.display(), e))
+        };
+        let (syntax_set, dir_themes) = match &options.dir {
+            Some(dir) => {
+                let mut builder = SyntaxSet::clone(&SYNTAX_SET).into_builder();
+                builder
+                    .add_from_folder(dir, true)
+                    .map_err(|e| dir_error(dir, &e))?;
+                let themes = ThemeSet::load_from_folder(dir).map_err(|e| dir_error(dir, &e))?;
+                (Arc::new(builder.build()), themes)
+            }
+            None => (SYNTAX_SET.clone(), ThemeSet::new()),
+        };
+        let dark_theme = options
+            .dark_theme
+            .as_deref()
+            .map(|name| find_theme(&dir_themes, name))
+            .transpose()?;
+        Ok(Self {
+            syntax_set,
+            theme: find_theme(
+                &dir_themes,
+                options.theme.as_deref().unwrap_or(DEFAULT_THEME),
+            )?,
+            dark_theme,
+            classes: options.classes,
+        })
+    }
+
+    /// The syntax for code in `language`, as named in an info string: by
+    /// the syntax's name or a file extension, or by a common alias such as
+    /// `objc`.
+    pub fn find_syntax(&self, language: &str) -> Option<&SyntaxReference> {
+        self.syntax_set.find_syntax_by_token(language).or_else(|| {
+            let (_, name) = LANGUAGE_ALIASES
+                .iter()
+                .find(|(alias, _)| alias.eq_ignore_ascii_case(language))?;
+            self.syntax_set.find_syntax_by_name(name)
+        })
+    }
+
+    /// The code of `code_block` as highlighted HTML, if its language is
+    /// known.
+    pub fn html(&self, code_block: &CodeBlock) -> Option<String> {
+        let syntax = self.find_syntax(code_block.language()?)?;
+        if !self.classes {
+            return highlighted_html_for_string(
+                &code_block.code,
+                &self.syntax_set,
+                syntax,
+                &self.theme,
+            )
+            .ok();
+        }
+        let mut generator =
+            ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntax_set, CLASS_STYLE);
+        for line in LinesWithEndings::from(&code_block.code) {
+            generator
+                .parse_html_for_line_which_includes_newline(line)
+                .ok()?;
+        }
+        Some(format!(
+            "<pre class=\"hl-code\">\n{}</pre>\n",
+            generator.finalize()
+        ))
+    }
+
+    /// The stylesheet colouring code highlighted with
+    /// [`HighlightOptions::classes`]: the theme's colours, and the dark
+    /// theme's for readers who prefer it.
+    pub fn stylesheet(&self) -> Result<String, ConverterError> {
+        let css = |theme: &Theme| {
+            css_for_theme_with_class_style(theme, CLASS_STYLE)
+                .map_err(|e| ConverterError::HighlightError(e.to_string()))
+        };
+        let mut stylesheet = css(&self.theme)?;
+        if let Some(dark_theme) = &self.dark_theme {
+            stylesheet.push_str("@media (prefers-color-scheme: dark) {\n");
+            stylesheet.push_str(&css(dark_theme)?);
+            stylesheet.push_str("}\n");
+        }
+        Ok(stylesheet)
+    }
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        DEFAULT_HIGHLIGHTER.clone()
+    }
+}
+
+/// The theme called `name` among those in `dir_themes` or else the bundled
+/// ones.
+fn find_theme(dir_themes: &ThemeSet, name: &str) -> Result<Arc<Theme>, ConverterError> {
+    dir_themes
+        .themes
+        .get(name)
+        .or_else(|| THEME_SET.themes.get(name))
+        .map(|theme| Arc::new(theme.clone()))
+        .ok_or_else(|| {
+            let names: BTreeSet<&str> = dir_themes
+                .themes
+                .keys()
+                .chain(THEME_SET.themes.keys())
+                .map(String::as_str)
+                .collect();
+            ConverterError::HighlightError(format!(
+                "no theme named \"{}\"; the themes are {}",
+                name,
+                names.into_iter().collect::<Vec<_>>().join(", ")
+            ))
+        })
+}
+
+/// The default [`Renderer`]: the standard HTML, plus heading anchors and
+/// syntect highlighting when enabled. Math is written as MathML.
+#[derive(Default)]
+pub struct HtmlRenderer {
+    options: HtmlOptions,
+    highlighter: Option<Highlighter>,
+    equations: Equations,
+    /// The anchors given so far, when headings are given them.
+    heading_ids: RefCell<HeadingIds>,
+}
+
+impl HtmlRenderer {
+    pub fn new(options: HtmlOptions) -> Self {
+        Self {
+            options,
+            highlighter: options.syntax_highlight.then(Highlighter::default),
+            equations: Equations::default(),
+            heading_ids: RefCell::default(),
+        }
+    }
+
+    /// Highlights code with `highlighter` instead of the default theme.
+    pub fn with_highlighter(mut self, highlighter: Highlighter) -> Self {
+        self.highlighter = Some(highlighter);
+        self
+    }
+
+    /// Numbers the labelled display equations as in `equations`.
+    pub fn with_equations(mut self, equations: Equations) -> Self {
+        self.equations = equations;
+        self
+    }
+
+    /// Forgets the anchors given so far, so that the next document rendered
+    /// can use them again.
+    pub fn reset_heading_ids(&self) {
+        self.heading_ids.take();
+    }
+
+    /// Builds a nested list linking to every top-level heading. The links only
+    /// resolve when rendering with [`HtmlOptions::heading_ids`].
+    pub fn table_of_contents(&self, document: &Document) -> String {
+        let entries: Vec<TocEntry> = heading_anchors(document)
+            .into_iter()
+            .map(|(level, content, id)| TocEntry {
+                level,
+                href: format!("#{}", id),
+                title: plain_text(content),
+            })
+            .collect();
+
+        let mut toc =
+            String::from("<div class=\"table-of-contents\">\n<h2>Table of Contents</h2>\n");
+        toc.push_str(&toc_list("ul", &entries));
+        toc.push_str("</div>\n");
+        toc
+    }
+}
+
+/// A link in a table of contents.
+pub struct TocEntry {
+    pub level: u8,
+    pub href: String,
+    pub title: String,
+}
+
+/// Nests `entries` into `<ul>` or `<ol>` lists (`tag`) by heading level, each
+/// sub-list inside the item before it.
+pub fn toc_list(tag: &str, entries: &[TocEntry]) -> String {
+    let mut out = format!("<{}>\n", tag);
+    // Levels of the lists currently open; the innermost is last.
+    let mut levels: Vec<u8> = Vec::new();
+
+    for entry in entries {
+        match levels.last() {
+            None => levels.push(entry.level),
+            Some(&level) if entry.level > level => {
+                out.push_str(&format!("\n<{}>\n", tag));
+                levels.push(entry.level);
+            }
+            Some(_) => {
+                out.push_str("</li>\n");
+                while levels.len() > 1 && entry.level < levels[levels.len() - 1] {
+                    out.push_str(&format!("</{}>\n</li>\n", tag));
+                    levels.pop();
+                }
+            }
+        }
+
+        out.push_str("<li><a href=\"");
+        push_attribute(&mut out, &entry.href);
+        out.push_str("\">");
+        push_text(&mut out, &entry.title);
+        out.push_str("</a>");
+    }
+
+    if !levels.is_empty() {
+        out.push_str("</li>\n");
+    }
+    for _ in 1..levels.len() {
+        out.push_str(&format!("</{}>\n</li>\n", tag));
+    }
+    out.push_str(&format!("</{}>\n", tag));
+    out
+}
+
+impl Renderer for HtmlRenderer {
+    fn heading_id(&self, content: &[Inline]) -> Option<String> {
+        self.options
+            .heading_ids
+            .then(|| self.heading_ids.borrow_mut().next(content))
+    }
+
+    fn highlight(&self, code_block: &CodeBlock) -> Option<String> {
+        self.highlighter.as_ref()?.html(code_block)
+    }
+
+    fn typeset_math(&self, math: &str, display: bool) -> Option<String> {
+        (!self.options.katex).then(|| mathml::typeset(math, display))
+    }
+
+    fn equation_number(&self, label: &str) -> Option<usize> {
+        self.equations.number(label)
+    }
+}
+
+// The standard HTML for each node. These back the provided methods of
+// [`Renderer`], and overriding implementations can call them to wrap or
+// extend the default markup.
+
+pub fn heading<R: Renderer + ?Sized>(
+    renderer: &R,
+    level: u8,
+    content: &[Inline],
+    out: &mut String,
+) {
+    out.push_str("<h");
+    out.push(char::from(b'0' + level));
+    if let Some(id) = renderer.heading_id(content) {
+        out.push_str(" id=\"");
+        push_attribute(out, &id);
+        out.push('"');
+    }
+    out.push('>');
+    renderer.inlines(content, out);
+    out.push_str("</h");
+    out.push(char::from(b'0' + level));
+    out.push_str(">\n");
+}
+
+pub fn paragraph<R: Renderer + ?Sized>(renderer: &R, content: &[Inline], out: &mut String) {
+    out.push_str("<p>");
+    renderer.inlines(content, out);
+    out.push_str("</p>\n");
+}
+
+pub fn list<R: Renderer + ?Sized>(renderer: &R, list: &List, out: &mut String) {
+    let tag = if list.ordered { "ol" } else { "ul" };
+    out.push('<');
+    out.push_str(tag);
+    if list.start != 1 {
+        out.push_str(&format!(" start=\"{}\"", list.start));
+    }
+    out.push_str(">\n");
+
+    for item in &list.items {
+        renderer.list_item(item, out);
+    }
+
+    out.push_str("</");
+    out.push_str(tag);
+    out.push_str(">\n");
+}
+
+pub fn list_item<R: Renderer + ?Sized>(renderer: &R, item: &ListItem, out: &mut String) {
+    out.push_str("<li>");
+    if let Some(checked) = item.checked {
+        out.push_str(r#"<input type="checkbox" disabled"#);
+        if checked {
+            out.push_str(" checked");
+        }
+        out.push_str("> ");
+    }
+    renderer.inlines(&item.content, out);
+    if !item.children.is_empty() {
+        out.push('\n');
+        renderer.blocks(&item.children, out);
+    }
+    out.push_str("</li>\n");
+}
+
+/// Writes a code block, highlighted or not. The info-string options
+/// `title="..."` add a caption, `linenos` numbers the lines from `start`
+/// (1 unless given), and `hl_lines="2 4-6"` marks lines, counted from 1
+/// whatever the numbers start at.
+pub fn code_block<R: Renderer + ?Sized>(renderer: &R, code_block: &CodeBlock, out: &mut String) {
+    let title = code_block.option("title");
+    if let Some(title) = title {
+        out.push_str("<figure class=\"code-block\">\n<figcaption class=\"code-title\">");
+        push_text(out, title);
+        out.push_str("</figcaption>\n");
+    }
+
+    let lines = CodeLines::new(code_block);
+    match renderer.highlight(code_block) {
+        Some(highlighted) => match (&lines, split_pre(&highlighted)) {
+            (Some(lines), Some((pre, inner))) => {
+                out.push_str(pre);
+                lines.push(out, &highlighted_lines(inner));
+                out.push_str("</pre>\n");
+            }
+            _ => out.push_str(&highlighted),
+        },
+        None => {
+            match code_block.language() {
+                Some(language) => {
+                    out.push_str(r#"<pre><code class="language-"#);
+                    push_attribute(out, language);
+                    out.push_str(r#"">"#);
+                }
+                None => out.push_str("<pre><code>"),
+            }
+            match &lines {
+                Some(lines) => {
+                    let code = &code_block.code;
+                    let code_lines: Vec<String> = code
+                        .strip_suffix('\n')
+                        .unwrap_or(code)
+                        .split('\n')
+                        .map(|line| {
+                            let mut escaped = String::new();
+                            push_code(&mut escaped, line);
+                            escaped
+                        })
+                        .collect();
+                    lines.push(out, &code_lines);
+                }
+                None => push_code(out, &code_block.code),
+            }
+            out.push_str("</code></pre>\n");
+        }
+    }
+
+    if title.is_some() {
+        out.push_str("</figure>\n");
+    }
+}
+
+/// How the lines of a code block are numbered and marked.
+struct CodeLines {
+    numbers: bool,
+    start: u64,
+    marked: Vec<(u64, u64)>,
+}
+
+impl CodeLines {
+    /// The line options of `code_block`, or `None` if it has none and its
+    /// lines are written as they are.
+    fn new(code_block: &CodeBlock) -> Option<Self> {
+        let numbers = code_block.option("linenos").is_some();
+        let marked: Vec<(u64, u64)> = code_block
+            .option("hl_lines")
+            .unwrap_or_default()
+            .split([' ', ','])
+            .filter_map(|range| match range.split_once('-') {
+                Some((first, last)) => Some((first.parse().ok()?, last.parse().ok()?)),
+                None => range.parse().ok().map(|line| (line, line)),
+            })
+            .collect();
+        if !numbers && marked.is_empty() {
+            return None;
+        }
+        Some(Self {
+            numbers,
+            start: code_block
+                .option("start")
+                .and_then(|start| start.parse().ok())
+                .unwrap_or(1),
+            marked,
+        })
+    }
+
+    /// Writes each line of markup in `lines` in an element of its own: a
+    /// `<mark>` for a marked line, and a `<span>` for any other. Numbers
+    /// are hidden from screen readers and left out of copied text.
+    fn push(&self, out: &mut String, lines: &[String]) {
+        for ((index, number), line) in (1..).zip(self.start..).zip(lines) {
+            let marked = self
+                .marked
+                .iter()
+                .any(|&(first, last)| (first..=last).contains(&index));
+            let tag = if marked { "mark" } else { "span" };
+            out.push_str(&format!("<{} class=\"line\">", tag));
+            if self.numbers {
+                out.push_str(&format!(
+                    "<span class=\"line-number\" aria-hidden=\"true\">{}</span>",
+                    number
+                ));
+            }
+            out.push_str(line);
+            out.push_str(&format!("</{}>\n", tag));
+        }
+    }
+}
+
+/// Splits highlighted markup into its `<pre ...>` tag and the markup inside.
+fn split_pre(highlighted: &str) -> Option<(&str, &str)> {
+    let tag_end = highlighted.strip_prefix("<pre")?.find('>')? + "<pre>".len();
+    let inner = highlighted[tag_end..].strip_suffix("</pre>\n")?;
+    Some((
+        &highlighted[..tag_end],
+        inner.strip_prefix('\n').unwrap_or(inner),
+    ))
+}
+
+/// Splits highlighted markup into lines, closing the spans still open at
+/// the end of each line and opening them again on the next.
+fn highlighted_lines(markup: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut has_text = false;
+    let mut open: Vec<&str> = Vec::new();
+    let mut rest = markup;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            let tag = &rest[..end];
+            if tag.starts_with("</") {
+                open.pop();
+            } else {
+                open.push(tag);
+            }
+            line.push_str(tag);
+            rest = &rest[end..];
+        } else if c == '\n' {
+            line.push_str(&"</span>".repeat(open.len()));
+            lines.push(std::mem::replace(&mut line, open.concat()));
+            has_text = false;
+            rest = &rest[1..];
+        } else {
+            line.push(c);
+            has_text = true;
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    // What follows the last line break only closes spans.
+    if has_text {
+        lines.push(line);
+    }
+    lines
+}
+
+pub fn table<R: Renderer + ?Sized>(renderer: &R, table: &Table, out: &mut String) {
+    out.push_str("<table>\n<thead>\n<tr>\n");
+    for (i, cell) in table.header.iter().enumerate() {
+        table_cell(renderer, "th", table.alignments.get(i), cell, out);
+    }
+    out.push_str("</tr>\n</thead>\n<tbody>\n");
+
+    for row in &table.rows {
+        out.push_str("<tr>\n");
+        for (i, cell) in row.iter().enumerate() {
+            table_cell(renderer, "td", table.alignments.get(i), cell, out);
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</tbody>\n</table>\n");
+}
+
+fn table_cell<R: Renderer + ?Sized>(
+    renderer: &R,
+    tag: &str,
+    alignment: Option<&Alignment>,
+    content: &[Inline],
+    out: &mut String,
+) {
+    out.push('<');
+    out.push_str(tag);
+    match alignment {
+        Some(Alignment::Left) => out.push_str(r#" style="text-align: left""#),
+        Some(Alignment::Center) => out.push_str(r#" style="text-align: center""#),
+        Some(Alignment::Right) => out.push_str(r#" style="text-align: right""#),
+        Some(Alignment::None) | None => {}
+    }
+    out.push('>');
+    renderer.inlines(content, out);
+    out.push_str("</");
+    out.push_str(tag);
+    out.push_str(">\n");
+}
+
+pub fn blockquote<R: Renderer + ?Sized>(renderer: &R, blocks: &[Block], out: &mut String) {
+    out.push_str("<blockquote>\n");
+    renderer.blocks(blocks, out);
+    out.push_str("</blockquote>\n");
+}
+
+pub fn math_block<R: Renderer + ?Sized>(renderer: &R, math_block: &MathBlock, out: &mut String) {
+    out.push_str(r#"<div class="math-block""#);
+    let label = math_block.label();
+    if let Some(label) = label {
+        out.push_str(" id=\"");
+        push_attribute(out, label);
+        out.push('"');
+    }
+    out.push('>');
+    let math = &math_block.math;
+    match renderer.typeset_math(math, true) {
+        Some(typeset) => out.push_str(&typeset),
+        None => {
+            out.push_str("$$");
+            push_text(out, math);
+            out.push_str("$$");
+        }
+    }
+    if let Some(number) = label.and_then(|label| renderer.equation_number(label)) {
+        out.push_str(&format!(r#"<span class="math-number">({})</span>"#, number));
+    }
+    out.push_str("</div>\n");
+}
+
+pub fn horizontal_rule<R: Renderer + ?Sized>(_renderer: &R, out: &mut String) {
+    out.push_str("<hr>\n");
+}
+
+pub fn text<R: Renderer + ?Sized>(_renderer: &R, text: &str, out: &mut String) {
+    push_text(out, text);
+}
+
+pub fn code<R: Renderer + ?Sized>(_renderer: &R, code: &str, out: &mut String) {
+    out.push_str("<code>");
+    push_text(out, code);
+    out.push_str("</code>");
+}
+
+pub fn math<R: Renderer + ?Sized>(renderer: &R, math: &str, out: &mut String) {
+    out.push_str(r#"<span class="math-inline">"#);
+    match renderer.typeset_math(math, false) {
+        Some(typeset) => out.push_str(&typeset),
+        None => {
+            out.push('$');
+            push_text(out, math);
+            out.push('$');
+        }
+    }
+    out.push_str("</span>");
+}
+
+pub fn strong<R: Renderer + ?Sized>(renderer: &R, children: &[Inline], out: &mut String) {
+    wrapped(renderer, "strong", children, out);
+}
+
+pub fn emphasis<R: Renderer + ?Sized>(renderer: &R, children: &[Inline], out: &mut String) {
+    wrapped(renderer, "em", children, out);
+}
+
+pub fn strikethrough<R: Renderer + ?Sized>(renderer: &R, children: &[Inline], out: &mut String) {
+    wrapped(renderer, "del", children, out);
+}
+
+fn wrapped<R: Renderer + ?Sized>(renderer: &R, tag: &str, children: &[Inline], out: &mut String) {
+    out.push('<');
+    out.push_str(tag);
+    out.push('>');
+    renderer.inlines(children, out);
+    out.push_str("</");
+    out.push_str(tag);
+    out.push('>');
+}
+
+pub fn link<R: Renderer + ?Sized>(renderer: &R, url: &str, content: &[Inline], out: &mut String) {
+    out.push_str(r#"<a href=""#);
+    push_attribute(out, url);
+    out.push_str(r#"">"#);
+    renderer.inlines(content, out);
+    out.push_str("</a>");
+}
+
+pub fn image<R: Renderer + ?Sized>(_renderer: &R, url: &str, alt: &str, out: &mut String) {
+    out.push_str(r#"<img src=""#);
+    push_attribute(out, url);
+    out.push_str(r#"" alt=""#);
+    push_attribute(out, alt);
+    out.push_str(r#"">"#);
+}
+
+pub fn soft_break<R: Renderer + ?Sized>(_renderer: &R, out: &mut String) {
+    out.push('\n');
+}
+
+pub fn line_break<R: Renderer + ?Sized>(_renderer: &R, out: &mut String) {
+    out.push_str("<br>\n");
+}
+
+/// The anchor used for a heading, e.g. `getting-started` for "Getting
+/// Started?". Punctuation other than `-` and `_` is left out.
+pub fn heading_id(content: &[Inline]) -> String {
+    let id: String = plain_text(content)
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_whitespace() => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect();
+    if id.is_empty() {
+        "section".to_string()
+    } else {
+        id
+    }
+}
+
+/// Gives the headings of one document their anchors, numbering repeats of
+/// the same one: `setup`, `setup-1`, `setup-2`.
+#[derive(Debug, Default)]
+pub struct HeadingIds {
+    used: HashSet<String>,
+    /// The last number given to a repeat of each anchor.
+    repeats: HashMap<String, usize>,
+}
+
+impl HeadingIds {
+    /// The anchor for the next heading, reading `content`.
+    pub fn next(&mut self, content: &[Inline]) -> String {
+        let base = heading_id(content);
+        let mut id = base.clone();
+        while !self.used.insert(id.clone()) {
+            let repeat = self.repeats.entry(base.clone()).or_default();
+            *repeat += 1;
+            id = format!("{}-{}", base, repeat);
+        }
+        id
+    }
+
+    /// Gives anchors to the headings in `block`, nested ones included, in
+    /// the order they are rendered. Returns the anchor of `block` itself if
+    /// it is a heading.
+    pub fn add(&mut self, block: &Block) -> Option<String> {
+        match block {
+            Block::Heading { content, .. } => return Some(self.next(content)),
+            Block::List(list) => {
+                for child in list.items.iter().flat_map(|item| &item.children) {
+                    self.add(child);
+                }
+            }
+            Block::Blockquote(blocks) => {
+                for block in blocks {
+                    self.add(block);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// The top-level headings of `document`, with their levels and the anchors
+/// an [`HtmlRenderer`] gives them.
+pub fn heading_anchors(document: &Document) -> Vec<(u8, &[Inline], String)> {
+    let mut ids = HeadingIds::default();
+    document
+        .blocks
+        .iter()
+        .filter_map(|block| {
+            let id = ids.add(block)?;
+            match block {
+                Block::Heading { level, content } => Some((*level, content.as_slice(), id)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Whether `block` is or holds a heading.
+pub fn has_heading(block: &Block) -> bool {
+    match block {
+        Block::Heading { .. } => true,
+        Block::List(list) => list
+            .items
+            .iter()
+            .flat_map(|item| &item.children)
+            .any(has_heading),
+        Block::Blockquote(blocks) => blocks.iter().any(has_heading),
+        _ => false,
+    }
+}
+
+fn push_text(out: &mut String, text: &str) {
+    html_escape::encode_text_to_string(text, out);
+}
+
+fn push_attribute(out: &mut String, value: &str) {
+    html_escape::encode_double_quoted_attribute_to_string(value, out);
+}
+
+fn push_code(out: &mut String, code: &str) {
+    for c in code.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::MarkdownParser;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_heading_ids() {
+        let document = MarkdownParser::new()
+            .parse_document("# Setup\n\n> ## Setup\n\n## Setup\n\n## What's new & <next>?");
+        let renderer = HtmlRenderer::new(HtmlOptions {
+            heading_ids: true,
+            ..HtmlOptions::default()
+        });
+        let html = renderer.render(&document);
+        assert!(html.contains(r#"<h1 id="setup">"#));
+        assert!(html.contains(r#"<h2 id="setup-1">"#));
+        assert!(html.contains(r#"<h2 id="setup-2">"#));
+        assert!(html.contains(r#"<h2 id="whats-new--next">"#));
+
+        // The contents link to the same anchors, skipping the quoted heading.
+        let toc = renderer.table_of_contents(&document);
+        assert!(toc.contains(r##"<a href="#setup">"##));
+        assert!(!toc.contains(r##"<a href="#setup-1">"##));
+        assert!(toc.contains(r##"<a href="#setup-2">"##));
+        assert!(toc.contains(r##"<a href="#whats-new--next">"##));
+    }
+
+    #[test]
+    fn test_find_syntax() {
+        let highlighter = Highlighter::default();
+        let names: Vec<Option<&str>> = ["c++", "objective-c", "objc", "CSharp", "rs", "klingon"]
+            .iter()
+            .map(|language| {
+                highlighter
+                    .find_syntax(language)
+                    .map(|syntax| syntax.name.as_str())
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                Some("C++"),
+                Some("Objective-C"),
+                Some("Objective-C"),
+                Some("C#"),
+                Some("Rust"),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_highlights_source_text() {
+        let html = Highlighter::default()
+            .html(&CodeBlock {
+                info: "c++".to_string(),
+                code: "if (a < b && c) {}\n".to_string(),
+            })
+            .unwrap();
+        assert!(html.contains("&lt;"));
+        assert!(html.contains("&amp;&amp;"));
+        assert!(!html.contains("&amp;lt;"));
+    }
+
+    #[test]
+    fn test_highlighters_share_loaded_sets() {
+        let (a, b) = (Highlighter::default(), Highlighter::default());
+        assert!(Arc::ptr_eq(&a.syntax_set, &b.syntax_set));
+        assert!(Arc::ptr_eq(&a.theme, &b.theme));
+
+        let highlighter = Highlighter::new(&HighlightOptions {
+            theme: Some("InspiredGitHub".to_string()),
+            dark_theme: Some("Solarized (dark)".to_string()),
+            classes: true,
+            ..HighlightOptions::default()
+        })
+        .unwrap();
+        let clone = highlighter.clone();
+        assert!(Arc::ptr_eq(&highlighter.theme, &clone.theme));
+        assert!(clone
+            .stylesheet()
+            .unwrap()
+            .contains("@media (prefers-color-scheme: dark)"));
+    }
+
+    #[test]
+    fn test_code_block_options() {
+        let block = CodeBlock {
+            info: r#"py title="a <b>.py" linenos start=40 hl_lines="2 3-9""#.to_string(),
+            code: "a = 1\nb = 2\nc = 3\n".to_string(),
+        };
+        assert_eq!(block.option("title"), Some("a <b>.py"));
+        assert_eq!(block.option("linenos"), Some(""));
+        assert_eq!(block.option("start"), Some("40"));
+        assert_eq!(block.option("py"), None);
+
+        let renderer = HtmlRenderer::new(HtmlOptions {
+            syntax_highlight: false,
+            ..HtmlOptions::default()
+        });
+        let mut out = String::new();
+        code_block(&renderer, &block, &mut out);
+        let number = |n: u32| {
+            format!(
+                "<span class=\"line-number\" aria-hidden=\"true\">{}</span>",
+                n
+            )
+        };
+        assert_eq!(
+            out,
+            format!(
+                "<figure class=\"code-block\">\n<figcaption class=\"code-title\">a &lt;b&gt;.py</figcaption>\n\
+                 <pre><code class=\"language-py\">\
+                 <span class=\"line\">{}a = 1</span>\n\
+                 <mark class=\"line\">{}b = 2</mark>\n\
+                 <mark class=\"line\">{}c = 3</mark>\n\
+                 </code></pre>\n</figure>\n",
+                number(40),
+                number(41),
+                number(42)
+            )
+        );
+    }
+
+    #[test]
+    fn test_code_block_options_without_language() {
+        let renderer = HtmlRenderer::new(HtmlOptions::default());
+        let render = |info: &str| {
+            let block = CodeBlock {
+                info: info.to_string(),
+                code: "a\n".to_string(),
+            };
+            assert_eq!(block.language(), None);
+            let mut out = String::new();
+            code_block(&renderer, &block, &mut out);
+            out
+        };
+
+        assert_eq!(
+            render("linenos"),
+            "<pre><code><span class=\"line\">\
+             <span class=\"line-number\" aria-hidden=\"true\">1</span>a</span>\n</code></pre>\n"
+        );
+        assert_eq!(
+            render(r#"title="x y""#),
+            "<figure class=\"code-block\">\n<figcaption class=\"code-title\">x y</figcaption>\n\
+             <pre><code>a\n</code></pre>\n</figure>\n"
+        );
+    }
+
+    #[test]
+    fn test_highlighted_lines() {
+        assert_eq!(
+            highlighted_lines("<span a><span b>x\ny</span>\n</span>"),
+            [
+                "<span a><span b>x</span></span>",
+                "<span a><span b>y</span></span>"
+            ]
+        );
+    }
+}
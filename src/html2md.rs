@@ -0,0 +1,806 @@
+use crate::ast::{Alignment, Block, CodeBlock, Document, Inline, List, ListItem, MathBlock, Table};
+use crate::error::ConverterError;
+use crate::formatter::MarkdownFormatter;
+use crate::render::Renderer;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+use scraper::node::Node;
+use scraper::{ElementRef, Html};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+lazy_static! {
+    static ref WHITESPACE: Regex = Regex::new(r"[ \t\r\n\x0c]+").unwrap();
+    static ref TEXT_ALIGN: Regex = Regex::new(r"text-align:\s*(left|center|right)").unwrap();
+    static ref EQUATION_NUMBER: Regex = Regex::new(r"^\(\d+\)$").unwrap();
+}
+
+/// Elements laid out as blocks that have no Markdown equivalent of their own;
+/// their content is read as if it stood in their place.
+const CONTAINERS: &[&str] = &[
+    "html",
+    "body",
+    "div",
+    "section",
+    "article",
+    "main",
+    "header",
+    "footer",
+    "aside",
+    "nav",
+    "figure",
+    "figcaption",
+    "center",
+    "details",
+    "summary",
+    "form",
+    "fieldset",
+    "address",
+    "hgroup",
+    "dl",
+    "dt",
+    "dd",
+];
+
+/// Elements whose content is not part of the text.
+const SKIPPED: &[&str] = &[
+    "head", "title", "meta", "link", "script", "style", "template", "noscript", "iframe", "object",
+    "svg", "canvas", "button", "select", "textarea",
+];
+
+/// Reads an HTML page, such as one this crate rendered or a legacy page being
+/// migrated, into a [`Document`].
+///
+/// Markup with a Markdown equivalent is kept: headings, lists (including task
+/// lists written with checkboxes), tables, block quotes, code blocks, math
+/// and the inline styles. Code takes its language from a `language-*` or
+/// `lang-*` class, and math is recognised by this crate's `math-inline` and
+/// `math-block` classes, Pandoc's `math` class and the TeX annotation or
+/// `alttext` of MathML (as KaTeX and this crate write them). Other elements
+/// contribute only their text.
+pub fn parse_html(html: &str) -> Document {
+    Document {
+        blocks: blocks(Html::parse_document(html).root_element()),
+    }
+}
+
+/// Converts `html` to Markdown in the style of [`MarkdownFormatter`], with
+/// paragraphs wrapped at `width`.
+pub fn html_to_markdown(html: &str, width: Option<usize>) -> String {
+    MarkdownFormatter::new(width).render(&parse_html(html))
+}
+
+/// Converts the HTML file at `input` to Markdown written to `output`, or to
+/// stdout if that is `-`.
+pub fn convert_file(input: &Path, output: &Path, width: Option<usize>) -> Result<()> {
+    let html = fs::read_to_string(input).map_err(ConverterError::InputFileError)?;
+    let markdown = html_to_markdown(&html, width);
+    if output == Path::new("-") {
+        io::stdout().lock().write_all(markdown.as_bytes())?;
+    } else {
+        fs::write(output, markdown).map_err(|e| {
+            ConverterError::OutputFileError(format!("Failed to write output: {}", e))
+        })?;
+    }
+    Ok(())
+}
+
+enum Child<'a> {
+    Text(&'a str),
+    Element(ElementRef<'a>),
+}
+
+/// The text and element children of `element`, leaving out comments.
+fn children(element: ElementRef) -> impl Iterator<Item = Child> {
+    element.children().filter_map(|node| match node.value() {
+        Node::Text(text) => Some(Child::Text(text)),
+        Node::Element(_) => ElementRef::wrap(node).map(Child::Element),
+        _ => None,
+    })
+}
+
+fn has_class(element: ElementRef, class: &str) -> bool {
+    element.value().classes().any(|c| c == class)
+}
+
+/// The TeX of a math element, and whether it is displayed as a block.
+fn math(element: ElementRef) -> Option<(String, bool)> {
+    let display = if element.value().name() == "math" {
+        element.attr("display") == Some("block")
+    } else if has_class(element, "math-block") || has_class(element, "katex-display") {
+        true
+    } else if has_class(element, "math-inline") || has_class(element, "katex") {
+        false
+    } else if has_class(element, "math") {
+        has_class(element, "display")
+    } else {
+        return None;
+    };
+
+    let annotation = element.descendent_elements().find(|e| {
+        e.value().name() == "annotation" && e.attr("encoding") == Some("application/x-tex")
+    });
+    let alttext = element
+        .descendent_elements()
+        .find(|e| e.value().name() == "math")
+        .and_then(|math| math.attr("alttext"));
+    let tex = match (annotation, alttext) {
+        (Some(annotation), _) => annotation.text().collect::<String>(),
+        (None, Some(alttext)) => alttext.to_string(),
+        (None, None) => {
+            // Leave out the equation number beside a math block.
+            let text = text_outside(element, "math-number");
+            let text = text.trim();
+            [("$$", "$$"), ("$", "$"), ("\\[", "\\]"), ("\\(", "\\)")]
+                .iter()
+                .find_map(|(open, close)| text.strip_prefix(open)?.strip_suffix(close))
+                .unwrap_or(text)
+                .to_string()
+        }
+    };
+    Some((tex.trim().to_string(), display))
+}
+
+/// The text of `element`, leaving out that of elements with the class
+/// `class`.
+fn text_outside(element: ElementRef, class: &str) -> String {
+    element
+        .descendants()
+        .filter(|node| {
+            !node
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .any(|e| has_class(e, class))
+        })
+        .filter_map(|node| node.value().as_text().map(|text| &**text))
+        .collect()
+}
+
+fn is_block(element: ElementRef) -> bool {
+    let name = element.value().name();
+    matches!(
+        name,
+        "h1" | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "p"
+            | "ul"
+            | "ol"
+            | "pre"
+            | "table"
+            | "blockquote"
+            | "hr"
+    ) || CONTAINERS.contains(&name)
+        || math(element).is_some_and(|(_, display)| display)
+}
+
+/// Reads the children of `element` as blocks, gathering runs of inline
+/// content into paragraphs.
+fn blocks(element: ElementRef) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut inlines = Vec::new();
+    for child in children(element) {
+        match child {
+            Child::Text(text) => push_text(&mut inlines, text),
+            Child::Element(child) if is_block(child) => {
+                push_paragraph(&mut blocks, std::mem::take(&mut inlines));
+                block(child, &mut blocks);
+            }
+            Child::Element(child) => inline(child, &mut inlines),
+        }
+    }
+    push_paragraph(&mut blocks, inlines);
+    blocks
+}
+
+fn push_paragraph(blocks: &mut Vec<Block>, inlines: Vec<Inline>) {
+    let content = normalize(inlines);
+    if !content.is_empty() {
+        blocks.push(Block::Paragraph(content));
+    }
+}
+
+fn block(element: ElementRef, blocks: &mut Vec<Block>) {
+    if let Some((tex, true)) = math(element) {
+        let mut math_block = MathBlock {
+            math: tex,
+            id: None,
+        };
+        // The id is the math's own `\label`, or else was given as `{#id}`.
+        if let Some(id) = element
+            .attr("id")
+            .filter(|&id| math_block.label() != Some(id))
+        {
+            math_block.id = Some(id.to_string());
+        }
+        blocks.push(Block::MathBlock(math_block));
+        return;
+    }
+
+    match element.value().name() {
+        name @ ("h1" | "h2" | "h3" | "h4" | "h5" | "h6") => blocks.push(Block::Heading {
+            level: name.as_bytes()[1] - b'0',
+            content: normalize(inlines(element)),
+        }),
+        "p" => push_paragraph(blocks, inlines(element)),
+        "ul" | "ol" => blocks.push(Block::List(list(element))),
+        "pre" => blocks.push(Block::CodeBlock(code_block(element, None))),
+        "figure" if has_class(element, "code-block") => {
+            let pre = element
+                .child_elements()
+                .find(|child| child.value().name() == "pre");
+            match pre {
+                Some(pre) => {
+                    let title = element
+                        .child_elements()
+                        .find(|&child| has_class(child, "code-title"))
+                        .map(|caption| caption.text().collect::<String>());
+                    blocks.push(Block::CodeBlock(code_block(pre, title.as_deref())));
+                }
+                None => blocks.extend(self::blocks(element)),
+            }
+        }
+        "table" => table(element, blocks),
+        "blockquote" => blocks.push(Block::Blockquote(self::blocks(element))),
+        "hr" => blocks.push(Block::HorizontalRule),
+        _ => blocks.extend(self::blocks(element)),
+    }
+}
+
+fn list(element: ElementRef) -> List {
+    let ordered = element.value().name() == "ol";
+    let start = element
+        .attr("start")
+        .filter(|_| ordered)
+        .and_then(|start| start.trim().parse().ok())
+        .unwrap_or(1);
+
+    let mut items: Vec<ListItem> = Vec::new();
+    for child in children(element) {
+        let Child::Element(child) = child else {
+            continue;
+        };
+        match child.value().name() {
+            "li" => items.push(list_item(child)),
+            // A list nested directly in another, as older pages do, belongs
+            // to the item before it.
+            "ul" | "ol" => match items.last_mut() {
+                Some(item) => item.children.push(Block::List(list(child))),
+                None => items.push(ListItem {
+                    checked: None,
+                    content: Vec::new(),
+                    children: vec![Block::List(list(child))],
+                }),
+            },
+            _ => {}
+        }
+    }
+    List {
+        ordered,
+        start,
+        items,
+    }
+}
+
+/// Reads a list item. Items cannot hold paragraphs in Markdown here, so the
+/// paragraphs up to the first other block join the item's text on lines of
+/// their own.
+fn list_item(element: ElementRef) -> ListItem {
+    let mut children = blocks(element).into_iter().peekable();
+    let mut content = Vec::new();
+    while let Some(Block::Paragraph(paragraph)) =
+        children.next_if(|block| matches!(block, Block::Paragraph(_)))
+    {
+        if !content.is_empty() {
+            content.push(Inline::LineBreak);
+        }
+        content.extend(paragraph);
+    }
+    ListItem {
+        checked: checkbox(element),
+        content,
+        children: children.collect(),
+    }
+}
+
+/// Whether the checkbox starting a list item is ticked, if it starts with
+/// one, directly or inside its first paragraph or label.
+fn checkbox(element: ElementRef) -> Option<bool> {
+    let first = children(element).find(|child| match child {
+        Child::Text(text) => !text.trim().is_empty(),
+        Child::Element(_) => true,
+    })?;
+    let Child::Element(first) = first else {
+        return None;
+    };
+    match first.value().name() {
+        "input" if first.attr("type") == Some("checkbox") => Some(first.attr("checked").is_some()),
+        "p" | "label" => checkbox(first),
+        _ => None,
+    }
+}
+
+/// Reads a `<pre>`, with the `title` of the figure it is in. The numbered
+/// and marked lines this crate writes become `linenos`, `start` and
+/// `hl_lines` options again.
+fn code_block(element: ElementRef, title: Option<&str>) -> CodeBlock {
+    let code_element = element
+        .child_elements()
+        .find(|child| child.value().name() == "code");
+    let language = code_element
+        .into_iter()
+        .chain([element])
+        .flat_map(|e| e.value().classes())
+        .find_map(|class| {
+            class
+                .strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+        });
+
+    let mut info = language.unwrap_or_default().to_string();
+    let mut option = |option: &str| {
+        if !info.is_empty() {
+            info.push(' ');
+        }
+        info.push_str(option);
+    };
+    if let Some(title) = title {
+        let quote = if title.contains('"') { '\'' } else { '"' };
+        option(&format!("title={1}{0}{1}", title, quote));
+    }
+    let lines: Vec<ElementRef> = code_element
+        .unwrap_or(element)
+        .child_elements()
+        .filter(|&child| has_class(child, "line"))
+        .collect();
+    let first_number = lines.iter().find_map(|line| {
+        let number = line
+            .child_elements()
+            .find(|&child| has_class(child, "line-number"))?;
+        number
+            .text()
+            .collect::<String>()
+            .trim()
+            .parse::<usize>()
+            .ok()
+    });
+    if let Some(start) = first_number {
+        option("linenos");
+        if start != 1 {
+            option(&format!("start={}", start));
+        }
+    }
+    let marked: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.value().name() == "mark")
+        .map(|(i, _)| i + 1)
+        .collect();
+    if !marked.is_empty() {
+        option(&format!("hl_lines=\"{}\"", line_ranges(&marked)));
+    }
+
+    // Line numbers are not part of the code.
+    let mut code = text_outside(code_element.unwrap_or(element), "line-number");
+    if !code.is_empty() && !code.ends_with('\n') {
+        code.push('\n');
+    }
+    CodeBlock { info, code }
+}
+
+/// Ascending line numbers as `hl_lines` writes them, runs as ranges:
+/// `2 4-6`.
+fn line_ranges(lines: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &line in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Reads a table, with its caption, if any, as a paragraph before it. The
+/// header is the first row of `<thead>`, or else the first row, and sets the
+/// number of columns.
+fn table(element: ElementRef, blocks: &mut Vec<Block>) {
+    let mut header = None;
+    let mut rows = Vec::new();
+    for child in element.child_elements() {
+        match child.value().name() {
+            "caption" => push_paragraph(blocks, inlines(child)),
+            "tr" => rows.push(child),
+            section @ ("thead" | "tbody" | "tfoot") => {
+                let mut section_rows = child
+                    .child_elements()
+                    .filter(|row| row.value().name() == "tr");
+                if section == "thead" && header.is_none() {
+                    header = section_rows.next();
+                }
+                rows.extend(section_rows);
+            }
+            _ => {}
+        }
+    }
+    let Some(header) = header.or_else(|| (!rows.is_empty()).then(|| rows.remove(0))) else {
+        return;
+    };
+
+    let columns = cells(header).len();
+    let row_content = |row: ElementRef| -> Vec<Vec<Inline>> {
+        let mut content: Vec<Vec<Inline>> = cells(row).into_iter().map(cell).collect();
+        content.resize(columns, Vec::new());
+        content
+    };
+
+    let header_cells = cells(header);
+    let alignments = (0..columns)
+        .map(|i| {
+            header_cells
+                .get(i)
+                .map_or(Alignment::None, |&c| alignment(c))
+        })
+        .collect();
+    blocks.push(Block::Table(Table {
+        alignments,
+        header: row_content(header),
+        rows: rows.into_iter().map(row_content).collect(),
+    }));
+}
+
+fn cells(row: ElementRef) -> Vec<ElementRef> {
+    row.child_elements()
+        .filter(|cell| matches!(cell.value().name(), "th" | "td"))
+        .collect()
+}
+
+/// A table cell's content on one line, as Markdown tables need.
+fn cell(element: ElementRef) -> Vec<Inline> {
+    let mut content = inlines(element);
+    replace_line_breaks(&mut content);
+    normalize(content)
+}
+
+fn replace_line_breaks(inlines: &mut [Inline]) {
+    for inline in inlines {
+        match inline {
+            Inline::LineBreak => *inline = Inline::Text(" ".to_string()),
+            Inline::Strong(children)
+            | Inline::Emphasis(children)
+            | Inline::Strikethrough(children)
+            | Inline::Link {
+                content: children, ..
+            } => replace_line_breaks(children),
+            _ => {}
+        }
+    }
+}
+
+fn alignment(cell: ElementRef) -> Alignment {
+    let style = cell.attr("style").unwrap_or_default();
+    let align = TEXT_ALIGN
+        .captures(style)
+        .map(|captures| captures.get(1).unwrap().as_str())
+        .or_else(|| cell.attr("align"));
+    match align.map(str::to_ascii_lowercase).as_deref() {
+        Some("left") => Alignment::Left,
+        Some("center") => Alignment::Center,
+        Some("right") => Alignment::Right,
+        _ => Alignment::None,
+    }
+}
+
+/// Reads the content of `element` as inlines, with whitespace not yet
+/// normalized.
+fn inlines(element: ElementRef) -> Vec<Inline> {
+    let mut inlines = Vec::new();
+    for child in children(element) {
+        match child {
+            Child::Text(text) => push_text(&mut inlines, text),
+            Child::Element(child) => inline(child, &mut inlines),
+        }
+    }
+    inlines
+}
+
+fn inline(element: ElementRef, out: &mut Vec<Inline>) {
+    if let Some((tex, _)) = math(element) {
+        out.push(Inline::Math(tex));
+        return;
+    }
+
+    let name = element.value().name();
+    match name {
+        "strong" | "b" => out.push(Inline::Strong(inlines(element))),
+        "em" | "i" | "cite" | "dfn" | "var" => out.push(Inline::Emphasis(inlines(element))),
+        "del" | "s" | "strike" => out.push(Inline::Strikethrough(inlines(element))),
+        "code" | "kbd" | "samp" | "tt" => {
+            let code: String = element.text().collect();
+            out.push(Inline::Code(
+                WHITESPACE.replace_all(&code, " ").into_owned(),
+            ));
+        }
+        "a" => match element.attr("href") {
+            // A numbered reference to an equation, as written from `@eq:...`
+            // or `\eqref{...}`.
+            Some(url)
+                if url.starts_with('#')
+                    && EQUATION_NUMBER.is_match(element.text().collect::<String>().trim()) =>
+            {
+                let label = &url[1..];
+                let reference = if label.starts_with("eq:") {
+                    format!("@{}", label)
+                } else {
+                    format!("\\eqref{{{}}}", label)
+                };
+                push_text(out, &reference);
+            }
+            Some(url) => out.push(Inline::Link {
+                url: url.to_string(),
+                content: inlines(element),
+            }),
+            None => out.extend(inlines(element)),
+        },
+        "img" => out.push(Inline::Image {
+            url: element.attr("src").unwrap_or_default().to_string(),
+            alt: element.attr("alt").unwrap_or_default().to_string(),
+        }),
+        "br" => out.push(Inline::LineBreak),
+        "input" => {}
+        _ if SKIPPED.contains(&name) => {}
+        _ => out.extend(inlines(element)),
+    }
+}
+
+/// Appends `text` with every run of whitespace made a single space, as a
+/// browser shows it.
+fn push_text(out: &mut Vec<Inline>, text: &str) {
+    let text = WHITESPACE.replace_all(text, " ");
+    match out.last_mut() {
+        Some(Inline::Text(last)) => last.push_str(&text),
+        _ => out.push(Inline::Text(text.into_owned())),
+    }
+}
+
+/// Drops the whitespace a browser would not show, at the ends of the content,
+/// around line breaks and after other whitespace, and moves spaces at the
+/// ends of spans outside them, since `**a **` is not bold in Markdown.
+/// Spans left empty are dropped.
+fn normalize(inlines: Vec<Inline>) -> Vec<Inline> {
+    let mut out = Vec::new();
+    let mut space = true;
+    normalize_into(inlines, &mut space, &mut out);
+    trim_end(&mut out);
+    out
+}
+
+/// Normalizes `inlines` onto `out`; `space` is whether the content so far
+/// ends in whitespace, so that a following space is dropped.
+fn normalize_into(inlines: Vec<Inline>, space: &mut bool, out: &mut Vec<Inline>) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(text) => {
+                let text = if *space {
+                    text.trim_start_matches(' ')
+                } else {
+                    &text
+                };
+                if !text.is_empty() {
+                    *space = text.ends_with(' ');
+                    match out.last_mut() {
+                        Some(Inline::Text(last)) => last.push_str(text),
+                        _ => out.push(Inline::Text(text.to_string())),
+                    }
+                }
+            }
+            Inline::LineBreak | Inline::SoftBreak => {
+                trim_end(out);
+                if !out.is_empty() {
+                    out.push(inline);
+                }
+                *space = true;
+            }
+            Inline::Strong(children) => span(Inline::Strong, children, space, out),
+            Inline::Emphasis(children) => span(Inline::Emphasis, children, space, out),
+            Inline::Strikethrough(children) => span(Inline::Strikethrough, children, space, out),
+            Inline::Link { url, content } => {
+                span(|content| Inline::Link { url, content }, content, space, out)
+            }
+            inline => {
+                out.push(inline);
+                *space = false;
+            }
+        }
+    }
+}
+
+fn span(
+    make: impl FnOnce(Vec<Inline>) -> Inline,
+    children: Vec<Inline>,
+    space: &mut bool,
+    out: &mut Vec<Inline>,
+) {
+    let mut content = Vec::new();
+    normalize_into(children, space, &mut content);
+    if let Some(Inline::Text(first)) = content.first_mut() {
+        if let Some(rest) = first.strip_prefix(' ') {
+            *first = rest.to_string();
+            push_space(out);
+            if first.is_empty() {
+                content.remove(0);
+            }
+        }
+    }
+
+    let line_break = content
+        .iter()
+        .rev()
+        .take_while(|inline| match inline {
+            Inline::Text(text) => text.trim_end_matches(' ').is_empty(),
+            _ => matches!(inline, Inline::LineBreak | Inline::SoftBreak),
+        })
+        .any(|inline| matches!(inline, Inline::LineBreak));
+    let trailing_space = match content.last() {
+        Some(Inline::Text(text)) => text.ends_with(' '),
+        Some(Inline::SoftBreak) => true,
+        _ => false,
+    };
+    trim_end(&mut content);
+    if !content.is_empty() {
+        out.push(make(content));
+    }
+    if line_break && !out.is_empty() {
+        trim_end(out);
+        out.push(Inline::LineBreak);
+    } else if trailing_space {
+        push_space(out);
+    }
+}
+
+fn push_space(out: &mut Vec<Inline>) {
+    match out.last_mut() {
+        Some(Inline::Text(text)) if text.ends_with(' ') => {}
+        Some(Inline::Text(text)) => text.push(' '),
+        Some(Inline::LineBreak | Inline::SoftBreak) | None => {}
+        _ => out.push(Inline::Text(" ".to_string())),
+    }
+}
+
+/// Drops trailing spaces and line breaks.
+fn trim_end(inlines: &mut Vec<Inline>) {
+    loop {
+        match inlines.last_mut() {
+            Some(Inline::LineBreak | Inline::SoftBreak) => {
+                inlines.pop();
+            }
+            Some(Inline::Text(text)) if text.ends_with(' ') => {
+                text.truncate(text.trim_end_matches(' ').len());
+                if text.is_empty() {
+                    inlines.pop();
+                }
+            }
+            _ => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crossref::Equations;
+    use crate::html::{HtmlOptions, HtmlRenderer};
+    use crate::parser::MarkdownParser;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    /// Renders `markdown` to HTML and back, checking the Markdown matches the
+    /// original as the formatter writes it.
+    fn assert_round_trips(markdown: &str) {
+        let document = MarkdownParser::new().parse_document(markdown);
+        let html = HtmlRenderer::new(HtmlOptions::default()).render(&document);
+        assert_eq!(
+            html_to_markdown(&html, None),
+            MarkdownFormatter::default().render(&document)
+        );
+    }
+
+    #[test]
+    fn test_example_round_trips() {
+        assert_round_trips(include_str!("../example.md"));
+    }
+
+    #[test_case("# Title\n\nSome **bold**, *emphasis*, ~~struck~~ and `code`." ; "inline styles")]
+    #[test_case("- [x] done\n- [ ] to do\n  1. nested" ; "task lists")]
+    #[test_case("3. third\n4. fourth" ; "list start")]
+    #[test_case("| a | b | c |\n|:--|:-:|--:|\n| 1 | a \\| b | [x](y) |" ; "tables")]
+    #[test_case("Inline $e^{i\\pi}$ math.\n\n$$\nx^2\n$$" ; "math")]
+    #[test_case("```rust\nfn main() {}\n```\n\n```\nplain\n```" ; "code blocks")]
+    #[test_case("> quoted\n> - list\n\n---\n\nline  \nbreak" ; "quotes and breaks")]
+    fn test_round_trips(markdown: &str) {
+        assert_round_trips(markdown);
+    }
+
+    #[test]
+    fn test_equation_references() {
+        let markdown = "$$\nE = mc^2\n$$ {#eq:energy}\n\n$$\nx \\label{x}\n$$\n\nBy @eq:energy and \\eqref{x}.";
+        let document = MarkdownParser::new().parse_document(markdown);
+        let mut linked = document.clone();
+        let equations = Equations::new(document.blocks.clone());
+        linked
+            .blocks
+            .iter_mut()
+            .for_each(|block| equations.link_references(block));
+        let html = HtmlRenderer::new(HtmlOptions {
+            katex: true,
+            ..HtmlOptions::default()
+        })
+        .with_equations(equations)
+        .render(&linked);
+        assert!(html.contains("<span class=\"math-number\">(1)</span>"));
+        assert_eq!(
+            html_to_markdown(&html, None),
+            MarkdownFormatter::default().render(&document)
+        );
+    }
+
+    #[test]
+    fn test_numbered_code_lines() {
+        let document = MarkdownParser::new()
+            .parse_document("```rust title=\"main.rs\" linenos hl_lines=2\nfn main() {\n}\n```");
+        let html = HtmlRenderer::new(HtmlOptions::default()).render(&document);
+        assert_eq!(
+            html_to_markdown(&html, None),
+            "```rust title=\"main.rs\" linenos hl_lines=\"2\"\nfn main() {\n}\n```\n"
+        );
+
+        // Without a language, and highlighted, which leaves the language out.
+        let document = MarkdownParser::new()
+            .parse_document("```linenos start=9 hl_lines=\"1-2 4\"\na\nb\nc\nd\n```");
+        let html = HtmlRenderer::new(HtmlOptions {
+            syntax_highlight: true,
+            ..HtmlOptions::default()
+        })
+        .render(&document);
+        assert_eq!(
+            html_to_markdown(&html, None),
+            "```linenos start=9 hl_lines=\"1-2 4\"\na\nb\nc\nd\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_legacy_markup() {
+        let html = r#"<html><head><title>Old</title><script>var x;</script></head>
+            <body><center><font size="5"><b>Welcome </b></font></center>
+            <div>Loose   text <i> with <br> </i> <a name="top">anchors</a>
+            <span class="math inline">\(x\)</span></div>
+            <ul><li><p><input type="checkbox" checked> Done</p><p>More.</p></li>
+            <ul><li>Nested</li></ul></ul>
+            <table><caption>Sizes</caption><tr><td align="right">1</td><td>a<br>b</td></tr></table>
+            <pre class="lang-sh">ls</pre></body></html>"#;
+        assert_eq!(
+            html_to_markdown(html, None),
+            "**Welcome**\n\n\
+             Loose text *with*\\\nanchors $x$\n\n\
+             - [x] Done\\\n  More.\n  - Nested\n\n\
+             Sizes\n\n\
+             |   1 | a b |\n\
+             | --: | --- |\n\n\
+             ```sh\nls\n```\n"
+        );
+    }
+}
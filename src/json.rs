@@ -0,0 +1,456 @@
+use crate::ast::{Alignment, Block, CodeBlock, Document, Inline, List, ListItem, MathBlock, Table};
+use crate::error::ConverterError;
+use crate::front_matter::FrontMatter;
+use crate::parser::MarkdownParser;
+use serde_json::{json, Map, Value};
+
+/// Version of the JSON format, bumped on incompatible changes. The format is
+/// described by the JSON Schema in `assets/ast.schema.json`.
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// Parses `markdown` and describes the document as JSON: its front matter as
+/// `metadata`, then its blocks, the top-level ones with the lines they came
+/// from.
+pub fn to_json(markdown: &str) -> Value {
+    let (metadata, body) = FrontMatter::split(markdown);
+    // Lines taken by the front matter, which positions count from the start of.
+    let offset = markdown[..markdown.len() - body.len()]
+        .matches('\n')
+        .count();
+
+    let blocks: Vec<Value> = MarkdownParser::new()
+        .blocks(body)
+        .with_positions()
+        .map(|(block, position)| {
+            let mut value = block_to_json(&block);
+            value["position"] = json!({
+                "start_line": position.start_line + offset,
+                "end_line": position.end_line + offset,
+            });
+            value
+        })
+        .collect();
+    with_metadata(&metadata, blocks)
+}
+
+/// Describes a document already parsed, or imported, as JSON, in the format
+/// of [`to_json`] but without positions.
+pub fn document_to_json(metadata: &FrontMatter, document: &Document) -> Value {
+    with_metadata(metadata, blocks_to_json(&document.blocks))
+}
+
+/// The JSON for a document with `metadata` and the already described `blocks`.
+fn with_metadata(metadata: &FrontMatter, blocks: Vec<Value>) -> Value {
+    let metadata: Map<String, Value> = metadata
+        .entries()
+        .map(|(key, values)| {
+            let value = match values {
+                [value] => json!(value),
+                values => json!(values),
+            };
+            (key.to_string(), value)
+        })
+        .collect();
+
+    json!({
+        "version": SCHEMA_VERSION,
+        "metadata": metadata,
+        "blocks": blocks,
+    })
+}
+
+/// Reads a document written by [`to_json`], or by a tool following the same
+/// schema. Positions are ignored.
+pub fn from_json(json: &str) -> Result<(FrontMatter, Document), ConverterError> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| ConverterError::JsonError(e.to_string()))?;
+    let root = Node::new(&value, String::new())?;
+
+    if let Some(version) = root.object.get("version") {
+        if version.as_u64() != Some(SCHEMA_VERSION) {
+            return Err(root.error(&format!("unsupported version {}", version)));
+        }
+    }
+
+    let mut metadata = FrontMatter::default();
+    if let Some(entries) = root.object.get("metadata") {
+        let entries = entries
+            .as_object()
+            .ok_or_else(|| root.error("metadata must be an object"))?;
+        for (key, value) in entries {
+            let values = match value {
+                Value::String(value) => vec![value.clone()],
+                Value::Array(values) => values
+                    .iter()
+                    .map(|value| value.as_str().map(str::to_string))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| root.error(&format!("metadata.{}: expected strings", key)))?,
+                _ => return Err(root.error(&format!("metadata.{}: expected a string", key))),
+            };
+            metadata.insert(key, values);
+        }
+    }
+
+    let document = Document {
+        blocks: root.blocks("blocks")?,
+    };
+    Ok((metadata, document))
+}
+
+fn block_to_json(block: &Block) -> Value {
+    match block {
+        Block::Heading { level, content } => json!({
+            "type": "heading",
+            "level": level,
+            "content": inlines_to_json(content),
+        }),
+        Block::Paragraph(content) => json!({
+            "type": "paragraph",
+            "content": inlines_to_json(content),
+        }),
+        Block::List(list) => json!({
+            "type": "list",
+            "ordered": list.ordered,
+            "start": list.start,
+            "items": list.items.iter().map(|item| json!({
+                "checked": item.checked,
+                "content": inlines_to_json(&item.content),
+                "children": blocks_to_json(&item.children),
+            })).collect::<Vec<_>>(),
+        }),
+        Block::CodeBlock(code_block) => json!({
+            "type": "code_block",
+            "info": code_block.info,
+            "code": code_block.code,
+        }),
+        Block::Table(table) => json!({
+            "type": "table",
+            "alignments": table.alignments.iter().map(|alignment| match alignment {
+                Alignment::None => "none",
+                Alignment::Left => "left",
+                Alignment::Center => "center",
+                Alignment::Right => "right",
+            }).collect::<Vec<_>>(),
+            "header": table.header.iter().map(|cell| inlines_to_json(cell)).collect::<Vec<_>>(),
+            "rows": table.rows.iter().map(|row| {
+                row.iter().map(|cell| inlines_to_json(cell)).collect::<Vec<_>>()
+            }).collect::<Vec<_>>(),
+        }),
+        Block::Blockquote(blocks) => json!({
+            "type": "blockquote",
+            "blocks": blocks_to_json(blocks),
+        }),
+        Block::MathBlock(math_block) => json!({
+            "type": "math_block",
+            "math": math_block.math,
+            "id": math_block.id,
+        }),
+        Block::HorizontalRule => json!({ "type": "horizontal_rule" }),
+    }
+}
+
+fn blocks_to_json(blocks: &[Block]) -> Vec<Value> {
+    blocks.iter().map(block_to_json).collect()
+}
+
+fn inlines_to_json(inlines: &[Inline]) -> Vec<Value> {
+    inlines.iter().map(inline_to_json).collect()
+}
+
+fn inline_to_json(inline: &Inline) -> Value {
+    match inline {
+        Inline::Text(text) => json!({ "type": "text", "text": text }),
+        Inline::Code(code) => json!({ "type": "code", "code": code }),
+        Inline::Math(math) => json!({ "type": "math", "math": math }),
+        Inline::Strong(children) => {
+            json!({ "type": "strong", "content": inlines_to_json(children) })
+        }
+        Inline::Emphasis(children) => {
+            json!({ "type": "emphasis", "content": inlines_to_json(children) })
+        }
+        Inline::Strikethrough(children) => {
+            json!({ "type": "strikethrough", "content": inlines_to_json(children) })
+        }
+        Inline::Link { url, content } => {
+            json!({ "type": "link", "url": url, "content": inlines_to_json(content) })
+        }
+        Inline::Image { url, alt } => json!({ "type": "image", "url": url, "alt": alt }),
+        Inline::SoftBreak => json!({ "type": "soft_break" }),
+        Inline::LineBreak => json!({ "type": "line_break" }),
+    }
+}
+
+/// A JSON object being read into the tree, with its path for error messages,
+/// e.g. `blocks[2].items[0]`.
+struct Node<'a> {
+    object: &'a Map<String, Value>,
+    path: String,
+}
+
+impl<'a> Node<'a> {
+    fn new(value: &'a Value, path: String) -> Result<Self, ConverterError> {
+        match value.as_object() {
+            Some(object) => Ok(Self { object, path }),
+            None => Err(ConverterError::JsonError(format!(
+                "{}: expected an object",
+                if path.is_empty() { "document" } else { &path }
+            ))),
+        }
+    }
+
+    fn error(&self, message: &str) -> ConverterError {
+        if self.path.is_empty() {
+            ConverterError::JsonError(message.to_string())
+        } else {
+            ConverterError::JsonError(format!("{}: {}", self.path, message))
+        }
+    }
+
+    fn path(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.path, key)
+        }
+    }
+
+    fn get(&self, key: &str) -> Result<&'a Value, ConverterError> {
+        self.object
+            .get(key)
+            .ok_or_else(|| self.error(&format!("missing \"{}\"", key)))
+    }
+
+    fn str(&self, key: &str) -> Result<&'a str, ConverterError> {
+        self.get(key)?
+            .as_str()
+            .ok_or_else(|| self.error(&format!("\"{}\" must be a string", key)))
+    }
+
+    fn u64(&self, key: &str) -> Result<u64, ConverterError> {
+        self.get(key)?
+            .as_u64()
+            .ok_or_else(|| self.error(&format!("\"{}\" must be a whole number", key)))
+    }
+
+    fn bool(&self, key: &str) -> Result<bool, ConverterError> {
+        self.get(key)?
+            .as_bool()
+            .ok_or_else(|| self.error(&format!("\"{}\" must be true or false", key)))
+    }
+
+    fn array(&self, key: &str) -> Result<&'a [Value], ConverterError> {
+        self.get(key)?
+            .as_array()
+            .map(Vec::as_slice)
+            .ok_or_else(|| self.error(&format!("\"{}\" must be an array", key)))
+    }
+
+    /// The elements of the array `key`, each read by `read` with its path.
+    fn each<T>(
+        &self,
+        key: &str,
+        read: impl Fn(&'a Value, String) -> Result<T, ConverterError>,
+    ) -> Result<Vec<T>, ConverterError> {
+        let path = self.path(key);
+        self.array(key)?
+            .iter()
+            .enumerate()
+            .map(|(i, value)| read(value, format!("{}[{}]", path, i)))
+            .collect()
+    }
+
+    fn blocks(&self, key: &str) -> Result<Vec<Block>, ConverterError> {
+        self.each(key, |value, path| Node::new(value, path)?.block())
+    }
+
+    fn inlines(&self, key: &str) -> Result<Vec<Inline>, ConverterError> {
+        self.each(key, |value, path| Node::new(value, path)?.inline())
+    }
+
+    fn cells(&self, value: &'a Value, path: String) -> Result<Vec<Vec<Inline>>, ConverterError> {
+        let row = value
+            .as_array()
+            .ok_or_else(|| ConverterError::JsonError(format!("{}: expected an array", path)))?;
+        row.iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                let path = format!("{}[{}]", path, i);
+                let cell = cell.as_array().ok_or_else(|| {
+                    ConverterError::JsonError(format!("{}: expected an array", path))
+                })?;
+                cell.iter()
+                    .enumerate()
+                    .map(|(j, inline)| Node::new(inline, format!("{}[{}]", path, j))?.inline())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn block(&self) -> Result<Block, ConverterError> {
+        Ok(match self.str("type")? {
+            "heading" => Block::Heading {
+                level: match self.u64("level")? {
+                    level @ 1..=6 => level as u8,
+                    _ => return Err(self.error("\"level\" must be from 1 to 6")),
+                },
+                content: self.inlines("content")?,
+            },
+            "paragraph" => Block::Paragraph(self.inlines("content")?),
+            "list" => Block::List(List {
+                ordered: self.bool("ordered")?,
+                start: self.u64("start")?,
+                items: self.each("items", |value, path| {
+                    let item = Node::new(value, path)?;
+                    Ok(ListItem {
+                        checked: match item.object.get("checked") {
+                            None | Some(Value::Null) => None,
+                            Some(_) => Some(item.bool("checked")?),
+                        },
+                        content: item.inlines("content")?,
+                        children: item.blocks("children")?,
+                    })
+                })?,
+            }),
+            "code_block" => Block::CodeBlock(CodeBlock {
+                info: self.str("info")?.to_string(),
+                code: self.str("code")?.to_string(),
+            }),
+            "table" => {
+                let alignments = self.each("alignments", |value, path| match value.as_str() {
+                    Some("none") => Ok(Alignment::None),
+                    Some("left") => Ok(Alignment::Left),
+                    Some("center") => Ok(Alignment::Center),
+                    Some("right") => Ok(Alignment::Right),
+                    _ => Err(ConverterError::JsonError(format!(
+                        "{}: expected \"none\", \"left\", \"center\" or \"right\"",
+                        path
+                    ))),
+                })?;
+                let header = self.cells(self.get("header")?, self.path("header"))?;
+                let rows = self.each("rows", |value, path| self.cells(value, path))?;
+                if header.len() != alignments.len()
+                    || rows.iter().any(|row| row.len() != header.len())
+                {
+                    return Err(self.error("every row must have a cell for each alignment"));
+                }
+                Block::Table(Table {
+                    alignments,
+                    header,
+                    rows,
+                })
+            }
+            "blockquote" => Block::Blockquote(self.blocks("blocks")?),
+            "math_block" => Block::MathBlock(MathBlock {
+                math: self.str("math")?.to_string(),
+                id: match self.object.get("id") {
+                    None | Some(Value::Null) => None,
+                    Some(_) => Some(self.str("id")?.to_string()),
+                },
+            }),
+            "horizontal_rule" => Block::HorizontalRule,
+            other => return Err(self.error(&format!("unknown block type \"{}\"", other))),
+        })
+    }
+
+    fn inline(&self) -> Result<Inline, ConverterError> {
+        Ok(match self.str("type")? {
+            "text" => Inline::Text(self.str("text")?.to_string()),
+            "code" => Inline::Code(self.str("code")?.to_string()),
+            "math" => Inline::Math(self.str("math")?.to_string()),
+            "strong" => Inline::Strong(self.inlines("content")?),
+            "emphasis" => Inline::Emphasis(self.inlines("content")?),
+            "strikethrough" => Inline::Strikethrough(self.inlines("content")?),
+            "link" => Inline::Link {
+                url: self.str("url")?.to_string(),
+                content: self.inlines("content")?,
+            },
+            "image" => Inline::Image {
+                url: self.str("url")?.to_string(),
+                alt: self.str("alt")?.to_string(),
+            },
+            "soft_break" => Inline::SoftBreak,
+            "line_break" => Inline::LineBreak,
+            other => return Err(self.error(&format!("unknown inline type \"{}\"", other))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARKDOWN: &str = "---\ntitle: Guide\ntags: [a, b]\n---\n# Guide\n\n\n- [x] **done** [link](https://x.org)\n  - nested\n\n| A | B |\n|:-:|---|\n| `1` | $x$ |\n\n> ```rust\n> let a = 1;\n> ```\n";
+
+    #[test]
+    fn test_export() {
+        let json = to_json(MARKDOWN);
+
+        assert_eq!(json["version"], 1);
+        assert_eq!(
+            json["metadata"],
+            json!({ "title": "Guide", "tags": ["a", "b"] })
+        );
+        let blocks = json["blocks"].as_array().unwrap();
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(
+            blocks[0],
+            json!({
+                "type": "heading",
+                "level": 1,
+                "content": [{ "type": "text", "text": "Guide" }],
+                "position": { "start_line": 5, "end_line": 5 },
+            })
+        );
+        assert_eq!(
+            blocks[1]["position"],
+            json!({ "start_line": 8, "end_line": 9 })
+        );
+        assert_eq!(blocks[1]["items"][0]["checked"], true);
+        assert_eq!(
+            blocks[1]["items"][0]["children"][0]["items"][0]["checked"],
+            Value::Null
+        );
+        assert_eq!(blocks[2]["alignments"], json!(["center", "none"]));
+        assert_eq!(
+            blocks[2]["rows"][0][1][0],
+            json!({ "type": "math", "math": "x" })
+        );
+        assert_eq!(blocks[3]["blocks"][0]["info"], "rust");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (metadata, document) = from_json(&to_json(MARKDOWN).to_string()).unwrap();
+
+        let (expected_metadata, body) = FrontMatter::split(MARKDOWN);
+        assert_eq!(metadata, expected_metadata);
+        assert_eq!(document, MarkdownParser::new().parse_document(body));
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let cases = [
+            ("[]", "document: expected an object"),
+            (r#"{"version": 2, "blocks": []}"#, "unsupported version 2"),
+            (
+                r#"{"blocks": [{"type": "aside"}]}"#,
+                r#"blocks[0]: unknown block type "aside""#,
+            ),
+            (
+                r#"{"blocks": [{"type": "paragraph", "content": [{"type": "strong", "content": [{"type": "text"}]}]}]}"#,
+                r#"blocks[0].content[0].content[0]: missing "text""#,
+            ),
+            (
+                r#"{"blocks": [{"type": "heading", "level": 7, "content": []}]}"#,
+                r#"blocks[0]: "level" must be from 1 to 6"#,
+            ),
+        ];
+        for (json, message) in cases {
+            let error = from_json(json).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("Invalid document JSON: {}", message)
+            );
+        }
+    }
+}
@@ -0,0 +1,509 @@
+use crate::ast::{Alignment, Block, CodeBlock, Document, Inline, List, ListItem, MathBlock, Table};
+use crate::crossref;
+use crate::render::Renderer;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CodeEnvironment {
+    /// `lstlisting` from the `listings` package.
+    #[default]
+    Listings,
+    /// `minted`, which highlights with Pygments and needs `-shell-escape`.
+    Minted,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LatexOptions {
+    pub code_environment: CodeEnvironment,
+}
+
+/// Renders a document as a complete LaTeX file: the preamble, then the body
+/// between `\begin{document}` and `\end{document}`. Math is passed through
+/// untouched, and images with a remote URL become links, since `graphicx`
+/// can only include local files.
+#[derive(Default)]
+pub struct LatexRenderer {
+    options: LatexOptions,
+    /// Everything before `\begin{document}`; the default template when `None`.
+    preamble: Option<String>,
+}
+
+impl LatexRenderer {
+    pub fn new(options: LatexOptions) -> Self {
+        Self {
+            options,
+            preamble: None,
+        }
+    }
+
+    /// Uses `preamble` instead of the default template. It must load the
+    /// packages the body needs (see [`LatexRenderer::default_preamble`]).
+    pub fn with_preamble(mut self, preamble: String) -> Self {
+        self.preamble = Some(preamble);
+        self
+    }
+
+    /// The bundled template, plus the package for the code environment.
+    pub fn default_preamble(&self) -> String {
+        let mut preamble = include_str!("../assets/preamble.tex").to_string();
+        match self.options.code_environment {
+            CodeEnvironment::Listings => preamble.push_str(
+                "\\usepackage{listings}\n\
+                 \\lstset{basicstyle=\\ttfamily\\small, breaklines=true, \
+                 columns=fullflexible, frame=single}\n",
+            ),
+            CodeEnvironment::Minted => preamble.push_str("\\usepackage{minted}\n"),
+        }
+        preamble
+    }
+
+    /// The preamble followed by `\begin{document}`.
+    pub fn document_start(&self) -> String {
+        let mut start = match &self.preamble {
+            Some(preamble) => preamble.clone(),
+            None => self.default_preamble(),
+        };
+        if !start.ends_with('\n') {
+            start.push('\n');
+        }
+        start.push_str("\n\\begin{document}\n\n");
+        start
+    }
+
+    pub fn document_end(&self) -> &'static str {
+        "\n\\end{document}\n"
+    }
+
+    fn figure(&self, url: &str, alt: &str, out: &mut String) {
+        out.push_str("\\begin{figure}[htbp]\n\\centering\n");
+        self.image(url, alt, out);
+        out.push('\n');
+        if !alt.is_empty() {
+            out.push_str("\\caption{");
+            push_text(out, alt);
+            out.push_str("}\n");
+        }
+        out.push_str("\\end{figure}\n");
+    }
+
+    fn environment(&self, name: &str, options: &str, render: impl FnOnce(&mut String)) -> String {
+        let mut out = format!("\\begin{{{}}}{}\n", name, options);
+        render(&mut out);
+        out.push_str(&format!("\\end{{{}}}\n", name));
+        out
+    }
+}
+
+impl Renderer for LatexRenderer {
+    fn render(&self, document: &Document) -> String {
+        let mut out = self.document_start();
+        self.blocks(&document.blocks, &mut out);
+        out.push_str(self.document_end());
+        out
+    }
+
+    fn render_to(&self, document: &Document, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(self.document_start().as_bytes())?;
+        for (i, block) in document.blocks.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b"\n")?;
+            }
+            self.render_block_to(block, writer)?;
+        }
+        writer.write_all(self.document_end().as_bytes())
+    }
+
+    fn blocks(&self, blocks: &[Block], out: &mut String) {
+        for (i, block) in blocks.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            self.block(block, out);
+        }
+    }
+
+    fn heading(&self, level: u8, content: &[Inline], out: &mut String) {
+        let command = match level {
+            1 => "section",
+            2 => "subsection",
+            3 => "subsubsection",
+            4 => "paragraph",
+            _ => "subparagraph",
+        };
+        out.push('\\');
+        out.push_str(command);
+        out.push('{');
+        self.inlines(content, out);
+        out.push_str("}\n");
+    }
+
+    fn paragraph(&self, content: &[Inline], out: &mut String) {
+        if let [Inline::Image { url, alt }] = content {
+            if is_local(url) {
+                return self.figure(url, alt, out);
+            }
+        }
+        self.inlines(content, out);
+        out.push('\n');
+    }
+
+    fn list(&self, list: &List, out: &mut String) {
+        let (name, options) = if !list.ordered {
+            ("itemize", String::new())
+        } else if list.start != 1 {
+            ("enumerate", format!("[start={}]", list.start))
+        } else {
+            ("enumerate", String::new())
+        };
+        out.push_str(&self.environment(name, &options, |body| {
+            for item in &list.items {
+                self.list_item(item, body);
+            }
+        }));
+    }
+
+    fn list_item(&self, item: &ListItem, out: &mut String) {
+        out.push_str(match item.checked {
+            Some(true) => "\\item[$\\boxtimes$] ",
+            Some(false) => "\\item[$\\square$] ",
+            None => "\\item ",
+        });
+        let mut content = String::new();
+        self.inlines(&item.content, &mut content);
+        // A bracket straight after `\item` would start its optional argument.
+        if item.checked.is_none() && content.starts_with('[') {
+            out.push_str("{}");
+        }
+        out.push_str(&content);
+        out.push('\n');
+        for child in &item.children {
+            self.block(child, out);
+        }
+    }
+
+    fn code_block(&self, code_block: &CodeBlock, out: &mut String) {
+        let (name, options) = match self.options.code_environment {
+            CodeEnvironment::Listings => (
+                "lstlisting",
+                code_block
+                    .language()
+                    .and_then(listings_language)
+                    .map(|language| format!("[language={}]", language))
+                    .unwrap_or_default(),
+            ),
+            CodeEnvironment::Minted => (
+                "minted",
+                format!(
+                    "{{{}}}",
+                    code_block.language().unwrap_or("text").to_lowercase()
+                ),
+            ),
+        };
+        out.push_str(&self.environment(name, &options, |body| {
+            body.push_str(&code_block.code);
+            if !code_block.code.is_empty() && !code_block.code.ends_with('\n') {
+                body.push('\n');
+            }
+        }));
+    }
+
+    fn table(&self, table: &Table, out: &mut String) {
+        let columns = table
+            .rows
+            .iter()
+            .map(Vec::len)
+            .chain([table.header.len(), table.alignments.len()])
+            .max()
+            .unwrap_or(0);
+        let spec: String = (0..columns)
+            .map(|i| match table.alignments.get(i) {
+                Some(Alignment::Center) => 'c',
+                Some(Alignment::Right) => 'r',
+                _ => 'l',
+            })
+            .collect();
+
+        let row = |cells: &[Vec<Inline>], out: &mut String| {
+            for i in 0..columns {
+                if i > 0 {
+                    out.push_str(" & ");
+                }
+                if let Some(cell) = cells.get(i) {
+                    self.inlines(cell, out);
+                }
+            }
+            out.push_str(" \\\\\n");
+        };
+
+        let tabular = self.environment("tabular", &format!("{{{}}}", spec), |body| {
+            body.push_str("\\toprule\n");
+            row(&table.header, body);
+            body.push_str("\\midrule\n");
+            for cells in &table.rows {
+                row(cells, body);
+            }
+            body.push_str("\\bottomrule\n");
+        });
+        out.push_str(&self.environment("center", "", |body| body.push_str(&tabular)));
+    }
+
+    fn blockquote(&self, blocks: &[Block], out: &mut String) {
+        out.push_str(&self.environment("quote", "", |body| self.blocks(blocks, body)));
+    }
+
+    /// Labelled equations are numbered, as `equation` environments.
+    fn math_block(&self, math_block: &MathBlock, out: &mut String) {
+        let math = math_block.math.trim();
+        if math_block.label().is_none() {
+            out.push_str("\\[\n");
+            out.push_str(math);
+            out.push_str("\n\\]\n");
+            return;
+        }
+        out.push_str("\\begin{equation}\n");
+        out.push_str(math);
+        // A `\label` in the math already names the equation.
+        match &math_block.id {
+            Some(id) if !math.contains("\\label{") => {
+                out.push_str("\n\\label{");
+                out.push_str(id);
+                out.push('}');
+            }
+            _ => {}
+        }
+        out.push_str("\n\\end{equation}\n");
+    }
+
+    fn horizontal_rule(&self, out: &mut String) {
+        out.push_str("\\noindent\\rule{\\linewidth}{0.4pt}\n");
+    }
+
+    /// References to equations become `\\eqref`s.
+    fn text(&self, text: &str, out: &mut String) {
+        let mut last = 0;
+        for (range, label) in crossref::references(text) {
+            push_text(out, &text[last..range.start]);
+            out.push_str("\\eqref{");
+            out.push_str(label);
+            out.push('}');
+            last = range.end;
+        }
+        push_text(out, &text[last..]);
+    }
+
+    fn code(&self, code: &str, out: &mut String) {
+        out.push_str("\\texttt{");
+        push_text(out, code);
+        out.push('}');
+    }
+
+    fn math(&self, math: &str, out: &mut String) {
+        out.push('$');
+        out.push_str(math);
+        out.push('$');
+    }
+
+    fn strong(&self, children: &[Inline], out: &mut String) {
+        command(self, "textbf", children, out);
+    }
+
+    fn emphasis(&self, children: &[Inline], out: &mut String) {
+        command(self, "emph", children, out);
+    }
+
+    fn strikethrough(&self, children: &[Inline], out: &mut String) {
+        command(self, "sout", children, out);
+    }
+
+    fn link(&self, url: &str, content: &[Inline], out: &mut String) {
+        out.push_str("\\href{");
+        push_url(out, url);
+        out.push_str("}{");
+        self.inlines(content, out);
+        out.push('}');
+    }
+
+    fn image(&self, url: &str, alt: &str, out: &mut String) {
+        if is_local(url) {
+            out.push_str(
+                "\\includegraphics[width=\\linewidth,height=0.5\\textheight,keepaspectratio]{",
+            );
+            out.push_str(url);
+            out.push('}');
+        } else {
+            out.push_str("\\href{");
+            push_url(out, url);
+            out.push_str("}{");
+            push_text(out, if alt.is_empty() { url } else { alt });
+            out.push('}');
+        }
+    }
+
+    fn soft_break(&self, out: &mut String) {
+        out.push('\n');
+    }
+
+    fn line_break(&self, out: &mut String) {
+        // Unlike `\\`, `\newline` takes no optional argument that a following
+        // `[` could be mistaken for.
+        out.push_str("\\newline\n");
+    }
+}
+
+fn command(renderer: &LatexRenderer, name: &str, children: &[Inline], out: &mut String) {
+    out.push('\\');
+    out.push_str(name);
+    out.push('{');
+    renderer.inlines(children, out);
+    out.push('}');
+}
+
+/// Whether `url` names a file `\includegraphics` can load.
+fn is_local(url: &str) -> bool {
+    !url.contains("://") && !url.starts_with("data:")
+}
+
+/// The `listings` name for a fence language, if the package knows it.
+fn listings_language(language: &str) -> Option<&'static str> {
+    Some(match language.to_lowercase().as_str() {
+        "c" | "h" => "C",
+        "c++" | "cpp" | "cxx" | "hpp" => "C++",
+        "java" => "Java",
+        "python" | "py" => "Python",
+        "ruby" | "rb" => "Ruby",
+        "perl" | "pl" => "Perl",
+        "php" => "PHP",
+        "bash" | "sh" | "shell" | "zsh" => "bash",
+        "html" => "HTML",
+        "xml" => "XML",
+        "sql" => "SQL",
+        "haskell" | "hs" => "Haskell",
+        "r" => "R",
+        "tex" | "latex" => "TeX",
+        "make" | "makefile" => "make",
+        "matlab" => "Matlab",
+        "fortran" => "Fortran",
+        "lisp" => "Lisp",
+        "pascal" => "Pascal",
+        _ => return None,
+    })
+}
+
+fn push_text(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            '{' | '}' | '$' | '&' | '#' | '_' | '%' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+/// Escapes the characters `\href` would otherwise read as TeX.
+fn push_url(out: &mut String, url: &str) {
+    for c in url.chars() {
+        if matches!(c, '#' | '%' | '{' | '}') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::MarkdownParser;
+
+    fn body(markdown: &str, options: LatexOptions) -> String {
+        let document = MarkdownParser::new().parse_document(markdown);
+        let mut out = String::new();
+        LatexRenderer::new(options).blocks(&document.blocks, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_structure() {
+        let latex = body(
+            "# Title\n\n## Costs & *50%* off\n\nSee [docs](https://x.org/#a) and $e^{i\\pi}$.\n\n$$\nx^2\n$$\n\n3. three\n4. [four]\n   - [x] done",
+            LatexOptions::default(),
+        );
+        assert_eq!(
+            latex,
+            "\\section{Title}\n\
+             \n\
+             \\subsection{Costs \\& \\emph{50\\%} off}\n\
+             \n\
+             See \\href{https://x.org/\\#a}{docs} and $e^{i\\pi}$.\n\
+             \n\
+             \\[\nx^2\n\\]\n\
+             \n\
+             \\begin{enumerate}[start=3]\n\
+             \\item three\n\
+             \\item {}[four]\n\
+             \\begin{itemize}\n\
+             \\item[$\\boxtimes$] done\n\
+             \\end{itemize}\n\
+             \\end{enumerate}\n"
+        );
+    }
+
+    #[test]
+    fn test_equations() {
+        let latex = body(
+            "$$ E = mc^2 $$ {#eq:energy}\n\n$$\na \\label{a}\n$$\n\nBy @eq:energy and \\eqref{a}.",
+            LatexOptions::default(),
+        );
+        assert_eq!(
+            latex,
+            "\\begin{equation}\nE = mc^2\n\\label{eq:energy}\n\\end{equation}\n\
+             \n\
+             \\begin{equation}\na \\label{a}\n\\end{equation}\n\
+             \n\
+             By \\eqref{eq:energy} and \\eqref{a}.\n"
+        );
+    }
+
+    #[test]
+    fn test_tables_code_and_figures() {
+        let markdown = "| a | b |\n|:-:|--:|\n| `x_1` |\n\n```python\nprint()\n```\n\n![A plot](plot.png)\n\n![Logo](https://x.org/logo.svg)";
+
+        let latex = body(markdown, LatexOptions::default());
+        assert!(latex.contains(
+            "\\begin{tabular}{cr}\n\\toprule\na & b \\\\\n\\midrule\n\\texttt{x\\_1} &  \\\\\n\\bottomrule\n\\end{tabular}"
+        ));
+        assert!(latex.contains("\\begin{lstlisting}[language=Python]\nprint()\n\\end{lstlisting}"));
+        assert!(latex.contains("\\begin{figure}[htbp]\n\\centering\n\\includegraphics["));
+        assert!(latex.contains("{plot.png}\n\\caption{A plot}\n\\end{figure}"));
+        assert!(latex.contains("\\href{https://x.org/logo.svg}{Logo}\n"));
+
+        let minted = body(
+            markdown,
+            LatexOptions {
+                code_environment: CodeEnvironment::Minted,
+            },
+        );
+        assert!(minted.contains("\\begin{minted}{python}\nprint()\n\\end{minted}"));
+    }
+
+    #[test]
+    fn test_complete_document() {
+        let document = MarkdownParser::new().parse_document("Hello");
+        let latex = LatexRenderer::default().render(&document);
+        assert!(latex.starts_with("\\documentclass"));
+        assert!(latex.contains("\\usepackage{listings}"));
+        assert!(latex.ends_with("\\begin{document}\n\nHello\n\n\\end{document}\n"));
+
+        let custom = LatexRenderer::default()
+            .with_preamble("\\documentclass{report}".to_string())
+            .render(&document);
+        assert_eq!(
+            custom,
+            "\\documentclass{report}\n\n\\begin{document}\n\nHello\n\n\\end{document}\n"
+        );
+    }
+}
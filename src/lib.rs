@@ -1,8 +1,29 @@
+pub mod ast;
+pub mod confluence;
 pub mod converter;
+pub mod crossref;
+pub mod docx;
+pub mod email;
+pub mod epub;
 pub mod error;
-
+pub mod formatter;
+pub mod front_matter;
+pub mod gemtext;
+pub mod html;
+pub mod html2md;
+pub mod json;
+pub mod latex;
+pub mod man;
+pub mod mathml;
+pub mod offline;
 pub mod parser;
-
+pub mod pdf;
+pub mod render;
+pub mod slides;
+pub mod template;
+pub mod terminal;
+pub mod tex;
+pub mod text;
 pub mod utils;
 
 #[cfg(test)]
@@ -1,30 +1,369 @@
 use anyhow::Result;
+use clap::{Parser, Subcommand};
 use colored::*;
 use console::Term;
-use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect};
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect, Select};
 use log::error;
-use markdown_converter::{converter, utils};
-use std::path::PathBuf;
+use markdown_converter::converter::OutputFormat;
+use markdown_converter::docx::DocxOptions;
+use markdown_converter::formatter::MarkdownFormatter;
+use markdown_converter::html::HighlightOptions;
+use markdown_converter::latex::{CodeEnvironment, LatexOptions};
+use markdown_converter::pdf::{PaperSize, PdfOptions};
+use markdown_converter::slides::SlideSplit;
+use markdown_converter::text::{TableStyle, TextOptions};
+use markdown_converter::{converter, html2md, utils};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Converts Markdown to HTML. Without a subcommand, the options are
+/// gathered interactively.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert a Markdown file without prompting for options
+    Convert {
+        /// Markdown file to convert, or a document exported as JSON; EPUB
+        /// output takes several, one per chapter
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Output file, or "-" for stdout [default: the input file with the
+        /// format's extension]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Output format: html, text, latex, pdf, epub, docx, man, json,
+        /// terminal, slides, gemtext, confluence or email [default: from the
+        /// output file's extension, or html]
+        #[arg(short, long)]
+        format: Option<OutputFormat>,
+        /// Custom CSS file for HTML, EPUB or email output, or to add to the
+        /// slides' styles
+        #[arg(long)]
+        css: Option<PathBuf>,
+        /// Leave code blocks in HTML output unhighlighted
+        #[arg(long)]
+        no_highlight: bool,
+        /// Colour theme of highlighted code in HTML pages: a bundled syntect
+        /// theme or one from --highlight-dir [default: base16-ocean.dark]
+        #[arg(long)]
+        highlight_theme: Option<String>,
+        /// Theme for readers who prefer dark colours, with --highlight-classes
+        #[arg(long)]
+        highlight_dark_theme: Option<String>,
+        /// Directory of .tmTheme themes and .sublime-syntax definitions to
+        /// highlight with as well as the bundled ones
+        #[arg(long)]
+        highlight_dir: Option<PathBuf>,
+        /// Mark up highlighted code with CSS classes and add a stylesheet for
+        /// them, instead of inline colours
+        #[arg(long)]
+        highlight_classes: bool,
+        /// Start HTML or PDF output with a table of contents
+        #[arg(long)]
+        toc: bool,
+        /// Minify HTML output
+        #[arg(long)]
+        minify: bool,
+        /// Write only the body of HTML output, to embed in another page
+        #[arg(long)]
+        fragment: bool,
+        /// HTML page template, or a directory with a page.html and the
+        /// files it includes
+        #[arg(long)]
+        template: Option<PathBuf>,
+        /// Leave math in HTML output for KaTeX to typeset in the browser
+        /// instead of writing MathML
+        #[arg(long)]
+        katex: bool,
+        /// Make HTML output work offline: inline local images and never
+        /// load KaTeX
+        #[arg(long)]
+        self_contained: bool,
+        /// Wrap plain-text or terminal output at this many columns
+        #[arg(long)]
+        width: Option<usize>,
+        /// Line up the columns of plain-text tables instead of drawing boxes
+        #[arg(long)]
+        aligned_tables: bool,
+        /// LaTeX preamble to use instead of the default one
+        #[arg(long)]
+        preamble: Option<PathBuf>,
+        /// Typeset LaTeX code blocks with minted instead of listings
+        #[arg(long)]
+        minted: bool,
+        /// Paper size of PDF or Word output: a4 or letter
+        #[arg(long, default_value = "a4")]
+        paper: PaperSize,
+        /// Word document whose styles Word output uses
+        #[arg(long)]
+        reference_doc: Option<PathBuf>,
+        /// Where slides output starts a new slide: at horizontal rules, or
+        /// also at level 2 headings
+        #[arg(long, default_value = "rules")]
+        slide_split: SlideSplit,
+        /// Convert again whenever the input file changes
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Show a Markdown file in the terminal, with colours and styles
+    Preview {
+        /// Markdown file to show, or a document exported as JSON
+        input: PathBuf,
+        /// Wrap at this many columns [default: the terminal's width]
+        #[arg(long)]
+        width: Option<usize>,
+        /// Leave code blocks unhighlighted
+        #[arg(long)]
+        no_highlight: bool,
+    },
+    /// Convert HTML pages to Markdown
+    Html2md {
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Output file, or "-" for stdout, when converting one page [default:
+        /// each input file with the .md extension]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Wrap paragraphs at this many columns instead of putting each on
+        /// one line
+        #[arg(long)]
+        width: Option<usize>,
+    },
+    /// Rewrite Markdown files in a consistent style
+    Fmt {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Report files that are not formatted instead of rewriting them, and
+        /// exit with an error if there are any
+        #[arg(long)]
+        check: bool,
+        /// Wrap paragraphs at this many columns instead of putting each on
+        /// one line
+        #[arg(long)]
+        width: Option<usize>,
+    },
+}
 
 struct ConversionOptions {
     input_path: PathBuf,
     output_path: PathBuf,
     css_path: Option<PathBuf>,
     syntax_highlight: bool,
+    highlight_options: HighlightOptions,
     generate_toc: bool,
     minify: bool,
+    fragment: bool,
+    template_path: Option<PathBuf>,
+    katex: bool,
+    self_contained: bool,
     watch: bool,
+    format: OutputFormat,
+    text_options: TextOptions,
+    latex_options: LatexOptions,
+    preamble_path: Option<PathBuf>,
+    pdf_options: PdfOptions,
+    chapters: Vec<PathBuf>,
+    docx_options: DocxOptions,
+    reference_doc: Option<PathBuf>,
+    slide_split: SlideSplit,
 }
 
 fn main() -> Result<()> {
-    env_logger::init();
+    // Warnings carry the diagnostics for TeX and equation labels, so they
+    // are shown unless RUST_LOG says otherwise.
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    match Cli::parse().command {
+        Some(Command::Convert {
+            mut inputs,
+            output,
+            format,
+            css,
+            no_highlight,
+            highlight_theme,
+            highlight_dark_theme,
+            highlight_dir,
+            highlight_classes,
+            toc,
+            minify,
+            fragment,
+            template,
+            katex,
+            self_contained,
+            width,
+            aligned_tables,
+            preamble,
+            minted,
+            paper,
+            reference_doc,
+            slide_split,
+            watch,
+        }) => {
+            let input = inputs.remove(0);
+            let format = format
+                .or_else(|| {
+                    let extension = output.as_deref()?.extension()?.to_str()?;
+                    OutputFormat::from_extension(extension)
+                })
+                .unwrap_or_default();
+            if !inputs.is_empty() && format != OutputFormat::Epub {
+                error!(
+                    "{}",
+                    "Only EPUB output takes more than one input file!".red()
+                );
+                std::process::exit(1);
+            }
+            run(ConversionOptions {
+                output_path: output.unwrap_or_else(|| input.with_extension(format.extension())),
+                input_path: input,
+                css_path: css,
+                syntax_highlight: !no_highlight,
+                highlight_options: HighlightOptions {
+                    theme: highlight_theme,
+                    dark_theme: highlight_dark_theme,
+                    dir: highlight_dir,
+                    classes: highlight_classes,
+                },
+                generate_toc: toc,
+                minify,
+                fragment,
+                template_path: template,
+                katex,
+                self_contained,
+                watch,
+                format,
+                text_options: TextOptions {
+                    width,
+                    table_style: if aligned_tables {
+                        TableStyle::Aligned
+                    } else {
+                        TableStyle::Box
+                    },
+                },
+                latex_options: LatexOptions {
+                    code_environment: if minted {
+                        CodeEnvironment::Minted
+                    } else {
+                        CodeEnvironment::Listings
+                    },
+                },
+                preamble_path: preamble,
+                pdf_options: PdfOptions { paper, toc },
+                chapters: inputs,
+                docx_options: DocxOptions { paper },
+                reference_doc,
+                slide_split,
+            })
+        }
+        Some(Command::Preview {
+            input,
+            width,
+            no_highlight,
+        }) => preview(input, width, !no_highlight),
+        Some(Command::Html2md {
+            inputs,
+            output,
+            width,
+        }) => convert_html(&inputs, output, width),
+        Some(Command::Fmt {
+            files,
+            check,
+            width,
+        }) => format_files(&files, check, width),
+        None => run_interactive(),
+    }
+}
+
+fn preview(input: PathBuf, width: Option<usize>, syntax_highlight: bool) -> Result<()> {
+    if !input.exists() {
+        error!("{}", "Input file does not exist!".red());
+        std::process::exit(1);
+    }
+
+    let width = width.or_else(|| Term::stdout().size_checked().map(|(_, cols)| cols as usize));
+    let result =
+        converter::MarkdownConverter::new(input, None, None, syntax_highlight, false, false)?
+            .with_format(OutputFormat::Terminal)
+            .with_text_options(TextOptions {
+                width,
+                ..TextOptions::default()
+            })
+            .convert_to(io::stdout().lock());
+    ignore_broken_pipe(result)
+}
+
+/// Treats output to stdout cut short as a success: quitting a pager such
+/// as `head` or `less` early is not an error.
+fn ignore_broken_pipe(result: Result<()>) -> Result<()> {
+    match result {
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+fn convert_html(inputs: &[PathBuf], output: Option<PathBuf>, width: Option<usize>) -> Result<()> {
+    if output.is_some() && inputs.len() > 1 {
+        error!(
+            "{}",
+            "An output file can only be given for one input!".red()
+        );
+        std::process::exit(1);
+    }
+
+    for input in inputs {
+        let output = output.clone().unwrap_or_else(|| input.with_extension("md"));
+        ignore_broken_pipe(html2md::convert_file(input, &output, width))?;
+        if output != Path::new("-") {
+            println!("{} {}", "Converted".green(), output.display());
+        }
+    }
+    Ok(())
+}
+
+fn format_files(files: &[PathBuf], check: bool, width: Option<usize>) -> Result<()> {
+    let formatter = MarkdownFormatter::new(width);
+    let mut unformatted = 0;
+
+    for file in files {
+        if formatter.format_file(file, check)? {
+            unformatted += 1;
+            if check {
+                println!("{} {}", "Not formatted:".yellow(), file.display());
+            } else {
+                println!("{} {}", "Formatted".green(), file.display());
+            }
+        }
+    }
+
+    if check && unformatted > 0 {
+        error!("{} of {} files need formatting", unformatted, files.len());
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_interactive() -> Result<()> {
     let _term = Term::stdout();
     let theme = ColorfulTheme::default();
 
     print_banner();
 
     let options = gather_options(&theme)?;
+    run(options)
+}
 
+fn run(options: ConversionOptions) -> Result<()> {
     if !options.input_path.exists() {
         error!("{}", "Input file does not exist!".red());
         std::process::exit(1);
@@ -37,15 +376,27 @@ fn main() -> Result<()> {
         options.syntax_highlight,
         options.generate_toc,
         options.minify,
-    )?;
+    )?
+    .with_format(options.format)
+    .with_highlight_options(options.highlight_options)
+    .with_fragment(options.fragment)
+    .with_template(options.template_path)
+    .with_katex(options.katex)
+    .with_self_contained(options.self_contained)
+    .with_text_options(options.text_options)
+    .with_latex_options(options.latex_options)
+    .with_preamble(options.preamble_path)
+    .with_pdf_options(options.pdf_options)
+    .with_chapters(options.chapters)
+    .with_docx_options(options.docx_options)
+    .with_reference_doc(options.reference_doc)
+    .with_slide_split(options.slide_split);
 
     if options.watch {
-        converter.watch()?;
+        converter.watch()
     } else {
-        converter.convert()?;
+        ignore_broken_pipe(converter.convert())
     }
-
-    Ok(())
 }
 
 fn gather_options(theme: &ColorfulTheme) -> Result<ConversionOptions> {
@@ -63,14 +414,22 @@ fn gather_options(theme: &ColorfulTheme) -> Result<ConversionOptions> {
         })
         .interact_text()?;
 
+    let formats = OutputFormat::ALL.map(OutputFormat::name);
+    let format = Select::with_theme(theme)
+        .with_prompt("Select output format")
+        .items(&formats)
+        .default(0)
+        .interact()?;
+    let format = OutputFormat::ALL[format];
+
     let output_path: String = Input::with_theme(theme)
-        .with_prompt("Enter output HTML file path (or press Enter for default)")
+        .with_prompt("Enter output file path (\"-\" for stdout, or press Enter for default)")
         .allow_empty(true)
         .interact_text()?;
 
     let output_path = if output_path.is_empty() {
         let mut path = PathBuf::from(&input_path);
-        path.set_extension("html");
+        path.set_extension(format.extension());
         path
     } else {
         PathBuf::from(output_path)
@@ -110,13 +469,31 @@ fn gather_options(theme: &ColorfulTheme) -> Result<ConversionOptions> {
         output_path,
         css_path,
         syntax_highlight: selections.contains(&0),
+        highlight_options: HighlightOptions::default(),
         generate_toc: selections.contains(&1),
         minify: selections.contains(&2),
+        fragment: false,
+        template_path: None,
+        katex: false,
+        self_contained: false,
         watch: selections.contains(&3),
+        format,
+        text_options: TextOptions::default(),
+        latex_options: LatexOptions::default(),
+        preamble_path: None,
+        pdf_options: PdfOptions {
+            toc: selections.contains(&1),
+            ..PdfOptions::default()
+        },
+        chapters: Vec::new(),
+        docx_options: DocxOptions::default(),
+        reference_doc: None,
+        slide_split: SlideSplit::default(),
     };
 
     println!("\n{}", "Configuration Summary:".bright_blue());
     println!("Input file: {:?}", options.input_path);
+    println!("Output format: {}", options.format.name());
     println!("Output file: {:?}", options.output_path);
     println!("CSS file: {:?}", options.css_path);
     println!("Syntax highlighting: {}", options.syntax_highlight);
@@ -170,9 +547,23 @@ mod tests {
             output_path: PathBuf::from("test.html"),
             css_path: None,
             syntax_highlight: true,
+            highlight_options: HighlightOptions::default(),
             generate_toc: false,
             minify: false,
+            fragment: false,
+            template_path: None,
+            katex: false,
+            self_contained: false,
             watch: false,
+            format: OutputFormat::Html,
+            text_options: TextOptions::default(),
+            latex_options: LatexOptions::default(),
+            preamble_path: None,
+            pdf_options: PdfOptions::default(),
+            chapters: Vec::new(),
+            docx_options: DocxOptions::default(),
+            reference_doc: None,
+            slide_split: SlideSplit::default(),
         };
         assert!(!options.watch);
         assert!(options.syntax_highlight);
@@ -0,0 +1,356 @@
+use crate::ast::{
+    plain_text, Alignment, Block, CodeBlock, Inline, List, ListItem, MathBlock, Table,
+};
+use crate::front_matter::FrontMatter;
+use crate::render::Renderer;
+use std::cell::Cell;
+
+/// Renders a document as a roff man page, for `man -l` or installing under
+/// `man1/`.
+///
+/// Level 1 headings become `.SH` sections (in capitals, as is customary) and
+/// level 2 `.SS` subsections. A bulleted list whose every item starts with a
+/// code span or bold text followed by a description, as options are usually
+/// documented, becomes a `.TP` definition list:
+///
+/// ```markdown
+/// - `--output <file>`: Write the page to *file*.
+/// ```
+///
+/// Tables are written for `tbl`, which `man` runs on pages starting with the
+/// `'\" t` line that [`ManRenderer::title_header`] emits.
+#[derive(Default)]
+pub struct ManRenderer {
+    bold: Cell<bool>,
+    italic: Cell<bool>,
+    /// Whether text is being set in capitals, for section headings.
+    upper: Cell<bool>,
+}
+
+impl ManRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The `.TH` line starting the page, from the `title`, `section`, `date`,
+    /// `source` and `manual` keys of the front matter. The title defaults to
+    /// `name` and the section to 1.
+    pub fn title_header(front_matter: &FrontMatter, name: &str) -> String {
+        let mut header = String::from("'\\\" t\n.TH");
+        let title = front_matter.get("title").unwrap_or(name).to_uppercase();
+        let section = front_matter.get("section").unwrap_or("1");
+        let fields = [
+            Some(title.as_str()),
+            Some(section),
+            front_matter.get("date"),
+            front_matter.get("source"),
+            front_matter.get("manual"),
+        ];
+        let last = fields.iter().rposition(Option::is_some).unwrap_or(0);
+        for field in &fields[..=last] {
+            header.push_str(" \"");
+            header.push_str(&escape(field.unwrap_or("")).replace('"', "\\(dq"));
+            header.push('"');
+        }
+        header.push('\n');
+        header
+    }
+
+    /// Switches to the current font.
+    fn push_font(&self, out: &mut String) {
+        out.push_str(match (self.bold.get(), self.italic.get()) {
+            (false, false) => "\\fR",
+            (true, false) => "\\fB",
+            (false, true) => "\\fI",
+            (true, true) => "\\f(BI",
+        });
+    }
+
+    fn with_font(&self, font: &Cell<bool>, out: &mut String, render: impl FnOnce(&mut String)) {
+        let outer = font.replace(true);
+        self.push_font(out);
+        render(out);
+        font.set(outer);
+        self.push_font(out);
+    }
+
+    /// The term and description of a `.TP` list item, if `item` is written
+    /// like one.
+    fn definition(item: &ListItem) -> Option<(&Inline, Vec<Inline>)> {
+        let (term @ (Inline::Code(_) | Inline::Strong(_)), rest) = item.content.split_first()?
+        else {
+            return None;
+        };
+        let mut description = rest.to_vec();
+        if let Some(Inline::Text(text)) = description.first_mut() {
+            *text = text
+                .trim_start()
+                .trim_start_matches([':', '-', '—', '–'])
+                .trim_start()
+                .to_string();
+            if text.is_empty() {
+                description.remove(0);
+            }
+        }
+        (!description.is_empty()).then_some((term, description))
+    }
+}
+
+/// Escapes the characters roff treats specially within a line.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\e").replace('-', "\\-")
+}
+
+/// Appends `text`, escaped, guarding a leading `.` or `'` that would make the
+/// line a request.
+fn push_line_text(out: &mut String, text: &str) {
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        if (out.is_empty() || out.ends_with('\n')) && line.starts_with(['.', '\'']) {
+            out.push_str("\\&");
+        }
+        out.push_str(&escape(line));
+    }
+}
+
+/// A block of literal lines, indented and without filling.
+fn push_literal(out: &mut String, text: &str) {
+    out.push_str(".PP\n.RS 4\n.nf\n");
+    push_line_text(out, text.trim_end_matches('\n'));
+    out.push_str("\n.fi\n.RE\n");
+}
+
+impl Renderer for ManRenderer {
+    fn heading(&self, level: u8, content: &[Inline], out: &mut String) {
+        match level {
+            1 => {
+                out.push_str(".SH\n");
+                self.upper.set(true);
+                self.inlines(content, out);
+                self.upper.set(false);
+            }
+            2 => {
+                out.push_str(".SS\n");
+                self.inlines(content, out);
+            }
+            _ => {
+                out.push_str(".PP\n");
+                self.with_font(&self.bold, out, |out| self.inlines(content, out));
+            }
+        }
+        out.push('\n');
+    }
+
+    fn paragraph(&self, content: &[Inline], out: &mut String) {
+        out.push_str(".PP\n");
+        self.inlines(content, out);
+        out.push('\n');
+    }
+
+    fn list(&self, list: &List, out: &mut String) {
+        let definitions: Option<Vec<_>> = (!list.ordered)
+            .then(|| list.items.iter().map(Self::definition).collect())
+            .flatten();
+        if let Some(definitions) = definitions {
+            for (item, (term, description)) in list.items.iter().zip(definitions) {
+                out.push_str(".TP\n");
+                self.inline(term, out);
+                out.push('\n');
+                self.inlines(&description, out);
+                out.push('\n');
+                self.nested(&item.children, out);
+            }
+            return;
+        }
+
+        for (i, item) in list.items.iter().enumerate() {
+            let tag = match item.checked {
+                Some(true) => "\"[x]\" 4".to_string(),
+                Some(false) => "\"[ ]\" 4".to_string(),
+                None if list.ordered => format!("{}. 4", list.start + i as u64),
+                None => "\\(bu 2".to_string(),
+            };
+            out.push_str(&format!(".IP {}\n", tag));
+            self.list_item(item, out);
+        }
+    }
+
+    fn list_item(&self, item: &ListItem, out: &mut String) {
+        self.inlines(&item.content, out);
+        out.push('\n');
+        self.nested(&item.children, out);
+    }
+
+    fn code_block(&self, code_block: &CodeBlock, out: &mut String) {
+        push_literal(out, &code_block.code);
+    }
+
+    fn table(&self, table: &Table, out: &mut String) {
+        out.push_str(".TS\ntab(\t) box;\n");
+        let formats: Vec<&str> = table
+            .alignments
+            .iter()
+            .map(|alignment| match alignment {
+                Alignment::None | Alignment::Left => "l",
+                Alignment::Center => "c",
+                Alignment::Right => "r",
+            })
+            .collect();
+        let header: Vec<String> = formats.iter().map(|f| format!("{}B", f)).collect();
+        out.push_str(&header.join(" "));
+        out.push('\n');
+        out.push_str(&formats.join(" "));
+        out.push_str(".\n");
+
+        for (i, row) in std::iter::once(&table.header)
+            .chain(&table.rows)
+            .enumerate()
+        {
+            for (j, cell) in row.iter().enumerate() {
+                if j > 0 {
+                    out.push('\t');
+                }
+                // A cell of only `_` or `=` would draw a rule.
+                if matches!(plain_text(cell).as_str(), "_" | "=") {
+                    out.push_str("\\&");
+                }
+                self.inlines(cell, out);
+            }
+            out.push('\n');
+            if i == 0 {
+                out.push_str("_\n");
+            }
+        }
+        out.push_str(".TE\n");
+    }
+
+    fn blockquote(&self, blocks: &[Block], out: &mut String) {
+        self.nested(blocks, out);
+    }
+
+    fn math_block(&self, math_block: &MathBlock, out: &mut String) {
+        push_literal(out, math_block.math.trim());
+    }
+
+    fn horizontal_rule(&self, out: &mut String) {
+        out.push_str(".sp\n");
+    }
+
+    fn text(&self, text: &str, out: &mut String) {
+        let text = text.replace('\t', " ");
+        if self.upper.get() {
+            push_line_text(out, &text.to_uppercase());
+        } else {
+            push_line_text(out, &text);
+        }
+    }
+
+    fn code(&self, code: &str, out: &mut String) {
+        self.with_font(&self.bold, out, |out| self.text(code, out));
+    }
+
+    fn math(&self, math: &str, out: &mut String) {
+        self.with_font(&self.italic, out, |out| self.text(math, out));
+    }
+
+    fn strong(&self, children: &[Inline], out: &mut String) {
+        self.with_font(&self.bold, out, |out| self.inlines(children, out));
+    }
+
+    fn emphasis(&self, children: &[Inline], out: &mut String) {
+        self.with_font(&self.italic, out, |out| self.inlines(children, out));
+    }
+
+    fn strikethrough(&self, children: &[Inline], out: &mut String) {
+        self.inlines(children, out);
+    }
+
+    fn link(&self, url: &str, content: &[Inline], out: &mut String) {
+        self.inlines(content, out);
+        let target = url.strip_prefix("mailto:").unwrap_or(url);
+        if !url.starts_with('#') && plain_text(content) != target {
+            out.push_str(" \\(la");
+            self.text(url, out);
+            out.push_str("\\(ra");
+        }
+    }
+
+    fn image(&self, _url: &str, alt: &str, out: &mut String) {
+        self.text(alt, out);
+    }
+
+    fn soft_break(&self, out: &mut String) {
+        out.push('\n');
+    }
+
+    fn line_break(&self, out: &mut String) {
+        out.push_str("\n.br\n");
+    }
+}
+
+impl ManRenderer {
+    /// Renders `blocks` indented relative to the current margin.
+    fn nested(&self, blocks: &[Block], out: &mut String) {
+        if blocks.is_empty() {
+            return;
+        }
+        out.push_str(".RS\n");
+        self.blocks(blocks, out);
+        out.push_str(".RE\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::MarkdownParser;
+
+    fn render(markdown: &str) -> String {
+        ManRenderer::new().render(&MarkdownParser::new().parse_document(markdown))
+    }
+
+    #[test]
+    fn test_title_header() {
+        let (front_matter, _) = FrontMatter::split(
+            "---\ntitle: md-convert\nsection: 8\nmanual: \"System \"Admin\"\"\n---\n",
+        );
+        assert_eq!(
+            ManRenderer::title_header(&front_matter, "ignored"),
+            "'\\\" t\n.TH \"MD\\-CONVERT\" \"8\" \"\" \"\" \"System \\(dqAdmin\\(dq\"\n"
+        );
+        assert_eq!(
+            ManRenderer::title_header(&FrontMatter::default(), "tool"),
+            "'\\\" t\n.TH \"TOOL\" \"1\"\n"
+        );
+    }
+
+    #[test]
+    fn test_sections_and_options() {
+        let result = render(
+            "# Options\n\n- `--output <file>`: Write the page to *file*.\n- **-q** Be quiet.\n\n## Exit status\n\n.5 and *nested **bold** text*\nnext  \nline",
+        );
+        assert_eq!(
+            result,
+            ".SH\nOPTIONS\n\
+             .TP\n\\fB\\-\\-output <file>\\fR\nWrite the page to \\fIfile\\fR.\n\
+             .TP\n\\fB\\-q\\fR\nBe quiet.\n\
+             .SS\nExit status\n\
+             .PP\n\\&.5 and \\fInested \\f(BIbold\\fI text\\fR\nnext\n.br\nline\n"
+        );
+    }
+
+    #[test]
+    fn test_lists_code_and_tables() {
+        let result = render(
+            "1. First\n   - inner\n2. Second\n\n```\n.hidden \\n\n```\n\n| Key | Value |\n|:---|---:|\n| a | 1 |",
+        );
+        assert_eq!(
+            result,
+            ".IP 1. 4\nFirst\n.RS\n.IP \\(bu 2\ninner\n.RE\n.IP 2. 4\nSecond\n\
+             .PP\n.RS 4\n.nf\n\\&.hidden \\en\n.fi\n.RE\n\
+             .TS\ntab(\t) box;\nlB rB\nl r.\nKey\tValue\n_\na\t1\n.TE\n"
+        );
+    }
+}
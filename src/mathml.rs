@@ -0,0 +1,1182 @@
+use crate::ast::{Block, Inline, Position};
+use crate::front_matter::FrontMatter;
+use crate::parser::MarkdownParser;
+use crate::tex::{self, Font, Symbol, FUNCTIONS};
+use std::fmt;
+
+/// The namespace MathML needs in XHTML; HTML puts `<math>` in it anyway.
+pub const NAMESPACE: &str = "http://www.w3.org/1998/Math/MathML";
+
+/// A syntax error in TeX, on a line counted from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TexError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Converts TeX to a MathML `<math>` element, with the TeX kept as its
+/// `alttext`. `display` sets it as a block rather than inline.
+pub fn tex_to_mathml(tex: &str, display: bool) -> Result<String, TexError> {
+    let body = Parser::new(tex, display).parse()?;
+    Ok(math_element(tex, display, &body))
+}
+
+/// Like [`tex_to_mathml`], but TeX with errors is shown as written, marked
+/// as an error, so one bad formula does not stop the rest of the page.
+pub fn typeset(tex: &str, display: bool) -> String {
+    tex_to_mathml(tex, display).unwrap_or_else(|_| {
+        let error = format!(
+            "<merror><mtext>{}</mtext></merror>",
+            html_escape::encode_text(tex)
+        );
+        math_element(tex, display, &error)
+    })
+}
+
+fn math_element(tex: &str, display: bool, body: &str) -> String {
+    format!(
+        "<math{} alttext=\"{}\">{}</math>",
+        if display { " display=\"block\"" } else { "" },
+        html_escape::encode_double_quoted_attribute(tex),
+        body
+    )
+}
+
+/// The TeX errors in the math of the Markdown file `markdown`, numbered by
+/// the lines of the file they are on.
+pub fn errors(markdown: &str) -> Vec<TexError> {
+    let body = FrontMatter::split(markdown).1;
+    let front_matter_lines = markdown[..markdown.len() - body.len()]
+        .matches('\n')
+        .count();
+    let lines: Vec<&str> = body.lines().collect();
+
+    let mut errors = Vec::new();
+    for (block, position) in MarkdownParser::new().blocks(body).with_positions() {
+        for mut error in block_errors(&block, position, &lines) {
+            error.line += front_matter_lines;
+            errors.push(error);
+        }
+    }
+    errors
+}
+
+/// The TeX errors in the math of `block`, parsed from `position` in
+/// `lines`, numbered by those lines.
+pub fn block_errors(block: &Block, position: Position, lines: &[&str]) -> Vec<TexError> {
+    let mut math = Vec::new();
+    block_math(block, &mut math);
+    // Find each formula's first line in the block, in order, to count its
+    // lines from.
+    let mut line = position.start_line;
+    let mut errors = Vec::new();
+    for (tex, display) in math {
+        let first = tex.lines().next().unwrap_or_default().trim();
+        if let Some(found) = (line..=position.end_line).find(|&n| lines[n - 1].contains(first)) {
+            line = found;
+        }
+        if let Err(mut error) = tex_to_mathml(tex, display) {
+            error.line += line - 1;
+            errors.push(error);
+        }
+    }
+    errors
+}
+
+/// Collects the math in `block`, with whether each is displayed.
+fn block_math<'a>(block: &'a Block, math: &mut Vec<(&'a str, bool)>) {
+    match block {
+        Block::Heading { content, .. } | Block::Paragraph(content) => inline_math(content, math),
+        Block::List(list) => {
+            for item in &list.items {
+                inline_math(&item.content, math);
+                for child in &item.children {
+                    block_math(child, math);
+                }
+            }
+        }
+        Block::Table(table) => {
+            for cell in table.header.iter().chain(table.rows.iter().flatten()) {
+                inline_math(cell, math);
+            }
+        }
+        Block::Blockquote(blocks) => {
+            for block in blocks {
+                block_math(block, math);
+            }
+        }
+        Block::MathBlock(math_block) => math.push((&math_block.math, true)),
+        Block::CodeBlock(_) | Block::HorizontalRule => {}
+    }
+}
+
+fn inline_math<'a>(inlines: &'a [Inline], math: &mut Vec<(&'a str, bool)>) {
+    for inline in inlines {
+        match inline {
+            Inline::Math(tex) => math.push((tex, false)),
+            Inline::Strong(children)
+            | Inline::Emphasis(children)
+            | Inline::Strikethrough(children)
+            | Inline::Link {
+                content: children, ..
+            } => inline_math(children, math),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token<'a> {
+    /// A command, named without its backslash.
+    Command(&'a str),
+    Char(char),
+}
+
+/// Splits TeX into commands and characters, each with its byte offset.
+/// Whitespace and comments are dropped.
+fn tokenize(tex: &str) -> Vec<(Token<'_>, usize)> {
+    let mut tokens = Vec::new();
+    let mut at = 0;
+    while let Some(c) = tex[at..].chars().next() {
+        match c {
+            '\\' => {
+                let name = tex::command_name(&tex[at + 1..]);
+                tokens.push((Token::Command(name), at));
+                at += 1 + name.len();
+                continue;
+            }
+            '%' => {
+                at += tex[at..].find('\n').unwrap_or(tex.len() - at);
+                continue;
+            }
+            c if c.is_whitespace() => {}
+            c => tokens.push((Token::Char(c), at)),
+        }
+        at += c.len_utf8();
+    }
+    tokens
+}
+
+/// A parsed piece of math, with how scripts attach to it.
+struct Atom {
+    markup: String,
+    /// Whether scripts go under and over it in display math, as on `\sum`.
+    limits: bool,
+    /// Whether it names a function, applied to what follows.
+    function: bool,
+}
+
+impl Atom {
+    fn new(markup: String) -> Self {
+        Self {
+            markup,
+            limits: false,
+            function: false,
+        }
+    }
+}
+
+/// Operators whose scripts are limits in display math.
+const LIMITS: &[&str] = &[
+    "sum",
+    "prod",
+    "coprod",
+    "bigcup",
+    "bigcap",
+    "bigoplus",
+    "bigotimes",
+    "bigvee",
+    "bigwedge",
+    "bigsqcup",
+    "lim",
+    "liminf",
+    "limsup",
+    "max",
+    "min",
+    "sup",
+    "inf",
+    "det",
+    "gcd",
+    "Pr",
+];
+
+/// Symbols that are delimiters, which keep their size.
+const FENCES: &[&str] = &[
+    "langle", "rangle", "lfloor", "rfloor", "lceil", "rceil", "lbrace", "rbrace", "lbrack",
+    "rbrack", "|", "Vert", "lVert", "rVert", "vert", "lvert", "rvert", "{", "}",
+];
+
+struct Parser<'a> {
+    tex: &'a str,
+    tokens: Vec<(Token<'a>, usize)>,
+    pos: usize,
+    display: bool,
+    font: Font,
+    /// How many `[` optional arguments are open, which `]` closes.
+    brackets: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tex: &'a str, display: bool) -> Self {
+        Self {
+            tex,
+            tokens: tokenize(tex),
+            pos: 0,
+            display,
+            font: Font::Italic,
+            brackets: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).map(|&(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<(Token<'a>, usize)> {
+        let token = self.tokens.get(self.pos).copied();
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, offset: usize, message: impl Into<String>) -> TexError {
+        TexError {
+            line: self.tex[..offset].matches('\n').count() + 1,
+            message: message.into(),
+        }
+    }
+
+    /// The error for `token` where it cannot go.
+    fn unexpected(&self, token: Token, offset: usize) -> TexError {
+        let message = match token {
+            Token::Char('}') => "Unmatched `}`".to_string(),
+            Token::Char('&') => "Misplaced `&`".to_string(),
+            Token::Command("\\") => "Misplaced `\\\\`".to_string(),
+            Token::Command("end") => "`\\end` without `\\begin`".to_string(),
+            Token::Command("right") => "`\\right` without `\\left`".to_string(),
+            Token::Char(c) => format!("Unexpected `{}`", c),
+            Token::Command(name) => format!("Unexpected `\\{}`", name),
+        };
+        self.error(offset, message)
+    }
+
+    /// Parses the whole expression. Lines split by `\\` are stacked.
+    fn parse(mut self) -> Result<String, TexError> {
+        let mut lines = vec![self.row()?];
+        while let Some((token, offset)) = self.next() {
+            match token {
+                Token::Command("\\") => {
+                    self.skip_optional();
+                    lines.push(self.row()?);
+                }
+                token => return Err(self.unexpected(token, offset)),
+            }
+        }
+        if lines.len() > 1 && lines.last().is_some_and(Vec::is_empty) {
+            lines.pop();
+        }
+
+        if let [line] = lines.as_slice() {
+            return Ok(line.concat());
+        }
+        let rows: Vec<Vec<String>> = lines.into_iter().map(|line| vec![mrow(line)]).collect();
+        Ok(table(&rows, " displaystyle=\"true\""))
+    }
+
+    /// Parses atoms up to the end of the group, cell or row they are in.
+    fn row(&mut self) -> Result<Vec<String>, TexError> {
+        let mut items = Vec::new();
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Char('}' | '&') | Token::Command("\\" | "end" | "right") => break,
+                Token::Char(']') if self.brackets > 0 => break,
+                Token::Command(style @ ("displaystyle" | "textstyle")) => {
+                    self.pos += 1;
+                    let rest = self.row()?;
+                    items.push(format!(
+                        "<mstyle displaystyle=\"{}\">{}</mstyle>",
+                        style == "displaystyle",
+                        mrow(rest)
+                    ));
+                }
+                _ => {
+                    let atom = self.atom()?;
+                    if !atom.is_empty() {
+                        items.push(atom);
+                    }
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    /// Parses cells split by `&` and rows split by `\\`, as in a matrix.
+    fn rows(&mut self) -> Result<Vec<Vec<String>>, TexError> {
+        let mut rows = vec![Vec::new()];
+        loop {
+            let cell = self.row()?;
+            let last = rows.last_mut().unwrap();
+            let empty = last.is_empty() && cell.is_empty();
+            last.push(mrow(cell));
+            match self.peek() {
+                Some(Token::Char('&')) => self.pos += 1,
+                Some(Token::Command("\\")) => {
+                    self.pos += 1;
+                    self.skip_optional();
+                    rows.push(Vec::new());
+                }
+                _ => {
+                    // A `\\` ending the last row does not start another.
+                    if empty && rows.len() > 1 {
+                        rows.pop();
+                    }
+                    return Ok(rows);
+                }
+            }
+        }
+    }
+
+    /// Skips the `[...]` spacing that may follow `\\`.
+    fn skip_optional(&mut self) {
+        if self.peek() == Some(Token::Char('[')) {
+            while let Some((token, _)) = self.next() {
+                if token == Token::Char(']') {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Parses an atom with its scripts.
+    fn atom(&mut self) -> Result<String, TexError> {
+        let base = self.primary(false)?;
+        self.scripts(base)
+    }
+
+    fn scripts(&mut self, base: Atom) -> Result<String, TexError> {
+        let mut limits = base.limits && self.display;
+        let mut primes = String::new();
+        let mut sup = None;
+        let mut sub = None;
+        while let Some(&(token, offset)) = self.tokens.get(self.pos) {
+            match token {
+                Token::Command("limits") => limits = true,
+                Token::Command("nolimits") => limits = false,
+                Token::Char('\'') if sup.is_none() => primes.push('′'),
+                Token::Char('^' | '\'') if sup.is_some() => {
+                    return Err(self.error(offset, "Double superscript"))
+                }
+                Token::Char('_') if sub.is_some() => {
+                    return Err(self.error(offset, "Double subscript"))
+                }
+                Token::Char(c @ ('^' | '_')) => {
+                    self.pos += 1;
+                    let script = self.argument(offset, &c.to_string())?;
+                    if c == '^' {
+                        sup = Some(script);
+                    } else {
+                        sub = Some(script);
+                    }
+                    continue;
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        if base.markup.is_empty() && primes.is_empty() && sup.is_none() && sub.is_none() {
+            return Ok(String::new());
+        }
+
+        if !primes.is_empty() {
+            let primes = format!("<mo>{}</mo>", primes);
+            sup = Some(match sup {
+                Some(sup) => format!("<mrow>{}{}</mrow>", primes, sup),
+                None => primes,
+            });
+        }
+        let base_markup = match base.markup.as_str() {
+            "" => "<mrow></mrow>",
+            markup => markup,
+        };
+        let (under, over, both) = match limits {
+            true => ("munder", "mover", "munderover"),
+            false => ("msub", "msup", "msubsup"),
+        };
+        let mut markup = match (sub, sup) {
+            (Some(sub), Some(sup)) => format!("<{0}>{1}{2}{3}</{0}>", both, base_markup, sub, sup),
+            (Some(sub), None) => format!("<{0}>{1}{2}</{0}>", under, base_markup, sub),
+            (None, Some(sup)) => format!("<{0}>{1}{2}</{0}>", over, base_markup, sup),
+            (None, None) => base.markup,
+        };
+        if base.function {
+            markup.push_str("<mo>&#x2061;</mo>");
+        }
+        Ok(markup)
+    }
+
+    /// Parses the argument of `command`, at `offset`: a group or a single
+    /// token.
+    fn argument(&mut self, offset: usize, command: &str) -> Result<String, TexError> {
+        match self.peek() {
+            Some(Token::Char('}' | '&' | '^' | '_'))
+            | Some(Token::Command("\\" | "end" | "right"))
+            | None => Err(self.error(offset, format!("Missing argument for `{}`", command))),
+            Some(_) => Ok(self.primary(true)?.markup),
+        }
+    }
+
+    /// Parses an argument with letters in `font`.
+    fn argument_in(
+        &mut self,
+        offset: usize,
+        command: &str,
+        font: Font,
+    ) -> Result<String, TexError> {
+        let outer = std::mem::replace(&mut self.font, font);
+        let argument = self.argument(offset, command);
+        self.font = outer;
+        argument
+    }
+
+    /// Parses a `[...]` optional argument, if there is one.
+    fn optional(&mut self) -> Result<Option<String>, TexError> {
+        let Some(&(Token::Char('['), offset)) = self.tokens.get(self.pos) else {
+            return Ok(None);
+        };
+        self.pos += 1;
+        self.brackets += 1;
+        let items = self.row()?;
+        self.brackets -= 1;
+        match self.next() {
+            Some((Token::Char(']'), _)) => Ok(Some(mrow(items))),
+            _ => Err(self.error(offset, "`[` without a matching `]`")),
+        }
+    }
+
+    /// The source of the group `{...}` following `command`, at `offset`,
+    /// as written.
+    fn raw_group(&mut self, offset: usize, command: &str) -> Result<&'a str, TexError> {
+        let Some(&(Token::Char('{'), open)) = self.tokens.get(self.pos) else {
+            return Err(self.error(offset, format!("Missing argument for `{}`", command)));
+        };
+        let mut depth = 0;
+        for (i, &(token, at)) in self.tokens.iter().enumerate().skip(self.pos) {
+            match token {
+                Token::Char('{') => depth += 1,
+                Token::Char('}') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos = i + 1;
+                        return Ok(&self.tex[open + 1..at]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(self.error(open, "`{` without a matching `}`"))
+    }
+
+    /// Parses one token, with the arguments it takes. `single` limits runs
+    /// of digits and letters to their first character, as in `x^23`.
+    fn primary(&mut self, single: bool) -> Result<Atom, TexError> {
+        let Some(&(token, offset)) = self.tokens.get(self.pos) else {
+            return Ok(Atom::new(String::new()));
+        };
+        if matches!(token, Token::Char('^' | '_')) {
+            return Ok(Atom::new(String::new()));
+        }
+        self.pos += 1;
+
+        let markup = match token {
+            Token::Char('{') => {
+                let outer = std::mem::take(&mut self.brackets);
+                let items = self.row()?;
+                self.brackets = outer;
+                match self.next() {
+                    Some((Token::Char('}'), _)) => mrow(items),
+                    Some((token @ (Token::Char('&') | Token::Command("\\")), offset)) => {
+                        return Err(self.unexpected(token, offset))
+                    }
+                    _ => return Err(self.error(offset, "`{` without a matching `}`")),
+                }
+            }
+            Token::Char(c) if c.is_ascii_digit() => {
+                let mut number = String::from(c);
+                while let (false, [next, ..]) = (single, &self.tokens[self.pos..]) {
+                    match (next.0, self.tokens.get(self.pos + 1)) {
+                        (Token::Char(d), _) if d.is_ascii_digit() => number.push(d),
+                        (Token::Char('.'), Some(&(Token::Char(d), _))) if d.is_ascii_digit() => {
+                            number.push('.');
+                        }
+                        _ => break,
+                    }
+                    self.pos += 1;
+                }
+                match self.font {
+                    Font::Bold => {
+                        format!("<mn>{}</mn>", number.chars().map(bold).collect::<String>())
+                    }
+                    _ => format!("<mn>{}</mn>", number),
+                }
+            }
+            Token::Char(c) if c.is_alphabetic() => self.letters(c, single),
+            Token::Char('\'') => "<mo>′</mo>".to_string(),
+            Token::Char('~') => "<mtext>&#xA0;</mtext>".to_string(),
+            Token::Char(c @ ('(' | ')' | '[' | ']' | '|')) => fence(&c.to_string()),
+            Token::Char('-') => operator("−"),
+            Token::Char('*') => operator("∗"),
+            Token::Char(c) => operator(&c.to_string()),
+            Token::Command(name) => return self.command(name, offset),
+        };
+        Ok(Atom::new(markup))
+    }
+
+    /// A letter, and in upright type the letters following it, which make
+    /// one name.
+    fn letters(&mut self, c: char, single: bool) -> String {
+        match self.font {
+            Font::Italic => format!("<mi>{}</mi>", c),
+            Font::Upright => {
+                let mut name = String::from(c);
+                while let (false, Some(Token::Char(c))) = (single, self.peek()) {
+                    if !c.is_alphabetic() {
+                        break;
+                    }
+                    name.push(c);
+                    self.pos += 1;
+                }
+                match name.chars().count() {
+                    1 => format!("<mi mathvariant=\"normal\">{}</mi>", name),
+                    _ => format!("<mi>{}</mi>", name),
+                }
+            }
+            Font::Bold => format!("<mi>{}</mi>", bold(c)),
+            Font::DoubleStruck => format!("<mi>{}</mi>", tex::double_struck(c)),
+            Font::Script => format!("<mi>{}</mi>", tex::script(c)),
+            Font::Fraktur => format!("<mi>{}</mi>", tex::fraktur(c)),
+        }
+    }
+
+    /// Parses the command `name`, at `offset`, with its arguments.
+    fn command(&mut self, name: &'a str, offset: usize) -> Result<Atom, TexError> {
+        let command = format!("\\{}", name);
+        if let Some(width) = space(name) {
+            return Ok(Atom::new(format!("<mspace width=\"{}\"></mspace>", width)));
+        }
+        if FENCES.contains(&name) {
+            if let Some(Symbol::Ordinary(text)) = tex::symbol(name) {
+                return Ok(Atom::new(fence(text)));
+            }
+        }
+        if let Some(symbol) = tex::symbol(name) {
+            return Ok(Atom {
+                markup: match symbol {
+                    Symbol::Ordinary(text) => ordinary(text),
+                    Symbol::Binary(text) | Symbol::Relation(text) => operator(text),
+                },
+                limits: LIMITS.contains(&name),
+                function: false,
+            });
+        }
+        if FUNCTIONS.contains(&name) {
+            return Ok(Atom {
+                markup: format!("<mi>{}</mi>", name),
+                limits: LIMITS.contains(&name),
+                function: true,
+            });
+        }
+        if let Some((mark, stretchy, under)) = accent(name) {
+            let base = self.argument(offset, &command)?;
+            let (tag, attribute) = match under {
+                true => ("munder", "accentunder"),
+                false => ("mover", "accent"),
+            };
+            return Ok(Atom {
+                markup: format!(
+                    "<{0} {1}=\"true\">{2}<mo stretchy=\"{3}\">{4}</mo></{0}>",
+                    tag, attribute, base, stretchy, mark
+                ),
+                limits: matches!(name, "overbrace" | "underbrace"),
+                function: false,
+            });
+        }
+
+        let markup = match name {
+            "{" | "}" => fence(name),
+            "&" => "<mo>&amp;</mo>".to_string(),
+            "%" | "$" | "#" | "_" => format!("<mi>{}</mi>", name),
+            " " => "<mtext>&#xA0;</mtext>".to_string(),
+            "frac" | "dfrac" | "tfrac" | "cfrac" | "binom" | "dbinom" | "tbinom" => {
+                let numerator = self.argument(offset, &command)?;
+                let denominator = self.argument(offset, &command)?;
+                let fraction = match name.ends_with("binom") {
+                    true => format!(
+                        "<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>",
+                        numerator, denominator
+                    ),
+                    false => format!("<mfrac>{}{}</mfrac>", numerator, denominator),
+                };
+                match name {
+                    "dfrac" | "cfrac" | "dbinom" => {
+                        format!("<mstyle displaystyle=\"true\">{}</mstyle>", fraction)
+                    }
+                    "tfrac" | "tbinom" => {
+                        format!("<mstyle displaystyle=\"false\">{}</mstyle>", fraction)
+                    }
+                    _ => fraction,
+                }
+            }
+            "sqrt" => {
+                let index = self.optional()?;
+                let radicand = self.argument(offset, &command)?;
+                match index {
+                    Some(index) => format!("<mroot>{}{}</mroot>", radicand, index),
+                    None => format!("<msqrt>{}</msqrt>", radicand),
+                }
+            }
+            "overset" | "stackrel" | "underset" => {
+                let script = self.argument(offset, &command)?;
+                let base = self.argument(offset, &command)?;
+                let tag = if name == "underset" {
+                    "munder"
+                } else {
+                    "mover"
+                };
+                format!("<{0}>{1}{2}</{0}>", tag, base, script)
+            }
+            "text" | "textrm" | "textnormal" | "textup" | "mbox" | "textit" | "textbf"
+            | "textsf" | "texttt" => {
+                let text = self.raw_group(offset, &command)?;
+                let variant = match name {
+                    "textit" => " mathvariant=\"italic\"",
+                    "textbf" => " mathvariant=\"bold\"",
+                    _ => "",
+                };
+                format!("<mtext{}>{}</mtext>", variant, text_markup(text))
+            }
+            "mathrm" | "mathup" | "mathsf" | "mathtt" | "mathit" | "mathbf" | "boldsymbol"
+            | "bm" | "mathbb" | "mathcal" | "mathscr" | "mathfrak" => {
+                let font = match name {
+                    "mathit" => Font::Italic,
+                    "mathbf" | "boldsymbol" | "bm" => Font::Bold,
+                    "mathbb" => Font::DoubleStruck,
+                    "mathcal" | "mathscr" => Font::Script,
+                    "mathfrak" => Font::Fraktur,
+                    _ => Font::Upright,
+                };
+                self.argument_in(offset, &command, font)?
+            }
+            "operatorname" => {
+                let limits = self.peek() == Some(Token::Char('*'));
+                if limits {
+                    self.pos += 1;
+                }
+                return Ok(Atom {
+                    markup: self.argument_in(offset, &command, Font::Upright)?,
+                    limits,
+                    function: true,
+                });
+            }
+            "bmod" => "<mo lspace=\"0.2222em\" rspace=\"0.2222em\">mod</mo>".to_string(),
+            "pmod" => {
+                let modulus = self.argument(offset, &command)?;
+                format!(
+                    "<mrow><mspace width=\"1em\"></mspace><mo>(</mo><mi>mod</mi><mspace width=\"0.3333em\"></mspace>{}<mo>)</mo></mrow>",
+                    modulus
+                )
+            }
+            "not" => {
+                let negated = match self.next() {
+                    Some((Token::Char(c), _)) => negate(&c.to_string()),
+                    Some((Token::Command(name), _)) => match tex::symbol(name) {
+                        Some(Symbol::Relation(text) | Symbol::Binary(text)) => negate(text),
+                        _ => None,
+                    },
+                    None => None,
+                };
+                match negated {
+                    Some(negated) => operator(&negated),
+                    None => return Err(self.error(offset, "Missing relation after `\\not`")),
+                }
+            }
+            "left" => {
+                let open = self.delimiter(offset, &command)?;
+                let items = self.row()?;
+                match self.next() {
+                    Some((Token::Command("right"), right)) => {
+                        let close = self.delimiter(right, "\\right")?;
+                        format!(
+                            "<mrow>{}{}{}</mrow>",
+                            stretchy(&open),
+                            items.concat(),
+                            stretchy(&close)
+                        )
+                    }
+                    _ => return Err(self.error(offset, "`\\left` without `\\right`")),
+                }
+            }
+            "middle" => {
+                let delimiter = self.delimiter(offset, &command)?;
+                stretchy(&delimiter)
+            }
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "Bigl" | "biggl" | "Biggl" | "bigr"
+            | "Bigr" | "biggr" | "Biggr" | "bigm" | "Bigm" | "biggm" | "Biggm" => {
+                let delimiter = self.delimiter(offset, &command)?;
+                let size = match name.trim_end_matches(['l', 'r', 'm']) {
+                    "big" => "1.2em",
+                    "Big" => "1.623em",
+                    "bigg" => "2.047em",
+                    _ => "2.470em",
+                };
+                format!(
+                    "<mo fence=\"true\" stretchy=\"true\" minsize=\"{0}\" maxsize=\"{0}\">{1}</mo>",
+                    size,
+                    escape(&delimiter)
+                )
+            }
+            "begin" => self.environment(offset)?,
+            "label" | "tag" => {
+                // Equations are not numbered.
+                if self.peek() == Some(Token::Char('*')) {
+                    self.pos += 1;
+                }
+                self.raw_group(offset, &command)?;
+                String::new()
+            }
+            "limits" | "nolimits" | "nonumber" | "notag" | "hline" | "hdashline" => String::new(),
+            "scriptstyle" | "scriptscriptstyle" => {
+                let rest = self.row()?;
+                let level = if name == "scriptstyle" { 1 } else { 2 };
+                format!("<mstyle scriptlevel=\"{}\">{}</mstyle>", level, mrow(rest))
+            }
+            _ => return Err(self.error(offset, format!("Unknown command `{}`", command))),
+        };
+        Ok(Atom::new(markup))
+    }
+
+    /// The delimiter following `command`, at `offset`, or nothing for `.`.
+    fn delimiter(&mut self, offset: usize, command: &str) -> Result<String, TexError> {
+        let delimiter = match self.next() {
+            Some((Token::Char('.'), _)) => Some(""),
+            Some((Token::Char('<'), _)) => Some("⟨"),
+            Some((Token::Char('>'), _)) => Some("⟩"),
+            Some((Token::Char(c @ ('(' | ')' | '[' | ']' | '|' | '/')), _)) => {
+                return Ok(c.to_string())
+            }
+            Some((Token::Command(name), _)) if FENCES.contains(&name) => match tex::symbol(name) {
+                Some(Symbol::Ordinary(text)) => Some(text),
+                _ => Some(name),
+            },
+            Some((Token::Command(name), _)) => match name {
+                "backslash" => Some("\\"),
+                "uparrow" => Some("↑"),
+                "downarrow" => Some("↓"),
+                "updownarrow" => Some("↕"),
+                "Uparrow" => Some("⇑"),
+                "Downarrow" => Some("⇓"),
+                _ => None,
+            },
+            _ => None,
+        };
+        delimiter
+            .map(str::to_string)
+            .ok_or_else(|| self.error(offset, format!("Missing delimiter after `{}`", command)))
+    }
+
+    /// Parses an environment, from the name following `\begin` at `offset`
+    /// to its `\end`.
+    fn environment(&mut self, offset: usize) -> Result<String, TexError> {
+        let name = self.raw_group(offset, "\\begin")?.trim();
+        let begin = format!("\\begin{{{}}}", name);
+        let (open, close) = match name {
+            "pmatrix" => ("(", ")"),
+            "bmatrix" => ("[", "]"),
+            "Bmatrix" | "cases" => ("{", ""),
+            "rcases" => ("", "}"),
+            "vmatrix" => ("|", "|"),
+            "Vmatrix" => ("‖", "‖"),
+            "matrix" | "smallmatrix" | "array" | "aligned" | "align" | "align*" | "alignat"
+            | "alignat*" | "alignedat" | "split" | "gather" | "gather*" | "gathered"
+            | "equation" | "equation*" | "eqnarray" | "eqnarray*" | "multline" | "multline*" => {
+                ("", "")
+            }
+            _ => return Err(self.error(offset, format!("Unknown environment `{}`", name))),
+        };
+        let mut columns = None;
+        if matches!(name, "array" | "alignat" | "alignat*" | "alignedat") {
+            let spec = self.raw_group(offset, &begin)?;
+            if name == "array" {
+                let spec: Vec<&str> = spec
+                    .chars()
+                    .filter_map(|c| match c {
+                        'l' => Some("left"),
+                        'c' => Some("center"),
+                        'r' => Some("right"),
+                        _ => None,
+                    })
+                    .collect();
+                columns = Some(spec.join(" "));
+            }
+        }
+
+        let rows = self.rows()?;
+        match self.next() {
+            Some((Token::Command("end"), end)) => {
+                let end_name = self.raw_group(end, "\\end")?.trim();
+                if end_name != name {
+                    return Err(
+                        self.error(end, format!("`{}` ended by `\\end{{{}}}`", begin, end_name))
+                    );
+                }
+            }
+            _ => return Err(self.error(offset, format!("`{}` without `\\end{{{}}}`", begin, name))),
+        }
+
+        let attributes = match name.trim_end_matches('*') {
+            "aligned" | "align" | "alignat" | "alignedat" | "split" | "eqnarray" => {
+                " columnalign=\"right left right left right left\" \
+                 columnspacing=\"0em 2em 0em 2em 0em\" displaystyle=\"true\""
+                    .to_string()
+            }
+            "gather" | "gathered" | "equation" | "multline" => " displaystyle=\"true\"".to_string(),
+            "cases" | "rcases" => " columnalign=\"left left\"".to_string(),
+            "array" => format!(" columnalign=\"{}\"", columns.unwrap_or_default()),
+            _ => String::new(),
+        };
+        let table = table(&rows, &attributes);
+        Ok(match (open, close) {
+            ("", "") => table,
+            (open, close) => format!(
+                "<mrow>{}{}{}</mrow>",
+                stretchy(open),
+                table,
+                stretchy(close)
+            ),
+        })
+    }
+}
+
+/// The items as one element.
+fn mrow(mut items: Vec<String>) -> String {
+    match items.len() {
+        1 => items.pop().unwrap_or_default(),
+        _ => format!("<mrow>{}</mrow>", items.concat()),
+    }
+}
+
+fn table(rows: &[Vec<String>], attributes: &str) -> String {
+    let mut table = format!("<mtable{}>", attributes);
+    for row in rows {
+        table.push_str("<mtr>");
+        for cell in row {
+            table.push_str("<mtd>");
+            table.push_str(cell);
+            table.push_str("</mtd>");
+        }
+        table.push_str("</mtr>");
+    }
+    table.push_str("</mtable>");
+    table
+}
+
+fn escape(text: &str) -> String {
+    html_escape::encode_text(text).into_owned()
+}
+
+fn operator(text: &str) -> String {
+    format!("<mo>{}</mo>", escape(text))
+}
+
+/// A delimiter at its normal size.
+fn fence(text: &str) -> String {
+    format!("<mo stretchy=\"false\">{}</mo>", escape(text))
+}
+
+/// A delimiter growing to the height of what it encloses, if any.
+fn stretchy(text: &str) -> String {
+    match text {
+        "" => String::new(),
+        text => format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", escape(text)),
+    }
+}
+
+/// A symbol of the ordinary kind: letters are identifiers, upright unless
+/// lowercase, and the rest operators such as `∑` and `∀`.
+fn ordinary(text: &str) -> String {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_lowercase() => format!("<mi>{}</mi>", c),
+        (Some(c), None) if c.is_alphabetic() || "∞∂∇∅".contains(c) => {
+            format!("<mi mathvariant=\"normal\">{}</mi>", c)
+        }
+        _ => operator(text),
+    }
+}
+
+/// The width of a spacing command.
+fn space(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "," | "thinspace" => "0.1667em",
+        ":" | ">" | "medspace" => "0.2222em",
+        ";" | "thickspace" => "0.2778em",
+        "!" | "negthinspace" => "-0.1667em",
+        "quad" => "1em",
+        "qquad" => "2em",
+        _ => return None,
+    })
+}
+
+/// The mark an accent command puts over (or under) its argument, whether
+/// it stretches, and whether it goes under.
+fn accent(name: &str) -> Option<(&'static str, bool, bool)> {
+    Some(match name {
+        "hat" => ("^", false, false),
+        "widehat" => ("^", true, false),
+        "check" => ("ˇ", false, false),
+        "tilde" => ("~", false, false),
+        "widetilde" => ("~", true, false),
+        "bar" => ("¯", false, false),
+        "overline" => ("‾", true, false),
+        "underline" => ("‾", true, true),
+        "vec" => ("→", false, false),
+        "overrightarrow" => ("→", true, false),
+        "overleftarrow" => ("←", true, false),
+        "dot" => ("˙", false, false),
+        "ddot" => ("¨", false, false),
+        "acute" => ("´", false, false),
+        "grave" => ("`", false, false),
+        "breve" => ("˘", false, false),
+        "mathring" => ("˚", false, false),
+        "overbrace" => ("⏞", true, false),
+        "underbrace" => ("⏟", true, true),
+        _ => return None,
+    })
+}
+
+/// A relation with a slash through it, for `\not`.
+fn negate(relation: &str) -> Option<String> {
+    Some(match relation {
+        "=" => "≠".to_string(),
+        "<" => "≮".to_string(),
+        ">" => "≯".to_string(),
+        "∈" => "∉".to_string(),
+        "⊂" => "⊄".to_string(),
+        "⊃" => "⊅".to_string(),
+        "⊆" => "⊈".to_string(),
+        "⊇" => "⊉".to_string(),
+        "≡" => "≢".to_string(),
+        "∼" => "≁".to_string(),
+        "≈" => "≉".to_string(),
+        "≤" => "≰".to_string(),
+        "≥" => "≱".to_string(),
+        "∣" => "∤".to_string(),
+        "∥" => "∦".to_string(),
+        relation if !relation.is_empty() => format!("{}\u{338}", relation),
+        _ => return None,
+    })
+}
+
+/// Mathematical bold letters and digits, as in `\mathbf{v}`.
+fn bold(c: char) -> char {
+    let code = match c {
+        'A'..='Z' => 0x1D400 + (c as u32 - 'A' as u32),
+        'a'..='z' => 0x1D41A + (c as u32 - 'a' as u32),
+        '0'..='9' => 0x1D7CE + (c as u32 - '0' as u32),
+        c => c as u32,
+    };
+    char::from_u32(code).unwrap_or(c)
+}
+
+/// The text of `\text{...}`, with TeX's escapes and ties undone.
+fn text_markup(text: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek().is_some_and(|c| "{}%$#_&".contains(*c)) => {
+                plain.extend(chars.next());
+            }
+            '~' => plain.push('\u{a0}'),
+            '{' | '}' => {}
+            c => plain.push(c),
+        }
+    }
+    escape(&plain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn body(tex: &str) -> String {
+        Parser::new(tex, false).parse().unwrap()
+    }
+
+    #[test]
+    fn test_tex_to_mathml() {
+        assert_eq!(
+            tex_to_mathml("x^2 < 1", true).unwrap(),
+            "<math display=\"block\" alttext=\"x^2 &lt; 1\">\
+             <msup><mi>x</mi><mn>2</mn></msup><mo>&lt;</mo><mn>1</mn></math>"
+        );
+        assert_eq!(
+            body(r"\frac{-b \pm \sqrt{b^2-4ac}}{2a}"),
+            "<mfrac><mrow><mo>−</mo><mi>b</mi><mo>±</mo><msqrt><mrow><msup><mi>b</mi><mn>2</mn></msup>\
+             <mo>−</mo><mn>4</mn><mi>a</mi><mi>c</mi></mrow></msqrt></mrow>\
+             <mrow><mn>2</mn><mi>a</mi></mrow></mfrac>"
+        );
+        assert_eq!(
+            body(r"\sin^2\theta + \mathbb{R}_{>0} \leq \left(\vec{v}'\right)"),
+            "<msup><mi>sin</mi><mn>2</mn></msup><mo>&#x2061;</mo><mi>θ</mi><mo>+</mo>\
+             <msub><mi>ℝ</mi><mrow><mo>&gt;</mo><mn>0</mn></mrow></msub><mo>≤</mo>\
+             <mrow><mo fence=\"true\" stretchy=\"true\">(</mo>\
+             <msup><mover accent=\"true\"><mi>v</mi><mo stretchy=\"false\">→</mo></mover><mo>′</mo></msup>\
+             <mo fence=\"true\" stretchy=\"true\">)</mo></mrow>"
+        );
+        assert_eq!(
+            Parser::new(r"\sum_{i=1}^n i \text{ and } \begin{pmatrix} a & b \\ c & d \end{pmatrix}", true)
+                .parse()
+                .unwrap(),
+            "<munderover><mo>∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></munderover>\
+             <mi>i</mi><mtext> and </mtext>\
+             <mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mtable>\
+             <mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr>\
+             <mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable>\
+             <mo fence=\"true\" stretchy=\"true\">)</mo></mrow>"
+        );
+    }
+
+    #[test]
+    fn test_environments() {
+        assert_eq!(
+            body(r"\begin{aligned} a & = b \\ c & = d \end{aligned}"),
+            "<mtable columnalign=\"right left right left right left\" \
+             columnspacing=\"0em 2em 0em 2em 0em\" displaystyle=\"true\">\
+             <mtr><mtd><mi>a</mi></mtd><mtd><mrow><mo>=</mo><mi>b</mi></mrow></mtd></mtr>\
+             <mtr><mtd><mi>c</mi></mtd><mtd><mrow><mo>=</mo><mi>d</mi></mrow></mtd></mtr></mtable>"
+        );
+        assert_eq!(
+            body(r"\begin{cases} 1 & x > 0 \\ 0 & \text{otherwise} \end{cases}"),
+            "<mrow><mo fence=\"true\" stretchy=\"true\">{</mo>\
+             <mtable columnalign=\"left left\">\
+             <mtr><mtd><mn>1</mn></mtd><mtd><mrow><mi>x</mi><mo>&gt;</mo><mn>0</mn></mrow></mtd></mtr>\
+             <mtr><mtd><mn>0</mn></mtd><mtd><mtext>otherwise</mtext></mtd></mtr></mtable></mrow>"
+        );
+        assert_eq!(
+            body(r"\begin{bmatrix} 1 & 0 \end{bmatrix}"),
+            "<mrow><mo fence=\"true\" stretchy=\"true\">[</mo>\
+             <mtable><mtr><mtd><mn>1</mn></mtd><mtd><mn>0</mn></mtd></mtr></mtable>\
+             <mo fence=\"true\" stretchy=\"true\">]</mo></mrow>"
+        );
+        assert_eq!(
+            body(r"\begin{array}{lr} a & b \end{array}"),
+            "<mtable columnalign=\"left right\">\
+             <mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr></mtable>"
+        );
+    }
+
+    #[test]
+    fn test_example_math() {
+        assert_eq!(
+            body(r"\vec{\mathbf{B}}"),
+            "<mover accent=\"true\"><mi>𝐁</mi><mo stretchy=\"false\">→</mo></mover>"
+        );
+        assert_eq!(body(r"\frac1c"), "<mfrac><mn>1</mn><mi>c</mi></mfrac>");
+        assert_eq!(
+            body(r"\frac{\partial\vec{\mathbf{E}}}{\partial t}"),
+            "<mfrac><mrow><mi mathvariant=\"normal\">∂</mi>\
+             <mover accent=\"true\"><mi>𝐄</mi><mo stretchy=\"false\">→</mo></mover></mrow>\
+             <mrow><mi mathvariant=\"normal\">∂</mi><mi>t</mi></mrow></mfrac>"
+        );
+        assert_eq!(
+            body(r"\nabla \cdot \vec{\mathbf{E}} = 4 \pi \rho"),
+            "<mi mathvariant=\"normal\">∇</mi><mo>⋅</mo>\
+             <mover accent=\"true\"><mi>𝐄</mi><mo stretchy=\"false\">→</mo></mover>\
+             <mo>=</mo><mn>4</mn><mi>π</mi><mi>ρ</mi>"
+        );
+        assert_eq!(
+            body(r"\dfrac{a}{b} + \tfrac12"),
+            "<mstyle displaystyle=\"true\"><mfrac><mi>a</mi><mi>b</mi></mfrac></mstyle><mo>+</mo>\
+             <mstyle displaystyle=\"false\"><mfrac><mn>1</mn><mn>2</mn></mfrac></mstyle>"
+        );
+
+        let example = include_str!("../example.md");
+        assert_eq!(errors(example), []);
+        let maxwell = example
+            .split("$$")
+            .find(|math| math.contains("aligned"))
+            .unwrap();
+        assert_eq!(body(maxwell).matches("<mtr>").count(), 4);
+    }
+
+    #[test]
+    fn test_unknown_commands() {
+        let error = |tex: &str| tex_to_mathml(tex, true).unwrap_err().to_string();
+        assert_eq!(error(r"\foo"), "line 1: Unknown command `\\foo`");
+        assert_eq!(error(r"a + \foo{b}"), "line 1: Unknown command `\\foo`");
+        assert_eq!(
+            error(r"\begin{weird} a \end{weird}"),
+            "line 1: Unknown environment `weird`"
+        );
+        assert_eq!(
+            typeset(r"\foo", true),
+            "<math display=\"block\" alttext=\"\\foo\">\
+             <merror><mtext>\\foo</mtext></merror></math>"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |tex: &str| tex_to_mathml(tex, true).unwrap_err().to_string();
+        assert_eq!(error(r"\frac{a}"), "line 1: Missing argument for `\\frac`");
+        assert_eq!(error("a\n+ {b\n+ c"), "line 2: `{` without a matching `}`");
+        assert_eq!(error("x^2^3"), "line 1: Double superscript");
+        assert_eq!(error("a}"), "line 1: Unmatched `}`");
+        assert_eq!(error("a \\\\\n\\foo"), "line 2: Unknown command `\\foo`");
+        assert_eq!(
+            error("\\begin{matrix}\na \\\\\n\\end{bmatrix}"),
+            "line 3: `\\begin{matrix}` ended by `\\end{bmatrix}`"
+        );
+        assert_eq!(error("a\n\nb}"), "line 3: Unmatched `}`");
+        assert_eq!(error("a & b"), "line 1: Misplaced `&`");
+        assert_eq!(error("a \\right)"), "line 1: `\\right` without `\\left`");
+        assert_eq!(error("\\end{aligned}"), "line 1: `\\end` without `\\begin`");
+        assert_eq!(
+            error("\\begin{aligned}\na & = b \\\\\nc & = \\frac{1}\n\\end{aligned}"),
+            "line 3: Missing argument for `\\frac`"
+        );
+        assert_eq!(
+            error("\\begin{aligned}\na"),
+            "line 1: `\\begin{aligned}` without `\\end{aligned}`"
+        );
+        assert_eq!(
+            typeset("\\left( x", false),
+            "<math alttext=\"\\left( x\"><merror><mtext>\\left( x</mtext></merror></math>"
+        );
+    }
+
+    #[test]
+    fn test_error_lines_in_document() {
+        let markdown = "---\ntitle: Notes\n---\n# Notes\n\nFine $x$ and\nbroken $\\frac{1}$.\n\n\
+                        $$\na \\\\\nb^\n$$";
+        assert_eq!(
+            errors(markdown),
+            vec![
+                TexError {
+                    line: 7,
+                    message: "Missing argument for `\\frac`".to_string()
+                },
+                TexError {
+                    line: 11,
+                    message: "Missing argument for `^`".to_string()
+                },
+            ]
+        );
+    }
+}
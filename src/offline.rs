@@ -0,0 +1,108 @@
+use crate::ast::{CodeBlock, Inline};
+use crate::html::{self, HtmlRenderer};
+use crate::mathml;
+use crate::render::Renderer;
+use crate::utils;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use log::warn;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The HTML renderer for pages that work offline from a single file: math
+/// is always MathML, never left for KaTeX from a CDN, and local images are
+/// inlined as `data:` URIs. Remote images are left as links.
+pub struct OfflineRenderer {
+    html: HtmlRenderer,
+    /// Where local images are resolved from: the input file's directory.
+    base_dir: PathBuf,
+}
+
+impl OfflineRenderer {
+    pub fn new(html: HtmlRenderer, base_dir: &Path) -> Self {
+        Self {
+            html,
+            base_dir: base_dir.to_path_buf(),
+        }
+    }
+}
+
+/// The file at `path` as a `data:` URI, typed by its extension.
+pub fn data_uri(path: &Path) -> std::io::Result<String> {
+    let data = fs::read(path)?;
+    let media_type = utils::image_media_type(&path.to_string_lossy());
+    Ok(format!(
+        "data:{};base64,{}",
+        media_type,
+        STANDARD.encode(data)
+    ))
+}
+
+impl Renderer for OfflineRenderer {
+    fn heading_id(&self, content: &[Inline]) -> Option<String> {
+        self.html.heading_id(content)
+    }
+
+    fn highlight(&self, code_block: &CodeBlock) -> Option<String> {
+        self.html.highlight(code_block)
+    }
+
+    fn typeset_math(&self, math: &str, display: bool) -> Option<String> {
+        Some(mathml::typeset(math, display))
+    }
+
+    fn equation_number(&self, label: &str) -> Option<usize> {
+        self.html.equation_number(label)
+    }
+
+    fn image(&self, url: &str, alt: &str, out: &mut String) {
+        if url.contains("://") || url.starts_with("data:") {
+            return html::image(self, url, alt, out);
+        }
+        let path = self.base_dir.join(url);
+        match data_uri(&path) {
+            Ok(uri) => html::image(self, &uri, alt, out),
+            Err(e) => {
+                warn!(
+                    "Image {} could not be read ({}); leaving the link as it is",
+                    path.display(),
+                    e
+                );
+                html::image(self, url, alt, out);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::HtmlOptions;
+    use crate::parser::MarkdownParser;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_images_and_math() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("dot.gif"), b"GIF89a").unwrap();
+        let html = HtmlRenderer::new(HtmlOptions {
+            katex: true,
+            ..HtmlOptions::default()
+        });
+        let renderer = OfflineRenderer::new(html, dir.path());
+        let document = MarkdownParser::new().parse_document(
+            "![dot](dot.gif) ![gone](gone.png) ![web](https://example.org/a.png)\n\n$$x^2$$",
+        );
+
+        assert_eq!(
+            renderer.render(&document),
+            format!(
+                "<p><img src=\"data:image/gif;base64,R0lGODlh\" alt=\"dot\"> \
+                 <img src=\"gone.png\" alt=\"gone\"> \
+                 <img src=\"https://example.org/a.png\" alt=\"web\"></p>\n\
+                 <div class=\"math-block\">{}</div>\n",
+                mathml::typeset("x^2", true)
+            )
+        );
+    }
+}
@@ -1,6 +1,34 @@
+use crate::ast::{
+    plain_text, Alignment, Block, CodeBlock, Document, Inline, List, ListItem, MathBlock, Position,
+    Table,
+};
+use crate::html::HtmlRenderer;
+use crate::render::Renderer;
 use anyhow::Result;
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::collections::HashMap;
+use std::io::Write;
+
+lazy_static! {
+    static ref HEADING_RE: Regex = Regex::new(r"^#{1,6}\s").unwrap();
+    static ref UNORDERED_LIST_RE: Regex = Regex::new(r"^[\s]*[-*](\s|$)").unwrap();
+    static ref ORDERED_LIST_RE: Regex = Regex::new(r"^[\s]*\d+\.(\s|$)").unwrap();
+    static ref LIST_MARKER_RE: Regex = Regex::new(r"^(?:[-*]|(\d+)\.)(?:\s+|$)").unwrap();
+    static ref CODE_BLOCK_RE: Regex = Regex::new(r"^```").unwrap();
+    static ref TABLE_RE: Regex = Regex::new(r"^[|].*[|]$").unwrap();
+    static ref TABLE_SEPARATOR_RE: Regex =
+        Regex::new(r"^\|?\s*:?-+:?\s*(\|\s*:?-+:?\s*)*\|?$").unwrap();
+    static ref BLOCKQUOTE_RE: Regex = Regex::new(r"^>").unwrap();
+    static ref HORIZONTAL_RULE_RE: Regex = Regex::new(r"^-{3,}$|^_{3,}$|^\*{3,}$").unwrap();
+    static ref MATH_BLOCK_RE: Regex = Regex::new(r"^\$\$").unwrap();
+    static ref MATH_ID_RE: Regex = Regex::new(r"\$\$\s*\{#([^\s{}]+)\}$").unwrap();
+}
+
+/// Blockquotes, lists and inline spans nested deeper than this are kept as
+/// literal text. This bounds recursion while rendering (and dropping) the tree,
+/// however hostile the input.
+const MAX_NESTING: usize = 32;
 
 #[derive(Default)]
 pub struct MarkdownParser {}
@@ -11,97 +39,91 @@ impl MarkdownParser {
     }
 
     pub fn parse(&self, content: &str) -> Result<String> {
-        let mut html = String::new();
-        let lines: Vec<&str> = content.lines().collect();
-        let mut i = 0;
-        let mut in_blockquote = false;
-        let mut blockquote_content = String::new();
-
-        while i < lines.len() {
-            let line = lines[i].trim();
-
-            if line.is_empty() && in_blockquote {
-                html.push_str(&format!(
-                    "<blockquote>{}</blockquote>\n",
-                    self.parse_inline(&blockquote_content)
-                ));
-                blockquote_content.clear();
-                in_blockquote = false;
-                i += 1;
-                continue;
-            }
+        Ok(HtmlRenderer::default().render(&self.parse_document(content)))
+    }
 
-            match self.identify_element(line) {
-                Element::Blockquote => {
-                    in_blockquote = true;
-                    blockquote_content.push_str(&line[2..]);
-                    blockquote_content.push_str("<br>");
-                    i += 1;
-                }
-                Element::HorizontalRule => {
-                    html.push_str("<hr>\n");
-                    i += 1;
-                }
-                Element::Heading(level) => {
-                    html.push_str(&self.parse_heading(line, level));
-                    i += 1;
-                }
-                Element::UnorderedList => {
-                    let (parsed, consumed) = self.parse_list(&lines[i..], false);
-                    html.push_str(&parsed);
-                    i += consumed;
-                }
-                Element::OrderedList => {
-                    let (parsed, consumed) = self.parse_list(&lines[i..], true);
-                    html.push_str(&parsed);
-                    i += consumed;
-                }
-                Element::CodeBlock => {
-                    let (parsed, consumed) = self.parse_code_block(&lines[i..]);
-                    html.push_str(&parsed);
-                    i += consumed;
-                }
-                Element::Table => {
-                    let (parsed, consumed) = self.parse_table(&lines[i..]);
-                    html.push_str(&parsed);
-                    i += consumed;
-                }
-                Element::Paragraph => {
-                    if !line.is_empty() {
-                        html.push_str(&self.parse_paragraph(line));
-                    }
-                    i += 1;
-                }
-                Element::MathBlock => {
-                    let (parsed, consumed) = self.parse_math_block(&lines[i..]);
-                    html.push_str(&parsed);
-                    i += consumed;
-                }
-            }
+    /// Renders `content` to `writer` one top-level block at a time, so neither
+    /// the full tree nor the full HTML is ever held in memory.
+    pub fn parse_to<W: Write>(&self, content: &str, writer: &mut W) -> Result<()> {
+        let renderer = HtmlRenderer::default();
+        for block in self.blocks(content) {
+            renderer.render_block_to(&block, writer)?;
+        }
+        Ok(())
+    }
+
+    /// Parses `content` into a [`Document`]. Runs in time linear in the input.
+    pub fn parse_document(&self, content: &str) -> Document {
+        Document {
+            blocks: self.blocks(content).collect(),
         }
+    }
 
-        if in_blockquote && !blockquote_content.is_empty() {
-            html.push_str(&format!(
-                "<blockquote>{}</blockquote>\n",
-                self.parse_inline(&blockquote_content)
-            ));
+    /// Lazily parses the top-level blocks of `content`.
+    pub fn blocks<'a>(&'a self, content: &'a str) -> Blocks<'a> {
+        Blocks {
+            parser: self,
+            lines: content.lines().collect(),
+            position: 0,
         }
+    }
 
-        Ok(html)
+    /// Whether `line` would start a block other than a paragraph, and so
+    /// cannot continue one.
+    pub(crate) fn starts_block(&self, line: &str) -> bool {
+        !matches!(self.identify_element(line.trim()), Element::Paragraph)
     }
 
-    fn identify_element(&self, line: &str) -> Element {
-        lazy_static! {
-            static ref HEADING_RE: Regex = Regex::new(r"^#{1,6}\s").unwrap();
-            static ref UNORDERED_LIST_RE: Regex = Regex::new(r"^[\s]*[-*]\s").unwrap();
-            static ref ORDERED_LIST_RE: Regex = Regex::new(r"^[\s]*\d+\.\s").unwrap();
-            static ref CODE_BLOCK_RE: Regex = Regex::new(r"^```").unwrap();
-            static ref TABLE_RE: Regex = Regex::new(r"^[|].*[|]$").unwrap();
-            static ref BLOCKQUOTE_RE: Regex = Regex::new(r"^>\s").unwrap();
-            static ref HORIZONTAL_RULE_RE: Regex = Regex::new(r"^-{3,}$|^_{3,}$|^\*{3,}$").unwrap();
-            static ref MATH_BLOCK_RE: Regex = Regex::new(r"^\$\$").unwrap();
+    fn parse_blocks(&self, lines: &[&str], depth: usize) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut i = 0;
+
+        while let Some((block, consumed)) = self.next_block(&lines[i..], depth) {
+            blocks.push(block);
+            i += consumed;
+        }
+
+        blocks
+    }
+
+    /// Parses the first block of `lines`, returning it with the number of
+    /// lines consumed (including any blank lines before it).
+    fn next_block(&self, lines: &[&str], depth: usize) -> Option<(Block, usize)> {
+        let blank = lines
+            .iter()
+            .take_while(|line| line.trim().is_empty())
+            .count();
+        let lines = &lines[blank..];
+        let line = lines.first()?.trim();
+
+        let (block, consumed) = match self.classify(line, depth) {
+            Element::Blockquote => self.parse_blockquote(lines, depth),
+            Element::HorizontalRule => (Block::HorizontalRule, 1),
+            Element::Heading(level) => (self.parse_heading(line, level), 1),
+            Element::UnorderedList => self.parse_list(lines, false, depth),
+            Element::OrderedList => self.parse_list(lines, true, depth),
+            Element::CodeBlock => self.parse_code_block(lines),
+            Element::Table => self.parse_table(lines),
+            Element::Paragraph => self.parse_paragraph(lines, depth),
+            Element::MathBlock => self.parse_math_block(lines),
+        };
+        Some((block, blank + consumed))
+    }
+
+    /// Like [`Self::identify_element`], but demotes containers to paragraphs
+    /// once the nesting limit is reached.
+    fn classify(&self, line: &str, depth: usize) -> Element {
+        match self.identify_element(line) {
+            Element::Blockquote | Element::UnorderedList | Element::OrderedList
+                if depth >= MAX_NESTING =>
+            {
+                Element::Paragraph
+            }
+            element => element,
         }
+    }
 
+    fn identify_element(&self, line: &str) -> Element {
         if let Some(captures) = HEADING_RE.find(line) {
             return Element::Heading(captures.as_str().trim().len());
         }
@@ -137,240 +159,297 @@ impl MarkdownParser {
         Element::Paragraph
     }
 
-    fn parse_heading(&self, line: &str, level: usize) -> String {
+    fn parse_heading(&self, line: &str, level: usize) -> Block {
         let content = line.trim_start_matches('#').trim();
-        format!("<h{}>{}</h{}>\n", level, content, level)
+        // An optional closing sequence of `#`s is not part of the heading.
+        let stripped = content.trim_end_matches('#');
+        let content = if stripped.is_empty() || stripped.ends_with(char::is_whitespace) {
+            stripped.trim_end()
+        } else {
+            content
+        };
+
+        Block::Heading {
+            level: level as u8,
+            content: self.parse_inline(content),
+        }
     }
 
-    fn parse_list(&self, lines: &[&str], ordered: bool) -> (String, usize) {
-        let mut result = String::new();
-        let mut consumed = 0;
-        let mut current_indent = 0;
-        let mut list_stack = vec![];
+    fn parse_blockquote(&self, lines: &[&str], depth: usize) -> (Block, usize) {
+        let mut inner = Vec::new();
+
+        for line in lines {
+            let Some(rest) = line.trim_start().strip_prefix('>') else {
+                break;
+            };
+            inner.push(rest.strip_prefix(' ').unwrap_or(rest));
+        }
+
+        let consumed = inner.len();
+        (
+            Block::Blockquote(self.parse_blocks(&inner, depth + 1)),
+            consumed,
+        )
+    }
 
-        let list_type = if ordered { "ol" } else { "ul" };
-        result.push_str(&format!("<{}>\n", list_type));
-        list_stack.push((list_type, 0));
+    fn parse_list(&self, lines: &[&str], ordered: bool, depth: usize) -> (Block, usize) {
+        let mut items = Vec::new();
+        let mut start = 1;
+        let mut consumed = 0;
 
         while consumed < lines.len() {
-            let line = lines[consumed].trim_start();
-            if line.is_empty() {
+            let line = lines[consumed];
+            let trimmed = line.trim_start();
+            let Some(marker) = LIST_MARKER_RE.captures(trimmed) else {
                 break;
-            }
-
-            let indent = (lines[consumed].len() - line.len()) / 2 * 2;
-            let is_list_item = if ordered {
-                line.contains(". ")
-            } else {
-                ['-', '*'].iter().any(|&c| line.contains(c))
             };
-
-            if !is_list_item {
+            let number = marker.get(1);
+            if number.is_some() != ordered {
                 break;
             }
-
-            match indent.cmp(&current_indent) {
-                std::cmp::Ordering::Greater => {
-                    let new_list_type = if ordered { "ol" } else { "ul" };
-                    result.push_str(&format!("<{}>\n", new_list_type));
-                    list_stack.push((new_list_type, indent));
-                    current_indent = indent;
+            if items.is_empty() {
+                if let Some(number) = number {
+                    start = number.as_str().parse().unwrap_or(1);
                 }
-                std::cmp::Ordering::Less => {
-                    while let Some((list_type, level)) = list_stack.last() {
-                        if *level > indent {
-                            result.push_str(&format!("</{}>\n", list_type));
-                            list_stack.pop();
-                            if let Some((_, prev_level)) = list_stack.last() {
-                                current_indent = *prev_level;
-                            } else {
-                                current_indent = 0;
-                            }
-                        } else {
-                            break;
-                        }
-                    }
-                }
-                std::cmp::Ordering::Equal => {}
             }
 
-            let content = if ordered {
-                line.split_once(". ")
-                    .map(|(_, content)| content.trim())
-                    .unwrap_or("")
-            } else {
-                line.split_once(&['-', '*'][..])
-                    .map(|(_, content)| content.trim())
-                    .unwrap_or("")
-            };
-
-            result.push_str(&format!("<li>{}</li>\n", self.parse_inline(content)));
+            let indent = indent_width(line);
+            let content_offset = indent + marker[0].chars().count();
+            let (checked, content) = split_task_marker(&trimmed[marker[0].len()..]);
             consumed += 1;
-        }
 
-        while let Some((list_type, _)) = list_stack.pop() {
-            result.push_str(&format!("</{}>\n", list_type));
+            // Anything indented past the marker belongs to this item.
+            let body_start = consumed;
+            while consumed < lines.len()
+                && !lines[consumed].trim().is_empty()
+                && indent_width(lines[consumed]) > indent
+            {
+                consumed += 1;
+            }
+            let body: Vec<&str> = lines[body_start..consumed]
+                .iter()
+                .map(|line| dedent(line, content_offset))
+                .collect();
+
+            // Paragraph lines straight after the marker continue the item's text.
+            let continuation = body
+                .iter()
+                .take_while(|line| {
+                    matches!(self.classify(line.trim(), depth + 1), Element::Paragraph)
+                })
+                .count();
+            let mut text = String::from(content);
+            for line in &body[..continuation] {
+                text.push('\n');
+                text.push_str(line.trim_start());
+            }
+
+            items.push(ListItem {
+                checked,
+                content: self.parse_inline(text.trim()),
+                children: self.parse_blocks(&body[continuation..], depth + 1),
+            });
         }
 
-        (result, consumed)
+        (
+            Block::List(List {
+                ordered,
+                start,
+                items,
+            }),
+            consumed.max(1),
+        )
     }
 
-    fn parse_code_block(&self, lines: &[&str]) -> (String, usize) {
-        let mut result = String::new();
-        let mut consumed = 0;
-        let mut in_code_block = false;
+    fn parse_code_block(&self, lines: &[&str]) -> (Block, usize) {
+        let opening = lines[0].trim();
+        let fence_len = opening.len() - opening.trim_start_matches('`').len();
+        let fence_indent = indent_width(lines[0]);
+        let info = opening[fence_len..].trim().to_string();
 
-        for line in lines {
-            if line.starts_with("```") {
-                if !in_code_block {
-                    let language = line.trim_start_matches('`').trim().to_string();
-                    if !language.is_empty() {
-                        result.push_str(&format!(r#"<pre><code class="language-{}">"#, language));
-                    } else {
-                        result.push_str(r#"<pre><code>"#);
-                    }
-                    in_code_block = true;
-                } else {
-                    result.push_str("</code></pre>\n");
-                    consumed += 1;
-                    break;
-                }
-            } else if in_code_block {
-                let escaped_line = line
-                    .replace('&', "&amp;")
-                    .replace('<', "&lt;")
-                    .replace('>', "&gt;")
-                    .replace('"', "&quot;")
-                    .replace('\'', "&#39;");
-                result.push_str(&escaped_line);
-                result.push('\n');
-            }
+        let mut code = String::new();
+        let mut consumed = 1;
+
+        while consumed < lines.len() {
+            let line = lines[consumed];
             consumed += 1;
+
+            let trimmed = line.trim();
+            if trimmed.len() >= fence_len && trimmed.bytes().all(|b| b == b'`') {
+                break;
+            }
+            code.push_str(dedent(line, fence_indent));
+            code.push('\n');
         }
 
-        (result, consumed)
+        (Block::CodeBlock(CodeBlock { info, code }), consumed)
     }
 
-    fn parse_paragraph(&self, line: &str) -> String {
-        format!("<p>{}</p>\n", self.parse_inline(line))
-    }
+    fn parse_paragraph(&self, lines: &[&str], depth: usize) -> (Block, usize) {
+        let mut text = String::from(lines[0].trim_start());
+        let mut consumed = 1;
 
-    fn parse_inline(&self, text: &str) -> String {
-        lazy_static! {
-            static ref BOLD_RE: Regex = Regex::new(r"\*\*(.+?)\*\*|__(.+?)__").unwrap();
-            static ref ITALIC_RE: Regex = Regex::new(r"\*(.+?)\*|_(.+?)_").unwrap();
-            static ref CODE_RE: Regex = Regex::new(r"`(.+?)`").unwrap();
-            static ref LINK_RE: Regex = Regex::new(r"\[(.+?)\]\((.+?)\)").unwrap();
-            static ref IMAGE_RE: Regex = Regex::new(r"!\[(.+?)\]\((.+?)\)").unwrap();
-            static ref STRIKETHROUGH_RE: Regex = Regex::new(r"~~(.+?)~~").unwrap();
-            static ref TASK_LIST_RE: Regex = Regex::new(r"^\[([x ])\](.+)$").unwrap();
-            static ref INLINE_MATH_RE: Regex = Regex::new(r"\$([^$]+?)\$").unwrap();
+        while consumed < lines.len() {
+            let line = lines[consumed].trim();
+            if line.is_empty() || !matches!(self.classify(line, depth), Element::Paragraph) {
+                break;
+            }
+            text.push('\n');
+            text.push_str(lines[consumed].trim_start());
+            consumed += 1;
         }
 
-        let mut result = text.to_string();
-
-        result = INLINE_MATH_RE
-            .replace_all(&result, r#"<span class="math-inline">$$$1$$</span>"#)
-            .to_string();
-
-        result = IMAGE_RE
-            .replace_all(&result, r#"<img src="$2" alt="$1">"#)
-            .to_string();
-
-        result = BOLD_RE
-            .replace_all(&result, "<strong>$1$2</strong>")
-            .to_string();
-        result = ITALIC_RE.replace_all(&result, "<em>$1$2</em>").to_string();
-        result = CODE_RE.replace_all(&result, "<code>$1</code>").to_string();
-        result = LINK_RE
-            .replace_all(&result, r#"<a href="$2">$1</a>"#)
-            .to_string();
-        result = STRIKETHROUGH_RE
-            .replace_all(&result, "<del>$1</del>")
-            .to_string();
-
-        result = TASK_LIST_RE
-            .replace_all(&result, |caps: &regex::Captures| {
-                let checked = caps[1].contains('x');
-                format!(
-                    r#"<input type="checkbox" disabled{}>{}"#,
-                    if checked { " checked" } else { "" },
-                    &caps[2]
-                )
-            })
-            .to_string();
+        (
+            Block::Paragraph(self.parse_inline(text.trim_end())),
+            consumed,
+        )
+    }
 
-        result
+    fn parse_inline(&self, text: &str) -> Vec<Inline> {
+        InlineParser::new(text).parse()
     }
 
-    fn parse_table(&self, lines: &[&str]) -> (String, usize) {
-        let mut result = String::from("<table>\n");
-        let mut consumed = 0;
+    fn parse_table(&self, lines: &[&str]) -> (Block, usize) {
+        let header: Vec<Vec<Inline>> = self
+            .split_table_row(lines[0])
+            .into_iter()
+            .map(|cell| self.parse_inline(cell.trim()))
+            .collect();
+        let mut alignments = vec![Alignment::None; header.len()];
+        let mut consumed = 1;
 
-        if consumed < lines.len() {
-            let cells = self.split_table_row(lines[consumed]);
-            result.push_str("<thead>\n<tr>\n");
-            for cell in cells {
-                result.push_str(&format!("<th>{}</th>\n", self.parse_inline(cell.trim())));
+        if consumed < lines.len() && TABLE_SEPARATOR_RE.is_match(lines[consumed].trim()) {
+            for (alignment, cell) in alignments
+                .iter_mut()
+                .zip(self.split_table_row(lines[consumed]))
+            {
+                let cell = cell.trim();
+                *alignment = match (cell.starts_with(':'), cell.ends_with(':')) {
+                    (true, true) => Alignment::Center,
+                    (true, false) => Alignment::Left,
+                    (false, true) => Alignment::Right,
+                    (false, false) => Alignment::None,
+                };
             }
-            result.push_str("</tr>\n</thead>\n");
-            consumed += 1;
-        }
-
-        if consumed < lines.len() && lines[consumed].contains('|') && lines[consumed].contains('-')
-        {
             consumed += 1;
         }
 
-        result.push_str("<tbody>\n");
+        let mut rows = Vec::new();
         while consumed < lines.len() {
             let line = lines[consumed];
             if !line.contains('|') {
                 break;
             }
 
-            let cells = self.split_table_row(line);
-            result.push_str("<tr>\n");
-            for cell in cells {
-                result.push_str(&format!("<td>{}</td>\n", self.parse_inline(cell.trim())));
-            }
-            result.push_str("</tr>\n");
+            rows.push(
+                self.split_table_row(line)
+                    .into_iter()
+                    .map(|cell| self.parse_inline(cell.trim()))
+                    .collect(),
+            );
             consumed += 1;
         }
-        result.push_str("</tbody>\n</table>\n");
 
-        (result, consumed)
+        (
+            Block::Table(Table {
+                alignments,
+                header,
+                rows,
+            }),
+            consumed,
+        )
     }
 
     fn split_table_row<'a>(&self, line: &'a str) -> Vec<&'a str> {
-        line.trim().trim_matches('|').split('|').collect()
-    }
+        let line = line.trim();
+        let line = line.strip_prefix('|').unwrap_or(line);
+        let line = line.strip_suffix('|').unwrap_or(line);
 
-    fn parse_math_block(&self, lines: &[&str]) -> (String, usize) {
-        let mut result = String::new();
-        let mut consumed = 0;
-        let mut in_math_block = false;
-        let mut math_content = String::new();
-
-        for line in lines {
-            if line.trim() == "$$" {
-                if !in_math_block {
-                    in_math_block = true;
-                } else {
-                    result.push_str(r#"<div class="math-block">$$"#);
-                    result.push_str(math_content.trim());
-                    result.push_str("$$</div>\n");
-                    consumed += 1;
-                    break;
+        let mut cells = Vec::new();
+        let mut cell_start = 0;
+        let mut escaped = false;
+        for (i, b) in line.bytes().enumerate() {
+            match b {
+                b'|' if !escaped => {
+                    cells.push(&line[cell_start..i]);
+                    cell_start = i + 1;
                 }
-            } else if in_math_block {
-                math_content.push_str(line);
-                math_content.push('\n');
+                b'\\' => {
+                    escaped = !escaped;
+                    continue;
+                }
+                _ => {}
             }
+            escaped = false;
+        }
+        cells.push(&line[cell_start..]);
+        cells
+    }
+
+    fn parse_math_block(&self, lines: &[&str]) -> (Block, usize) {
+        let opening = lines[0].trim()["$$".len()..].trim();
+
+        // `$$ ... $$` on a single line.
+        let (single, id) = split_math_id(opening);
+        if let Some(math) = single.strip_suffix("$$") {
+            return (math_block(math, id), 1);
+        }
+
+        let mut math = String::from(opening);
+        let mut id = None;
+        let mut consumed = 1;
+
+        while consumed < lines.len() {
+            let (line, line_id) = split_math_id(lines[consumed].trim());
             consumed += 1;
+
+            if !math.is_empty() {
+                math.push('\n');
+            }
+            if let Some(last) = line.strip_suffix("$$") {
+                math.push_str(last);
+                id = line_id;
+                break;
+            }
+            math.push_str(line);
         }
 
-        (result, consumed)
+        (math_block(&math, id), consumed)
+    }
+}
+
+/// Iterator over the top-level blocks of a document, returned by
+/// [`MarkdownParser::blocks`].
+pub struct Blocks<'a> {
+    parser: &'a MarkdownParser,
+    lines: Vec<&'a str>,
+    position: usize,
+}
+
+impl<'a> Blocks<'a> {
+    /// Pairs each block with the lines it was parsed from.
+    pub fn with_positions(mut self) -> impl Iterator<Item = (Block, Position)> + 'a {
+        std::iter::from_fn(move || self.next_positioned())
+    }
+
+    fn next_positioned(&mut self) -> Option<(Block, Position)> {
+        let (block, consumed) = self.parser.next_block(&self.lines[self.position..], 0)?;
+        let lines = &self.lines[self.position..self.position + consumed];
+        let blank = |line: &&&str| line.trim().is_empty();
+        let position = Position {
+            start_line: self.position + lines.iter().take_while(blank).count() + 1,
+            end_line: self.position + consumed - lines.iter().rev().take_while(blank).count(),
+        };
+        self.position += consumed;
+        Some((block, position))
+    }
+}
+
+impl Iterator for Blocks<'_> {
+    type Item = Block;
+
+    fn next(&mut self) -> Option<Block> {
+        self.next_positioned().map(|(block, _)| block)
     }
 }
 
@@ -386,6 +465,439 @@ enum Element {
     MathBlock,
 }
 
+/// A math block holding `math`, labelled `id` if given.
+fn math_block(math: &str, id: Option<String>) -> Block {
+    Block::MathBlock(MathBlock {
+        math: math.trim().to_string(),
+        id,
+    })
+}
+
+/// Splits the `{#id}` off a math block's closing `$$`.
+fn split_math_id(line: &str) -> (&str, Option<String>) {
+    match MATH_ID_RE.captures(line) {
+        Some(captures) => {
+            let end = captures.get(0).unwrap().start() + "$$".len();
+            (&line[..end], Some(captures[1].to_string()))
+        }
+        None => (line, None),
+    }
+}
+
+/// Width of the leading whitespace of `line`, counting tabs as four columns.
+fn indent_width(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+/// Removes up to `width` columns of leading whitespace from `line`.
+fn dedent(line: &str, width: usize) -> &str {
+    let mut removed = 0;
+    for (i, c) in line.char_indices() {
+        if removed >= width || !c.is_whitespace() {
+            return &line[i..];
+        }
+        removed += if c == '\t' { 4 } else { 1 };
+    }
+    ""
+}
+
+/// Splits a leading `[ ]` or `[x]` task marker off a list item.
+fn split_task_marker(content: &str) -> (Option<bool>, &str) {
+    for (marker, checked) in [("[ ]", false), ("[x]", true), ("[X]", true)] {
+        if let Some(rest) = content.strip_prefix(marker) {
+            if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+                return (Some(checked), rest.trim_start());
+            }
+        }
+    }
+    (None, content)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Delimiter {
+    Emphasis(u8),
+    Strong(u8),
+    Strikethrough,
+    Link,
+    Image,
+}
+
+impl Delimiter {
+    fn index(self) -> usize {
+        match self {
+            Delimiter::Emphasis(b'*') => 0,
+            Delimiter::Emphasis(_) => 1,
+            Delimiter::Strong(b'*') => 2,
+            Delimiter::Strong(_) => 3,
+            Delimiter::Strikethrough => 4,
+            Delimiter::Link => 5,
+            Delimiter::Image => 6,
+        }
+    }
+
+    fn width(self) -> usize {
+        match self {
+            Delimiter::Emphasis(_) | Delimiter::Link => 1,
+            _ => 2,
+        }
+    }
+
+    fn is_bracket(self) -> bool {
+        matches!(self, Delimiter::Link | Delimiter::Image)
+    }
+}
+
+struct Opener {
+    delimiter: Delimiter,
+    /// Index of the placeholder text node holding the delimiter itself.
+    node: usize,
+}
+
+/// Single left-to-right scan over a span of inline text.
+///
+/// Openers wait on a stack as placeholder text nodes. A closer pops every
+/// opener above its match (those stay literal) and wraps the nodes after the
+/// match, so each node is moved at most once. Backtick runs are indexed by
+/// length up front and searches for `$` and `)` only ever move forward, which
+/// keeps the whole scan linear even on long runs of unmatched delimiters.
+struct InlineParser<'a> {
+    text: &'a str,
+    pos: usize,
+    text_start: usize,
+    nodes: Vec<Inline>,
+    stack: Vec<Opener>,
+    open_counts: [usize; 7],
+    backtick_runs: Vec<(usize, usize)>,
+    /// Indices into `backtick_runs`, grouped by run length.
+    runs_by_len: HashMap<usize, Vec<usize>>,
+    run_cursor: usize,
+    dollars_exhausted: bool,
+    parens_exhausted: bool,
+}
+
+impl<'a> InlineParser<'a> {
+    fn new(text: &'a str) -> Self {
+        let bytes = text.as_bytes();
+        let mut backtick_runs = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'`' {
+                let start = i;
+                while i < bytes.len() && bytes[i] == b'`' {
+                    i += 1;
+                }
+                backtick_runs.push((start, i - start));
+            } else {
+                i += 1;
+            }
+        }
+
+        let mut runs_by_len: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (index, &(_, len)) in backtick_runs.iter().enumerate() {
+            runs_by_len.entry(len).or_default().push(index);
+        }
+
+        Self {
+            text,
+            pos: 0,
+            text_start: 0,
+            nodes: Vec::new(),
+            stack: Vec::new(),
+            open_counts: [0; 7],
+            backtick_runs,
+            runs_by_len,
+            run_cursor: 0,
+            dollars_exhausted: false,
+            parens_exhausted: false,
+        }
+    }
+
+    fn parse(mut self) -> Vec<Inline> {
+        let bytes = self.text.as_bytes();
+
+        while self.pos < bytes.len() {
+            let handled = match bytes[self.pos] {
+                b'\\' => self.backslash(),
+                b'\n' => self.line_break(),
+                b'`' => self.code_span(),
+                b'$' => self.math_span(),
+                b'*' | b'_' | b'~' => self.emphasis(),
+                b'[' => self.open_bracket(Delimiter::Link, 1),
+                b'!' if bytes.get(self.pos + 1) == Some(&b'[') => {
+                    self.open_bracket(Delimiter::Image, 2)
+                }
+                b']' => self.close_bracket(),
+                _ => false,
+            };
+            if !handled {
+                self.pos += 1;
+            }
+        }
+
+        self.flush_text(self.text.len());
+        merge_text(&mut self.nodes);
+        self.nodes
+    }
+
+    fn flush_text(&mut self, end: usize) {
+        if end > self.text_start {
+            self.nodes
+                .push(Inline::Text(self.text[self.text_start..end].to_string()));
+        }
+        self.text_start = end;
+    }
+
+    /// Emits `node` for the markup spanning `self.pos..end`.
+    fn emit(&mut self, node: Inline, end: usize) {
+        self.flush_text(self.pos);
+        self.nodes.push(node);
+        self.pos = end;
+        self.text_start = end;
+    }
+
+    fn backslash(&mut self) -> bool {
+        match self.text[self.pos + 1..].chars().next() {
+            Some('\n') => {
+                self.emit(Inline::LineBreak, self.pos + 2);
+                true
+            }
+            Some(c) if c.is_ascii_punctuation() => {
+                self.emit(Inline::Text(c.to_string()), self.pos + 2);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn line_break(&mut self) -> bool {
+        let line = &self.text[self.text_start..self.pos];
+        let content = line.trim_end_matches(' ');
+        let hard = line.len() - content.len() >= 2;
+        self.flush_text(self.text_start + content.len());
+        self.text_start = self.pos;
+        let node = if hard {
+            Inline::LineBreak
+        } else {
+            Inline::SoftBreak
+        };
+        self.emit(node, self.pos + 1);
+        true
+    }
+
+    fn code_span(&mut self) -> bool {
+        while self.run_cursor < self.backtick_runs.len()
+            && self.backtick_runs[self.run_cursor].0 + self.backtick_runs[self.run_cursor].1
+                <= self.pos
+        {
+            self.run_cursor += 1;
+        }
+        let Some(&(start, len)) = self.backtick_runs.get(self.run_cursor) else {
+            return false;
+        };
+        // After an escaped backtick, the rest of its run opens the span.
+        let len = start + len - self.pos;
+        let closing = self.runs_by_len.get(&len).and_then(|runs| {
+            let next = runs.partition_point(|&index| index <= self.run_cursor);
+            runs.get(next).copied()
+        });
+
+        match closing {
+            Some(closing) => {
+                let closing_start = self.backtick_runs[closing].0;
+                let mut code = self.text[self.pos + len..closing_start].replace('\n', " ");
+                if code.len() >= 2
+                    && code.starts_with(' ')
+                    && code.ends_with(' ')
+                    && !code.trim().is_empty()
+                {
+                    code = code[1..code.len() - 1].to_string();
+                }
+                self.emit(Inline::Code(code), closing_start + len);
+            }
+            None => self.pos += len,
+        }
+        true
+    }
+
+    fn math_span(&mut self) -> bool {
+        if self.dollars_exhausted {
+            return false;
+        }
+        match self.text[self.pos + 1..].find('$') {
+            None => {
+                self.dollars_exhausted = true;
+                false
+            }
+            Some(0) => {
+                self.pos += 2;
+                true
+            }
+            Some(offset) => {
+                let end = self.pos + 1 + offset;
+                let math = self.text[self.pos + 1..end].to_string();
+                self.emit(Inline::Math(math), end + 1);
+                true
+            }
+        }
+    }
+
+    fn emphasis(&mut self) -> bool {
+        let bytes = self.text.as_bytes();
+        let c = bytes[self.pos];
+        let run_end = self.pos + bytes[self.pos..].iter().take_while(|&&b| b == c).count();
+        let run_len = run_end - self.pos;
+
+        let before = self.text[..self.pos].chars().next_back();
+        let after = self.text[run_end..].chars().next();
+        let left_flanking = after.is_some_and(|c| !c.is_whitespace());
+        let right_flanking = before.is_some_and(|c| !c.is_whitespace());
+        let (can_open, can_close) = if c == b'_' {
+            (
+                left_flanking && !before.is_some_and(char::is_alphanumeric),
+                right_flanking && !after.is_some_and(char::is_alphanumeric),
+            )
+        } else {
+            (left_flanking, right_flanking)
+        };
+
+        let pieces: &[Delimiter] = match (c, run_len) {
+            (b'~', 2) => &[Delimiter::Strikethrough],
+            (b'~', _) => &[],
+            (_, 1) => &[Delimiter::Emphasis(c)],
+            (_, 2) => &[Delimiter::Strong(c)],
+            _ => &[Delimiter::Strong(c), Delimiter::Emphasis(c)],
+        };
+        let closes = can_close && pieces.iter().any(|&d| self.open_counts[d.index()] > 0);
+        if pieces.is_empty() || !(closes || can_open) {
+            self.pos = run_end;
+            return true;
+        }
+
+        // Surplus characters of a long run stay literal on the outside: before
+        // the openers, after the closers.
+        let surplus = run_len - pieces.iter().map(|d| d.width()).sum::<usize>();
+        if !closes {
+            self.pos += surplus;
+        }
+
+        // Closers match innermost first; openers are pushed outermost first.
+        let mut ordered = pieces.to_vec();
+        if closes {
+            ordered.reverse();
+        }
+        for delimiter in ordered {
+            let end = self.pos + delimiter.width();
+            if closes && self.open_counts[delimiter.index()] > 0 {
+                let (_, children) = self.close(|d| d == delimiter);
+                self.nodes.push(match delimiter {
+                    Delimiter::Strong(_) => Inline::Strong(children),
+                    Delimiter::Strikethrough => Inline::Strikethrough(children),
+                    _ => Inline::Emphasis(children),
+                });
+                self.text_start = end;
+            } else if can_open && self.stack.len() < MAX_NESTING {
+                self.push_opener(delimiter, end);
+            }
+            self.pos = end;
+        }
+
+        self.pos = run_end;
+        true
+    }
+
+    fn open_bracket(&mut self, delimiter: Delimiter, width: usize) -> bool {
+        if self.stack.len() >= MAX_NESTING {
+            return false;
+        }
+        self.push_opener(delimiter, self.pos + width);
+        true
+    }
+
+    fn push_opener(&mut self, delimiter: Delimiter, end: usize) {
+        self.flush_text(self.pos);
+        self.stack.push(Opener {
+            delimiter,
+            node: self.nodes.len(),
+        });
+        self.open_counts[delimiter.index()] += 1;
+        self.nodes
+            .push(Inline::Text(self.text[self.pos..end].to_string()));
+        self.pos = end;
+        self.text_start = end;
+    }
+
+    fn close_bracket(&mut self) -> bool {
+        let open_brackets =
+            self.open_counts[Delimiter::Link.index()] + self.open_counts[Delimiter::Image.index()];
+        if open_brackets == 0
+            || self.parens_exhausted
+            || self.text.as_bytes().get(self.pos + 1) != Some(&b'(')
+        {
+            return false;
+        }
+        let Some(offset) = self.text[self.pos + 2..].find(')') else {
+            self.parens_exhausted = true;
+            return false;
+        };
+
+        let url_end = self.pos + 2 + offset;
+        let url = self.text[self.pos + 2..url_end]
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_string();
+
+        let (delimiter, children) = self.close(Delimiter::is_bracket);
+        self.nodes.push(if delimiter == Delimiter::Image {
+            Inline::Image {
+                url,
+                alt: plain_text(&children),
+            }
+        } else {
+            Inline::Link {
+                url,
+                content: children,
+            }
+        });
+        self.pos = url_end + 1;
+        self.text_start = self.pos;
+        true
+    }
+
+    /// Pops openers down to the nearest one accepted by `matches` and returns
+    /// it along with everything emitted since. Openers above it stay literal.
+    fn close(&mut self, matches: impl Fn(Delimiter) -> bool) -> (Delimiter, Vec<Inline>) {
+        self.flush_text(self.pos);
+        let opener = loop {
+            let opener = self.stack.pop().expect("caller checked open_counts");
+            self.open_counts[opener.delimiter.index()] -= 1;
+            if matches(opener.delimiter) {
+                break opener;
+            }
+        };
+
+        let mut children = self.nodes.split_off(opener.node + 1);
+        self.nodes.pop();
+        merge_text(&mut children);
+        (opener.delimiter, children)
+    }
+}
+
+/// Joins adjacent text nodes left behind by unmatched delimiters.
+fn merge_text(nodes: &mut Vec<Inline>) {
+    let mut merged: Vec<Inline> = Vec::with_capacity(nodes.len());
+    for node in nodes.drain(..) {
+        match (merged.last_mut(), node) {
+            (Some(Inline::Text(previous)), Inline::Text(text)) => previous.push_str(&text),
+            (_, node) => merged.push(node),
+        }
+    }
+    *nodes = merged;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -457,4 +969,203 @@ mod tests {
         assert!(result.contains(r#"<div class="math-block">"#));
         assert!(result.contains("E = mc^2"));
     }
+
+    #[test]
+    fn test_math_labels() {
+        let document = MarkdownParser::new().parse_document(
+            "$$ E = mc^2 $$ {#eq:energy}\n\n$$\na \\label{eq:a}\n$$ {#eq:b}\n\n$$\nx \\label{eq:x}\n$$",
+        );
+        let blocks: Vec<_> = document
+            .blocks
+            .iter()
+            .map(|block| match block {
+                Block::MathBlock(math_block) => (math_block.math.as_str(), math_block.label()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            blocks,
+            [
+                ("E = mc^2", Some("eq:energy")),
+                ("a \\label{eq:a}", Some("eq:b")),
+                ("x \\label{eq:x}", Some("eq:x")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_to_matches_parse() {
+        let parser = MarkdownParser::new();
+        let input = include_str!("../example.md");
+        let mut streamed = Vec::new();
+        parser.parse_to(input, &mut streamed).unwrap();
+        assert_eq!(
+            String::from_utf8(streamed).unwrap(),
+            parser.parse(input).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_code_spans_take_precedence() {
+        let parser = MarkdownParser::new();
+        let result = parser.parse("`**not bold**` and ``a ` b``").unwrap();
+        assert!(result.contains("<code>**not bold**</code>"));
+        assert!(result.contains("<code>a ` b</code>"));
+    }
+
+    #[test]
+    fn test_unmatched_delimiters_stay_literal() {
+        let parser = MarkdownParser::new();
+        let result = parser.parse("**a *b [c and snake_case_name").unwrap();
+        assert!(result.contains("<p>**a *b [c and snake_case_name</p>"));
+    }
+
+    #[test]
+    fn test_escapes_and_links() {
+        let parser = MarkdownParser::new();
+        let result = parser
+            .parse(r"\*literal\* [**bold** link](https://x.y/?a=1&b=2)")
+            .unwrap();
+        assert!(result.contains("*literal*"));
+        assert!(
+            result.contains(r#"<a href="https://x.y/?a=1&amp;b=2"><strong>bold</strong> link</a>"#)
+        );
+    }
+
+    #[test]
+    fn test_paragraph_lines_are_joined() {
+        let parser = MarkdownParser::new();
+        let result = parser.parse("first line  \nsecond line\nthird").unwrap();
+        assert_eq!(result, "<p>first line<br>\nsecond line\nthird</p>\n");
+    }
+
+    #[test]
+    fn test_task_list_and_nesting() {
+        let parser = MarkdownParser::new();
+        let document = parser.parse_document("- [x] done\n  - [ ] todo\n- plain");
+        let Block::List(list) = &document.blocks[0] else {
+            panic!("expected a list");
+        };
+        assert_eq!(list.items.len(), 2);
+        assert_eq!(list.items[0].checked, Some(true));
+        let Block::List(nested) = &list.items[0].children[0] else {
+            panic!("expected a nested list");
+        };
+        assert_eq!(nested.items[0].checked, Some(false));
+        assert_eq!(list.items[1].checked, None);
+    }
+
+    #[test]
+    fn test_list_item_continuation() {
+        let parser = MarkdownParser::new();
+        let document = parser.parse_document("- one\n  continued\n  - nested\n-\n- last");
+        let Block::List(list) = &document.blocks[0] else {
+            panic!("expected a list");
+        };
+        assert_eq!(list.items.len(), 3);
+        assert_eq!(
+            list.items[0].content,
+            vec![
+                Inline::Text("one".to_string()),
+                Inline::SoftBreak,
+                Inline::Text("continued".to_string()),
+            ]
+        );
+        assert!(matches!(list.items[0].children[..], [Block::List(_)]));
+        assert!(list.items[1].content.is_empty());
+    }
+
+    #[test]
+    fn test_escaped_backtick_before_code_span() {
+        let parser = MarkdownParser::new();
+        assert_eq!(
+            parser.parse("\\```a`` and `b`").unwrap(),
+            "<p>`<code>a</code> and <code>b</code></p>\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const VOID_ELEMENTS: &[&str] = &["br", "hr", "img", "input"];
+
+    /// Checks that every non-void element opened in `html` is closed, in
+    /// order. User text is escaped, so each `<` in the output is a real tag.
+    fn tags_balanced(html: &str) -> bool {
+        let mut open = Vec::new();
+        for tag in html.split('<').skip(1) {
+            let (closing, tag) = match tag.strip_prefix('/') {
+                Some(rest) => (true, rest),
+                None => (false, tag),
+            };
+            let name: String = tag
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .collect();
+            if name.is_empty() && tag.starts_with('!') {
+                continue;
+            }
+            if VOID_ELEMENTS.contains(&name.as_str()) {
+                continue;
+            }
+            if !closing {
+                open.push(name);
+            } else if open.pop().as_ref() != Some(&name) {
+                return false;
+            }
+        }
+        open.is_empty()
+    }
+
+    fn markdownish() -> impl Strategy<Value = String> {
+        proptest::string::string_regex(
+            "([-#>*_`~$|\\[\\]()!.:1 \t\n\u{3000}\u{a0}éß😀<&\"'x]|```|\\$\\$|\n  |\n    ){0,64}",
+        )
+        .unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics(input in any::<String>()) {
+            prop_assert!(MarkdownParser::new().parse(&input).is_ok());
+        }
+
+        #[test]
+        fn parse_markdownish_never_panics(input in markdownish()) {
+            prop_assert!(MarkdownParser::new().parse(&input).is_ok());
+        }
+
+        #[test]
+        fn output_tags_are_balanced(input in markdownish()) {
+            let html = MarkdownParser::new().parse(&input).unwrap();
+            prop_assert!(tags_balanced(&html), "unbalanced output: {:?}", html);
+        }
+
+        #[test]
+        fn output_size_is_linear(input in markdownish()) {
+            let html = MarkdownParser::new().parse(&input).unwrap();
+            prop_assert!(html.len() <= 32 * input.len() + 128);
+        }
+    }
+
+    #[test]
+    fn blockquote_with_multibyte_whitespace() {
+        let result = MarkdownParser::new().parse(">\u{3000}quoted").unwrap();
+        assert!(result.contains("<blockquote>\n<p>quoted</p>\n</blockquote>"));
+    }
+
+    #[test]
+    fn ordered_list_with_tab_terminates() {
+        let result = MarkdownParser::new().parse("1.\tFirst").unwrap();
+        assert!(result.contains("<li>First</li>"));
+    }
+
+    #[test]
+    fn unterminated_code_block_is_closed() {
+        let result = MarkdownParser::new().parse("```\n<b>").unwrap();
+        assert!(result.ends_with("&lt;b&gt;\n</code></pre>\n"));
+    }
 }
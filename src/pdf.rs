@@ -0,0 +1,1515 @@
+use crate::ast::{plain_text, Alignment, Block, CodeBlock, Document, Inline, List, Table};
+use crate::error::ConverterError;
+use crate::html::HeadingIds;
+use anyhow::Result;
+use log::warn;
+use pdf_writer::types::{
+    ActionType, AnnotationType, CidFontType, FontFlags, SystemInfo, UnicodeCmap,
+};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use subsetter::GlyphRemapper;
+use ttf_parser::{Face, GlyphId};
+
+const MARGIN: f32 = 56.0;
+const BODY_SIZE: f32 = 11.0;
+const CODE_SIZE: f32 = 9.0;
+const LINE_HEIGHT: f32 = 1.45;
+/// Space left after every block.
+const BLOCK_GAP: f32 = 8.0;
+const HEADING_SIZES: [f32; 6] = [22.0, 18.0, 15.0, 13.0, 11.5, 11.0];
+const LIST_INDENT: f32 = 18.0;
+const QUOTE_INDENT: f32 = 14.0;
+const CELL_PADDING: f32 = 4.0;
+const CODE_PADDING: f32 = 6.0;
+/// Headings deeper than this are left out of the table of contents.
+const TOC_DEPTH: u8 = 3;
+
+const TEXT_COLOR: Color = [0.1, 0.1, 0.1];
+const QUOTE_COLOR: Color = [0.35, 0.35, 0.35];
+const LINK_COLOR: Color = [0.05, 0.3, 0.7];
+
+type Color = [f32; 3];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PaperSize {
+    #[default]
+    A4,
+    Letter,
+}
+
+impl PaperSize {
+    /// Width and height in points.
+    pub(crate) fn dimensions(self) -> (f32, f32) {
+        match self {
+            PaperSize::A4 => (595.28, 841.89),
+            PaperSize::Letter => (612.0, 792.0),
+        }
+    }
+}
+
+impl FromStr for PaperSize {
+    type Err = ConverterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "a4" => Ok(PaperSize::A4),
+            "letter" => Ok(PaperSize::Letter),
+            _ => Err(ConverterError::UnknownPaperSize(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PdfOptions {
+    pub paper: PaperSize,
+    /// Start with a table of contents giving the page of every heading.
+    pub toc: bool,
+}
+
+/// Lays a document out on pages and writes it as a PDF with the bundled
+/// DejaVu fonts embedded.
+///
+/// Unlike the other backends this is not a [`Renderer`](crate::render::Renderer):
+/// the table of contents needs the page of every heading, so the whole
+/// document is laid out before anything is written. Math is set in the
+/// monospace font as TeX source, and images that are not local PNG or JPEG
+/// files show their alt text instead.
+#[derive(Default)]
+pub struct PdfRenderer {
+    options: PdfOptions,
+    /// Directory relative image paths are resolved against.
+    base_dir: PathBuf,
+    fonts: Fonts,
+}
+
+impl PdfRenderer {
+    pub fn new(options: PdfOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_base_dir(mut self, base_dir: impl Into<PathBuf>) -> Self {
+        self.base_dir = base_dir.into();
+        self
+    }
+
+    pub fn render(&self, document: &Document) -> Result<Vec<u8>> {
+        let body = self.lay_out(document);
+        let toc = self.options.toc.then(|| {
+            // The entries give page numbers counted from the first page of the
+            // contents, so find out how many pages the contents take first.
+            let pages = self.lay_out_toc(&body.anchors, 0).pages.len();
+            self.lay_out_toc(&body.anchors, pages)
+        });
+        self.write(toc, body)
+    }
+
+    fn lay_out(&self, document: &Document) -> Layout<'_> {
+        let mut layout = Layout::new(&self.fonts, self.options.paper, &self.base_dir);
+        let width = layout.content_width();
+        layout.blocks(&document.blocks, MARGIN, width);
+        layout
+    }
+
+    fn lay_out_toc(&self, anchors: &[Anchor], page_offset: usize) -> Layout<'_> {
+        let mut layout = Layout::new(&self.fonts, self.options.paper, &self.base_dir);
+        let width = layout.content_width();
+        let title = [Inline::Text("Contents".to_string())];
+        layout.heading(2, &title, MARGIN, width, false);
+
+        let number_width = 30.0;
+        for (i, anchor) in anchors.iter().enumerate() {
+            if anchor.level > TOC_DEPTH {
+                continue;
+            }
+            let indent = f32::from(anchor.level - 1) * LIST_INDENT;
+            let style = layout.style(FontKind::Regular, BODY_SIZE);
+            let tokens = layout.tokens(&[Inline::Text(anchor.title.clone())], &style);
+            let lines = layout.break_lines(&tokens, width - indent - number_width);
+            let (start_page, top) = (layout.page(), layout.y);
+            let placed = layout.lines(&lines, MARGIN + indent, width - indent, Alignment::Left);
+            let Some((page, baseline)) = placed.last else {
+                continue;
+            };
+
+            let number = (anchor.page + page_offset + 1).to_string();
+            let number_x =
+                MARGIN + width - layout.fonts.width(FontKind::Regular, &number, BODY_SIZE);
+            let line_end = MARGIN + indent + lines.last().map_or(0.0, |line| line.width);
+            let dot = layout.fonts.width(FontKind::Regular, ".", BODY_SIZE) * 2.0;
+            let dots = ((number_x - line_end - 12.0) / dot).max(0.0) as usize;
+            let bottom = baseline - BODY_SIZE * 0.3;
+            let top = if start_page == page {
+                top
+            } else {
+                layout.top()
+            };
+            let ops = &mut layout.pages[page];
+            ops.push(Op::Text {
+                x: line_end + 6.0,
+                y: baseline,
+                font: FontKind::Regular,
+                size: BODY_SIZE,
+                color: QUOTE_COLOR,
+                text: ". ".repeat(dots),
+            });
+            ops.push(Op::Text {
+                x: number_x,
+                y: baseline,
+                font: FontKind::Regular,
+                size: BODY_SIZE,
+                color: TEXT_COLOR,
+                text: number,
+            });
+            ops.push(Op::Link {
+                rect: [MARGIN + indent, bottom, MARGIN + width, top],
+                target: Target::Anchor(i),
+            });
+        }
+        layout
+    }
+
+    fn write(&self, toc: Option<Layout>, body: Layout) -> Result<Vec<u8>> {
+        let (width, height) = self.options.paper.dimensions();
+        let toc_pages = toc.as_ref().map_or(0, |toc| toc.pages.len());
+        let pages: Vec<&Vec<Op>> = toc
+            .iter()
+            .chain([&body])
+            .flat_map(|layout| &layout.pages)
+            .collect();
+
+        let mut pdf = Pdf::new();
+        let mut alloc = Ref::new(1);
+        let catalog_id = alloc.bump();
+        let tree_id = alloc.bump();
+        let info_id = alloc.bump();
+        let page_ids: Vec<Ref> = pages.iter().map(|_| alloc.bump()).collect();
+        let font_ids: Vec<Ref> = FontKind::ALL.iter().map(|_| alloc.bump()).collect();
+        let image_ids: Vec<Ref> = body.images.iter().map(|_| alloc.bump()).collect();
+
+        let mut usage: Vec<FontUsage> =
+            FontKind::ALL.iter().map(|_| FontUsage::default()).collect();
+        for (i, ops) in pages.iter().enumerate() {
+            let mut content = Content::new();
+            let footer = Op::Text {
+                x: width / 2.0
+                    - self
+                        .fonts
+                        .width(FontKind::Regular, &(i + 1).to_string(), 9.0)
+                        / 2.0,
+                y: MARGIN / 2.0,
+                font: FontKind::Regular,
+                size: 9.0,
+                color: QUOTE_COLOR,
+                text: (i + 1).to_string(),
+            };
+            let mut annotations = Vec::new();
+            let mut fonts_used = [false; 5];
+
+            for op in ops.iter().chain([&footer]) {
+                match op {
+                    Op::Text {
+                        x,
+                        y,
+                        font,
+                        size,
+                        color,
+                        text,
+                    } => {
+                        fonts_used[*font as usize] = true;
+                        let encoded = usage[*font as usize].encode(self.fonts.get(*font), text);
+                        content
+                            .begin_text()
+                            .set_font(Name(font.resource().as_bytes()), *size)
+                            .set_fill_rgb(color[0], color[1], color[2])
+                            .next_line(*x, *y)
+                            .show(Str(&encoded))
+                            .end_text();
+                    }
+                    Op::Fill { rect, gray } => {
+                        content
+                            .set_fill_gray(*gray)
+                            .rect(rect[0], rect[1], rect[2], rect[3])
+                            .fill_nonzero();
+                    }
+                    Op::Line {
+                        from,
+                        to,
+                        width,
+                        gray,
+                    } => {
+                        content
+                            .set_stroke_gray(*gray)
+                            .set_line_width(*width)
+                            .move_to(from.0, from.1)
+                            .line_to(to.0, to.1)
+                            .stroke();
+                    }
+                    Op::Image { rect, image } => {
+                        content
+                            .save_state()
+                            .transform([rect[2], 0.0, 0.0, rect[3], rect[0], rect[1]])
+                            .x_object(Name(format!("Im{}", image).as_bytes()))
+                            .restore_state();
+                    }
+                    Op::Link { rect, target } => {
+                        let destination = match target {
+                            Target::Uri(uri) => Some(Err(uri)),
+                            Target::Anchor(i) => Some(Ok(&body.anchors[*i])),
+                            Target::Heading(id) => {
+                                body.anchors.iter().find(|a| &a.id == id).map(Ok)
+                            }
+                        };
+                        if let Some(destination) = destination {
+                            annotations.push((alloc.bump(), *rect, destination));
+                        }
+                    }
+                }
+            }
+
+            let content_id = alloc.bump();
+            pdf.stream(content_id, &deflate(&content.finish()))
+                .filter(Filter::FlateDecode);
+
+            let mut page = pdf.page(page_ids[i]);
+            page.media_box(Rect::new(0.0, 0.0, width, height))
+                .parent(tree_id)
+                .contents(content_id)
+                .annotations(annotations.iter().map(|(id, ..)| *id));
+            let mut resources = page.resources();
+            let mut fonts = resources.fonts();
+            for kind in FontKind::ALL
+                .into_iter()
+                .filter(|&kind| fonts_used[kind as usize])
+            {
+                fonts.pair(Name(kind.resource().as_bytes()), font_ids[kind as usize]);
+            }
+            fonts.finish();
+            let mut x_objects = resources.x_objects();
+            for (i, id) in image_ids.iter().enumerate() {
+                x_objects.pair(Name(format!("Im{}", i).as_bytes()), *id);
+            }
+            x_objects.finish();
+            resources.finish();
+            page.finish();
+
+            for (id, rect, destination) in annotations {
+                let mut annotation = pdf.annotation(id);
+                annotation
+                    .subtype(AnnotationType::Link)
+                    .rect(Rect::new(rect[0], rect[1], rect[2], rect[3]))
+                    .border(0.0, 0.0, 0.0, None);
+                match destination {
+                    Err(uri) => {
+                        annotation
+                            .action()
+                            .action_type(ActionType::Uri)
+                            .uri(Str(uri.as_bytes()));
+                    }
+                    Ok(anchor) => {
+                        annotation
+                            .action()
+                            .action_type(ActionType::GoTo)
+                            .destination()
+                            .page(page_ids[toc_pages + anchor.page])
+                            .xyz(0.0, anchor.y, None);
+                    }
+                }
+            }
+        }
+
+        for kind in FontKind::ALL {
+            usage[kind as usize].write(
+                &mut pdf,
+                &mut alloc,
+                font_ids[kind as usize],
+                self.fonts.get(kind),
+            )?;
+        }
+
+        for (image, id) in body.images.iter().zip(&image_ids) {
+            let mask_id = image.alpha.as_ref().map(|alpha| {
+                let mask_id = alloc.bump();
+                let data = deflate(alpha);
+                let mut mask = pdf.image_xobject(mask_id, &data);
+                mask.width(image.width as i32)
+                    .height(image.height as i32)
+                    .bits_per_component(8)
+                    .filter(Filter::FlateDecode);
+                mask.color_space().device_gray();
+                mask_id
+            });
+            let data = deflate(&image.rgb);
+            let mut xobject = pdf.image_xobject(*id, &data);
+            xobject
+                .width(image.width as i32)
+                .height(image.height as i32)
+                .bits_per_component(8)
+                .filter(Filter::FlateDecode);
+            xobject.color_space().device_rgb();
+            if let Some(mask_id) = mask_id {
+                xobject.s_mask(mask_id);
+            }
+        }
+
+        pdf.catalog(catalog_id).pages(tree_id);
+        pdf.pages(tree_id)
+            .kids(page_ids.iter().copied())
+            .count(page_ids.len() as i32);
+        let mut info = pdf.document_info(info_id);
+        info.producer(TextStr("markdown_converter"));
+        if let Some(title) = body.anchors.iter().find(|anchor| anchor.level == 1) {
+            info.title(TextStr(&title.title));
+        }
+        info.finish();
+
+        Ok(pdf.finish())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FontKind {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Mono,
+}
+
+impl FontKind {
+    const ALL: [FontKind; 5] = [
+        FontKind::Regular,
+        FontKind::Bold,
+        FontKind::Italic,
+        FontKind::BoldItalic,
+        FontKind::Mono,
+    ];
+
+    fn resource(self) -> String {
+        format!("F{}", self as usize)
+    }
+
+    fn bold(self) -> FontKind {
+        match self {
+            FontKind::Regular => FontKind::Bold,
+            FontKind::Italic => FontKind::BoldItalic,
+            kind => kind,
+        }
+    }
+
+    fn italic(self) -> FontKind {
+        match self {
+            FontKind::Regular => FontKind::Italic,
+            FontKind::Bold => FontKind::BoldItalic,
+            kind => kind,
+        }
+    }
+}
+
+struct Font {
+    name: &'static str,
+    data: &'static [u8],
+    face: Face<'static>,
+}
+
+impl Font {
+    fn new(name: &'static str, data: &'static [u8]) -> Self {
+        let face = Face::parse(data, 0).expect("bundled fonts are valid");
+        Self { name, data, face }
+    }
+
+    fn glyph(&self, c: char) -> u16 {
+        self.face.glyph_index(c).map_or(0, |glyph| glyph.0)
+    }
+
+    /// The advance of `glyph` in thousandths of the font size.
+    fn advance(&self, glyph: u16) -> f32 {
+        let advance = self.face.glyph_hor_advance(GlyphId(glyph)).unwrap_or(0);
+        f32::from(advance) * 1000.0 / f32::from(self.face.units_per_em())
+    }
+
+    fn to_pdf_units(&self, value: i16) -> f32 {
+        f32::from(value) * 1000.0 / f32::from(self.face.units_per_em())
+    }
+}
+
+struct Fonts([Font; 5]);
+
+impl Default for Fonts {
+    fn default() -> Self {
+        Self([
+            Font::new(
+                "DejaVuSans",
+                include_bytes!("../assets/fonts/DejaVuSans.ttf"),
+            ),
+            Font::new(
+                "DejaVuSans-Bold",
+                include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf"),
+            ),
+            Font::new(
+                "DejaVuSans-Oblique",
+                include_bytes!("../assets/fonts/DejaVuSans-Oblique.ttf"),
+            ),
+            Font::new(
+                "DejaVuSans-BoldOblique",
+                include_bytes!("../assets/fonts/DejaVuSans-BoldOblique.ttf"),
+            ),
+            Font::new(
+                "DejaVuSansMono",
+                include_bytes!("../assets/fonts/DejaVuSansMono.ttf"),
+            ),
+        ])
+    }
+}
+
+impl Fonts {
+    fn get(&self, kind: FontKind) -> &Font {
+        &self.0[kind as usize]
+    }
+
+    fn width(&self, kind: FontKind, text: &str, size: f32) -> f32 {
+        let font = self.get(kind);
+        let advance: f32 = text.chars().map(|c| font.advance(font.glyph(c))).sum();
+        advance * size / 1000.0
+    }
+}
+
+/// The glyphs of one font used by the document, numbered in order of first
+/// use as the embedded subset numbers them.
+#[derive(Default)]
+struct FontUsage {
+    remapper: GlyphRemapper,
+    chars: HashMap<u16, char>,
+    used: bool,
+}
+
+impl FontUsage {
+    /// `text` as the two-byte glyph IDs of the subset.
+    fn encode(&mut self, font: &Font, text: &str) -> Vec<u8> {
+        self.used = true;
+        let mut encoded = Vec::with_capacity(text.len() * 2);
+        for c in text.chars() {
+            let glyph = self.remapper.remap(font.glyph(c));
+            self.chars.entry(glyph).or_insert(c);
+            encoded.extend_from_slice(&glyph.to_be_bytes());
+        }
+        encoded
+    }
+
+    fn write(&self, pdf: &mut Pdf, alloc: &mut Ref, id: Ref, font: &Font) -> Result<()> {
+        if !self.used {
+            return Ok(());
+        }
+
+        const SYSTEM_INFO: SystemInfo = SystemInfo {
+            registry: Str(b"Adobe"),
+            ordering: Str(b"Identity"),
+            supplement: 0,
+        };
+        let cid_id = alloc.bump();
+        let descriptor_id = alloc.bump();
+        let cmap_id = alloc.bump();
+        let file_id = alloc.bump();
+        // Subsets are named with a six-letter tag in front of the font name.
+        let name = format!("MDCVTR+{}", font.name);
+
+        pdf.type0_font(id)
+            .base_font(Name(name.as_bytes()))
+            .encoding_predefined(Name(b"Identity-H"))
+            .descendant_font(cid_id)
+            .to_unicode(cmap_id);
+
+        let mut cid_font = pdf.cid_font(cid_id);
+        cid_font
+            .subtype(CidFontType::Type2)
+            .base_font(Name(name.as_bytes()))
+            .system_info(SYSTEM_INFO)
+            .font_descriptor(descriptor_id)
+            .cid_to_gid_map_predefined(Name(b"Identity"));
+        let widths: Vec<f32> = self
+            .remapper
+            .remapped_gids()
+            .map(|glyph| font.advance(glyph))
+            .collect();
+        cid_font.widths().consecutive(0, widths);
+        cid_font.finish();
+
+        let face = &font.face;
+        let bbox = face.global_bounding_box();
+        let mut flags = FontFlags::NON_SYMBOLIC;
+        if face.is_monospaced() {
+            flags |= FontFlags::FIXED_PITCH;
+        }
+        if face.is_italic() || face.is_oblique() {
+            flags |= FontFlags::ITALIC;
+        }
+        pdf.font_descriptor(descriptor_id)
+            .name(Name(name.as_bytes()))
+            .flags(flags)
+            .bbox(Rect::new(
+                font.to_pdf_units(bbox.x_min),
+                font.to_pdf_units(bbox.y_min),
+                font.to_pdf_units(bbox.x_max),
+                font.to_pdf_units(bbox.y_max),
+            ))
+            .italic_angle(face.italic_angle())
+            .ascent(font.to_pdf_units(face.ascender()))
+            .descent(font.to_pdf_units(face.descender()))
+            .cap_height(font.to_pdf_units(face.capital_height().unwrap_or(face.ascender())))
+            .stem_v(if face.is_bold() { 140.0 } else { 80.0 })
+            .font_file2(file_id);
+
+        let subset = subsetter::subset(font.data, 0, &self.remapper).map_err(|e| {
+            ConverterError::PdfError(format!("Failed to embed {}: {}", font.name, e))
+        })?;
+        pdf.stream(file_id, &deflate(&subset))
+            .filter(Filter::FlateDecode);
+
+        let mut cmap = UnicodeCmap::new(Name(b"Custom"), SYSTEM_INFO);
+        for (&glyph, &c) in &self.chars {
+            cmap.pair(glyph, c);
+        }
+        pdf.cmap(cmap_id, &cmap.finish());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    Uri(String),
+    /// A heading by its id, for links like `[Setup](#setup)`.
+    Heading(String),
+    /// A heading by its index in [`Layout::anchors`].
+    Anchor(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Op {
+    Text {
+        x: f32,
+        y: f32,
+        font: FontKind,
+        size: f32,
+        color: Color,
+        text: String,
+    },
+    /// `[x, y, width, height]`
+    Fill { rect: [f32; 4], gray: f32 },
+    Line {
+        from: (f32, f32),
+        to: (f32, f32),
+        width: f32,
+        gray: f32,
+    },
+    /// `[x, y, width, height]`
+    Image { rect: [f32; 4], image: usize },
+    /// `[left, bottom, right, top]`
+    Link { rect: [f32; 4], target: Target },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Style {
+    font: FontKind,
+    size: f32,
+    color: Color,
+    link: Option<Target>,
+    strikethrough: bool,
+}
+
+struct Segment {
+    text: String,
+    style: Style,
+}
+
+/// Text that can't be broken across lines, possibly in several styles, e.g.
+/// `**bold**,`.
+struct Word {
+    segments: Vec<Segment>,
+    space_before: bool,
+}
+
+enum Token {
+    Word(Word),
+    LineBreak,
+}
+
+/// Text in one style placed `x` points into a line.
+struct Run {
+    x: f32,
+    width: f32,
+    text: String,
+    style: Style,
+}
+
+#[derive(Default)]
+struct Line {
+    runs: Vec<Run>,
+    width: f32,
+    /// Size of the largest text on the line.
+    size: f32,
+}
+
+/// Where the first and last of a sequence of lines were placed, as page index
+/// and baseline.
+#[derive(Default)]
+struct Placed {
+    first: Option<(usize, f32)>,
+    last: Option<(usize, f32)>,
+}
+
+struct Anchor {
+    level: u8,
+    title: String,
+    id: String,
+    page: usize,
+    /// Top of the heading on its page.
+    y: f32,
+}
+
+struct Image {
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+    alpha: Option<Vec<u8>>,
+}
+
+/// Places blocks on pages from the top down, breaking pages as needed.
+struct Layout<'a> {
+    fonts: &'a Fonts,
+    width: f32,
+    height: f32,
+    base_dir: &'a Path,
+    pages: Vec<Vec<Op>>,
+    /// Top of the free space on the last page.
+    y: f32,
+    anchors: Vec<Anchor>,
+    heading_ids: HeadingIds,
+    images: Vec<Image>,
+    image_paths: HashMap<PathBuf, usize>,
+    color: Color,
+    list_depth: usize,
+}
+
+impl<'a> Layout<'a> {
+    fn new(fonts: &'a Fonts, paper: PaperSize, base_dir: &'a Path) -> Self {
+        let (width, height) = paper.dimensions();
+        Self {
+            fonts,
+            width,
+            height,
+            base_dir,
+            pages: vec![Vec::new()],
+            y: height - MARGIN,
+            anchors: Vec::new(),
+            heading_ids: HeadingIds::default(),
+            images: Vec::new(),
+            image_paths: HashMap::new(),
+            color: TEXT_COLOR,
+            list_depth: 0,
+        }
+    }
+
+    fn content_width(&self) -> f32 {
+        self.width - 2.0 * MARGIN
+    }
+
+    fn top(&self) -> f32 {
+        self.height - MARGIN
+    }
+
+    fn page(&self) -> usize {
+        self.pages.len() - 1
+    }
+
+    fn push(&mut self, op: Op) {
+        self.pages
+            .last_mut()
+            .expect("there is always a page")
+            .push(op);
+    }
+
+    /// Starts a new page unless `height` still fits on this one, or nothing
+    /// would fit better on an empty page.
+    fn ensure(&mut self, height: f32) {
+        if self.y - height < MARGIN && self.y < self.top() {
+            self.pages.push(Vec::new());
+            self.y = self.top();
+        }
+    }
+
+    fn gap(&mut self, gap: f32) {
+        if self.y < self.top() {
+            self.y -= gap;
+        }
+    }
+
+    fn style(&self, font: FontKind, size: f32) -> Style {
+        Style {
+            font,
+            size,
+            color: self.color,
+            link: None,
+            strikethrough: false,
+        }
+    }
+
+    fn blocks(&mut self, blocks: &[Block], x: f32, width: f32) {
+        for block in blocks {
+            self.block(block, x, width);
+        }
+    }
+
+    fn block(&mut self, block: &Block, x: f32, width: f32) {
+        match block {
+            Block::Heading { level, content } => self.heading(*level, content, x, width, true),
+            Block::Paragraph(content) => {
+                if let [Inline::Image { url, alt }] = content.as_slice() {
+                    if self.image(url, alt, x, width) {
+                        return;
+                    }
+                }
+                let style = self.style(FontKind::Regular, BODY_SIZE);
+                self.text(content, &style, x, width, Alignment::Left);
+                self.gap(BLOCK_GAP);
+            }
+            Block::List(list) => self.list(list, x, width),
+            Block::CodeBlock(code_block) => self.code_block(code_block, x, width),
+            Block::Table(table) => self.table(table, x, width),
+            Block::Blockquote(blocks) => self.blockquote(blocks, x, width),
+            Block::MathBlock(math_block) => {
+                for line in math_block.math.trim().lines() {
+                    let style = self.style(FontKind::Mono, CODE_SIZE + 1.0);
+                    let content = [Inline::Text(line.to_string())];
+                    self.text(&content, &style, x, width, Alignment::Center);
+                }
+                self.gap(BLOCK_GAP);
+            }
+            Block::HorizontalRule => {
+                self.ensure(BLOCK_GAP * 2.0);
+                self.gap(BLOCK_GAP);
+                let y = self.y;
+                self.push(Op::Line {
+                    from: (x, y),
+                    to: (x + width, y),
+                    width: 0.5,
+                    gray: 0.6,
+                });
+                self.gap(BLOCK_GAP * 2.0);
+            }
+        }
+    }
+
+    fn heading(&mut self, level: u8, content: &[Inline], x: f32, width: f32, anchor: bool) {
+        let size = HEADING_SIZES[usize::from(level.clamp(1, 6)) - 1];
+        let style = self.style(FontKind::Bold, size);
+        let tokens = self.tokens(content, &style);
+        let lines = self.break_lines(&tokens, width);
+        // Keep the heading together with the first lines of what follows.
+        let height = lines.len() as f32 * size * LINE_HEIGHT;
+        self.ensure(height + 3.0 * BODY_SIZE * LINE_HEIGHT);
+        self.gap(size * 0.6);
+
+        if anchor {
+            self.anchors.push(Anchor {
+                level,
+                title: plain_text(content),
+                id: self.heading_ids.next(content),
+                page: self.page(),
+                y: self.y,
+            });
+        }
+        self.lines(&lines, x, width, Alignment::Left);
+        self.gap(size * 0.3);
+    }
+
+    fn text(
+        &mut self,
+        content: &[Inline],
+        style: &Style,
+        x: f32,
+        width: f32,
+        alignment: Alignment,
+    ) -> Placed {
+        let tokens = self.tokens(content, style);
+        let lines = self.break_lines(&tokens, width);
+        self.lines(&lines, x, width, alignment)
+    }
+
+    fn lines(&mut self, lines: &[Line], x: f32, width: f32, alignment: Alignment) -> Placed {
+        let mut placed = Placed::default();
+        for line in lines {
+            let size = if line.size > 0.0 {
+                line.size
+            } else {
+                BODY_SIZE
+            };
+            let height = size * LINE_HEIGHT;
+            self.ensure(height);
+            let baseline = self.y - size * 1.1;
+            self.draw_line(line, x + align(alignment, width, line.width), baseline);
+            placed.first.get_or_insert((self.page(), baseline));
+            placed.last = Some((self.page(), baseline));
+            self.y -= height;
+        }
+        placed
+    }
+
+    fn draw_line(&mut self, line: &Line, x: f32, baseline: f32) {
+        for run in &line.runs {
+            self.push(Op::Text {
+                x: x + run.x,
+                y: baseline,
+                font: run.style.font,
+                size: run.style.size,
+                color: run.style.color,
+                text: run.text.clone(),
+            });
+            if run.style.strikethrough {
+                let y = baseline + run.style.size * 0.3;
+                self.push(Op::Line {
+                    from: (x + run.x, y),
+                    to: (x + run.x + run.width, y),
+                    width: run.style.size * 0.07,
+                    gray: 0.1,
+                });
+            }
+            if let Some(target) = &run.style.link {
+                self.push(Op::Link {
+                    rect: [
+                        x + run.x,
+                        baseline - run.style.size * 0.3,
+                        x + run.x + run.width,
+                        baseline + run.style.size,
+                    ],
+                    target: target.clone(),
+                });
+            }
+        }
+    }
+
+    fn list(&mut self, list: &List, x: f32, width: f32) {
+        let bullet = ["•", "◦", "▪"][self.list_depth % 3];
+        self.list_depth += 1;
+        for (i, item) in list.items.iter().enumerate() {
+            let marker = match item.checked {
+                Some(true) => "☑".to_string(),
+                Some(false) => "☐".to_string(),
+                None if list.ordered => format!("{}.", list.start + i as u64),
+                None => bullet.to_string(),
+            };
+
+            let style = self.style(FontKind::Regular, BODY_SIZE);
+            let placed = self.text(
+                &item.content,
+                &style,
+                x + LIST_INDENT,
+                width - LIST_INDENT,
+                Alignment::Left,
+            );
+            let (page, baseline) = placed
+                .first
+                .unwrap_or((self.page(), self.y - BODY_SIZE * 1.1));
+            let marker_width = self.fonts.width(FontKind::Regular, &marker, BODY_SIZE);
+            self.pages[page].push(Op::Text {
+                x: x + LIST_INDENT - marker_width - 5.0,
+                y: baseline,
+                font: FontKind::Regular,
+                size: BODY_SIZE,
+                color: self.color,
+                text: marker,
+            });
+
+            self.blocks(&item.children, x + LIST_INDENT, width - LIST_INDENT);
+            self.y -= 2.0;
+        }
+        self.list_depth -= 1;
+        if self.list_depth == 0 {
+            self.gap(BLOCK_GAP);
+        }
+    }
+
+    fn code_block(&mut self, code_block: &CodeBlock, x: f32, width: f32) {
+        let char_width = self.fonts.width(FontKind::Mono, " ", CODE_SIZE);
+        let columns = (((width - 2.0 * CODE_PADDING) / char_width) as usize).max(1);
+        let mut lines = Vec::new();
+        for line in code_block.code.replace('\t', "    ").lines() {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                lines.push(String::new());
+            }
+            lines.extend(
+                chars
+                    .chunks(columns)
+                    .map(|chunk| chunk.iter().collect::<String>()),
+            );
+        }
+
+        let height = CODE_SIZE * LINE_HEIGHT;
+        self.ensure(height + 2.0 * CODE_PADDING);
+        // The background goes under the text, so it is inserted at the start
+        // of the block's ops once the block's extent on each page is known.
+        let mut start = (self.page(), self.pages[self.page()].len(), self.y);
+        self.y -= CODE_PADDING;
+        for line in lines {
+            if self.y - height < MARGIN {
+                self.backdrop(start, x, width);
+                self.pages.push(Vec::new());
+                self.y = self.top();
+                start = (self.page(), 0, self.y);
+            }
+            let baseline = self.y - CODE_SIZE * 1.1;
+            self.push(Op::Text {
+                x: x + CODE_PADDING,
+                y: baseline,
+                font: FontKind::Mono,
+                size: CODE_SIZE,
+                color: TEXT_COLOR,
+                text: line,
+            });
+            self.y -= height;
+        }
+        self.y -= CODE_PADDING;
+        self.backdrop(start, x, width);
+        self.gap(BLOCK_GAP);
+    }
+
+    /// Fills from `(page, op index, top)` down to the cursor behind the ops
+    /// placed since.
+    fn backdrop(&mut self, (page, index, top): (usize, usize, f32), x: f32, width: f32) {
+        let rect = [x, self.y, width, top - self.y];
+        self.pages[page].insert(index, Op::Fill { rect, gray: 0.95 });
+    }
+
+    fn table(&mut self, table: &Table, x: f32, width: f32) {
+        let columns = table
+            .rows
+            .iter()
+            .map(Vec::len)
+            .chain([table.header.len()])
+            .max()
+            .unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+
+        let header_style = self.style(FontKind::Bold, BODY_SIZE);
+        let body_style = self.style(FontKind::Regular, BODY_SIZE);
+        let cell_tokens =
+            |layout: &Self, cells: &[Vec<Inline>], style: &Style| -> Vec<Vec<Token>> {
+                (0..columns)
+                    .map(|i| {
+                        cells
+                            .get(i)
+                            .map_or_else(Vec::new, |cell| layout.tokens(cell, style))
+                    })
+                    .collect()
+            };
+        let header = cell_tokens(self, &table.header, &header_style);
+        let rows: Vec<Vec<Vec<Token>>> = table
+            .rows
+            .iter()
+            .map(|row| cell_tokens(self, row, &body_style))
+            .collect();
+
+        // Each column gets at least its longest word, then a share of the
+        // rest in proportion to how much more its content would take.
+        let mut natural = vec![0.0f32; columns];
+        let mut minimum = vec![0.0f32; columns];
+        for row in [&header].into_iter().chain(&rows) {
+            for (i, tokens) in row.iter().enumerate() {
+                let line = self.break_lines(tokens, f32::INFINITY);
+                natural[i] = natural[i].max(line.iter().map(|l| l.width).fold(0.0, f32::max));
+                for token in tokens {
+                    if let Token::Word(word) = token {
+                        minimum[i] = minimum[i].max(self.word_width(word));
+                    }
+                }
+            }
+        }
+        let padding = 2.0 * CELL_PADDING;
+        let natural: Vec<f32> = natural.iter().map(|w| w + padding).collect();
+        let mut minimum: Vec<f32> = minimum.iter().map(|w| w + padding).collect();
+        let minimum_total: f32 = minimum.iter().sum();
+        if minimum_total > width {
+            minimum.iter_mut().for_each(|w| *w *= width / minimum_total);
+        }
+        let widths: Vec<f32> = if natural.iter().sum::<f32>() <= width {
+            natural
+        } else {
+            let spare = width - minimum.iter().sum::<f32>();
+            let wanted: f32 = natural.iter().zip(&minimum).map(|(n, m)| n - m).sum();
+            natural
+                .iter()
+                .zip(&minimum)
+                .map(|(n, m)| m + (n - m) / wanted.max(f32::EPSILON) * spare.max(0.0))
+                .collect()
+        };
+        let table_width: f32 = widths.iter().sum();
+
+        let rule = |layout: &mut Self, width: f32, gray: f32| {
+            let y = layout.y;
+            layout.push(Op::Line {
+                from: (x, y),
+                to: (x + table_width, y),
+                width,
+                gray,
+            });
+        };
+
+        for (r, row) in [&header].into_iter().chain(&rows).enumerate() {
+            let cells: Vec<Vec<Line>> = row
+                .iter()
+                .zip(&widths)
+                .map(|(tokens, width)| self.break_lines(tokens, width - padding))
+                .collect();
+            let lines = cells.iter().map(Vec::len).max().unwrap_or(1).max(1);
+            let height = lines as f32 * BODY_SIZE * LINE_HEIGHT + padding;
+            self.ensure(height);
+            if r == 0 {
+                rule(self, 0.8, 0.2);
+            }
+
+            let top = self.y - CELL_PADDING;
+            let mut cell_x = x;
+            for (i, lines) in cells.iter().enumerate() {
+                let alignment = table.alignments.get(i).copied().unwrap_or(Alignment::None);
+                let inner = widths[i] - padding;
+                for (l, line) in lines.iter().enumerate() {
+                    let baseline = top - l as f32 * BODY_SIZE * LINE_HEIGHT - BODY_SIZE * 1.1;
+                    let offset = align(alignment, inner, line.width);
+                    self.draw_line(line, cell_x + CELL_PADDING + offset, baseline);
+                }
+                cell_x += widths[i];
+            }
+
+            self.y -= height;
+            match r {
+                0 => rule(self, 0.5, 0.2),
+                r if r == rows.len() => rule(self, 0.8, 0.2),
+                _ => rule(self, 0.25, 0.75),
+            }
+        }
+        self.gap(BLOCK_GAP);
+    }
+
+    fn blockquote(&mut self, blocks: &[Block], x: f32, width: f32) {
+        let color = std::mem::replace(&mut self.color, QUOTE_COLOR);
+        let (start_page, start_y) = (self.page(), self.y);
+        self.blocks(blocks, x + QUOTE_INDENT, width - QUOTE_INDENT);
+        self.color = color;
+
+        // The bar runs beside the quote on every page it spans.
+        let end_y = self.y + BLOCK_GAP;
+        for page in start_page..=self.page() {
+            let top = if page == start_page {
+                start_y
+            } else {
+                self.top()
+            };
+            let bottom = if page == self.page() { end_y } else { MARGIN };
+            self.pages[page].push(Op::Line {
+                from: (x + 3.0, top),
+                to: (x + 3.0, bottom),
+                width: 2.5,
+                gray: 0.8,
+            });
+        }
+    }
+
+    /// Places a local image as a block, scaled to fit, with its alt text as a
+    /// caption. Returns false if it could not be loaded.
+    fn image(&mut self, url: &str, alt: &str, x: f32, width: f32) -> bool {
+        if url.contains("://") || url.starts_with("data:") {
+            return false;
+        }
+        let path = self.base_dir.join(url);
+        let index = match self.image_paths.get(&path) {
+            Some(&index) => index,
+            None => match image::open(&path) {
+                Ok(decoded) => {
+                    let alpha = decoded.color().has_alpha().then(|| {
+                        decoded
+                            .to_rgba8()
+                            .pixels()
+                            .map(|pixel| pixel.0[3])
+                            .collect()
+                    });
+                    self.images.push(Image {
+                        width: decoded.width(),
+                        height: decoded.height(),
+                        rgb: decoded.to_rgb8().into_raw(),
+                        alpha,
+                    });
+                    self.image_paths.insert(path, self.images.len() - 1);
+                    self.images.len() - 1
+                }
+                Err(e) => {
+                    warn!("Could not load image {}: {}", path.display(), e);
+                    return false;
+                }
+            },
+        };
+
+        // Pixels at 96 dpi, shrunk to the width and height available.
+        let image = &self.images[index];
+        let (natural_width, natural_height) =
+            (image.width as f32 * 0.75, image.height as f32 * 0.75);
+        let scale = 1f32
+            .min(width / natural_width)
+            .min((self.top() - MARGIN - 2.0 * BODY_SIZE * LINE_HEIGHT) / natural_height);
+        let (image_width, image_height) = (natural_width * scale, natural_height * scale);
+
+        self.ensure(image_height);
+        self.push(Op::Image {
+            rect: [
+                x + (width - image_width) / 2.0,
+                self.y - image_height,
+                image_width,
+                image_height,
+            ],
+            image: index,
+        });
+        self.y -= image_height + 4.0;
+        if !alt.is_empty() {
+            let style = self.style(FontKind::Italic, BODY_SIZE - 1.0);
+            self.text(
+                &[Inline::Text(alt.to_string())],
+                &style,
+                x,
+                width,
+                Alignment::Center,
+            );
+        }
+        self.gap(BLOCK_GAP);
+        true
+    }
+
+    /// Splits `inlines` into words, styled starting from `style`.
+    fn tokens(&self, inlines: &[Inline], style: &Style) -> Vec<Token> {
+        let mut builder = TokenBuilder::default();
+        builder.inlines(inlines, style);
+        builder.finish()
+    }
+
+    fn word_width(&self, word: &Word) -> f32 {
+        word.segments
+            .iter()
+            .map(|segment| {
+                self.fonts
+                    .width(segment.style.font, &segment.text, segment.style.size)
+            })
+            .sum()
+    }
+
+    /// Fills lines of at most `width` points greedily, splitting words that
+    /// don't fit on a line of their own.
+    fn break_lines(&self, tokens: &[Token], width: f32) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut line = Line::default();
+        for token in tokens {
+            let word = match token {
+                Token::Word(word) => word,
+                Token::LineBreak => {
+                    lines.push(std::mem::take(&mut line));
+                    continue;
+                }
+            };
+            for (i, piece) in self.split_word(word, width).into_iter().enumerate() {
+                let first = &piece.segments[0].style;
+                let mut space = if piece.space_before && !line.runs.is_empty() {
+                    self.fonts.width(first.font, " ", first.size)
+                } else {
+                    0.0
+                };
+                let piece_width = self.word_width(&piece);
+                if !line.runs.is_empty() && (i > 0 || line.width + space + piece_width > width) {
+                    lines.push(std::mem::take(&mut line));
+                    space = 0.0;
+                }
+                self.append(&mut line, piece, space);
+            }
+        }
+        if !line.runs.is_empty() || lines.is_empty() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn append(&self, line: &mut Line, word: Word, mut space: f32) {
+        for segment in word.segments {
+            let width = self
+                .fonts
+                .width(segment.style.font, &segment.text, segment.style.size);
+            line.size = line.size.max(segment.style.size);
+            match line.runs.last_mut() {
+                Some(run) if run.style == segment.style => {
+                    if space > 0.0 {
+                        run.text.push(' ');
+                    }
+                    run.text.push_str(&segment.text);
+                    run.width += space + width;
+                }
+                _ => line.runs.push(Run {
+                    x: line.width + space,
+                    width,
+                    text: segment.text,
+                    style: segment.style,
+                }),
+            }
+            line.width += space + width;
+            space = 0.0;
+        }
+    }
+
+    /// `word` cut into pieces no wider than `width`, or just `word` if it fits.
+    fn split_word(&self, word: &Word, width: f32) -> Vec<Word> {
+        let mut pieces = vec![Word {
+            segments: Vec::new(),
+            space_before: word.space_before,
+        }];
+        let mut piece_width = 0.0;
+        for segment in &word.segments {
+            for c in segment.text.chars() {
+                let char_width = self.fonts.width(
+                    segment.style.font,
+                    c.encode_utf8(&mut [0; 4]),
+                    segment.style.size,
+                );
+                let piece = pieces.last_mut().expect("there is always a piece");
+                if piece_width + char_width > width && !piece.segments.is_empty() {
+                    pieces.push(Word {
+                        segments: Vec::new(),
+                        space_before: false,
+                    });
+                    piece_width = 0.0;
+                }
+                piece_width += char_width;
+                let piece = pieces.last_mut().expect("there is always a piece");
+                match piece.segments.last_mut() {
+                    Some(last) if last.style == segment.style => last.text.push(c),
+                    _ => piece.segments.push(Segment {
+                        text: c.to_string(),
+                        style: segment.style.clone(),
+                    }),
+                }
+            }
+        }
+        pieces
+    }
+}
+
+#[derive(Default)]
+struct TokenBuilder {
+    tokens: Vec<Token>,
+    word: Vec<Segment>,
+    space_before: bool,
+    pending_space: bool,
+}
+
+impl TokenBuilder {
+    fn inlines(&mut self, inlines: &[Inline], style: &Style) {
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => self.text(text, style),
+                Inline::Code(code) | Inline::Math(code) => {
+                    let style = Style {
+                        font: FontKind::Mono,
+                        size: style.size * 0.9,
+                        ..style.clone()
+                    };
+                    self.text(code, &style);
+                }
+                Inline::Strong(children) => {
+                    let style = Style {
+                        font: style.font.bold(),
+                        ..style.clone()
+                    };
+                    self.inlines(children, &style);
+                }
+                Inline::Emphasis(children) => {
+                    let style = Style {
+                        font: style.font.italic(),
+                        ..style.clone()
+                    };
+                    self.inlines(children, &style);
+                }
+                Inline::Strikethrough(children) => {
+                    let style = Style {
+                        strikethrough: true,
+                        ..style.clone()
+                    };
+                    self.inlines(children, &style);
+                }
+                Inline::Link { url, content } => {
+                    let style = Style {
+                        color: LINK_COLOR,
+                        link: Some(link_target(url)),
+                        ..style.clone()
+                    };
+                    self.inlines(content, &style);
+                }
+                Inline::Image { url, alt } => {
+                    let style = Style {
+                        font: style.font.italic(),
+                        color: LINK_COLOR,
+                        link: Some(link_target(url)),
+                        ..style.clone()
+                    };
+                    self.text(if alt.is_empty() { url } else { alt }, &style);
+                }
+                Inline::SoftBreak => self.end_word(),
+                Inline::LineBreak => {
+                    self.end_word();
+                    self.tokens.push(Token::LineBreak);
+                    self.pending_space = false;
+                }
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str, style: &Style) {
+        for c in text.chars() {
+            if c.is_whitespace() {
+                self.end_word();
+                continue;
+            }
+            if self.word.is_empty() {
+                self.space_before = std::mem::take(&mut self.pending_space);
+            }
+            match self.word.last_mut() {
+                Some(segment) if segment.style == *style => segment.text.push(c),
+                _ => self.word.push(Segment {
+                    text: c.to_string(),
+                    style: style.clone(),
+                }),
+            }
+        }
+    }
+
+    fn end_word(&mut self) {
+        if !self.word.is_empty() {
+            self.tokens.push(Token::Word(Word {
+                segments: std::mem::take(&mut self.word),
+                space_before: self.space_before,
+            }));
+        }
+        self.pending_space = true;
+    }
+
+    fn finish(mut self) -> Vec<Token> {
+        if !self.word.is_empty() {
+            self.end_word();
+        }
+        self.tokens
+    }
+}
+
+fn link_target(url: &str) -> Target {
+    match url.strip_prefix('#') {
+        Some(id) => Target::Heading(id.to_string()),
+        None => Target::Uri(url.to_string()),
+    }
+}
+
+/// The offset of a line `line_width` wide in a column `width` wide.
+fn align(alignment: Alignment, width: f32, line_width: f32) -> f32 {
+    match alignment {
+        Alignment::Center => (width - line_width).max(0.0) / 2.0,
+        Alignment::Right => (width - line_width).max(0.0),
+        Alignment::Left | Alignment::None => 0.0,
+    }
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec_zlib(data, 6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::MarkdownParser;
+
+    fn text_on(layout: &Layout, page: usize) -> String {
+        layout.pages[page]
+            .iter()
+            .filter_map(|op| match op {
+                Op::Text { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let renderer = PdfRenderer::default();
+        let paragraph = "word ".repeat(200);
+        let layout = renderer.lay_out(&MarkdownParser::new().parse_document(&paragraph));
+
+        let lines: Vec<_> = layout.pages[0].iter().collect();
+        assert!(lines.len() > 5);
+        for op in lines {
+            let Op::Text { x, text, size, .. } = op else {
+                panic!("unexpected {:?}", op);
+            };
+            assert_eq!(*x, MARGIN);
+            assert!(
+                x + renderer.fonts.width(FontKind::Regular, text, *size)
+                    <= MARGIN + layout.content_width()
+            );
+        }
+    }
+
+    #[test]
+    fn test_page_breaks_and_contents() {
+        let mut markdown = String::new();
+        for section in 1..=3 {
+            markdown.push_str(&format!("# Section {}\n\n", section));
+            markdown.push_str(&"Some text that fills the page.\n\n".repeat(40));
+        }
+        let renderer = PdfRenderer::new(PdfOptions {
+            toc: true,
+            ..PdfOptions::default()
+        });
+        let body = renderer.lay_out(&MarkdownParser::new().parse_document(&markdown));
+
+        let pages: Vec<usize> = body.anchors.iter().map(|anchor| anchor.page).collect();
+        assert_eq!(pages.len(), 3);
+        assert!(pages[0] == 0 && pages[1] > pages[0] && pages[2] > pages[1]);
+
+        let toc = renderer.lay_out_toc(&body.anchors, 1);
+        let contents = text_on(&toc, 0);
+        assert!(contents.starts_with("Contents Section 1 "));
+        assert!(contents.contains(&format!("Section 2 {}", ". ".repeat(3))));
+        assert!(contents.ends_with(&(pages[2] + 2).to_string()));
+        assert!(toc.pages[0].iter().any(|op| matches!(
+            op,
+            Op::Link {
+                target: Target::Anchor(2),
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn test_pdf_output() {
+        let markdown = "# Title\n\nSee [docs](https://example.com) or [below](#table).\n\n## Table\n\n| a | b |\n|---|--:|\n| 1 | 2 |\n\n```\nfn main() {}\n```\n\n- one\n- [x] two";
+        let document = MarkdownParser::new().parse_document(markdown);
+        let pdf = PdfRenderer::default().render(&document).unwrap();
+        let pdf = String::from_utf8_lossy(&pdf);
+
+        assert!(pdf.starts_with("%PDF-"));
+        assert!(pdf.contains("/FontFile2"));
+        assert!(pdf.contains("DejaVuSans-Bold"));
+        assert!(pdf.contains("DejaVuSansMono"));
+        assert!(!pdf.contains("DejaVuSans-Oblique"));
+        assert!(pdf.contains("/URI (https://example.com)"));
+        assert!(pdf.contains("/S /GoTo"));
+        assert!(pdf.contains("/Title (Title)"));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::ast::{Block, CodeBlock, Document, Inline, List, ListItem, MathBlock, Table};
+use crate::html;
+use std::io::{self, Write};
+
+/// Turns a parsed [`Document`] into text output.
+///
+/// Every node type has its own method, and the provided implementations
+/// produce the crate's standard HTML (see the functions in [`crate::html`]).
+/// A backend overrides the methods for the nodes it renders differently.
+/// Children are always rendered through `self`, so an override also applies
+/// to nodes nested inside lists, blockquotes and tables.
+pub trait Renderer {
+    /// The `id` to give a heading, if any.
+    fn heading_id(&self, _content: &[Inline]) -> Option<String> {
+        None
+    }
+
+    /// Highlighted markup replacing the whole code block, if any.
+    fn highlight(&self, _code_block: &CodeBlock) -> Option<String> {
+        None
+    }
+
+    /// Typeset markup replacing the TeX of math, if any. `display` is set
+    /// for math blocks.
+    fn typeset_math(&self, _math: &str, _display: bool) -> Option<String> {
+        None
+    }
+
+    /// The number shown beside the display equation labelled `label`, if
+    /// any.
+    fn equation_number(&self, _label: &str) -> Option<usize> {
+        None
+    }
+
+    fn render(&self, document: &Document) -> String {
+        let mut out = String::new();
+        self.blocks(&document.blocks, &mut out);
+        out
+    }
+
+    /// Streams `document` to `writer`, buffering at most one top-level block.
+    fn render_to(&self, document: &Document, writer: &mut dyn Write) -> io::Result<()> {
+        for block in &document.blocks {
+            self.render_block_to(block, writer)?;
+        }
+        Ok(())
+    }
+
+    fn render_block_to(&self, block: &Block, writer: &mut dyn Write) -> io::Result<()> {
+        let mut out = String::new();
+        self.block(block, &mut out);
+        writer.write_all(out.as_bytes())
+    }
+
+    fn blocks(&self, blocks: &[Block], out: &mut String) {
+        for block in blocks {
+            self.block(block, out);
+        }
+    }
+
+    fn block(&self, block: &Block, out: &mut String) {
+        match block {
+            Block::Heading { level, content } => self.heading(*level, content, out),
+            Block::Paragraph(content) => self.paragraph(content, out),
+            Block::List(list) => self.list(list, out),
+            Block::CodeBlock(code_block) => self.code_block(code_block, out),
+            Block::Table(table) => self.table(table, out),
+            Block::Blockquote(blocks) => self.blockquote(blocks, out),
+            Block::MathBlock(math_block) => self.math_block(math_block, out),
+            Block::HorizontalRule => self.horizontal_rule(out),
+        }
+    }
+
+    fn heading(&self, level: u8, content: &[Inline], out: &mut String) {
+        html::heading(self, level, content, out)
+    }
+
+    fn paragraph(&self, content: &[Inline], out: &mut String) {
+        html::paragraph(self, content, out)
+    }
+
+    fn list(&self, list: &List, out: &mut String) {
+        html::list(self, list, out)
+    }
+
+    fn list_item(&self, item: &ListItem, out: &mut String) {
+        html::list_item(self, item, out)
+    }
+
+    fn code_block(&self, code_block: &CodeBlock, out: &mut String) {
+        html::code_block(self, code_block, out)
+    }
+
+    fn table(&self, table: &Table, out: &mut String) {
+        html::table(self, table, out)
+    }
+
+    fn blockquote(&self, blocks: &[Block], out: &mut String) {
+        html::blockquote(self, blocks, out)
+    }
+
+    fn math_block(&self, math_block: &MathBlock, out: &mut String) {
+        html::math_block(self, math_block, out)
+    }
+
+    fn horizontal_rule(&self, out: &mut String) {
+        html::horizontal_rule(self, out)
+    }
+
+    fn inlines(&self, inlines: &[Inline], out: &mut String) {
+        for inline in inlines {
+            self.inline(inline, out);
+        }
+    }
+
+    fn inline(&self, inline: &Inline, out: &mut String) {
+        match inline {
+            Inline::Text(text) => self.text(text, out),
+            Inline::Code(code) => self.code(code, out),
+            Inline::Math(math) => self.math(math, out),
+            Inline::Strong(children) => self.strong(children, out),
+            Inline::Emphasis(children) => self.emphasis(children, out),
+            Inline::Strikethrough(children) => self.strikethrough(children, out),
+            Inline::Link { url, content } => self.link(url, content, out),
+            Inline::Image { url, alt } => self.image(url, alt, out),
+            Inline::SoftBreak => self.soft_break(out),
+            Inline::LineBreak => self.line_break(out),
+        }
+    }
+
+    fn text(&self, text: &str, out: &mut String) {
+        html::text(self, text, out)
+    }
+
+    fn code(&self, code: &str, out: &mut String) {
+        html::code(self, code, out)
+    }
+
+    fn math(&self, math: &str, out: &mut String) {
+        html::math(self, math, out)
+    }
+
+    fn strong(&self, children: &[Inline], out: &mut String) {
+        html::strong(self, children, out)
+    }
+
+    fn emphasis(&self, children: &[Inline], out: &mut String) {
+        html::emphasis(self, children, out)
+    }
+
+    fn strikethrough(&self, children: &[Inline], out: &mut String) {
+        html::strikethrough(self, children, out)
+    }
+
+    fn link(&self, url: &str, content: &[Inline], out: &mut String) {
+        html::link(self, url, content, out)
+    }
+
+    fn image(&self, url: &str, alt: &str, out: &mut String) {
+        html::image(self, url, alt, out)
+    }
+
+    fn soft_break(&self, out: &mut String) {
+        html::soft_break(self, out)
+    }
+
+    fn line_break(&self, out: &mut String) {
+        html::line_break(self, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::MarkdownParser;
+
+    /// Wraps tables in a scrolling container and leaves everything else alone.
+    struct ScrollingTables;
+
+    impl Renderer for ScrollingTables {
+        fn table(&self, table: &Table, out: &mut String) {
+            out.push_str("<div class=\"scroll\">\n");
+            html::table(self, table, out);
+            out.push_str("</div>\n");
+        }
+
+        fn code(&self, code: &str, out: &mut String) {
+            out.push_str("<kbd>");
+            html::text(self, code, out);
+            out.push_str("</kbd>");
+        }
+    }
+
+    #[test]
+    fn test_override_single_node() {
+        let document = MarkdownParser::new().parse_document("# Title\n\n| `a` |\n|---|\n| b |");
+        let result = ScrollingTables.render(&document);
+
+        assert!(result.starts_with("<h1>Title</h1>\n<div class=\"scroll\">\n<table>"));
+        assert!(result.contains("<th><kbd>a</kbd></th>"));
+        assert!(result.ends_with("</table>\n</div>\n"));
+    }
+}
@@ -0,0 +1,328 @@
+use crate::ast::{plain_text, Block, Inline};
+use crate::error::ConverterError;
+use crate::front_matter::FrontMatter;
+use crate::html::{HtmlOptions, HtmlRenderer};
+use crate::parser::MarkdownParser;
+use crate::render::Renderer;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::io::Write;
+use std::str::FromStr;
+
+lazy_static! {
+    /// Classes for a slide, as in `## Results {.dark .wide}`.
+    static ref SLIDE_CLASSES: Regex = Regex::new(r"\s*\{((?:\s*\.[\w-]+)+)\s*\}$").unwrap();
+    static ref NOTE: Regex = Regex::new(r"^Notes?:\s*").unwrap();
+}
+
+/// Where a document is split into slides.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SlideSplit {
+    /// At every horizontal rule (`---`).
+    #[default]
+    Rules,
+    /// At every horizontal rule and before every level 2 heading.
+    Headings,
+}
+
+impl FromStr for SlideSplit {
+    type Err = ConverterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rules" | "rule" => Ok(SlideSplit::Rules),
+            "headings" | "heading" => Ok(SlideSplit::Headings),
+            _ => Err(ConverterError::UnknownSlideSplit(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlideOptions {
+    pub split: SlideSplit,
+    pub syntax_highlight: bool,
+}
+
+/// One slide: what is shown and the speaker notes.
+#[derive(Debug, Default, PartialEq)]
+pub struct Slide {
+    /// Classes given with `{.name}` after the slide's first heading or on a
+    /// line of their own.
+    pub classes: Vec<String>,
+    pub blocks: Vec<Block>,
+    /// Everything from a paragraph starting `Note:` to the end of the slide.
+    pub notes: Vec<Block>,
+    in_notes: bool,
+}
+
+impl Slide {
+    fn is_empty(&self) -> bool {
+        self.blocks.is_empty() && self.notes.is_empty() && !self.in_notes
+    }
+
+    fn push(&mut self, mut block: Block) {
+        if self.in_notes {
+            self.notes.push(block);
+            return;
+        }
+
+        if let Block::Paragraph(content) | Block::Heading { content, .. } = &mut block {
+            if let Some(classes) = take_classes(content) {
+                self.classes.extend(classes);
+                if content.is_empty() {
+                    return;
+                }
+            }
+        }
+        if let Block::Paragraph(content) = &mut block {
+            if let Some(Inline::Text(text)) = content.first_mut() {
+                if let Some(label) = NOTE.find(text) {
+                    text.replace_range(..label.end(), "");
+                    if text.is_empty() {
+                        content.remove(0);
+                    }
+                    self.in_notes = true;
+                    if !content.is_empty() {
+                        self.notes.push(block);
+                    }
+                    return;
+                }
+            }
+        }
+        self.blocks.push(block);
+    }
+}
+
+/// Removes the `{.class}` list ending `content`, returning the classes.
+fn take_classes(content: &mut Vec<Inline>) -> Option<Vec<String>> {
+    let Some(Inline::Text(text)) = content.last_mut() else {
+        return None;
+    };
+    let found = SLIDE_CLASSES.captures(text)?;
+    let classes = found[1]
+        .split_whitespace()
+        .map(|class| class.trim_start_matches('.').to_string())
+        .collect();
+    let start = found.get(0).unwrap().start();
+    text.truncate(start);
+    if text.is_empty() {
+        content.pop();
+    }
+    Some(classes)
+}
+
+/// Splits `blocks` into slides. Slides left with nothing on them, such as
+/// between a rule and a heading that both start one, are dropped.
+pub fn split_slides(
+    blocks: impl IntoIterator<Item = Block>,
+    split: SlideSplit,
+) -> Slides<impl Iterator<Item = Block>> {
+    Slides {
+        blocks: blocks.into_iter(),
+        split,
+        pending: None,
+    }
+}
+
+/// The slides of a document, read from its blocks as they are parsed.
+pub struct Slides<I> {
+    blocks: I,
+    split: SlideSplit,
+    /// A heading that started the next slide.
+    pending: Option<Block>,
+}
+
+impl<I: Iterator<Item = Block>> Iterator for Slides<I> {
+    type Item = Slide;
+
+    fn next(&mut self) -> Option<Slide> {
+        let mut slide = Slide::default();
+        if let Some(block) = self.pending.take() {
+            slide.push(block);
+        }
+        for block in self.blocks.by_ref() {
+            match block {
+                Block::HorizontalRule if !slide.is_empty() => return Some(slide),
+                Block::HorizontalRule => {}
+                Block::Heading { level: 2, .. }
+                    if self.split == SlideSplit::Headings && !slide.is_empty() =>
+                {
+                    self.pending = Some(block);
+                    return Some(slide);
+                }
+                block => slide.push(block),
+            }
+        }
+        (!slide.is_empty()).then_some(slide)
+    }
+}
+
+/// Writes a presentation as one HTML page with its stylesheet and script
+/// inline, so it works offline and from a single file. Arrow keys, Page
+/// Up/Down and the space bar move between slides, `n` shows the speaker
+/// notes, and printing gives a handout with each slide's notes beneath it.
+///
+/// Math is written as MathML.
+pub struct SlidesWriter {
+    options: SlideOptions,
+    css: String,
+}
+
+impl SlidesWriter {
+    pub fn new(options: SlideOptions) -> Self {
+        Self {
+            options,
+            css: include_str!("../assets/slides.css").to_string(),
+        }
+    }
+
+    /// Adds `css` after the default slide styles.
+    pub fn with_css(mut self, css: &str) -> Self {
+        self.css.push('\n');
+        self.css.push_str(css);
+        self
+    }
+
+    /// Writes the deck for `markdown`, titled by its front matter or else
+    /// its first heading.
+    pub fn write<W: Write>(&self, markdown: &str, writer: W) -> Result<()> {
+        let (front_matter, content) = FrontMatter::split(markdown);
+        let parser = MarkdownParser::new();
+        self.write_blocks(&front_matter, || parser.blocks(content), writer)
+    }
+
+    /// Writes the deck for the blocks `blocks` yields, which it is called
+    /// for twice: once for the title, then for the slides.
+    pub fn write_blocks<I, W>(
+        &self,
+        front_matter: &FrontMatter,
+        blocks: impl Fn() -> I,
+        mut writer: W,
+    ) -> Result<()>
+    where
+        I: Iterator<Item = Block>,
+        W: Write,
+    {
+        let title = match front_matter.get("title") {
+            Some(title) => title.to_string(),
+            None => blocks()
+                .find_map(|block| match block {
+                    Block::Heading { content, .. } => Some(plain_text(&content)),
+                    _ => None,
+                })
+                .unwrap_or_default(),
+        };
+
+        write!(
+            writer,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"UTF-8\">\n\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+             <title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n",
+            html_escape::encode_text(&title),
+            self.css
+        )?;
+
+        let renderer = HtmlRenderer::new(HtmlOptions {
+            syntax_highlight: self.options.syntax_highlight,
+            heading_ids: false,
+            katex: false,
+        });
+        for (i, slide) in split_slides(blocks(), self.options.split).enumerate() {
+            let mut out = String::from("<section class=\"slide");
+            for class in &slide.classes {
+                out.push(' ');
+                out.push_str(class);
+            }
+            out.push_str(&format!("\" id=\"slide-{}\">\n", i + 1));
+            renderer.blocks(&slide.blocks, &mut out);
+            if !slide.notes.is_empty() {
+                out.push_str("<aside class=\"notes\">\n");
+                renderer.blocks(&slide.notes, &mut out);
+                out.push_str("</aside>\n");
+            }
+            out.push_str("</section>\n");
+            writer.write_all(out.as_bytes())?;
+        }
+
+        write!(
+            writer,
+            "<div class=\"slide-number\"></div>\n<script>\n{}</script>\n</body>\n</html>\n",
+            include_str!("../assets/slides.js")
+        )?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn slides(markdown: &str, split: SlideSplit) -> Vec<Slide> {
+        split_slides(MarkdownParser::new().blocks(markdown), split).collect()
+    }
+
+    #[test]
+    fn test_split() {
+        let markdown = "# Deck\n\n---\n\n## One\n\ntext\n\n## Two\n\n---\n\n---\n\nlast";
+        let by_rules: Vec<usize> = slides(markdown, SlideSplit::Rules)
+            .iter()
+            .map(|slide| slide.blocks.len())
+            .collect();
+        assert_eq!(by_rules, [1, 3, 1]);
+
+        let by_headings: Vec<usize> = slides(markdown, SlideSplit::Headings)
+            .iter()
+            .map(|slide| slide.blocks.len())
+            .collect();
+        assert_eq!(by_headings, [1, 2, 1, 1]);
+    }
+
+    #[test]
+    fn test_classes_and_notes() {
+        let slides = slides(
+            "## Results {.dark .wide}\n\nShown\n\nNote: Mention the *caveat*.\n\n- also a note\n\n---\n\n{.title}\n\n# Thanks",
+            SlideSplit::Rules,
+        );
+        assert_eq!(slides[0].classes, ["dark", "wide"]);
+        assert_eq!(
+            slides[0].blocks,
+            [
+                Block::Heading {
+                    level: 2,
+                    content: vec![Inline::Text("Results".to_string())]
+                },
+                Block::Paragraph(vec![Inline::Text("Shown".to_string())])
+            ]
+        );
+        assert_eq!(slides[0].notes.len(), 2);
+        assert_eq!(
+            slides[0].notes[0],
+            Block::Paragraph(vec![
+                Inline::Text("Mention the ".to_string()),
+                Inline::Emphasis(vec![Inline::Text("caveat".to_string())]),
+                Inline::Text(".".to_string())
+            ])
+        );
+        assert_eq!(slides[1].classes, ["title"]);
+        assert_eq!(slides[1].blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_write() {
+        let mut output = Vec::new();
+        SlidesWriter::new(SlideOptions::default())
+            .write("---\ntitle: A & B\n---\n# Hi\n\nNote: psst", &mut output)
+            .unwrap();
+        let html = String::from_utf8(output).unwrap();
+
+        assert!(html.contains("<title>A &amp; B</title>"));
+        assert!(html.contains(
+            "<section class=\"slide\" id=\"slide-1\">\n<h1>Hi</h1>\n\
+             <aside class=\"notes\">\n<p>psst</p>\n</aside>\n</section>\n"
+        ));
+        assert!(!html.contains("http"));
+    }
+}
@@ -0,0 +1,509 @@
+use crate::error::ConverterError;
+use anyhow::Result;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The page template a directory of templates starts from.
+const PAGE: &str = "page.html";
+
+/// How deeply includes may nest, which also stops an include cycle.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A value a template can show, test or loop over.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// Text, escaped when shown.
+    Text(String),
+    /// Markup, shown as it is.
+    Html(String),
+    List(Vec<Value>),
+    /// Named fields, read with `{{ item.field }}`.
+    Map(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Text(text) | Value::Html(text) => !text.is_empty(),
+            Value::List(items) => !items.is_empty(),
+            Value::Map(fields) => !fields.is_empty(),
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Map(fields) => fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn write_to(&self, out: &mut String) {
+        match self {
+            Value::Text(text) => {
+                html_escape::encode_text_to_string(text, out);
+            }
+            Value::Html(html) => out.push_str(html),
+            Value::List(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    item.write_to(out);
+                }
+            }
+            Value::Map(_) => {}
+        }
+    }
+}
+
+/// The values a template is rendered with, by name.
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    values: Vec<(String, Value)>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `name` to `value`, replacing any value it had.
+    pub fn insert(&mut self, name: &str, value: Value) {
+        match self.values.iter_mut().find(|(key, _)| key == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.values.push((name.to_string(), value)),
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&Value> {
+        self.values
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Variable(String),
+    If {
+        negated: bool,
+        path: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    For {
+        name: String,
+        path: String,
+        body: Vec<Node>,
+    },
+}
+
+/// An HTML page template. `{{ name }}` shows a value, escaped unless it is
+/// markup such as `{{ content }}`, and `{{ item.field }}` a field of one.
+/// `{% if name %}`, `{% if not name %}`, `{% else %}` and `{% endif %}`
+/// test whether a value is set and not empty, `{% for item in list %}` …
+/// `{% endfor %}` repeats for each item of a list (with `loop.index`,
+/// `loop.first` and `loop.last`), and `{% include "file.html" %}` inserts
+/// another template from the same directory. A `{% %}` tag alone on its
+/// line leaves no blank line behind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Default for Template {
+    /// The page HTML output has without a template of its own.
+    fn default() -> Self {
+        Self::parse(include_str!("../assets/template.html"), None)
+            .expect("the default template is valid")
+    }
+}
+
+impl Template {
+    /// Loads the template at `path`: a file, or a directory holding a
+    /// `page.html` and the files it includes.
+    pub fn load(path: &Path) -> Result<Self> {
+        let (file, base_dir) = if path.is_dir() {
+            (path.join(PAGE), path.to_path_buf())
+        } else {
+            let base_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+            (path.to_path_buf(), base_dir)
+        };
+        let source = read(&file)?;
+        Self::parse(&source, Some(&base_dir)).map_err(|e| in_file(&file, e))
+    }
+
+    /// Parses `source`, reading included files from `base_dir`.
+    pub fn parse(source: &str, base_dir: Option<&Path>) -> Result<Self> {
+        let nodes = Parser { base_dir, depth: 0 }.parse(source)?;
+        Ok(Self { nodes })
+    }
+
+    /// Writes the page for `context` to `writer`. `{{ content }}` calls
+    /// `content`, which writes the document's body, so that it can be
+    /// streamed rather than held in memory.
+    pub fn render<W: Write>(
+        &self,
+        context: &Context,
+        writer: &mut W,
+        content: &mut dyn FnMut(&mut W) -> Result<()>,
+    ) -> Result<()> {
+        let mut renderer = Rendering {
+            context,
+            locals: Vec::new(),
+            writer,
+            content,
+        };
+        renderer.nodes(&self.nodes)
+    }
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path).map_err(|e| {
+        ConverterError::TemplateError(format!("Failed to read {:?}: {}", path, e)).into()
+    })
+}
+
+fn in_file(path: &Path, error: anyhow::Error) -> anyhow::Error {
+    match error.downcast::<ConverterError>() {
+        Ok(ConverterError::TemplateError(message)) if message.starts_with("line ") => {
+            ConverterError::TemplateError(format!("{}: {}", path.display(), message)).into()
+        }
+        Ok(error) => error.into(),
+        Err(error) => error,
+    }
+}
+
+/// A piece of template source: text, `{{ … }}` or `{% … %}`.
+enum Token<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+    Tag(&'a str, usize),
+}
+
+fn error(line: usize, message: impl std::fmt::Display) -> anyhow::Error {
+    ConverterError::TemplateError(format!("line {}: {}", line, message)).into()
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    // Whether `rest` starts at the beginning of a line.
+    let mut line_begins = true;
+    while let Some(start) = rest.find('{') {
+        let (close, is_tag) = match rest[start..].get(..2) {
+            Some("{{") => ("}}", false),
+            Some("{%") => ("%}", true),
+            _ => {
+                tokens.push(Token::Text(&rest[..start + 1]));
+                rest = &rest[start + 1..];
+                line_begins = false;
+                continue;
+            }
+        };
+        let line = source[..source.len() - rest.len() + start]
+            .matches('\n')
+            .count()
+            + 1;
+        let Some(end) = rest[start + 2..].find(close) else {
+            return Err(error(
+                line,
+                format!("unclosed \"{}\"", &rest[start..start + 2]),
+            ));
+        };
+        let inner = rest[start + 2..start + 2 + end].trim();
+        let mut text = &rest[..start];
+        let mut after = &rest[start + 2 + end + 2..];
+
+        // A tag with nothing else on its line takes the line with it.
+        let mut standalone = false;
+        if is_tag {
+            let line_start = text.rfind('\n').map_or(0, |i| i + 1);
+            let at_line_start = line_start > 0 || line_begins;
+            let line_end = after.find('\n');
+            let before_blank = text[line_start..].trim().is_empty();
+            let after_blank = after[..line_end.unwrap_or(after.len())].trim().is_empty();
+            if at_line_start && before_blank && after_blank {
+                text = &text[..line_start];
+                after = &after[line_end.map_or(after.len(), |i| i + 1)..];
+                standalone = true;
+            }
+        }
+
+        if !text.is_empty() {
+            tokens.push(Token::Text(text));
+        }
+        tokens.push(if is_tag {
+            Token::Tag(inner, line)
+        } else {
+            Token::Variable(inner)
+        });
+        rest = after;
+        line_begins = standalone;
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    base_dir: Option<&'a Path>,
+    depth: usize,
+}
+
+/// The tag that ended a run of nodes.
+enum Stop {
+    Eof,
+    Else(usize),
+    EndIf(usize),
+    EndFor(usize),
+}
+
+impl Parser<'_> {
+    fn parse(&self, source: &str) -> Result<Vec<Node>> {
+        let tokens = tokenize(source)?;
+        let mut tokens = tokens.into_iter();
+        let (nodes, end) = self.nodes(&mut tokens)?;
+        match end {
+            Stop::Eof => Ok(nodes),
+            Stop::Else(line) => Err(error(line, "\"else\" outside \"if\"")),
+            Stop::EndIf(line) => Err(error(line, "\"endif\" without \"if\"")),
+            Stop::EndFor(line) => Err(error(line, "\"endfor\" without \"for\"")),
+        }
+    }
+
+    fn nodes<'a>(&self, tokens: &mut impl Iterator<Item = Token<'a>>) -> Result<(Vec<Node>, Stop)> {
+        let mut nodes = Vec::new();
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Text(text) => nodes.push(Node::Text(text.to_string())),
+                Token::Variable(path) => nodes.push(Node::Variable(path.to_string())),
+                Token::Tag(tag, line) => {
+                    let words: Vec<&str> = tag.split_whitespace().collect();
+                    match words.as_slice() {
+                        ["else"] => return Ok((nodes, Stop::Else(line))),
+                        ["endif"] => return Ok((nodes, Stop::EndIf(line))),
+                        ["endfor"] => return Ok((nodes, Stop::EndFor(line))),
+                        ["if", "not", path] | ["if", path] => {
+                            let (then, end) = self.nodes(tokens)?;
+                            let otherwise = match end {
+                                Stop::Else(_) => match self.nodes(tokens)? {
+                                    (otherwise, Stop::EndIf(_)) => otherwise,
+                                    _ => return Err(error(line, "\"if\" without \"endif\"")),
+                                },
+                                Stop::EndIf(_) => Vec::new(),
+                                _ => return Err(error(line, "\"if\" without \"endif\"")),
+                            };
+                            nodes.push(Node::If {
+                                negated: words.len() == 3,
+                                path: path.to_string(),
+                                then,
+                                otherwise,
+                            });
+                        }
+                        ["for", name, "in", path] => {
+                            let body = match self.nodes(tokens)? {
+                                (body, Stop::EndFor(_)) => body,
+                                _ => return Err(error(line, "\"for\" without \"endfor\"")),
+                            };
+                            nodes.push(Node::For {
+                                name: name.to_string(),
+                                path: path.to_string(),
+                                body,
+                            });
+                        }
+                        ["include", file] => nodes.extend(self.include(file, line)?),
+                        _ => return Err(error(line, format!("unknown tag \"{}\"", tag))),
+                    }
+                }
+            }
+        }
+        Ok((nodes, Stop::Eof))
+    }
+
+    fn include(&self, file: &str, line: usize) -> Result<Vec<Node>> {
+        let Some(base_dir) = self.base_dir else {
+            return Err(error(line, "includes need a template file or directory"));
+        };
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(error(line, "includes nest too deeply"));
+        }
+        let path: PathBuf = base_dir.join(file.trim_matches(['"', '\'']));
+        let source = read(&path)?;
+        Parser {
+            base_dir: self.base_dir,
+            depth: self.depth + 1,
+        }
+        .parse(&source)
+        .map_err(|e| in_file(&path, e))
+    }
+}
+
+struct Rendering<'a, W: Write> {
+    context: &'a Context,
+    /// Loop variables, innermost last.
+    locals: Vec<(String, Value)>,
+    writer: &'a mut W,
+    content: &'a mut dyn FnMut(&mut W) -> Result<()>,
+}
+
+impl<W: Write> Rendering<'_, W> {
+    fn lookup(&self, path: &str) -> Option<Value> {
+        let mut parts = path.split('.');
+        let name = parts.next()?;
+        let mut value = self
+            .locals
+            .iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .or_else(|| self.context.get(name))?;
+        for part in parts {
+            value = value.field(part)?;
+        }
+        Some(value.clone())
+    }
+
+    fn nodes(&mut self, nodes: &[Node]) -> Result<()> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => self.writer.write_all(text.as_bytes())?,
+                Node::Variable(path) if path == "content" => (self.content)(self.writer)?,
+                Node::Variable(path) => {
+                    if let Some(value) = self.lookup(path) {
+                        let mut out = String::new();
+                        value.write_to(&mut out);
+                        self.writer.write_all(out.as_bytes())?;
+                    }
+                }
+                Node::If {
+                    negated,
+                    path,
+                    then,
+                    otherwise,
+                } => {
+                    let set = path == "content"
+                        || self.lookup(path).is_some_and(|value| value.is_truthy());
+                    self.nodes(if set != *negated { then } else { otherwise })?;
+                }
+                Node::For { name, path, body } => {
+                    let items = match self.lookup(path) {
+                        Some(Value::List(items)) => items,
+                        Some(value) if value.is_truthy() => vec![value],
+                        _ => Vec::new(),
+                    };
+                    let count = items.len();
+                    for (i, item) in items.into_iter().enumerate() {
+                        let flag = |set: bool| Value::Text(if set { "true" } else { "" }.into());
+                        self.locals.push((
+                            "loop".to_string(),
+                            Value::Map(vec![
+                                ("index".to_string(), Value::Text((i + 1).to_string())),
+                                ("first".to_string(), flag(i == 0)),
+                                ("last".to_string(), flag(i + 1 == count)),
+                            ]),
+                        ));
+                        self.locals.push((name.clone(), item));
+                        self.nodes(body)?;
+                        self.locals.truncate(self.locals.len() - 2);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn render(source: &str, context: &Context) -> String {
+        let mut output = Vec::new();
+        Template::parse(source, None)
+            .unwrap()
+            .render(context, &mut output, &mut |writer: &mut Vec<u8>| {
+                writer.extend_from_slice(b"<p>Body</p>");
+                Ok(())
+            })
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_placeholders_and_blocks() {
+        let mut context = Context::new();
+        context.insert("title", Value::Text("Fish & Chips".to_string()));
+        context.insert("author", Value::Text(String::new()));
+        context.insert(
+            "nav",
+            Value::List(vec![
+                Value::Map(vec![
+                    ("title".to_string(), Value::Text("Intro".to_string())),
+                    ("href".to_string(), Value::Text("#intro".to_string())),
+                ]),
+                Value::Map(vec![
+                    ("title".to_string(), Value::Text("Usage".to_string())),
+                    ("href".to_string(), Value::Text("#usage".to_string())),
+                ]),
+            ]),
+        );
+
+        assert_eq!(
+            render(
+                "<title>{{ title }}</title>\n\
+                 {% if author %}\n<p>{{ author }}</p>\n{% else %}\n<p>Anonymous</p>\n{% endif %}\n\
+                 <nav>\n  {% for item in nav %}\n  <a href=\"{{ item.href }}\">{{ loop.index }}. {{ item.title }}</a>{% if not loop.last %} |{% endif %}\n  {% endfor %}\n</nav>\n\
+                 {{ content }}{{ missing }}",
+                &context
+            ),
+            "<title>Fish &amp; Chips</title>\n<p>Anonymous</p>\n\
+             <nav>\n  <a href=\"#intro\">1. Intro</a> |\n  <a href=\"#usage\">2. Usage</a>\n</nav>\n\
+             <p>Body</p>"
+        );
+    }
+
+    #[test]
+    fn test_includes_and_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(PAGE),
+            "{% include \"header.html\" %}\n{{ content }}\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("header.html"),
+            "<header>{{ title }}</header>",
+        )
+        .unwrap();
+        let mut context = Context::new();
+        context.insert("title", Value::Html("<b>Docs</b>".to_string()));
+        let mut output = Vec::new();
+        Template::load(dir.path())
+            .unwrap()
+            .render(&context, &mut output, &mut |_: &mut Vec<u8>| Ok(()))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<header><b>Docs</b></header>\n"
+        );
+
+        for (source, message) in [
+            ("a\n{% if x %}", "line 2: \"if\" without \"endif\""),
+            ("{% endfor %}", "line 1: \"endfor\" without \"for\""),
+            ("{{ title", "line 1: unclosed \"{{\""),
+            ("{% block x %}", "line 1: unknown tag \"block x\""),
+        ] {
+            let error = Template::parse(source, None).unwrap_err();
+            assert!(error.to_string().ends_with(message), "{}", error);
+        }
+    }
+}
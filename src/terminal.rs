@@ -0,0 +1,444 @@
+use crate::ast::{Alignment, Block, CodeBlock, Document, Inline, List, ListItem, MathBlock, Table};
+use crate::html::Highlighter;
+use crate::render::Renderer;
+use crate::text::{display_width, push_border, push_row};
+use console::Style;
+use std::cell::{Cell, RefCell};
+use std::io::{self, Write};
+use syntect::easy::HighlightLines;
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalOptions {
+    /// Column to wrap paragraphs at, usually the terminal's width; `None`
+    /// puts each paragraph on one line.
+    pub width: Option<usize>,
+    /// Colour fenced code with syntect, in 24-bit colour.
+    pub syntax_highlight: bool,
+}
+
+/// Bullets of nested lists, from the outermost in.
+const BULLETS: [&str; 3] = ["•", "◦", "▪"];
+
+/// Renders a document for reading in a terminal, styled with ANSI escape
+/// sequences: coloured bold headings, bulleted lists, box-drawn tables,
+/// highlighted code and links that supporting terminals make clickable
+/// (OSC 8).
+///
+/// Styling is applied word by word, so wrapping a line never splits an
+/// escape sequence or carries a style into the indent of the next line.
+#[derive(Default)]
+pub struct TerminalRenderer {
+    options: TerminalOptions,
+    highlighter: Option<Highlighter>,
+    /// The style of the text being rendered.
+    style: RefCell<Style>,
+    /// Target of the link the text being rendered is in.
+    link: RefCell<Option<String>>,
+    /// Columns taken up by the list markers and quote bars around the block
+    /// being rendered.
+    indent: Cell<usize>,
+    /// How many lists the block being rendered is in.
+    depth: Cell<usize>,
+}
+
+impl TerminalRenderer {
+    pub fn new(options: TerminalOptions) -> Self {
+        Self {
+            options,
+            highlighter: options.syntax_highlight.then(Highlighter::default),
+            style: RefCell::new(Self::style()),
+            ..Self::default()
+        }
+    }
+
+    /// A style to print with regardless of whether stdout is a terminal, so
+    /// that `preview | less -R` keeps its colours.
+    fn style() -> Style {
+        Style::new().force_styling(true)
+    }
+
+    /// Renders `render` with the current style changed by `change`.
+    fn styled(&self, change: impl FnOnce(Style) -> Style, render: impl FnOnce()) {
+        let style = change(self.style.borrow().clone());
+        let outer = self.style.replace(style);
+        render();
+        self.style.replace(outer);
+    }
+
+    /// Appends `text` in the current style, each word styled on its own.
+    fn push_words(&self, text: &str, out: &mut String) {
+        let style = self.style.borrow();
+        let link = self.link.borrow();
+        for (i, word) in text.split(' ').enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            if word.is_empty() {
+                continue;
+            }
+            let word = style.apply_to(word).to_string();
+            match link.as_deref() {
+                Some(url) => out.push_str(&format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, word)),
+                None => out.push_str(&word),
+            }
+        }
+    }
+
+    /// Renders `render` into a string with the indent raised by `indent`.
+    fn indented(&self, indent: usize, render: impl FnOnce(&mut String)) -> String {
+        let mut out = String::new();
+        self.indent.set(self.indent.get() + indent);
+        render(&mut out);
+        self.indent.set(self.indent.get() - indent);
+        out
+    }
+
+    /// Renders `content` as lines wrapped to the width left at this indent.
+    fn fill(&self, content: &[Inline]) -> Vec<String> {
+        let mut text = String::new();
+        self.inlines(content, &mut text);
+
+        let width = self
+            .options
+            .width
+            .map(|width| width.saturating_sub(self.indent.get()));
+        let mut lines = Vec::new();
+        for hard_line in text.split('\n') {
+            let mut line = String::new();
+            let mut line_width = 0;
+            for word in hard_line.split(' ').filter(|word| !word.is_empty()) {
+                let word_width = display_width(word);
+                if !line.is_empty() {
+                    if width.is_some_and(|width| line_width + 1 + word_width > width) {
+                        lines.push(std::mem::take(&mut line));
+                        line_width = 0;
+                    } else {
+                        line.push(' ');
+                        line_width += 1;
+                    }
+                }
+                line.push_str(word);
+                line_width += word_width;
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn list_item_with_marker(&self, marker: &str, item: &ListItem, out: &mut String) {
+        let indent = display_width(marker) + 1;
+        let body = self.indented(indent, |body| {
+            for line in self.fill(&item.content) {
+                body.push_str(&line);
+                body.push('\n');
+            }
+            for child in &item.children {
+                self.block(child, body);
+            }
+        });
+
+        let padding = " ".repeat(indent);
+        for (i, line) in body.lines().enumerate() {
+            if i == 0 {
+                out.push_str(marker);
+                out.push(' ');
+            } else if !line.is_empty() {
+                out.push_str(&padding);
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    fn rule_width(&self) -> usize {
+        self.options
+            .width
+            .unwrap_or(40)
+            .saturating_sub(self.indent.get())
+            .max(3)
+    }
+}
+
+impl Renderer for TerminalRenderer {
+    fn highlight(&self, code_block: &CodeBlock) -> Option<String> {
+        let highlighter = self.highlighter.as_ref()?;
+        let syntax = highlighter.find_syntax(code_block.language()?)?;
+        let mut lines = HighlightLines::new(syntax, &highlighter.theme);
+        let mut out = String::new();
+        for line in LinesWithEndings::from(&code_block.code) {
+            let ranges = lines.highlight_line(line, &highlighter.syntax_set).ok()?;
+            out.push_str(&as_24_bit_terminal_escaped(&ranges, false));
+        }
+        Some(out)
+    }
+
+    fn render(&self, document: &Document) -> String {
+        let mut out = String::new();
+        self.blocks(&document.blocks, &mut out);
+        out
+    }
+
+    fn render_to(&self, document: &Document, writer: &mut dyn Write) -> io::Result<()> {
+        for (i, block) in document.blocks.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b"\n")?;
+            }
+            self.render_block_to(block, writer)?;
+        }
+        Ok(())
+    }
+
+    fn blocks(&self, blocks: &[Block], out: &mut String) {
+        for (i, block) in blocks.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            self.block(block, out);
+        }
+    }
+
+    fn heading(&self, level: u8, content: &[Inline], out: &mut String) {
+        let mut lines = Vec::new();
+        self.styled(
+            |style| match level {
+                1 => style.bold().underlined().magenta(),
+                2 => style.bold().cyan(),
+                _ => style.bold().blue(),
+            },
+            || lines = self.fill(content),
+        );
+        for line in lines {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+
+    fn paragraph(&self, content: &[Inline], out: &mut String) {
+        for line in self.fill(content) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+
+    fn list(&self, list: &List, out: &mut String) {
+        let depth = self.depth.get();
+        self.depth.set(depth + 1);
+        for (number, item) in (list.start..).zip(&list.items) {
+            if list.ordered && item.checked.is_none() {
+                let marker = Self::style().cyan().apply_to(format!("{}.", number));
+                self.list_item_with_marker(&marker.to_string(), item, out);
+            } else {
+                self.list_item(item, out);
+            }
+        }
+        self.depth.set(depth);
+    }
+
+    fn list_item(&self, item: &ListItem, out: &mut String) {
+        let marker = match item.checked {
+            Some(true) => Self::style().green().apply_to("☑"),
+            Some(false) => Self::style().apply_to("☐"),
+            None => {
+                let bullet = BULLETS[self.depth.get().saturating_sub(1) % BULLETS.len()];
+                Self::style().cyan().apply_to(bullet)
+            }
+        };
+        self.list_item_with_marker(&marker.to_string(), item, out);
+    }
+
+    fn code_block(&self, code_block: &CodeBlock, out: &mut String) {
+        let code = self.highlight(code_block).unwrap_or_else(|| {
+            let style = Self::style().green();
+            code_block
+                .code
+                .lines()
+                .map(|line| format!("{}\n", style.apply_to(line)))
+                .collect()
+        });
+        for line in code.lines() {
+            if !line.is_empty() {
+                out.push_str("    ");
+            }
+            out.push_str(line);
+            // Highlighted lines leave their last colour set.
+            out.push_str("\x1b[0m\n");
+        }
+    }
+
+    fn table(&self, table: &Table, out: &mut String) {
+        let render_row = |row: &[Vec<Inline>]| -> Vec<String> {
+            row.iter()
+                .map(|cell| {
+                    let mut text = String::new();
+                    self.inlines(cell, &mut text);
+                    text.replace('\n', " ")
+                })
+                .collect()
+        };
+        let mut header = Vec::new();
+        self.styled(Style::bold, || header = render_row(&table.header));
+        let rows: Vec<Vec<String>> = table.rows.iter().map(|row| render_row(row)).collect();
+
+        let mut widths = Vec::new();
+        for row in std::iter::once(&header).chain(&rows) {
+            for (i, cell) in row.iter().enumerate() {
+                if widths.len() <= i {
+                    widths.push(0);
+                }
+                widths[i] = widths[i].max(display_width(cell));
+            }
+        }
+        let alignments: Vec<Alignment> = (0..widths.len())
+            .map(|i| table.alignments.get(i).copied().unwrap_or(Alignment::None))
+            .collect();
+
+        push_border(&widths, ['┌', '┬', '┐'], out);
+        push_row(&header, &widths, &alignments, ["│ ", " │ ", " │"], out);
+        push_border(&widths, ['├', '┼', '┤'], out);
+        for row in &rows {
+            push_row(row, &widths, &alignments, ["│ ", " │ ", " │"], out);
+        }
+        push_border(&widths, ['└', '┴', '┘'], out);
+    }
+
+    fn blockquote(&self, blocks: &[Block], out: &mut String) {
+        let inner = self.indented(2, |inner| {
+            self.styled(Style::italic, || self.blocks(blocks, inner))
+        });
+        let bar = Self::style().dim().apply_to("│").to_string();
+        for line in inner.lines() {
+            out.push_str(&bar);
+            if !line.is_empty() {
+                out.push(' ');
+                out.push_str(line);
+            }
+            out.push('\n');
+        }
+    }
+
+    fn math_block(&self, math_block: &MathBlock, out: &mut String) {
+        let style = Self::style().italic();
+        for line in math_block.math.lines() {
+            out.push_str("    ");
+            out.push_str(&style.apply_to(line).to_string());
+            out.push('\n');
+        }
+    }
+
+    fn horizontal_rule(&self, out: &mut String) {
+        let rule = "─".repeat(self.rule_width());
+        out.push_str(&Self::style().dim().apply_to(rule).to_string());
+        out.push('\n');
+    }
+
+    fn text(&self, text: &str, out: &mut String) {
+        self.push_words(text, out);
+    }
+
+    fn code(&self, code: &str, out: &mut String) {
+        self.styled(Style::yellow, || self.push_words(code, out));
+    }
+
+    fn math(&self, math: &str, out: &mut String) {
+        self.styled(Style::italic, || self.push_words(math, out));
+    }
+
+    fn strong(&self, children: &[Inline], out: &mut String) {
+        self.styled(Style::bold, || self.inlines(children, out));
+    }
+
+    fn emphasis(&self, children: &[Inline], out: &mut String) {
+        self.styled(Style::italic, || self.inlines(children, out));
+    }
+
+    fn strikethrough(&self, children: &[Inline], out: &mut String) {
+        self.styled(Style::strikethrough, || self.inlines(children, out));
+    }
+
+    fn link(&self, url: &str, content: &[Inline], out: &mut String) {
+        // Links within the document have nowhere to go in a terminal.
+        let outer = (!url.starts_with('#')).then(|| self.link.replace(Some(url.to_string())));
+        self.styled(
+            |style| style.blue().underlined(),
+            || self.inlines(content, out),
+        );
+        if let Some(outer) = outer {
+            self.link.replace(outer);
+        }
+    }
+
+    fn image(&self, url: &str, alt: &str, out: &mut String) {
+        let outer = self.link.replace(Some(url.to_string()));
+        self.styled(Style::dim, || {
+            self.push_words(
+                &format!("[{}]", if alt.is_empty() { "image" } else { alt }),
+                out,
+            )
+        });
+        self.link.replace(outer);
+    }
+
+    fn soft_break(&self, out: &mut String) {
+        out.push(' ');
+    }
+
+    fn line_break(&self, out: &mut String) {
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::MarkdownParser;
+    use pretty_assertions::assert_eq;
+
+    fn render(markdown: &str, width: Option<usize>) -> String {
+        TerminalRenderer::new(TerminalOptions {
+            width,
+            syntax_highlight: false,
+        })
+        .render(&MarkdownParser::new().parse_document(markdown))
+    }
+
+    #[test]
+    fn test_styles() {
+        assert_eq!(
+            render(
+                "# Title\n\nSome **bold** and `code`, see [the docs](https://example.com).",
+                None
+            ),
+            "\x1b[35m\x1b[1m\x1b[4mTitle\x1b[0m\n\n\
+             Some \x1b[1mbold\x1b[0m and \x1b[33mcode\x1b[0m, see \
+             \x1b]8;;https://example.com\x1b\\\x1b[34m\x1b[4mthe\x1b[0m\x1b]8;;\x1b\\ \
+             \x1b]8;;https://example.com\x1b\\\x1b[34m\x1b[4mdocs\x1b[0m\x1b]8;;\x1b\\.\n"
+        );
+    }
+
+    #[test]
+    fn test_wrapping_ignores_escapes() {
+        let result = render("- **a list item** long enough to wrap\n  - inner", Some(16));
+        assert_eq!(
+            console::strip_ansi_codes(&result),
+            "• a list item\n  long enough to\n  wrap\n  ◦ inner\n"
+        );
+    }
+
+    #[test]
+    fn test_tables_and_quotes() {
+        let result = render(
+            "| Name | Size |\n|------|-----:|\n| `a` | 10 |\n\n> quoted",
+            None,
+        );
+        assert_eq!(
+            console::strip_ansi_codes(&result),
+            "┌──────┬──────┐\n\
+             │ Name │ Size │\n\
+             ├──────┼──────┤\n\
+             │ a    │   10 │\n\
+             └──────┴──────┘\n\n\
+             │ quoted\n"
+        );
+    }
+}
@@ -0,0 +1,795 @@
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Font {
+    Italic,
+    Upright,
+    Bold,
+    DoubleStruck,
+    Script,
+    Fraktur,
+}
+
+/// Typesets TeX as HTML text: letters in italics, scripts with `<sup>` and
+/// `<sub>`, commands as the Unicode symbols they stand for, and matrices
+/// and cases as tables. Commands it does not know are kept as written.
+pub fn tex_to_html(tex: &str) -> String {
+    let mut out = String::new();
+    render_tex(tex, Font::Italic, &mut out);
+    out.trim().to_string()
+}
+
+fn render_tex(tex: &str, font: Font, out: &mut String) {
+    let mut rest = tex;
+    // Whether the last thing written can be the left side of a binary
+    // operator, so that `-x` is a sign but `y - x` a subtraction.
+    let mut operand = false;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '\\' => {
+                let name = command_name(rest);
+                rest = &rest[name.len()..];
+                operand = render_command(name, &mut rest, font, operand, out);
+            }
+            '{' => {
+                let (group, after) = take_group(rest);
+                rest = after;
+                render_tex(group, font, out);
+                operand = true;
+            }
+            '^' | '_' => {
+                let (argument, after) = take_argument(rest);
+                rest = after;
+                let tag = if c == '^' { "sup" } else { "sub" };
+                out.push_str(&format!("<{}>", tag));
+                render_tex(argument, font, out);
+                out.push_str(&format!("</{}>", tag));
+                operand = true;
+            }
+            '+' | '-' | '*' if operand => {
+                out.push(' ');
+                out.push(binary(c));
+                out.push(' ');
+                operand = false;
+            }
+            '+' | '-' | '*' => out.push(binary(c)),
+            '=' | '<' | '>' => {
+                out.push(' ');
+                html_escape::encode_text_to_string(c.to_string(), out);
+                out.push(' ');
+                operand = false;
+            }
+            ',' | ';' => {
+                out.push(c);
+                out.push(' ');
+                operand = false;
+            }
+            '\'' => out.push('′'),
+            '~' => out.push('\u{a0}'),
+            '&' => out.push(' '),
+            '}' => {}
+            c if c.is_whitespace() => {}
+            c if c.is_alphabetic() => {
+                push_letter(c, font, out);
+                operand = true;
+            }
+            c => {
+                html_escape::encode_text_to_string(c.to_string(), out);
+                operand = !matches!(c, '(' | '[' | '|');
+            }
+        }
+    }
+}
+
+fn binary(c: char) -> char {
+    match c {
+        '-' => '−',
+        '*' => '∗',
+        c => c,
+    }
+}
+
+/// The name of the command `\` starts in `rest`: a run of letters, or one
+/// other character.
+pub(crate) fn command_name(rest: &str) -> &str {
+    let letters = rest
+        .char_indices()
+        .find(|(_, c)| !c.is_ascii_alphabetic())
+        .map_or(rest.len(), |(i, _)| i);
+    match letters {
+        0 => rest.chars().next().map_or("", |c| &rest[..c.len_utf8()]),
+        letters => &rest[..letters],
+    }
+}
+
+/// Splits the content of the group `rest` starts inside from what follows
+/// its closing brace.
+fn take_group(rest: &str) -> (&str, &str) {
+    let mut depth = 1;
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return (&rest[..i], &rest[i + 1..]);
+                }
+            }
+            _ => {}
+        }
+    }
+    (rest, "")
+}
+
+/// Splits the argument at the start of `rest`, a group or a single token,
+/// from what follows it.
+fn take_argument(rest: &str) -> (&str, &str) {
+    let rest = rest.trim_start();
+    match rest.chars().next() {
+        Some('{') => take_group(&rest[1..]),
+        Some('\\') => {
+            let end = 1 + command_name(&rest[1..]).len();
+            (&rest[..end], &rest[end..])
+        }
+        Some(c) => (&rest[..c.len_utf8()], &rest[c.len_utf8()..]),
+        None => ("", ""),
+    }
+}
+
+/// Splits an optional `[...]` argument at the start of `rest` from what
+/// follows it.
+fn take_optional(rest: &str) -> (Option<&str>, &str) {
+    let trimmed = rest.trim_start();
+    match trimmed
+        .strip_prefix('[')
+        .and_then(|inner| inner.split_once(']'))
+    {
+        Some((argument, after)) => (Some(argument), after),
+        None => (None, rest),
+    }
+}
+
+fn push_letter(c: char, font: Font, out: &mut String) {
+    match font {
+        Font::Italic => {
+            // Letters in a row share one `<i>`.
+            if out.ends_with("</i>") {
+                out.truncate(out.len() - "</i>".len());
+            } else {
+                out.push_str("<i>");
+            }
+            out.push(c);
+            out.push_str("</i>");
+        }
+        Font::Upright => out.push(c),
+        Font::Bold => {
+            if out.ends_with("</b>") {
+                out.truncate(out.len() - "</b>".len());
+            } else {
+                out.push_str("<b>");
+            }
+            out.push(c);
+            out.push_str("</b>");
+        }
+        Font::DoubleStruck => out.push(double_struck(c)),
+        Font::Script => out.push(script(c)),
+        Font::Fraktur => out.push(fraktur(c)),
+    }
+}
+
+/// Mathematical double-struck capitals, as in `\mathbb{R}`.
+pub(crate) fn double_struck(c: char) -> char {
+    match c {
+        'C' => 'ℂ',
+        'H' => 'ℍ',
+        'N' => 'ℕ',
+        'P' => 'ℙ',
+        'Q' => 'ℚ',
+        'R' => 'ℝ',
+        'Z' => 'ℤ',
+        'A'..='Z' => char::from_u32(0x1D538 + (c as u32 - 'A' as u32)).unwrap_or(c),
+        c => c,
+    }
+}
+
+/// Mathematical script capitals, as in `\mathcal{L}`.
+pub(crate) fn script(c: char) -> char {
+    match c {
+        'B' => 'ℬ',
+        'E' => 'ℰ',
+        'F' => 'ℱ',
+        'H' => 'ℋ',
+        'I' => 'ℐ',
+        'L' => 'ℒ',
+        'M' => 'ℳ',
+        'R' => 'ℛ',
+        'A'..='Z' => char::from_u32(0x1D49C + (c as u32 - 'A' as u32)).unwrap_or(c),
+        c => c,
+    }
+}
+
+/// Mathematical Fraktur letters, as in `\mathfrak{g}`.
+pub(crate) fn fraktur(c: char) -> char {
+    match c {
+        'C' => 'ℭ',
+        'H' => 'ℌ',
+        'I' => 'ℑ',
+        'R' => 'ℜ',
+        'Z' => 'ℨ',
+        'A'..='Z' => char::from_u32(0x1D504 + (c as u32 - 'A' as u32)).unwrap_or(c),
+        'a'..='z' => char::from_u32(0x1D51E + (c as u32 - 'a' as u32)).unwrap_or(c),
+        c => c,
+    }
+}
+
+/// How a symbol command is spaced.
+pub(crate) enum Symbol {
+    Ordinary(&'static str),
+    Binary(&'static str),
+    Relation(&'static str),
+}
+
+pub(crate) fn symbol(name: &str) -> Option<Symbol> {
+    use Symbol::*;
+    Some(match name {
+        "alpha" => Ordinary("α"),
+        "beta" => Ordinary("β"),
+        "gamma" => Ordinary("γ"),
+        "delta" => Ordinary("δ"),
+        "epsilon" => Ordinary("ϵ"),
+        "varepsilon" => Ordinary("ε"),
+        "zeta" => Ordinary("ζ"),
+        "eta" => Ordinary("η"),
+        "theta" => Ordinary("θ"),
+        "vartheta" => Ordinary("ϑ"),
+        "iota" => Ordinary("ι"),
+        "kappa" => Ordinary("κ"),
+        "lambda" => Ordinary("λ"),
+        "mu" => Ordinary("μ"),
+        "nu" => Ordinary("ν"),
+        "xi" => Ordinary("ξ"),
+        "pi" => Ordinary("π"),
+        "varpi" => Ordinary("ϖ"),
+        "rho" => Ordinary("ρ"),
+        "varrho" => Ordinary("ϱ"),
+        "sigma" => Ordinary("σ"),
+        "varsigma" => Ordinary("ς"),
+        "tau" => Ordinary("τ"),
+        "upsilon" => Ordinary("υ"),
+        "phi" => Ordinary("ϕ"),
+        "varphi" => Ordinary("φ"),
+        "chi" => Ordinary("χ"),
+        "psi" => Ordinary("ψ"),
+        "omega" => Ordinary("ω"),
+        "Gamma" => Ordinary("Γ"),
+        "Delta" => Ordinary("Δ"),
+        "Theta" => Ordinary("Θ"),
+        "Lambda" => Ordinary("Λ"),
+        "Xi" => Ordinary("Ξ"),
+        "Pi" => Ordinary("Π"),
+        "Sigma" => Ordinary("Σ"),
+        "Upsilon" => Ordinary("Υ"),
+        "Phi" => Ordinary("Φ"),
+        "Psi" => Ordinary("Ψ"),
+        "Omega" => Ordinary("Ω"),
+        "infty" => Ordinary("∞"),
+        "partial" => Ordinary("∂"),
+        "nabla" => Ordinary("∇"),
+        "forall" => Ordinary("∀"),
+        "exists" => Ordinary("∃"),
+        "emptyset" | "varnothing" => Ordinary("∅"),
+        "hbar" => Ordinary("ℏ"),
+        "ell" => Ordinary("ℓ"),
+        "Re" => Ordinary("ℜ"),
+        "Im" => Ordinary("ℑ"),
+        "aleph" => Ordinary("ℵ"),
+        "beth" => Ordinary("ℶ"),
+        "wp" => Ordinary("℘"),
+        "imath" => Ordinary("ı"),
+        "jmath" => Ordinary("ȷ"),
+        "varkappa" => Ordinary("ϰ"),
+        "nexists" => Ordinary("∄"),
+        "complement" => Ordinary("∁"),
+        "angle" => Ordinary("∠"),
+        "triangle" => Ordinary("△"),
+        "top" => Ordinary("⊤"),
+        "bot" => Ordinary("⊥"),
+        "flat" => Ordinary("♭"),
+        "natural" => Ordinary("♮"),
+        "sharp" => Ordinary("♯"),
+        "clubsuit" => Ordinary("♣"),
+        "diamondsuit" => Ordinary("♢"),
+        "heartsuit" => Ordinary("♡"),
+        "spadesuit" => Ordinary("♠"),
+        "checkmark" => Ordinary("✓"),
+        "surd" => Ordinary("√"),
+        "backslash" => Ordinary("\\"),
+        "colon" => Ordinary(":"),
+        "prime" => Ordinary("′"),
+        "neg" | "lnot" => Ordinary("¬"),
+        "ldots" | "dots" => Ordinary("…"),
+        "cdots" => Ordinary("⋯"),
+        "vdots" => Ordinary("⋮"),
+        "ddots" => Ordinary("⋱"),
+        "sum" => Ordinary("∑"),
+        "prod" => Ordinary("∏"),
+        "coprod" => Ordinary("∐"),
+        "int" => Ordinary("∫"),
+        "iint" => Ordinary("∬"),
+        "iiint" => Ordinary("∭"),
+        "oint" => Ordinary("∮"),
+        "bigcup" => Ordinary("⋃"),
+        "bigcap" => Ordinary("⋂"),
+        "bigoplus" => Ordinary("⨁"),
+        "bigotimes" => Ordinary("⨂"),
+        "bigvee" => Ordinary("⋁"),
+        "bigwedge" => Ordinary("⋀"),
+        "bigsqcup" => Ordinary("⨆"),
+        "langle" => Ordinary("⟨"),
+        "rangle" => Ordinary("⟩"),
+        "lfloor" => Ordinary("⌊"),
+        "rfloor" => Ordinary("⌋"),
+        "lceil" => Ordinary("⌈"),
+        "rceil" => Ordinary("⌉"),
+        "lbrace" => Ordinary("{"),
+        "rbrace" => Ordinary("}"),
+        "lbrack" => Ordinary("["),
+        "rbrack" => Ordinary("]"),
+        "|" | "Vert" | "lVert" | "rVert" => Ordinary("‖"),
+        "vert" | "lvert" | "rvert" => Ordinary("|"),
+        "," => Ordinary("\u{2009}"),
+        ":" | ";" | " " => Ordinary(" "),
+        "!" => Ordinary(""),
+        "quad" => Ordinary("\u{2003}"),
+        "qquad" => Ordinary("\u{2003}\u{2003}"),
+        "pm" => Binary("±"),
+        "mp" => Binary("∓"),
+        "times" => Binary("×"),
+        "div" => Binary("÷"),
+        "cdot" => Binary("⋅"),
+        "ast" => Binary("∗"),
+        "star" => Binary("⋆"),
+        "circ" => Binary("∘"),
+        "bullet" => Binary("∙"),
+        "oplus" => Binary("⊕"),
+        "otimes" => Binary("⊗"),
+        "cup" => Binary("∪"),
+        "cap" => Binary("∩"),
+        "setminus" => Binary("∖"),
+        "wedge" | "land" => Binary("∧"),
+        "vee" | "lor" => Binary("∨"),
+        "odot" => Binary("⊙"),
+        "ominus" => Binary("⊖"),
+        "uplus" => Binary("⊎"),
+        "sqcup" => Binary("⊔"),
+        "sqcap" => Binary("⊓"),
+        "diamond" => Binary("⋄"),
+        "dagger" => Binary("†"),
+        "ddagger" => Binary("‡"),
+        "triangleleft" => Binary("◃"),
+        "triangleright" => Binary("▹"),
+        "leq" | "le" => Relation("≤"),
+        "geq" | "ge" => Relation("≥"),
+        "neq" | "ne" => Relation("≠"),
+        "ll" => Relation("≪"),
+        "gg" => Relation("≫"),
+        "approx" => Relation("≈"),
+        "equiv" => Relation("≡"),
+        "sim" => Relation("∼"),
+        "simeq" => Relation("≃"),
+        "cong" => Relation("≅"),
+        "propto" => Relation("∝"),
+        "in" => Relation("∈"),
+        "notin" => Relation("∉"),
+        "ni" => Relation("∋"),
+        "subset" => Relation("⊂"),
+        "subseteq" => Relation("⊆"),
+        "supset" => Relation("⊃"),
+        "supseteq" => Relation("⊇"),
+        "mid" => Relation("∣"),
+        "parallel" => Relation("∥"),
+        "perp" => Relation("⊥"),
+        "to" | "rightarrow" => Relation("→"),
+        "leftarrow" | "gets" => Relation("←"),
+        "leftrightarrow" => Relation("↔"),
+        "Rightarrow" | "implies" => Relation("⇒"),
+        "Leftarrow" => Relation("⇐"),
+        "Leftrightarrow" | "iff" => Relation("⇔"),
+        "mapsto" => Relation("↦"),
+        "longmapsto" => Relation("⟼"),
+        "longrightarrow" => Relation("⟶"),
+        "longleftarrow" => Relation("⟵"),
+        "longleftrightarrow" => Relation("⟷"),
+        "Longrightarrow" => Relation("⟹"),
+        "Longleftarrow" => Relation("⟸"),
+        "Longleftrightarrow" => Relation("⟺"),
+        "hookrightarrow" => Relation("↪"),
+        "hookleftarrow" => Relation("↩"),
+        "rightleftharpoons" => Relation("⇌"),
+        "uparrow" => Relation("↑"),
+        "downarrow" => Relation("↓"),
+        "updownarrow" => Relation("↕"),
+        "Uparrow" => Relation("⇑"),
+        "Downarrow" => Relation("⇓"),
+        "nearrow" => Relation("↗"),
+        "searrow" => Relation("↘"),
+        "swarrow" => Relation("↙"),
+        "nwarrow" => Relation("↖"),
+        "leqslant" => Relation("⩽"),
+        "geqslant" => Relation("⩾"),
+        "nleq" => Relation("≰"),
+        "ngeq" => Relation("≱"),
+        "lesssim" => Relation("≲"),
+        "gtrsim" => Relation("≳"),
+        "prec" => Relation("≺"),
+        "succ" => Relation("≻"),
+        "preceq" => Relation("⪯"),
+        "succeq" => Relation("⪰"),
+        "nsubseteq" => Relation("⊈"),
+        "asymp" => Relation("≍"),
+        "doteq" => Relation("≐"),
+        "vdash" => Relation("⊢"),
+        "dashv" => Relation("⊣"),
+        "models" => Relation("⊨"),
+        "therefore" => Relation("∴"),
+        "because" => Relation("∵"),
+        _ => return None,
+    })
+}
+
+/// Combining marks for accents over a single symbol, as in `\hat{x}`.
+fn accent(name: &str) -> Option<char> {
+    Some(match name {
+        "hat" | "widehat" => '\u{302}',
+        "bar" => '\u{304}',
+        "vec" => '\u{20d7}',
+        "dot" => '\u{307}',
+        "ddot" => '\u{308}',
+        "tilde" | "widetilde" => '\u{303}',
+        _ => return None,
+    })
+}
+
+pub(crate) const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "lg", "exp", "lim", "liminf", "limsup", "max", "min", "sup", "inf", "det", "dim",
+    "gcd", "deg", "arg", "ker", "Pr",
+];
+
+/// Writes the command `name`, taking its arguments from `rest`. Returns
+/// whether what it wrote is an operand.
+fn render_command(
+    name: &str,
+    rest: &mut &str,
+    font: Font,
+    operand: bool,
+    out: &mut String,
+) -> bool {
+    if matches!(name, "{" | "}" | "%" | "$" | "#" | "_" | "&") {
+        html_escape::encode_text_to_string(name, out);
+        return true;
+    }
+    if let Some(symbol) = symbol(name) {
+        return match symbol {
+            Symbol::Ordinary(text) => {
+                out.push_str(text);
+                !text.trim().is_empty() || operand
+            }
+            Symbol::Binary(text) if operand => {
+                out.push_str(&format!(" {} ", text));
+                false
+            }
+            Symbol::Binary(text) => {
+                out.push_str(text);
+                false
+            }
+            Symbol::Relation(text) => {
+                out.push_str(&format!(" {} ", text));
+                false
+            }
+        };
+    }
+    if FUNCTIONS.contains(&name) {
+        out.push_str(name);
+        out.push('\u{2009}');
+        return false;
+    }
+    if let Some(mark) = accent(name) {
+        let (argument, after) = take_argument(rest);
+        *rest = after;
+        let mut accented = String::new();
+        render_tex(argument, font, &mut accented);
+        // The mark goes on the last character, inside its tag.
+        let at = match accented.ends_with('>') {
+            true => accented.rfind('<').unwrap_or(accented.len()),
+            false => accented.len(),
+        };
+        accented.insert(at, mark);
+        out.push_str(&accented);
+        return true;
+    }
+
+    match name {
+        "frac" | "dfrac" | "tfrac" => {
+            let (numerator, after) = take_argument(rest);
+            let (denominator, after) = take_argument(after);
+            *rest = after;
+            push_operand(numerator, font, out);
+            out.push('/');
+            push_operand(denominator, font, out);
+        }
+        "sqrt" => {
+            let (degree, after) = take_optional(rest);
+            let (radicand, after) = take_argument(after);
+            *rest = after;
+            if let Some(degree) = degree {
+                out.push_str("<sup>");
+                render_tex(degree, Font::Upright, out);
+                out.push_str("</sup>");
+            }
+            out.push_str("√<span style=\"text-decoration: overline;\">");
+            render_tex(radicand, font, out);
+            out.push_str("</span>");
+        }
+        "overline" => {
+            let (argument, after) = take_argument(rest);
+            *rest = after;
+            out.push_str("<span style=\"text-decoration: overline;\">");
+            render_tex(argument, font, out);
+            out.push_str("</span>");
+        }
+        "text" | "textrm" | "mbox" | "textit" | "textbf" => {
+            let (text, after) = take_argument(rest);
+            *rest = after;
+            let tag = match name {
+                "textit" => Some("i"),
+                "textbf" => Some("b"),
+                _ => None,
+            };
+            if let Some(tag) = tag {
+                out.push_str(&format!("<{}>", tag));
+            }
+            html_escape::encode_text_to_string(text, out);
+            if let Some(tag) = tag {
+                out.push_str(&format!("</{}>", tag));
+            }
+        }
+        "mathrm" | "mathbf" | "mathit" | "mathbb" | "mathcal" | "mathfrak" | "boldsymbol"
+        | "operatorname" => {
+            let (argument, after) = take_argument(rest);
+            *rest = after;
+            let font = match name {
+                "mathbf" | "boldsymbol" => Font::Bold,
+                "mathit" => Font::Italic,
+                "mathbb" => Font::DoubleStruck,
+                "mathcal" => Font::Script,
+                "mathfrak" => Font::Fraktur,
+                _ => Font::Upright,
+            };
+            render_tex(argument, font, out);
+            if name == "operatorname" {
+                out.push('\u{2009}');
+                return false;
+            }
+        }
+        "left" | "right" | "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr"
+        | "biggl" | "biggr" | "Biggl" | "Biggr" => {
+            // Delimiters keep their size; `.` stands for none.
+            if let Some(after) = rest.trim_start().strip_prefix('.') {
+                *rest = after;
+            }
+            return operand;
+        }
+        "\\" => out.push_str("<br>"),
+        "begin" => {
+            let (environment, after) = take_argument(rest);
+            let (body, after) = take_environment(environment, after);
+            *rest = after;
+            push_environment(environment, body, out);
+        }
+        "displaystyle" | "textstyle" | "limits" | "nolimits" | "nonumber" => return operand,
+        _ => {
+            out.push('\\');
+            html_escape::encode_text_to_string(name, out);
+        }
+    }
+    true
+}
+
+/// Writes a fraction's numerator or denominator, in brackets unless it is
+/// a single number, letter or symbol.
+fn push_operand(tex: &str, font: Font, out: &mut String) {
+    let tex = tex.trim();
+    let simple = tex.chars().count() == 1
+        || tex.chars().all(|c| c.is_ascii_digit())
+        || (tex.starts_with('\\') && tex[1..].chars().all(|c| c.is_ascii_alphabetic()));
+    if !simple {
+        out.push('(');
+    }
+    render_tex(tex, font, out);
+    if !simple {
+        out.push(')');
+    }
+}
+
+/// Splits the body of the environment `name` from what follows its `\end`.
+fn take_environment<'a>(name: &str, rest: &'a str) -> (&'a str, &'a str) {
+    let begin = format!("\\begin{{{}}}", name);
+    let end = format!("\\end{{{}}}", name);
+    let mut depth = 1;
+    let mut at = 0;
+    while at < rest.len() {
+        if rest[at..].starts_with(&begin) {
+            depth += 1;
+            at += begin.len();
+        } else if rest[at..].starts_with(&end) {
+            depth -= 1;
+            if depth == 0 {
+                return (&rest[..at], &rest[at + end.len()..]);
+            }
+            at += end.len();
+        } else {
+            at += rest[at..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    (rest, "")
+}
+
+/// Writes a matrix, cases or alignment as a table of its rows and cells.
+fn push_environment(name: &str, body: &str, out: &mut String) {
+    let (open, close) = match name.trim_end_matches('*') {
+        "pmatrix" => ("(", ")"),
+        "bmatrix" => ("[", "]"),
+        "Bmatrix" | "cases" => ("{", ""),
+        "vmatrix" => ("|", "|"),
+        "Vmatrix" => ("‖", "‖"),
+        _ => ("", ""),
+    };
+    let aligned = matches!(
+        name.trim_end_matches('*'),
+        "align" | "aligned" | "alignat" | "split" | "eqnarray" | "cases"
+    );
+    let body = match name {
+        // The column specification is not shown.
+        "array" => take_argument(body).1,
+        _ => body,
+    };
+
+    out.push_str(open);
+    out.push_str(
+        "<table role=\"presentation\" style=\"display: inline-table; \
+         border-collapse: collapse; vertical-align: middle;\">",
+    );
+    for row in split_top_level(body, "\\\\") {
+        if row.trim().is_empty() {
+            continue;
+        }
+        out.push_str("<tr>");
+        for (i, cell) in split_top_level(row, "&").into_iter().enumerate() {
+            let align = match (aligned, i % 2) {
+                (true, 0) if name != "cases" => "right",
+                (true, _) => "left",
+                (false, _) => "center",
+            };
+            out.push_str(&format!(
+                "<td style=\"padding: 0 0.3em; text-align: {};\">",
+                align
+            ));
+            let mut html = String::new();
+            render_tex(cell, Font::Italic, &mut html);
+            out.push_str(html.trim());
+            out.push_str("</td>");
+        }
+        out.push_str("</tr>");
+    }
+    out.push_str("</table>");
+    out.push_str(close);
+}
+
+/// Splits `tex` at each `separator` outside braces and nested environments.
+fn split_top_level<'a>(tex: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut at = 0;
+    while at < tex.len() {
+        let rest = &tex[at..];
+        if depth == 0 && rest.starts_with(separator) {
+            parts.push(&tex[start..at]);
+            at += separator.len();
+            start = at;
+            continue;
+        }
+        if rest.starts_with("\\begin") || rest.starts_with('{') {
+            depth += 1;
+        } else if rest.starts_with("\\end") || rest.starts_with('}') {
+            depth = depth.saturating_sub(1);
+        }
+        if rest.starts_with('\\') && rest.len() > 1 {
+            // Skip the escaped character, so that `\&` and `\{` are text.
+            at += 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+            if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+                at -= 1;
+                at += command_name(&rest[1..]).len();
+            }
+            continue;
+        }
+        at += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    parts.push(&tex[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_tex_to_html() {
+        assert_eq!(tex_to_html("E = mc^2"), "<i>E</i> = <i>mc</i><sup>2</sup>");
+        assert_eq!(
+            tex_to_html(r"\frac{-b \pm \sqrt{b^2 - 4ac}}{2a}"),
+            "(−<i>b</i> ± √<span style=\"text-decoration: overline;\">\
+             <i>b</i><sup>2</sup> − 4<i>ac</i></span>)/(2<i>a</i>)"
+        );
+        assert_eq!(
+            tex_to_html(r"\sum_{i=1}^{n} x_i \leq \alpha \cdot \mathbb{R}"),
+            "∑<sub><i>i</i> = 1</sub><sup><i>n</i></sup><i>x</i><sub><i>i</i></sub> ≤ α ⋅ ℝ"
+        );
+        assert_eq!(
+            tex_to_html(r"\vec{v} + \unknown"),
+            "<i>v\u{20d7}</i> + \\unknown"
+        );
+        assert_eq!(
+            tex_to_html(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"),
+            "(<table role=\"presentation\" style=\"display: inline-table; \
+             border-collapse: collapse; vertical-align: middle;\">\
+             <tr><td style=\"padding: 0 0.3em; text-align: center;\"><i>a</i></td>\
+             <td style=\"padding: 0 0.3em; text-align: center;\"><i>b</i></td></tr>\
+             <tr><td style=\"padding: 0 0.3em; text-align: center;\"><i>c</i></td>\
+             <td style=\"padding: 0 0.3em; text-align: center;\"><i>d</i></td></tr></table>)"
+        );
+    }
+
+    #[test]
+    fn test_example_math() {
+        assert_eq!(
+            tex_to_html(r"\frac1c\, \frac{\partial\vec{\mathbf{E}}}{\partial t}"),
+            "1/<i>c</i>\u{2009}(∂<b>E\u{20d7}</b>)/(∂<i>t</i>)"
+        );
+        assert_eq!(
+            tex_to_html(r"\nabla \cdot \vec{\mathbf{E}} = 4 \pi \rho"),
+            "∇ ⋅ <b>E\u{20d7}</b> = 4πρ"
+        );
+        let cell = |align: &str, content: &str| {
+            format!(
+                "<td style=\"padding: 0 0.3em; text-align: {};\">{}</td>",
+                align, content
+            )
+        };
+        assert_eq!(
+            tex_to_html(r"\begin{aligned} a & = b \\ c & = d \end{aligned}"),
+            format!(
+                "<table role=\"presentation\" style=\"display: inline-table; \
+                 border-collapse: collapse; vertical-align: middle;\">\
+                 <tr>{}{}</tr><tr>{}{}</tr></table>",
+                cell("right", "<i>a</i>"),
+                cell("left", "= <i>b</i>"),
+                cell("right", "<i>c</i>"),
+                cell("left", "= <i>d</i>")
+            )
+        );
+    }
+
+    #[test]
+    fn test_tolerates_errors() {
+        // The fallback shows what it can rather than failing.
+        assert_eq!(tex_to_html(r"\frac{a}{b"), "<i>a</i>/<i>b</i>");
+        assert_eq!(tex_to_html(r"x^{2"), "<i>x</i><sup>2</sup>");
+        assert_eq!(tex_to_html(r"\foo{a}"), "\\foo<i>a</i>");
+    }
+}
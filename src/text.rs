@@ -0,0 +1,493 @@
+use crate::ast::{
+    plain_text, Alignment, Block, CodeBlock, Document, Inline, List, ListItem, MathBlock, Table,
+};
+use crate::render::Renderer;
+use std::cell::{Cell, RefCell};
+use std::io::{self, Write};
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TableStyle {
+    /// Cells enclosed in box-drawing characters.
+    #[default]
+    Box,
+    /// Columns padded to line up, with a dashed rule under the header.
+    Aligned,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextOptions {
+    /// Column to wrap paragraphs at; `None` puts each paragraph on one line.
+    pub width: Option<usize>,
+    pub table_style: TableStyle,
+}
+
+/// Renders a document as plain text that stays readable without any markup:
+/// underlined headings, indented lists, drawn tables and numbered link
+/// references, with the URLs listed at the end.
+#[derive(Default)]
+pub struct TextRenderer {
+    options: TextOptions,
+    /// Targets of the links and images seen so far; a reference `[n]` points
+    /// at the `n`th.
+    links: RefCell<Vec<String>>,
+    /// Columns taken up by the list markers and quote prefixes around the
+    /// block being rendered.
+    indent: Cell<usize>,
+}
+
+impl TextRenderer {
+    pub fn new(options: TextOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// The URLs referenced by everything rendered so far, one `[n] url` line
+    /// each, preceded by a blank line. Empty if there were no links.
+    pub fn footnotes(&self) -> String {
+        let mut out = String::new();
+        for (i, url) in self.links.borrow().iter().enumerate() {
+            if i == 0 {
+                out.push('\n');
+            }
+            out.push_str(&format!("[{}] {}\n", i + 1, url));
+        }
+        out
+    }
+
+    /// The number of the reference to `url`, adding it if it is new.
+    fn reference(&self, url: &str) -> usize {
+        let mut links = self.links.borrow_mut();
+        match links.iter().position(|link| link == url) {
+            Some(i) => i + 1,
+            None => {
+                links.push(url.to_string());
+                links.len()
+            }
+        }
+    }
+
+    /// Renders `render` into a string with the indent raised by `indent`.
+    fn indented(&self, indent: usize, render: impl FnOnce(&mut String)) -> String {
+        let mut out = String::new();
+        self.indent.set(self.indent.get() + indent);
+        render(&mut out);
+        self.indent.set(self.indent.get() - indent);
+        out
+    }
+
+    /// Renders `content` as lines wrapped to the width left at this indent.
+    fn fill(&self, content: &[Inline]) -> Vec<String> {
+        let mut text = String::new();
+        self.inlines(content, &mut text);
+
+        let width = self
+            .options
+            .width
+            .map(|width| width.saturating_sub(self.indent.get()));
+        let mut lines = Vec::new();
+        for hard_line in text.split('\n') {
+            let mut line = String::new();
+            for word in hard_line.split(' ').filter(|word| !word.is_empty()) {
+                if !line.is_empty() {
+                    if width.is_some_and(|width| line.width() + 1 + word.width() > width) {
+                        lines.push(std::mem::take(&mut line));
+                    } else {
+                        line.push(' ');
+                    }
+                }
+                line.push_str(word);
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn list_item_with_marker(&self, marker: &str, item: &ListItem, out: &mut String) {
+        let indent = marker.width() + 1;
+        let body = self.indented(indent, |body| {
+            let mut lines = self.fill(&item.content);
+            match item.checked {
+                Some(true) => lines[0].insert_str(0, "[x] "),
+                Some(false) => lines[0].insert_str(0, "[ ] "),
+                None => {}
+            }
+            for line in lines {
+                body.push_str(&line);
+                body.push('\n');
+            }
+            for child in &item.children {
+                self.block(child, body);
+            }
+        });
+
+        let padding = " ".repeat(indent);
+        for (i, line) in body.lines().enumerate() {
+            if i == 0 {
+                out.push_str(marker);
+                out.push(' ');
+            } else if !line.is_empty() {
+                out.push_str(&padding);
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    fn rule_width(&self) -> usize {
+        self.options
+            .width
+            .unwrap_or(40)
+            .saturating_sub(self.indent.get())
+            .max(3)
+    }
+}
+
+impl Renderer for TextRenderer {
+    fn render(&self, document: &Document) -> String {
+        let mut out = String::new();
+        self.blocks(&document.blocks, &mut out);
+        out.push_str(&self.footnotes());
+        out
+    }
+
+    fn render_to(&self, document: &Document, writer: &mut dyn Write) -> io::Result<()> {
+        for (i, block) in document.blocks.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b"\n")?;
+            }
+            self.render_block_to(block, writer)?;
+        }
+        writer.write_all(self.footnotes().as_bytes())
+    }
+
+    fn blocks(&self, blocks: &[Block], out: &mut String) {
+        for (i, block) in blocks.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            self.block(block, out);
+        }
+    }
+
+    fn heading(&self, level: u8, content: &[Inline], out: &mut String) {
+        let mut text = String::new();
+        self.inlines(content, &mut text);
+        let underline = match level {
+            1 => '=',
+            2 => '-',
+            _ => '~',
+        };
+
+        out.push_str(&text);
+        out.push('\n');
+        for _ in 0..text.width().max(1) {
+            out.push(underline);
+        }
+        out.push('\n');
+    }
+
+    fn paragraph(&self, content: &[Inline], out: &mut String) {
+        for line in self.fill(content) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+
+    fn list(&self, list: &List, out: &mut String) {
+        for (number, item) in (list.start..).zip(&list.items) {
+            if list.ordered {
+                self.list_item_with_marker(&format!("{}.", number), item, out);
+            } else {
+                self.list_item(item, out);
+            }
+        }
+    }
+
+    fn list_item(&self, item: &ListItem, out: &mut String) {
+        self.list_item_with_marker("*", item, out);
+    }
+
+    fn code_block(&self, code_block: &CodeBlock, out: &mut String) {
+        for line in code_block.code.lines() {
+            if !line.is_empty() {
+                out.push_str("    ");
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    fn table(&self, table: &Table, out: &mut String) {
+        let render_row = |row: &[Vec<Inline>]| -> Vec<String> {
+            row.iter()
+                .map(|cell| {
+                    let mut text = String::new();
+                    self.inlines(cell, &mut text);
+                    text.replace('\n', " ")
+                })
+                .collect()
+        };
+        let header = render_row(&table.header);
+        let rows: Vec<Vec<String>> = table.rows.iter().map(|row| render_row(row)).collect();
+
+        let mut widths = Vec::new();
+        for row in std::iter::once(&header).chain(&rows) {
+            for (i, cell) in row.iter().enumerate() {
+                if widths.len() <= i {
+                    widths.push(0);
+                }
+                widths[i] = widths[i].max(cell.width());
+            }
+        }
+        let alignments: Vec<Alignment> = (0..widths.len())
+            .map(|i| table.alignments.get(i).copied().unwrap_or(Alignment::None))
+            .collect();
+
+        match self.options.table_style {
+            TableStyle::Box => {
+                push_border(&widths, ['┌', '┬', '┐'], out);
+                push_row(&header, &widths, &alignments, ["│ ", " │ ", " │"], out);
+                push_border(&widths, ['├', '┼', '┤'], out);
+                for row in &rows {
+                    push_row(row, &widths, &alignments, ["│ ", " │ ", " │"], out);
+                }
+                push_border(&widths, ['└', '┴', '┘'], out);
+            }
+            TableStyle::Aligned => {
+                push_row(&header, &widths, &alignments, ["", "  ", ""], out);
+                let rule: Vec<String> = widths.iter().map(|&width| "-".repeat(width)).collect();
+                push_row(&rule, &widths, &alignments, ["", "  ", ""], out);
+                for row in &rows {
+                    push_row(row, &widths, &alignments, ["", "  ", ""], out);
+                }
+            }
+        }
+    }
+
+    fn blockquote(&self, blocks: &[Block], out: &mut String) {
+        let inner = self.indented(2, |inner| self.blocks(blocks, inner));
+        for line in inner.lines() {
+            out.push('>');
+            if !line.is_empty() {
+                out.push(' ');
+                out.push_str(line);
+            }
+            out.push('\n');
+        }
+    }
+
+    fn math_block(&self, math_block: &MathBlock, out: &mut String) {
+        for line in math_block.math.lines() {
+            out.push_str("    ");
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    fn horizontal_rule(&self, out: &mut String) {
+        out.push_str(&"-".repeat(self.rule_width()));
+        out.push('\n');
+    }
+
+    fn text(&self, text: &str, out: &mut String) {
+        out.push_str(text);
+    }
+
+    fn code(&self, code: &str, out: &mut String) {
+        out.push_str(code);
+    }
+
+    fn math(&self, math: &str, out: &mut String) {
+        out.push_str(math);
+    }
+
+    fn strong(&self, children: &[Inline], out: &mut String) {
+        self.inlines(children, out);
+    }
+
+    fn emphasis(&self, children: &[Inline], out: &mut String) {
+        self.inlines(children, out);
+    }
+
+    fn strikethrough(&self, children: &[Inline], out: &mut String) {
+        self.inlines(children, out);
+    }
+
+    fn link(&self, url: &str, content: &[Inline], out: &mut String) {
+        self.inlines(content, out);
+        // A bare URL already says where it goes.
+        if !url.is_empty() && plain_text(content) != url {
+            out.push_str(&format!("[{}]", self.reference(url)));
+        }
+    }
+
+    fn image(&self, url: &str, alt: &str, out: &mut String) {
+        out.push_str(if alt.is_empty() { "image" } else { alt });
+        if !url.is_empty() {
+            out.push_str(&format!("[{}]", self.reference(url)));
+        }
+    }
+
+    fn soft_break(&self, out: &mut String) {
+        out.push(' ');
+    }
+
+    fn line_break(&self, out: &mut String) {
+        out.push('\n');
+    }
+}
+
+pub(crate) fn push_border(widths: &[usize], [left, middle, right]: [char; 3], out: &mut String) {
+    out.push(left);
+    for (i, width) in widths.iter().enumerate() {
+        if i > 0 {
+            out.push(middle);
+        }
+        out.push_str(&"─".repeat(width + 2));
+    }
+    out.push(right);
+    out.push('\n');
+}
+
+/// Writes one table row, padding missing cells so every column lines up.
+pub(crate) fn push_row(
+    cells: &[String],
+    widths: &[usize],
+    alignments: &[Alignment],
+    [left, separator, right]: [&str; 3],
+    out: &mut String,
+) {
+    let mut line = String::from(left);
+    for (i, width) in widths.iter().enumerate() {
+        if i > 0 {
+            line.push_str(separator);
+        }
+        let cell = cells.get(i).map_or("", String::as_str);
+        let padding = width - display_width(cell);
+        let before = match alignments[i] {
+            Alignment::Right => padding,
+            Alignment::Center => padding / 2,
+            Alignment::None | Alignment::Left => 0,
+        };
+        line.push_str(&" ".repeat(before));
+        line.push_str(cell);
+        line.push_str(&" ".repeat(padding - before));
+    }
+    line.push_str(right);
+    out.push_str(line.trim_end());
+    out.push('\n');
+}
+
+/// The columns `text` takes up on screen, not counting ANSI escape
+/// sequences.
+pub(crate) fn display_width(text: &str) -> usize {
+    if !text.contains('\x1b') {
+        return text.width();
+    }
+    let mut visible = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            visible.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI, such as a colour: ends at a byte in `@`..=`~`.
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC, such as a hyperlink: ends at BEL or `ESC \`.
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next() == Some('\\')) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    visible.width()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::MarkdownParser;
+    use pretty_assertions::assert_eq;
+
+    fn render(markdown: &str, options: TextOptions) -> String {
+        TextRenderer::new(options).render(&MarkdownParser::new().parse_document(markdown))
+    }
+
+    #[test]
+    fn test_structure() {
+        let markdown = "# Release notes\n\n\
+                        Read the **full** [changelog](https://example.com/changes) and\n\
+                        see [the docs](https://example.com/docs).\n\n\
+                        ## Changes\n\n\
+                        - Faster `parse`\n  1. streaming\n- [x] Done\n\n\
+                        > Quoted\n\n\
+                        ```\nlet x = 1;\n```";
+
+        assert_eq!(
+            render(markdown, TextOptions::default()),
+            "Release notes\n\
+             =============\n\n\
+             Read the full changelog[1] and see the docs[2].\n\n\
+             Changes\n\
+             -------\n\n\
+             * Faster parse\n  1. streaming\n\
+             * [x] Done\n\n\
+             > Quoted\n\n    \
+             let x = 1;\n\n\
+             [1] https://example.com/changes\n\
+             [2] https://example.com/docs\n"
+        );
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let options = TextOptions {
+            width: Some(16),
+            ..TextOptions::default()
+        };
+        assert_eq!(
+            render("- a list item long enough to wrap", options),
+            "* a list item\n  long enough to\n  wrap\n"
+        );
+    }
+
+    #[test]
+    fn test_tables() {
+        let markdown = "| Name | Size |\n|------|-----:|\n| a | 10 |\n| long name | 2 |";
+        assert_eq!(
+            render(markdown, TextOptions::default()),
+            "┌───────────┬──────┐\n\
+             │ Name      │ Size │\n\
+             ├───────────┼──────┤\n\
+             │ a         │   10 │\n\
+             │ long name │    2 │\n\
+             └───────────┴──────┘\n"
+        );
+
+        let options = TextOptions {
+            table_style: TableStyle::Aligned,
+            ..TextOptions::default()
+        };
+        assert_eq!(
+            render(markdown, options),
+            "Name       Size\n\
+             ---------  ----\n\
+             a            10\n\
+             long name     2\n"
+        );
+    }
+}
@@ -6,3 +6,23 @@ pub fn is_markdown_file<P: AsRef<Path>>(path: P) -> bool {
         .map(|ext| ext.eq_ignore_ascii_case("md"))
         .unwrap_or(false)
 }
+
+pub fn is_json_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("json"))
+        .unwrap_or(false)
+}
+
+/// The media type of the image file `name`, by its extension; PNG if it is
+/// not one of the common web formats.
+pub fn image_media_type(name: &str) -> &'static str {
+    let extension = name.rsplit('.').next().unwrap_or("").to_ascii_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        _ => "image/png",
+    }
+}